@@ -0,0 +1,30 @@
+//! Timing of parsing a large vertex array, run with `cargo bench`.
+
+use std::time::Instant;
+
+use pbrt4::{Element, Parser};
+
+fn main() {
+    const LEN: usize = 1_200_000;
+
+    let array = "0.123456 -7.5e-3 42 ".repeat(LEN / 3);
+    let data = format!("Shape \"trianglemesh\" \"point3 P\" [ {array}]");
+
+    let start = Instant::now();
+
+    let mut parser = Parser::new(&data);
+    let Ok(Element::Shape { params, .. }) = parser.parse_next() else {
+        panic!("Unexpected element");
+    };
+
+    let mut positions = Vec::new();
+    params
+        .get("P")
+        .unwrap()
+        .read_into::<f32>(&mut positions)
+        .unwrap();
+
+    assert_eq!(positions.len(), LEN);
+
+    println!("Parsed {LEN} floats in {:?}", start.elapsed());
+}
@@ -14,7 +14,7 @@ fn main() -> Result<()> {
 
     println!("Global options: {:#?}", scene.options);
 
-    if let Some(camera) = scene.camera {
+    for camera in scene.cameras {
         println!("Camera: {:#?}", camera);
     }
 
@@ -0,0 +1,309 @@
+//! Scene cache for repeated loads of the same file.
+
+use std::{
+    collections::HashMap,
+    fs, mem,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use crate::{
+    parser::PbrtVersion,
+    scene::{IncludeBase, IncludeData, LoadHooks},
+    LoadOptions, Result, Scene, ShapeEntity,
+};
+
+/// Scene loaded through [SceneCache].
+pub struct CachedScene {
+    /// Scene with per-load overrides applied.
+    ///
+    /// `scene.shapes` is empty, shapes are shared between loads via `shapes` instead.
+    pub scene: Scene,
+    /// Shapes of the scene, shared with other loads of the same file.
+    pub shapes: Arc<Vec<ShapeEntity>>,
+}
+
+/// The [LoadOptions] that change how shapes are loaded, an entry is only reused
+/// by loads with the same ones.
+#[derive(PartialEq)]
+struct ShapeOptions {
+    lenient: bool,
+    max_array_length: usize,
+    version: PbrtVersion,
+    tessellate: bool,
+    include_base: IncludeBase,
+    strict_parameters: bool,
+    instance_material_override: bool,
+}
+
+impl ShapeOptions {
+    fn new(options: &LoadOptions) -> Self {
+        Self {
+            lenient: options.lenient,
+            max_array_length: options.max_array_length,
+            version: options.version,
+            tessellate: options.tessellate,
+            include_base: options.include_base.clone(),
+            strict_parameters: options.strict_parameters,
+            instance_material_override: options.instance_material_override,
+        }
+    }
+}
+
+struct CacheEntry {
+    modified: SystemTime,
+    options: ShapeOptions,
+    data: Arc<str>,
+    shapes: Arc<Vec<ShapeEntity>>,
+    /// Emitting area of each area light, for resolving `power` without the shapes.
+    area_light_areas: Vec<Option<f32>>,
+}
+
+/// Thread safe cache for loading the same scene file many times with different overrides.
+///
+/// The file contents and the shapes (usually the bulk of a scene) are parsed once and shared
+/// between loads, everything else is rebuilt on each load so [LoadOptions::overrides] are applied.
+///
+/// Entries are keyed by the file path and invalidated when the file's modification time changes.
+/// Only the root file is checked, edits to included files are not detected.
+/// An entry is replaced when it's loaded with different options, other than the overrides.
+/// Hooks can't be compared, so loads with a [LoadOptions::rewrite_path] hook bypass the cache.
+#[derive(Default)]
+pub struct SceneCache {
+    entries: Mutex<HashMap<PathBuf, Arc<CacheEntry>>>,
+}
+
+impl SceneCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a scene from a file at path, reusing cached data if the file hasn't changed.
+    ///
+    /// Overrides of `Shape` parameters change the geometry, such loads bypass the cache,
+    /// as do loads with a [LoadOptions::rewrite_path] hook.
+    pub fn load<P: AsRef<Path>>(&self, path: P, options: &LoadOptions) -> Result<CachedScene> {
+        let path = path.as_ref();
+        let working_directory = path.parent();
+
+        let overrides_shapes = options
+            .overrides
+            .iter()
+            .any(|(selector, _)| selector.directive == "Shape");
+
+        if overrides_shapes || options.rewrite_path.is_some() {
+            let mut scene = Scene::from_file_with_options(path, options)?;
+            let shapes = Arc::new(mem::take(&mut scene.shapes));
+
+            return Ok(CachedScene { scene, shapes });
+        }
+
+        let modified = fs::metadata(path)?.modified()?;
+        let shape_options = ShapeOptions::new(options);
+
+        let entry = match self.cached(path, modified, &shape_options) {
+            Some(entry) => entry,
+            None => {
+                let data: Arc<str> = fs::read_to_string(path)?.into();
+
+                let mut scene = Scene::load_with_options(&data, working_directory, options)?;
+                let area_light_areas = scene.area_light_areas();
+                let shapes = Arc::new(mem::take(&mut scene.shapes));
+
+                let entry = Arc::new(CacheEntry {
+                    modified,
+                    options: shape_options,
+                    data,
+                    shapes: shapes.clone(),
+                    area_light_areas,
+                });
+
+                self.lock().insert(path.to_path_buf(), entry);
+
+                return Ok(CachedScene { scene, shapes });
+            }
+        };
+
+        let mut scene = Scene::load_with_includes(
+            &entry.data,
+            working_directory,
+            options,
+            &mut IncludeData::read,
+            LoadHooks {
+                skip_shapes: true,
+                ..Default::default()
+            },
+        )?;
+
+        // Area light power depends on the shapes, which were skipped.
+        scene.apply_area_light_power(&entry.area_light_areas);
+
+        Ok(CachedScene {
+            scene,
+            shapes: entry.shapes.clone(),
+        })
+    }
+
+    /// Remove all cached entries.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn cached(
+        &self,
+        path: &Path,
+        modified: SystemTime,
+        options: &ShapeOptions,
+    ) -> Option<Arc<CacheEntry>> {
+        self.lock()
+            .get(path)
+            .filter(|entry| entry.modified == modified && entry.options == *options)
+            .cloned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<PathBuf, Arc<CacheEntry>>> {
+        // The map is always left in a consistent state, so a poisoned lock is safe to reuse.
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::{
+        param::{ParamType, ParamValue},
+        types::{AreaLight, Sampler, Shape},
+        Selector,
+    };
+
+    #[test]
+    fn reuse_cached_shapes() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt4_cache")?;
+        let path = temp_dir.path().join("scene.pbrt");
+
+        fs::write(
+            &path,
+            r#"
+Sampler "halton"
+
+WorldBegin
+
+ObjectBegin "quad"
+Shape "trianglemesh"
+    "integer indices" [ 0 1 2 0 2 3 ]
+    "point3 P" [ 0 0 0 1 0 0 1 1 0 0 1 0 ]
+ObjectEnd
+
+ObjectInstance "quad"
+            "#,
+        )?;
+
+        let cache = SceneCache::new();
+
+        let first = cache.load(&path, &LoadOptions::default())?;
+
+        let options = LoadOptions {
+            overrides: vec![(
+                Selector::new("Sampler", "pixelsamples"),
+                ParamValue::new(ParamType::Integer, "4"),
+            )],
+            ..Default::default()
+        };
+
+        let second = cache.load(&path, &options)?;
+
+        assert!(Arc::ptr_eq(&first.shapes, &second.shapes));
+        assert_eq!(second.shapes.len(), 1);
+        assert!(second.scene.shapes.is_empty());
+        assert_eq!(second.scene.objects[0].shape_start, Some(0));
+
+        assert!(matches!(
+            first.scene.sampler,
+            Some(Sampler::Halton { pixel_samples: 16 })
+        ));
+        assert!(matches!(
+            second.scene.sampler,
+            Some(Sampler::Halton { pixel_samples: 4 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cached_area_light_power() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt4_cache")?;
+        let path = temp_dir.path().join("scene.pbrt");
+
+        fs::write(
+            &path,
+            r#"
+WorldBegin
+Scale 2 2 2
+AreaLightSource "diffuse" "float power" 10
+Shape "sphere"
+            "#,
+        )?;
+
+        let cache = SceneCache::new();
+
+        let first = cache.load(&path, &LoadOptions::default())?;
+        let second = cache.load(&path, &LoadOptions::default())?;
+
+        assert!(Arc::ptr_eq(&first.shapes, &second.shapes));
+        assert_eq!(
+            format!("{:?}", first.scene.area_lights),
+            format!("{:?}", second.scene.area_lights)
+        );
+        assert!(matches!(
+            second.scene.area_lights[0],
+            AreaLight::Diffuse { power: None, .. }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cache_keyed_by_options() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt4_cache")?;
+        let path = temp_dir.path().join("scene.pbrt");
+
+        fs::write(
+            &path,
+            "WorldBegin\nShape \"bilinearmesh\" \"point3 P\" [ 0 0 0  1 0 0  0 1 0  1 1 0 ]\n",
+        )?;
+
+        let cache = SceneCache::new();
+
+        let tessellate = LoadOptions {
+            tessellate: true,
+            ..Default::default()
+        };
+
+        let first = cache.load(&path, &tessellate)?;
+        let second = cache.load(&path, &tessellate)?;
+
+        assert!(Arc::ptr_eq(&first.shapes, &second.shapes));
+        assert!(matches!(first.shapes[0].params, Shape::TriangleMesh { .. }));
+
+        // Without tessellation the shape fails to load, instead of reusing the mesh.
+        assert!(cache.load(&path, &LoadOptions::default()).is_err());
+
+        // Hooks can't be compared, such loads aren't cached.
+        let rewrite = LoadOptions {
+            tessellate: true,
+            rewrite_path: Some(Box::new(|path| path.to_path_buf())),
+            ..Default::default()
+        };
+        let third = cache.load(&path, &rewrite)?;
+        assert!(!Arc::ptr_eq(&first.shapes, &third.shapes));
+
+        Ok(())
+    }
+}
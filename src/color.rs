@@ -0,0 +1,288 @@
+//! Conversion of spectra to RGB.
+
+use glam::Vec3;
+
+use crate::{param::Spectrum, types::ColorSpace};
+
+/// Shortest wavelength of the visible range, in nm.
+const LAMBDA_MIN: f32 = 360.0;
+/// Longest wavelength of the visible range, in nm.
+const LAMBDA_MAX: f32 = 830.0;
+
+/// Spectra that pbrt makes available by name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum NamedSpectrum {
+    /// Index of refraction given by the Sellmeier equation, `[B1, B2, B3, C1, C2, C3]`
+    /// with `C` coefficients in µm².
+    Sellmeier([f32; 6]),
+    /// Emission of a blackbody at the given temperature in Kelvin.
+    Blackbody(f32),
+    /// Standard illuminant represented by the xy chromaticity of its white point.
+    WhitePoint([f32; 2]),
+}
+
+impl NamedSpectrum {
+    /// Look up a built-in named spectrum.
+    ///
+    /// Only spectra that can be described by a formula are available,
+    /// measured data (metals, camera sensors, fluorescent illuminants) is not bundled.
+    pub fn new(name: &str) -> Option<NamedSpectrum> {
+        let spectrum = match name {
+            "glass-BK7" => NamedSpectrum::Sellmeier([
+                1.039_612,
+                0.231_792_34,
+                1.010_469_5,
+                0.006_000_699,
+                0.020_017_914,
+                103.560_65,
+            ]),
+            "glass-BAF10" => NamedSpectrum::Sellmeier([
+                1.585_149_5,
+                0.143_559_4,
+                1.085_212_7,
+                0.009_266_813,
+                0.042_448_98,
+                105.613_57,
+            ]),
+            "glass-FK51A" => NamedSpectrum::Sellmeier([
+                0.971_247_8,
+                0.216_901_42,
+                0.904_651_67,
+                0.004_723_02,
+                0.015_357_561,
+                168.681_33,
+            ]),
+            "glass-LASF9" => NamedSpectrum::Sellmeier([
+                2.000_295_5,
+                0.298_926_9,
+                1.806_918_4,
+                0.012_142_602,
+                0.053_873_624,
+                156.530_83,
+            ]),
+            "stdillum-A" => NamedSpectrum::Blackbody(2856.0),
+            "stdillum-D50" => NamedSpectrum::WhitePoint([0.3457, 0.3585]),
+            "stdillum-D65" => NamedSpectrum::WhitePoint([0.3127, 0.3290]),
+            "illum-acesD60" => NamedSpectrum::WhitePoint([0.32168, 0.33767]),
+            _ => return None,
+        };
+
+        Some(spectrum)
+    }
+
+    /// Index of refraction at `lambda` nm, `None` for spectra that aren't dispersion curves.
+    pub fn ior(&self, lambda: f32) -> Option<f32> {
+        match self {
+            NamedSpectrum::Sellmeier(coefficients) => Some(sellmeier(coefficients, lambda)),
+            NamedSpectrum::Blackbody(_) | NamedSpectrum::WhitePoint(_) => None,
+        }
+    }
+}
+
+/// CIE 1931 color matching functions tabulated at 1 nm steps over the visible range.
+///
+/// Building the tables is the expensive part of converting spectra,
+/// so a single instance should be reused for converting many spectra.
+pub(crate) struct ColorMatching {
+    /// `(x̄, ȳ, z̄)` per wavelength, starting at [LAMBDA_MIN].
+    cmf: Vec<Vec3>,
+    /// Sum of ȳ, used to normalize the integrals.
+    y_integral: f32,
+}
+
+impl ColorMatching {
+    pub fn new() -> Self {
+        let cmf = (LAMBDA_MIN as usize..=LAMBDA_MAX as usize)
+            .map(|lambda| cie_1931(lambda as f32))
+            .collect::<Vec<_>>();
+        let y_integral = cmf.iter().map(|xyz| xyz.y).sum();
+
+        Self { cmf, y_integral }
+    }
+
+    /// Integrate a spectral distribution against the matching functions.
+    ///
+    /// The result is normalized so that a constant spectrum of 1 has `Y = 1`.
+    fn xyz(&self, spectrum: impl Fn(f32) -> f32) -> Vec3 {
+        let sum = self
+            .cmf
+            .iter()
+            .enumerate()
+            .map(|(i, xyz)| *xyz * spectrum(LAMBDA_MIN + i as f32))
+            .sum::<Vec3>();
+
+        sum / self.y_integral
+    }
+
+    /// Convert a spectrum to RGB in `color_space`.
+    ///
+    /// RGB values are converted from their annotated color space, sRGB if there is none.
+    /// Spectral distributions are white balanced so that a constant spectrum maps to gray,
+    /// named standard illuminants map to the RGB value of their white point.
+    /// Returns `None` for textures and unknown named spectra.
+    pub fn to_rgb(&self, spectrum: &Spectrum, color_space: &ColorSpace) -> Option<[f32; 3]> {
+        let xyz_to_rgb = color_space.xyz_to_rgb();
+
+        // RGB value of a spectral distribution, scaled so that the equal energy spectrum is white.
+        let balanced = |xyz: Vec3| {
+            let white = xyz_to_rgb * self.xyz(|_| 1.0);
+            (xyz_to_rgb * xyz / white).to_array()
+        };
+
+        let rgb = match spectrum {
+            Spectrum::Rgb(rgb, source) => {
+                let rgb_to_xyz = source.unwrap_or_default().xyz_to_rgb().inverse();
+                (xyz_to_rgb * rgb_to_xyz * Vec3::from(*rgb)).to_array()
+            }
+            Spectrum::Blackbody(temperature) => {
+                balanced(self.xyz(|lambda| blackbody(lambda, *temperature as f32)))
+            }
+            Spectrum::Sampled(samples) => balanced(self.xyz(|lambda| sample(samples, lambda))),
+            Spectrum::Named(name) => match NamedSpectrum::new(name)? {
+                NamedSpectrum::Sellmeier(coefficients) => {
+                    balanced(self.xyz(|lambda| sellmeier(&coefficients, lambda)))
+                }
+                NamedSpectrum::Blackbody(temperature) => {
+                    balanced(self.xyz(|lambda| blackbody(lambda, temperature)))
+                }
+                NamedSpectrum::WhitePoint([x, y]) => {
+                    (xyz_to_rgb * Vec3::new(x / y, 1.0, (1.0 - x - y) / y)).to_array()
+                }
+            },
+            Spectrum::Texture(_) => return None,
+        };
+
+        Some(rgb)
+    }
+}
+
+/// Analytic multi-lobe fit of the CIE 1931 2° observer by Wyman, Sloan and Shirley.
+fn cie_1931(lambda: f32) -> Vec3 {
+    let g = |mu: f32, sigma_low: f32, sigma_high: f32| {
+        let sigma = if lambda < mu { sigma_low } else { sigma_high };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    let x =
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+
+    Vec3::new(x, y, z)
+}
+
+/// Planck's law at `lambda` nm, normalized so that the peak of the distribution is 1.
+fn blackbody(lambda: f32, temperature: f32) -> f32 {
+    if temperature <= 0.0 {
+        return 0.0;
+    }
+
+    let planck = |lambda: f64| {
+        const C: f64 = 299_792_458.0;
+        const H: f64 = 6.626_070_15e-34;
+        const KB: f64 = 1.380_649e-23;
+
+        let l = lambda * 1e-9;
+        2.0 * H * C * C / (l.powi(5) * ((H * C / (l * KB * temperature as f64)).exp() - 1.0))
+    };
+
+    // Wien's displacement law gives the wavelength of the peak.
+    let lambda_max = 2.897_771_955e-3 / temperature as f64 * 1e9;
+
+    (planck(lambda as f64) / planck(lambda_max)) as f32
+}
+
+/// Evaluate a piecewise linear spectrum given as `[lambda, value]` pairs, zero outside of its range.
+pub(crate) fn sample(samples: &[[f32; 2]], lambda: f32) -> f32 {
+    let index = samples.partition_point(|[l, _]| *l <= lambda);
+
+    match (index.checked_sub(1).map(|i| samples[i]), samples.get(index)) {
+        (Some([l0, v0]), Some([l1, v1])) => v0 + (v1 - v0) * (lambda - l0) / (l1 - l0),
+        (Some([l0, v0]), None) if l0 == lambda => v0,
+        _ => 0.0,
+    }
+}
+
+/// Index of refraction at `lambda` nm from Sellmeier coefficients.
+fn sellmeier(coefficients: &[f32; 6], lambda: f32) -> f32 {
+    let [b1, b2, b3, c1, c2, c3] = *coefficients;
+    let l2 = (lambda * 1e-3) * (lambda * 1e-3);
+
+    (1.0 + b1 * l2 / (l2 - c1) + b2 * l2 / (l2 - c2) + b3 * l2 / (l2 - c3)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_spectrum_is_gray() {
+        let matching = ColorMatching::new();
+
+        for color_space in [
+            ColorSpace::Srgb,
+            ColorSpace::Rec2020,
+            ColorSpace::Aces2065_1,
+        ] {
+            let rgb = matching
+                .to_rgb(
+                    &Spectrum::Sampled(vec![[300.0, 0.5], [900.0, 0.5]]),
+                    &color_space,
+                )
+                .unwrap();
+
+            assert!(
+                Vec3::from(rgb).abs_diff_eq(Vec3::splat(0.5), 1e-4),
+                "{rgb:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn named_spectra() {
+        let matching = ColorMatching::new();
+
+        let [r, g, b] = matching
+            .to_rgb(&Spectrum::Named("glass-BK7".to_string()), &ColorSpace::Srgb)
+            .unwrap();
+        // BK7 is slightly more refractive for short wavelengths.
+        assert!((1.51..1.53).contains(&g));
+        assert!(r < g && g < b);
+
+        let rgb = matching
+            .to_rgb(
+                &Spectrum::Named("stdillum-D65".to_string()),
+                &ColorSpace::Srgb,
+            )
+            .unwrap();
+        assert!(Vec3::from(rgb).abs_diff_eq(Vec3::ONE, 1e-3), "{rgb:?}");
+
+        // Low color temperature is reddish.
+        let [r, _, b] = matching
+            .to_rgb(&Spectrum::Blackbody(2000), &ColorSpace::Srgb)
+            .unwrap();
+        assert!(r > b);
+
+        assert_eq!(
+            matching.to_rgb(
+                &Spectrum::Named("metal-Au-eta".to_string()),
+                &ColorSpace::Srgb
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn rgb_between_color_spaces() {
+        let matching = ColorMatching::new();
+        let red = Spectrum::Rgb([1.0, 0.0, 0.0], None);
+
+        let [r, g, b] = matching.to_rgb(&red, &ColorSpace::Srgb).unwrap();
+        assert!(Vec3::new(r, g, b).abs_diff_eq(Vec3::X, 1e-4));
+
+        // sRGB red is inside the wider rec2020 gamut.
+        let [r, g, b] = matching.to_rgb(&red, &ColorSpace::Rec2020).unwrap();
+        assert!(r < 1.0 && g > 0.0 && b > 0.0);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     io,
     num::{ParseFloatError, ParseIntError},
+    path::PathBuf,
     str::ParseBoolError,
 };
 
@@ -35,6 +36,38 @@ pub enum Error {
     #[error("Unable to parse bool")]
     ParseBool(#[from] ParseBoolError),
 
+    /// Numeric token is malformed.
+    #[error("Invalid number `{token}` at line {line}, column {column}")]
+    InvalidNumber {
+        token: String,
+        line: usize,
+        column: usize,
+    },
+
+    /// Array parameter has more values than allowed by the load options.
+    #[error("Array of {declared} values exceeds the limit of {limit}")]
+    ArrayTooLarge { declared: usize, limit: usize },
+
+    /// Textures reference each other in a cycle.
+    #[error("Texture dependency cycle involving texture {0}")]
+    TextureCycle(usize),
+
+    /// Light parameters that can't be used together were specified.
+    #[error("Conflicting light parameters")]
+    ConflictingLightParameters,
+
+    /// Portal corners of an infinite light don't form a planar quadrilateral.
+    #[error("Portal points are not coplanar")]
+    InvalidPortal,
+
+    /// Photometric data of a goniometric light is malformed or in an unsupported format.
+    #[error("Invalid IES data: {0}")]
+    InvalidIes(String),
+
+    /// Mesh data of a PLY file is malformed or in an unsupported format.
+    #[error("Invalid PLY data: {0}")]
+    InvalidPly(String),
+
     /// Unable to cast from slice to array.
     #[error("Unexpected number of arguments in array")]
     ParseSlice,
@@ -66,12 +99,19 @@ pub enum Error {
     #[error("Duplicated WorldBegin statement")]
     WorldAlreadyStarted,
 
+    /// Directive that is only allowed inside the world block appeared before `WorldBegin`.
+    #[error("{0} is not allowed before WorldBegin")]
+    ElementBeforeWorldBegin(&'static str),
+
     #[error("Element is not allowed")]
     ElementNotAllowed,
 
     #[error("Too many AttributeEnd")]
     TooManyEndAttributes,
 
+    #[error("Too many TransformEnd")]
+    TooManyEndTransforms,
+
     #[error("Attempt to restore CoordSysTransform matrix with invalid name")]
     InvalidMatrixName,
 
@@ -84,6 +124,22 @@ pub enum Error {
     #[error("Unknown material type: {0}")]
     InvalidMaterialType(String),
 
+    /// `NamedMaterial` references a material that wasn't declared before.
+    #[error("Undefined material: {0}")]
+    UndefinedMaterial(String),
+
+    /// `Attribute` directive with a target other than shape, light, material, medium or texture.
+    #[error("Unknown attribute target: {0}")]
+    UnknownAttributeTarget(String),
+
+    /// Texture parameter references a texture that wasn't declared before.
+    #[error("Undefined texture: {0}")]
+    UndefinedTexture(String),
+
+    /// Material parameter has a value the material can't be built with.
+    #[error("Invalid material parameter: {0}")]
+    InvalidMaterialParameter(String),
+
     #[error("Unexpted token received")]
     UnexpectedToken,
 
@@ -95,4 +151,32 @@ pub enum Error {
 
     #[error("Not found")]
     NotFound,
+
+    /// Relative include in a scene loaded without a working directory.
+    #[error("Relative include without a working directory: {0}")]
+    RelativeInclude(PathBuf),
+
+    /// Film sensor is not one of the built-in sensors.
+    #[error("Unknown sensor: {0}")]
+    UnknownSensor(String),
+
+    /// Color space is not one of the built-in color spaces.
+    #[error("Unknown color space: {0}")]
+    UnknownColorSpace(String),
+
+    /// Directive has a parameter that isn't recognized, see `LoadOptions::strict_parameters`.
+    #[error("Unknown parameter `{key}` in {directive}")]
+    UnknownParameter { directive: String, key: String },
+
+    /// Parameter value is outside of the allowed range.
+    #[error("Parameter value is out of range: {0}")]
+    ValueOutOfRange(String),
+
+    /// Per-vertex attribute doesn't match the number of vertices.
+    #[error("Vertex attribute count doesn't match the number of positions")]
+    AttributeCountMismatch,
+
+    /// Loading was cancelled, see `Scene::load_cancellable`.
+    #[error("Loading was cancelled")]
+    Cancelled,
 }
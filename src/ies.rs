@@ -0,0 +1,141 @@
+//! IES (IESNA LM-63) photometric data.
+
+use std::str::SplitWhitespace;
+
+use crate::{Error, Result};
+
+/// Luminous intensity sampled over a grid of angles, as used by goniometric lights.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GonioDistribution {
+    /// Vertical (polar) angles in degrees, measured from the nadir.
+    pub vertical_angles: Vec<f32>,
+    /// Horizontal (azimuthal) angles in degrees.
+    pub horizontal_angles: Vec<f32>,
+    /// Intensity in candela with the file's multiplier applied.
+    ///
+    /// Stored row by row, one row of `vertical_angles.len()` values per horizontal angle.
+    pub candela: Vec<f32>,
+}
+
+impl GonioDistribution {
+    /// Parse the contents of an IES file.
+    ///
+    /// Only the photometric grid is read. Tilt data embedded with `TILT=INCLUDE` is skipped,
+    /// files that reference an external tilt file are accepted as well.
+    pub fn from_ies(data: &str) -> Result<GonioDistribution> {
+        // Keywords precede the `TILT=` line, numeric data follows it.
+        let mut lines = data.lines();
+        let tilt = loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| Error::InvalidIes("missing TILT line".to_string()))?;
+
+            if let Some(tilt) = line.trim().strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+
+        let rest = lines.collect::<Vec<_>>().join("\n");
+        let mut values = rest.split_whitespace();
+
+        if tilt == "INCLUDE" {
+            // Lamp to luminaire geometry, followed by pairs of angles and multipliers.
+            next_value(&mut values)?;
+            let count = next_value(&mut values)? as usize;
+            for _ in 0..count * 2 {
+                next_value(&mut values)?;
+            }
+        }
+
+        let _lamp_count = next_value(&mut values)?;
+        let _lumens_per_lamp = next_value(&mut values)?;
+        let multiplier = next_value(&mut values)?;
+        let vertical_count = next_value(&mut values)? as usize;
+        let horizontal_count = next_value(&mut values)? as usize;
+
+        // Photometric type, units, luminous opening dimensions,
+        // ballast factor, future use and input watts.
+        for _ in 0..8 {
+            next_value(&mut values)?;
+        }
+
+        let mut read = |count: usize| {
+            (0..count)
+                .map(|_| next_value(&mut values))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        let vertical_angles = read(vertical_count)?;
+        let horizontal_angles = read(horizontal_count)?;
+        let candela = read(vertical_count * horizontal_count)?
+            .into_iter()
+            .map(|value| value * multiplier)
+            .collect();
+
+        Ok(GonioDistribution {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+        })
+    }
+
+    /// Returns the intensity at the given angle indices.
+    pub fn candela(&self, horizontal: usize, vertical: usize) -> Option<f32> {
+        if vertical >= self.vertical_angles.len() {
+            return None;
+        }
+
+        self.candela
+            .get(horizontal * self.vertical_angles.len() + vertical)
+            .copied()
+    }
+}
+
+/// Read the next number of the photometric data.
+fn next_value(values: &mut SplitWhitespace) -> Result<f32> {
+    let value = values
+        .next()
+        .ok_or_else(|| Error::InvalidIes("unexpected end of data".to_string()))?;
+
+    Ok(value.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tilt_include() -> Result<()> {
+        let data = "IESNA91
+TILT=INCLUDE
+1
+3
+0 90 180
+1 0.9 0.8
+1 -1 2 2 1 1 2 0 0 0
+1 1 50
+0 90
+0
+100 50";
+
+        let distribution = GonioDistribution::from_ies(data)?;
+
+        assert_eq!(distribution.vertical_angles, vec![0.0, 90.0]);
+        assert_eq!(distribution.horizontal_angles, vec![0.0]);
+        assert_eq!(distribution.candela, vec![200.0, 100.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_truncated() {
+        assert!(matches!(
+            GonioDistribution::from_ies("IESNA:LM-63-2002\n[TEST] x\n"),
+            Err(Error::InvalidIes(_))
+        ));
+        assert!(matches!(
+            GonioDistribution::from_ies("TILT=NONE\n1 -1 1 3 1"),
+            Err(Error::InvalidIes(_))
+        ));
+    }
+}
@@ -1,15 +1,26 @@
 //! PBRT v4 file format parser and loader.
 
+mod cache;
+mod color;
 mod error;
+mod ies;
 pub mod param;
 mod parser;
+mod ply;
 mod scene;
+mod source;
+mod tessellate;
 mod token;
 mod tokenizer;
 pub mod types;
+mod writer;
 
+pub use cache::*;
 pub use error::Error;
+pub use ies::*;
 pub use parser::*;
+pub use ply::*;
 pub use scene::*;
+pub use source::*;
 
 pub type Result<T> = std::result::Result<T, Error>;
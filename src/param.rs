@@ -1,13 +1,17 @@
 //! Parameter management.
 
 use std::{
-    collections::HashMap,
+    any::Any,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt,
     num::{ParseFloatError, ParseIntError},
     result,
     str::{FromStr, ParseBoolError},
+    sync::{Arc, Mutex, PoisonError},
 };
 
-use crate::{Error, Result};
+use crate::{types::ColorSpace, Error, Result};
 
 /// Parameter type.
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -58,29 +62,97 @@ impl FromStr for ParamType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Spectrum {
     //  "rgb L" [ r g b ]
-    Rgb([f32; 3]),
+    //
+    // Annotated with the color space set by the `ColorSpace` directive when the parameter
+    // was parsed, `None` stands for the scene's default color space (sRGB).
+    Rgb([f32; 3], Option<ColorSpace>),
     // "blackbody L" 3000
     Blackbody(i32),
+    // "spectrum eta" [ 300 1.5 800 1.4 ]
+    Sampled(Vec<[f32; 2]>),
+    // "spectrum eta" "glass-BK7"
+    Named(String),
     // "texture reflectance" [ "Texture01" ]
     Texture(String),
 }
 
+impl Spectrum {
+    /// Reduce the spectrum to a single scalar value.
+    ///
+    /// RGB values are averaged, blackbody emitters are normalized by pbrt so that
+    /// their maximum value is 1, sampled spectra average their values.
+    /// Textures and named spectra can't be evaluated and return `None`.
+    pub fn average(&self) -> Option<f32> {
+        match self {
+            Spectrum::Rgb([r, g, b], _) => Some((r + g + b) / 3.0),
+            Spectrum::Blackbody(_) => Some(1.0),
+            Spectrum::Sampled(samples) if !samples.is_empty() => {
+                Some(samples.iter().map(|[_, value]| value).sum::<f32>() / samples.len() as f32)
+            }
+            Spectrum::Sampled(_) | Spectrum::Named(_) | Spectrum::Texture(_) => None,
+        }
+    }
+
+    /// Approximate luminance (CIE Y) of the spectrum.
+    ///
+    /// RGB values are weighted with the sRGB luminance coefficients,
+    /// other spectra fall back to [Spectrum::average].
+    pub fn luminance(&self) -> Option<f32> {
+        match self {
+            Spectrum::Rgb([r, g, b], _) => Some(0.2126 * r + 0.7152 * g + 0.0722 * b),
+            _ => self.average(),
+        }
+    }
+}
+
+/// Values of a numeric parameter, parsed once by the [crate::Parser].
+#[derive(Clone)]
+enum Numbers {
+    Float(Arc<[f32]>),
+    Integer(Arc<[i32]>),
+}
+
 /// Represents a single parsed parameter.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Clone)]
 pub struct Param<'a> {
     /// Parameter name.
     pub name: &'a str,
     /// Parameter type.
     pub ty: ParamType,
-    /// One or more values.
-    value: &'a str,
+    /// One or more values, strings are unquoted and unescaped.
+    value: Cow<'a, str>,
+    /// Color space of `rgb` values, see [ParamList::set_color_space].
+    color_space: Option<ColorSpace>,
+    /// `value` parsed by [Param::parse_numbers], reused instead of parsing it again.
+    numbers: Option<Numbers>,
+}
+
+// `numbers` is derived from `value`, leave it out.
+impl PartialEq for Param<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.ty == other.ty
+            && self.value == other.value
+            && self.color_space == other.color_space
+    }
+}
+
+impl fmt::Debug for Param<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Param")
+            .field("name", &self.name)
+            .field("ty", &self.ty)
+            .field("value", &self.value)
+            .field("color_space", &self.color_space)
+            .finish()
+    }
 }
 
 impl<'a> Param<'a> {
-    pub fn new(type_and_name: &'a str, value: &'a str) -> Result<Self> {
+    pub fn new(type_and_name: &'a str, value: impl Into<Cow<'a, str>>) -> Result<Self> {
         // Param name is "type name"
         let mut split = type_and_name.split_whitespace();
 
@@ -89,12 +161,58 @@ impl<'a> Param<'a> {
 
         let name = split.next().ok_or(Error::InvalidParamName)?;
 
-        Ok(Self { name, ty, value })
+        Ok(Self {
+            name,
+            ty,
+            value: value.into(),
+            color_space: None,
+            numbers: None,
+        })
+    }
+
+    /// Parse the values of integer and float parameters, keeping them for [Param::read_into].
+    ///
+    /// Returns the first value that isn't a valid number of the parameter's type.
+    pub(crate) fn parse_numbers(&mut self) -> result::Result<(), &str> {
+        fn parse<T: FromStr>(value: &str) -> result::Result<Arc<[T]>, &str> {
+            let mut values = Vec::with_capacity(value.split_whitespace().count());
+            for str in value.split_whitespace() {
+                values.push(str.parse().map_err(|_| str)?);
+            }
+
+            Ok(values.into())
+        }
+
+        self.numbers = match self.ty {
+            ParamType::Integer => Some(Numbers::Integer(parse(&self.value)?)),
+            ParamType::Float
+            | ParamType::Point2
+            | ParamType::Point3
+            | ParamType::Vector2
+            | ParamType::Vector3
+            | ParamType::Normal3
+            | ParamType::Normal
+            | ParamType::Rgb => Some(Numbers::Float(parse(&self.value)?)),
+            _ => None,
+        };
+
+        Ok(())
+    }
+
+    /// Returns `true` if `str` looks like a parameter declaration (`"type name"`).
+    pub fn is_declaration(str: &str) -> bool {
+        let mut split = str.split_whitespace();
+
+        let is_type = split
+            .next()
+            .map_or(false, |ty| ParamType::from_str(ty).is_ok());
+
+        is_type && split.next().is_some() && split.next().is_none()
     }
 
     pub fn items<T: FromStr>(
         &self,
-    ) -> impl Iterator<Item = result::Result<T, <T as FromStr>::Err>> + 'a {
+    ) -> impl Iterator<Item = result::Result<T, <T as FromStr>::Err>> + '_ {
         self.value.split_whitespace().map(|str| T::from_str(str))
     }
 
@@ -108,57 +226,233 @@ impl<'a> Param<'a> {
         Ok([r, g, b])
     }
 
+    /// Returns the value without quotes and with escape sequences decoded.
+    pub fn string(&self) -> &str {
+        &self.value
+    }
+
     pub fn single<T: FromStr>(&self) -> result::Result<T, <T as FromStr>::Err> {
-        T::from_str(self.value)
+        T::from_str(&self.value)
     }
 
-    pub fn vec<T: FromStr>(&self) -> result::Result<Vec<T>, <T as FromStr>::Err> {
-        self.items()
-            .collect::<result::Result<Vec<T>, <T as FromStr>::Err>>()
+    pub fn vec<T: FromStr + 'static>(&self) -> result::Result<Vec<T>, <T as FromStr>::Err> {
+        let mut values = Vec::new();
+        self.read_into(&mut values)?;
+        Ok(values)
+    }
+
+    /// Parse all values into `values`, replacing its contents.
+    ///
+    /// Allows reusing one scratch buffer when reading many large arrays.
+    /// Numbers already parsed by the parser are copied instead of being parsed again.
+    pub fn read_into<T: FromStr + 'static>(
+        &self,
+        values: &mut Vec<T>,
+    ) -> result::Result<(), <T as FromStr>::Err> {
+        values.clear();
+
+        match &self.numbers {
+            Some(Numbers::Float(numbers)) => {
+                if let Some(values) = (values as &mut dyn Any).downcast_mut::<Vec<f32>>() {
+                    values.extend_from_slice(numbers);
+                    return Ok(());
+                }
+            }
+            Some(Numbers::Integer(numbers)) => {
+                if let Some(values) = (values as &mut dyn Any).downcast_mut::<Vec<i32>>() {
+                    values.extend_from_slice(numbers);
+                    return Ok(());
+                }
+            }
+            None => {}
+        }
+
+        // Counting values is much cheaper than parsing them,
+        // so reserve upfront instead of growing the buffer while parsing.
+        values.reserve(self.value.split_whitespace().count());
+
+        for str in self.value.split_whitespace() {
+            values.push(T::from_str(str)?);
+        }
+
+        Ok(())
     }
 
     pub fn spectrum(&self) -> Result<Spectrum> {
         let res = match self.ty {
-            ParamType::Rgb => Spectrum::Rgb(self.rgb()?),
+            ParamType::Rgb => Spectrum::Rgb(self.rgb()?, self.color_space),
             ParamType::Blackbody => Spectrum::Blackbody(self.single()?),
             ParamType::Texture => Spectrum::Texture(self.value.to_string()),
-            _ => unreachable!(),
+            ParamType::Spectrum => {
+                // Either (wavelength, value) pairs or the name of a built-in spectrum or file.
+                match self.vec::<f32>() {
+                    Ok(values) if values.len() % 2 != 0 => return Err(Error::ParseSlice),
+                    Ok(values) if !values.is_empty() => Spectrum::Sampled(
+                        values
+                            .chunks_exact(2)
+                            .map(|pair| [pair[0], pair[1]])
+                            .collect(),
+                    ),
+                    _ => Spectrum::Named(self.value.to_string()),
+                }
+            }
+            _ => return Err(Error::InvalidParamType(format!("{:?}", self.ty))),
         };
 
         Ok(res)
     }
 }
 
+/// An owned parameter value, used to override parameters at load time.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParamValue {
+    /// Parameter type.
+    pub ty: ParamType,
+    /// One or more values, in the same format as in a pbrt file (without brackets).
+    pub value: String,
+}
+
+impl ParamValue {
+    pub fn new(ty: ParamType, value: impl Into<String>) -> Self {
+        Self {
+            ty,
+            value: value.into(),
+        }
+    }
+
+    /// Make a parameter with the given name out of this value.
+    pub fn to_param<'a>(&'a self, name: &'a str) -> Param<'a> {
+        Param {
+            name,
+            ty: self.ty,
+            value: Cow::Borrowed(&self.value),
+            color_space: None,
+            numbers: None,
+        }
+    }
+}
+
 /// Parameters collection.
-#[derive(Default, Debug, PartialEq, Clone)]
-pub struct ParamList<'a>(HashMap<&'a str, Param<'a>>);
+#[derive(Default, Debug, Clone)]
+pub struct ParamList<'a> {
+    params: HashMap<&'a str, Param<'a>>,
+    /// Names of the parameters read with [ParamList::get], if tracking is enabled.
+    ///
+    /// Shared between clones, so usage can be inspected after the list was moved
+    /// into a constructor.
+    used: Option<Arc<Mutex<HashSet<String>>>>,
+}
+
+impl PartialEq for ParamList<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params
+    }
+}
+
+/// Record of the parameters read from a [ParamList], see [ParamList::track_usage].
+pub(crate) struct ParamUsage {
+    names: Vec<String>,
+    used: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ParamUsage {
+    /// Returns the sorted names of parameters that were never read.
+    pub fn unused(&self) -> Vec<String> {
+        let used = self.used.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut unused = self
+            .names
+            .iter()
+            .filter(|name| !used.contains(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        unused.sort();
+
+        unused
+    }
+}
 
 impl<'a> ParamList<'a> {
     /// Add a new parameter to the list.
     pub fn add(&mut self, param: Param<'a>) -> Result<()> {
-        if self.0.insert(param.name, param).is_some() {
+        if self.params.insert(param.name, param).is_some() {
             return Err(Error::DuplicatedParamName);
         }
 
         Ok(())
     }
 
+    /// Insert a parameter, replacing the existing one with the same name.
+    pub fn set(&mut self, param: Param<'a>) {
+        self.params.insert(param.name, param);
+    }
+
+    /// Rename the parameter `from` to `to`, unless a parameter named `to` already exists.
+    pub(crate) fn rename(&mut self, from: &str, to: &'a str) {
+        if self.params.contains_key(to) {
+            return;
+        }
+
+        if let Some(mut param) = self.params.remove(from) {
+            param.name = to;
+            self.params.insert(to, param);
+        }
+    }
+
+    /// Annotate `rgb` values with the color space they are given in.
+    ///
+    /// Used for the color space that's active when a directive is parsed.
+    pub(crate) fn set_color_space(&mut self, color_space: ColorSpace) {
+        for param in self.params.values_mut() {
+            param.color_space = Some(color_space);
+        }
+    }
+
     /// Get parameter by name.
     pub fn get(&self, name: &str) -> Option<&Param<'a>> {
-        self.0.get(name)
+        let param = self.params.get(name);
+
+        if let (Some(used), Some(_)) = (&self.used, param) {
+            used.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(name.to_string());
+        }
+
+        param
+    }
+
+    /// Start recording which parameters are read.
+    ///
+    /// Only the parameters present at the time of the call are reported by [ParamUsage::unused].
+    pub(crate) fn track_usage(&mut self) -> ParamUsage {
+        let used = Arc::new(Mutex::new(HashSet::new()));
+        self.used = Some(Arc::clone(&used));
+
+        ParamUsage {
+            names: self.params.keys().map(|name| name.to_string()).collect(),
+            used,
+        }
+    }
+
+    /// Iterate over all parameters in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = &Param<'a>> {
+        self.params.values()
     }
 
     /// Return the number of parameters.
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.params.len()
     }
 
     /// Returns `true` when the list is empty.
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.params.is_empty()
     }
 
-    fn vec<T: FromStr>(&self, name: &str) -> result::Result<Option<Vec<T>>, <T as FromStr>::Err> {
+    fn vec<T: FromStr + 'static>(
+        &self,
+        name: &str,
+    ) -> result::Result<Option<Vec<T>>, <T as FromStr>::Err> {
         let res = match self.get(name).map(|param| param.vec()) {
             Some(v) => Some(v?),
             None => None,
@@ -201,7 +495,7 @@ impl<'a> ParamList<'a> {
     }
 
     pub fn string(&self, name: &str) -> Option<&str> {
-        self.get(name).map(|v| v.value)
+        self.get(name).map(Param::string)
     }
 
     pub fn spectrum(&self, name: &str, default: Spectrum) -> result::Result<Spectrum, Error> {
@@ -212,8 +506,8 @@ impl<'a> ParamList<'a> {
     }
 
     pub fn rgb(&self, name: &str, default: [f32; 3]) -> result::Result<[f32; 3], Error> {
-        match self.spectrum(name, Spectrum::Rgb(default))? {
-            Spectrum::Rgb(rgb) => Ok(rgb),
+        match self.spectrum(name, Spectrum::Rgb(default, None))? {
+            Spectrum::Rgb(rgb, _) => Ok(rgb),
             _ => unreachable!(),
         }
     }
@@ -225,9 +519,36 @@ impl<'a> ParamList<'a> {
         }
     }
 
+    /// Get an array of 2D points, for example `"point2 uv" [ 0 0 1 0 1 1 ]`.
+    pub fn get_point2_array(&self, name: &str) -> Result<Option<Vec<[f32; 2]>>> {
+        self.pairs(name)
+    }
+
+    /// Get an array of 2D vectors.
+    pub fn get_vector2_array(&self, name: &str) -> Result<Option<Vec<[f32; 2]>>> {
+        self.pairs(name)
+    }
+
+    fn pairs(&self, name: &str) -> Result<Option<Vec<[f32; 2]>>> {
+        let Some(values) = self.floats(name)? else {
+            return Ok(None);
+        };
+
+        if values.len() % 2 != 0 {
+            return Err(Error::ParseSlice);
+        }
+
+        let pairs = values
+            .chunks_exact(2)
+            .map(|pair| [pair[0], pair[1]])
+            .collect();
+
+        Ok(Some(pairs))
+    }
+
     pub fn extend(&mut self, other: &ParamList<'a>) {
-        for (k, v) in &other.0 {
-            self.0.insert(k, v.clone());
+        for (k, v) in &other.params {
+            self.params.insert(k, v.clone());
         }
     }
 }
@@ -262,6 +583,25 @@ mod tests {
         assert!(matches!(list.add(param), Err(Error::DuplicatedParamName)));
     }
 
+    #[test]
+    fn sampled_spectrum() {
+        let param = Param::new("spectrum eta", "400 1.5 800 1.4").unwrap();
+        assert_eq!(
+            param.spectrum().unwrap(),
+            Spectrum::Sampled(vec![[400.0, 1.5], [800.0, 1.4]])
+        );
+
+        // Sampled spectra need (wavelength, value) pairs.
+        let param = Param::new("spectrum eta", "400 1.5 800").unwrap();
+        assert!(matches!(param.spectrum(), Err(Error::ParseSlice)));
+
+        let param = Param::new("spectrum eta", "glass-BK7").unwrap();
+        assert_eq!(
+            param.spectrum().unwrap(),
+            Spectrum::Named("glass-BK7".to_string())
+        );
+    }
+
     #[test]
     fn as_ints() {
         let param = Param::new("integer test", "-1 0 1").unwrap();
@@ -269,6 +609,20 @@ mod tests {
         assert_eq!(param.vec::<i32>().unwrap(), vec![-1, 0, 1]);
     }
 
+    #[test]
+    fn parsed_numbers() {
+        let mut param = Param::new("float test", "0.5 -2 1e3").unwrap();
+        param.parse_numbers().unwrap();
+
+        assert_eq!(param.vec::<f32>().unwrap(), vec![0.5, -2.0, 1e3]);
+        // Other types are still parsed from the text.
+        assert_eq!(param.vec::<f64>().unwrap(), vec![0.5, -2.0, 1e3]);
+        assert_eq!(param, Param::new("float test", "0.5 -2 1e3").unwrap());
+
+        let mut param = Param::new("integer test", "1 2.5").unwrap();
+        assert_eq!(param.parse_numbers(), Err("2.5"));
+    }
+
     #[test]
     fn parse_blackbody() -> Result<()> {
         let param = Param::new("blackbody I", "5500")?;
@@ -284,7 +638,26 @@ mod tests {
         let param = Param::new("rgb L", "7 0 7")?;
         let i = param.spectrum().unwrap();
 
-        assert!(matches!(i, Spectrum::Rgb(_)));
+        assert!(matches!(i, Spectrum::Rgb(_, _)));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_point2_array() -> Result<()> {
+        let mut list = ParamList::default();
+        list.add(Param::new("point2 uv", "0 0 1 0 1 1 0 1")?)?;
+        list.add(Param::new("vector2 odd", "0 1 2")?)?;
+
+        let uv = list.get_point2_array("uv")?.unwrap();
+        assert_eq!(uv.len(), 4);
+        assert_eq!(uv[2], [1.0, 1.0]);
+
+        assert!(list.get_vector2_array("missing")?.is_none());
+        assert!(matches!(
+            list.get_vector2_array("odd"),
+            Err(Error::ParseSlice)
+        ));
+
         Ok(())
     }
 }
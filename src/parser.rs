@@ -1,5 +1,7 @@
 //! Directives parser.
 
+use std::{borrow::Cow, ops::Range};
+
 use crate::{
     param::{Param, ParamList},
     token::{Directive, Token},
@@ -10,8 +12,8 @@ use crate::{
 /// Parsed directive.
 #[derive(Debug, PartialEq)]
 pub enum Element<'a> {
-    Include(&'a str),
-    Import(&'a str),
+    Include(Cow<'a, str>),
+    Import(Cow<'a, str>),
     Option(Param<'a>),
     Film {
         ty: &'a str,
@@ -37,10 +39,10 @@ pub enum Element<'a> {
         params: ParamList<'a>,
     },
     CoordinateSystem {
-        name: &'a str,
+        name: Cow<'a, str>,
     },
     CoordSysTransform {
-        name: &'a str,
+        name: Cow<'a, str>,
     },
     PixelFilter {
         ty: &'a str,
@@ -57,6 +59,7 @@ pub enum Element<'a> {
     },
     /// `Rotate angle x y z`
     Rotate {
+        /// Rotation angle in degrees, as written in the scene file.
         angle: f32,
         v: [f32; 3],
     },
@@ -67,10 +70,17 @@ pub enum Element<'a> {
         up: [f32; 3],
     },
     /// `Transform m00 ... m33`
+    ///
+    /// The 16 values are stored as written in the file, which is column-major order:
+    /// each group of four values is one column of the matrix and the translation is in
+    /// `m[12..15]`. pbrt reads them row by row and transposes the result, so this is
+    /// the same matrix as [glam::Mat4::from_cols_array].
     Transform {
         m: [f32; 16],
     },
     /// `ConcatTransform m00 .. m33`
+    ///
+    /// Column-major like [Element::Transform].
     ConcatTransform {
         m: [f32; 16],
     },
@@ -90,6 +100,10 @@ pub enum Element<'a> {
     AttributeBegin,
     /// `AttributeEnd`
     AttributeEnd,
+    /// `TransformBegin`, only in [PbrtVersion::V3Compat] mode.
+    TransformBegin,
+    /// `TransformEnd`, only in [PbrtVersion::V3Compat] mode.
+    TransformEnd,
     /// `Attribute "target" parameter-list`
     Attribute {
         target: &'a str,
@@ -108,15 +122,15 @@ pub enum Element<'a> {
         params: ParamList<'a>,
     },
     MakeNamedMaterial {
-        name: &'a str,
+        name: Cow<'a, str>,
         params: ParamList<'a>,
     },
     NamedMaterial {
-        name: &'a str,
+        name: Cow<'a, str>,
     },
     /// `Texture "name" "type" "class" [ parameter-list ]`
     Texture {
-        name: &'a str,
+        name: Cow<'a, str>,
         ty: &'a str,
         class: &'a str,
         params: ParamList<'a>,
@@ -127,30 +141,109 @@ pub enum Element<'a> {
         params: ParamList<'a>,
     },
     ObjectBegin {
-        name: &'a str,
+        name: Cow<'a, str>,
     },
     ObjectEnd,
     ObjectInstance {
-        name: &'a str,
+        name: Cow<'a, str>,
     },
     MakeNamedMedium {
-        name: &'a str,
+        name: Cow<'a, str>,
         params: ParamList<'a>,
     },
     MediumInterface {
-        interior: &'a str,
-        exterior: &'a str,
+        interior: Cow<'a, str>,
+        exterior: Cow<'a, str>,
+    },
+    /// Unrecognized directive, only emitted in lenient mode.
+    Unknown {
+        keyword: &'a str,
+        params: ParamList<'a>,
     },
 }
 
+/// Scene file format version accepted by the [Parser].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PbrtVersion {
+    /// Strict pbrt-v4 syntax.
+    #[default]
+    V4,
+    /// Also accept pbrt-v3 constructs: `TransformBegin`/`TransformEnd` blocks
+    /// and v3 material names and parameters, which are translated to their v4 equivalents.
+    /// Material parameters without an equivalent are reported in `Scene::diagnostics`.
+    V3Compat,
+}
+
+/// Parsed directive with its location in the source, see [Parser::parse_next_spanned].
+#[derive(Debug, PartialEq)]
+pub struct SpannedElement<'a> {
+    pub element: Element<'a>,
+    /// Byte range of the directive and its arguments in the parsed string.
+    pub span: Range<usize>,
+    /// Identifier of the parsed file, see [Parser::set_file_id].
+    pub file_id: usize,
+}
+
 pub struct Parser<'a> {
     tokenizer: Tokenizer<'a>,
+    lenient: bool,
+    max_array_length: usize,
+    version: PbrtVersion,
+    file_id: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(str: &'a str) -> Self {
         let tokenizer = Tokenizer::new(str);
-        Self { tokenizer }
+        Self {
+            tokenizer,
+            lenient: false,
+            max_array_length: usize::MAX,
+            version: PbrtVersion::V4,
+            file_id: 0,
+        }
+    }
+
+    /// In lenient mode, unrecognized directives are returned as [Element::Unknown]
+    /// instead of failing with [Error::UnknownDirective].
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Limit the number of values in array parameters,
+    /// longer arrays fail with [Error::ArrayTooLarge].
+    pub fn set_max_array_length(&mut self, limit: usize) {
+        self.max_array_length = limit;
+    }
+
+    /// Select the accepted scene file format, see [PbrtVersion].
+    pub fn set_version(&mut self, version: PbrtVersion) {
+        self.version = version;
+    }
+
+    /// Set the identifier reported in [SpannedElement::file_id], 0 by default.
+    ///
+    /// Allows callers that follow `Include` directives with a parser per file
+    /// to tell which file an element came from. Scene loading gives the top-level
+    /// file id 0 and numbers included files from 1 in the order they're reached.
+    pub fn set_file_id(&mut self, file_id: usize) {
+        self.file_id = file_id;
+    }
+
+    /// Parse next element along with the byte range it occupies in the source.
+    pub fn parse_next_spanned(&mut self) -> Result<SpannedElement<'a>> {
+        let start = match self.tokenizer.peek_token() {
+            Some(token) => self.tokenizer.offset_of(token.value()),
+            None => return Err(Error::EndOfFile),
+        };
+
+        let element = self.parse_next()?;
+
+        Ok(SpannedElement {
+            element,
+            span: start..self.tokenizer.offset(),
+            file_id: self.file_id,
+        })
     }
 
     /// Parse next element.
@@ -160,11 +253,26 @@ impl<'a> Parser<'a> {
         };
 
         // Check if token is directive
-        let directive = next_token.directive().ok_or(Error::UnknownDirective)?;
+        let Some(directive) = next_token.directive() else {
+            if self.lenient {
+                return self.read_unknown(next_token.value());
+            }
+
+            return Err(Error::UnknownDirective);
+        };
+
+        let is_v3 = self.version == PbrtVersion::V3Compat;
 
         let element = match directive {
-            Directive::Include => Element::Include(self.read_str()?),
-            Directive::Import => Element::Import(self.read_str()?),
+            Directive::TransformBegin | Directive::TransformEnd if !is_v3 => {
+                if self.lenient {
+                    return self.read_unknown(next_token.value());
+                }
+
+                return Err(Error::UnknownDirective);
+            }
+            Directive::Include => Element::Include(self.read_string()?),
+            Directive::Import => Element::Import(self.read_string()?),
             Directive::Option => Element::Option(self.read_param()?),
             Directive::Film => Element::Film {
                 ty: self.read_str()?,
@@ -190,10 +298,10 @@ impl<'a> Parser<'a> {
                 params: self.read_param_list()?,
             },
             Directive::CoordinateSystem => Element::CoordinateSystem {
-                name: self.read_str()?,
+                name: self.read_string()?,
             },
             Directive::CoordSysTransform => Element::CoordSysTransform {
-                name: self.read_str()?,
+                name: self.read_string()?,
             },
             Directive::PixelFilter => Element::PixelFilter {
                 ty: self.read_str()?,
@@ -252,6 +360,8 @@ impl<'a> Parser<'a> {
             Directive::WorldBegin => Element::WorldBegin,
             Directive::AttributeBegin => Element::AttributeBegin,
             Directive::AttributeEnd => Element::AttributeEnd,
+            Directive::TransformBegin => Element::TransformBegin,
+            Directive::TransformEnd => Element::TransformEnd,
             Directive::Attribute => Element::Attribute {
                 target: self.read_str()?,
                 params: self.read_param_list()?,
@@ -264,19 +374,40 @@ impl<'a> Parser<'a> {
                 ty: self.read_str()?,
                 params: self.read_param_list()?,
             },
-            Directive::Material => Element::Material {
-                ty: self.read_str()?,
-                params: self.read_param_list()?,
-            },
-            Directive::MakeNamedMaterial => Element::MakeNamedMaterial {
-                name: self.read_str()?,
-                params: self.read_param_list()?,
-            },
+            Directive::Material => {
+                let ty = self.read_str()?;
+                let mut params = self.read_param_list()?;
+
+                if is_v3 {
+                    upgrade_v3_material_params(ty, &mut params);
+                }
+
+                Element::Material {
+                    ty: if is_v3 { v4_material_type(ty) } else { ty },
+                    params,
+                }
+            }
+            Directive::MakeNamedMaterial => {
+                let name = self.read_string()?;
+                let mut params = self.read_param_list()?;
+
+                if is_v3 {
+                    if let Some(ty) = params.get("type").map(|ty| ty.string().to_string()) {
+                        upgrade_v3_material_params(&ty, &mut params);
+                        params.set(Param::new(
+                            "string type",
+                            v4_material_type(&ty).to_string(),
+                        )?);
+                    }
+                }
+
+                Element::MakeNamedMaterial { name, params }
+            }
             Directive::NamedMaterial => Element::NamedMaterial {
-                name: self.read_str()?,
+                name: self.read_string()?,
             },
             Directive::Texture => Element::Texture {
-                name: self.read_str()?,
+                name: self.read_string()?,
                 ty: self.read_str()?,
                 class: self.read_str()?,
                 params: self.read_param_list()?,
@@ -286,19 +417,19 @@ impl<'a> Parser<'a> {
                 params: self.read_param_list()?,
             },
             Directive::ObjectBegin => Element::ObjectBegin {
-                name: self.read_str()?,
+                name: self.read_string()?,
             },
             Directive::ObjectEnd => Element::ObjectEnd,
             Directive::ObjectInstance => Element::ObjectInstance {
-                name: self.read_str()?,
+                name: self.read_string()?,
             },
             Directive::MakeNamedMedium => Element::MakeNamedMedium {
-                name: self.read_str()?,
+                name: self.read_string()?,
                 params: self.read_param_list()?,
             },
             Directive::MediumInterface => Element::MediumInterface {
-                interior: self.read_str()?,
-                exterior: self.read_str()?,
+                interior: self.read_string()?,
+                exterior: self.read_string()?,
             },
         };
 
@@ -338,8 +469,20 @@ impl<'a> Parser<'a> {
     /// Read token as `f32`.
     fn read_float(&mut self) -> Result<f32> {
         let token = self.read_token()?;
-        let parsed = token.parse::<f32>()?;
-        Ok(parsed)
+        token
+            .parse::<f32>()
+            .map_err(|_| self.invalid_number(token.value()))
+    }
+
+    /// Make [Error::InvalidNumber] pointing at `token`.
+    fn invalid_number(&self, token: &str) -> Error {
+        let (line, column) = self.tokenizer.location(token);
+
+        Error::InvalidNumber {
+            token: token.to_string(),
+            line,
+            column,
+        }
     }
 
     /// Read 3 floats.
@@ -360,8 +503,18 @@ impl<'a> Parser<'a> {
         Ok(m)
     }
 
-    /// Read a quoted string.
+    /// Read a quoted keyword, like a type name.
+    ///
+    /// Keywords can't contain escape sequences, so they are borrowed from the source.
     fn read_str(&mut self) -> Result<&'a str> {
+        match self.read_string()? {
+            Cow::Borrowed(str) => Ok(str),
+            Cow::Owned(_) => Err(Error::InvalidString),
+        }
+    }
+
+    /// Read a quoted string, decoding escape sequences.
+    fn read_string(&mut self) -> Result<Cow<'a, str>> {
         let token = self.read_token()?;
         token.unquote().ok_or(Error::InvalidString)
     }
@@ -386,19 +539,37 @@ impl<'a> Parser<'a> {
             // Skip brace offset
             start = self.tokenizer.offset();
 
-            // Read array of values
-            loop {
-                let value = self.read_token()?;
-
-                if value.is_close_brace() {
-                    end = self.tokenizer.offset() - 1;
-                    break;
+            let mut count = 0;
+
+            // Large numeric arrays (vertex positions, indices, etc.) are scanned in bulk,
+            // anything else is read token by token.
+            if let Some(len) = self.tokenizer.skip_number_array() {
+                count = len;
+                end = self.tokenizer.offset() - 1;
+            } else {
+                // Read array of values
+                loop {
+                    let value = self.read_token()?;
+
+                    if value.is_close_brace() {
+                        end = self.tokenizer.offset() - 1;
+                        break;
+                    }
+
+                    count += 1;
+
+                    // Got directive without closing bracket token.
+                    if value.is_directive() {
+                        return Err(Error::UnexpectedToken);
+                    }
                 }
+            }
 
-                // Got directive without closing bracket token.
-                if value.is_directive() {
-                    return Err(Error::UnexpectedToken);
-                }
+            if count > self.max_array_length {
+                return Err(Error::ArrayTooLarge {
+                    declared: count,
+                    limit: self.max_array_length,
+                });
             }
         } else {
             // Single value
@@ -408,12 +579,46 @@ impl<'a> Parser<'a> {
         let token = self.tokenizer.token(start, end);
         let param = match token.unquote() {
             Some(value) => Param::new(type_and_name, value)?,
-            None => Param::new(type_and_name, token.value())?,
+            None => {
+                let mut param = Param::new(type_and_name, token.value())?;
+                param
+                    .parse_numbers()
+                    .map_err(|str| self.invalid_number(str))?;
+                param
+            }
         };
 
         Ok(param)
     }
 
+    /// Read arguments of an unknown directive.
+    ///
+    /// Parameters are collected into a list, all other arguments are skipped
+    /// until the next known directive.
+    fn read_unknown(&mut self, keyword: &'a str) -> Result<Element<'a>> {
+        let mut params = ParamList::default();
+
+        loop {
+            match self.tokenizer.peek_token() {
+                Some(token) if token.is_directive() => break,
+                Some(token)
+                    if token
+                        .unquote()
+                        .map_or(false, |str| Param::is_declaration(&str)) =>
+                {
+                    let param = self.read_param()?;
+                    params.add(param)?;
+                }
+                Some(_) => {
+                    self.tokenizer.next();
+                }
+                None => break,
+            }
+        }
+
+        Ok(Element::Unknown { keyword, params })
+    }
+
     #[inline]
     fn read_param_list(&mut self) -> Result<ParamList<'a>> {
         let mut list = ParamList::default();
@@ -436,6 +641,40 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Translate a pbrt-v3 material name to the closest pbrt-v4 material.
+fn v4_material_type(ty: &str) -> &str {
+    match ty {
+        "matte" => "diffuse",
+        "glass" => "dielectric",
+        "metal" | "mirror" => "conductor",
+        "plastic" | "substrate" | "uber" => "coateddiffuse",
+        "translucent" => "diffusetransmission",
+        _ => ty,
+    }
+}
+
+/// Parameters of pbrt-v3 materials that don't exist in pbrt-v4.
+///
+/// They are left in place when [upgrade_v3_material_params] can't translate them.
+pub(crate) const V3_MATERIAL_PARAMS: &[&str] = &[
+    "Kd", "Kr", "Ks", "Kt", "bumpmap", "index", "opacity", "reflect", "sigma", "transmit",
+];
+
+/// Rename parameters of a pbrt-v3 material of type `ty` to their pbrt-v4 names.
+fn upgrade_v3_material_params(ty: &str, params: &mut ParamList) {
+    let renames: &[(&str, &str)] = match ty {
+        "matte" | "plastic" | "substrate" | "uber" => &[("Kd", "reflectance")],
+        "glass" => &[("index", "eta")],
+        "mirror" => &[("Kr", "reflectance")],
+        "translucent" => &[("reflect", "reflectance"), ("transmit", "transmittance")],
+        _ => &[],
+    };
+
+    for (from, to) in renames.iter().chain(&[("bumpmap", "displacement")]) {
+        params.rename(from, to);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::param::ParamType;
@@ -452,12 +691,12 @@ Import \"geometry/bigcar.pbrt.gz\"
         );
 
         let element = parser.parse_next().unwrap();
-        assert!(matches!(element, Element::Include("geometry/car.pbrt")));
+        assert!(matches!(element, Element::Include(path) if path == "geometry/car.pbrt"));
 
         let element = parser.parse_next().unwrap();
         assert!(matches!(
             element,
-            Element::Import("geometry/bigcar.pbrt.gz")
+            Element::Import(path) if path == "geometry/bigcar.pbrt.gz"
         ));
     }
 
@@ -566,6 +805,67 @@ LookAt 0 5.5 24 0 11 -10 0 1 0
         ));
     }
 
+    #[test]
+    fn parse_quoted_string_params() {
+        let mut parser = Parser::new(
+            r#"
+Shape "plymesh" "string filename" "my mesh #1.ply" # Comment
+Texture "a \"quoted\" name" "spectrum" "imagemap" "string filename" [ "tex #2.png" ]
+Shape "plymesh" "string filename" "a\"b.png"
+        "#,
+        );
+
+        match parser.parse_next().unwrap() {
+            Element::Shape { params, .. } => {
+                assert_eq!(params.string("filename"), Some("my mesh #1.ply"));
+            }
+            _ => panic!("Unexpected element type"),
+        }
+
+        match parser.parse_next().unwrap() {
+            Element::Texture { name, params, .. } => {
+                assert_eq!(name, r#"a "quoted" name"#);
+                assert_eq!(params.string("filename"), Some("tex #2.png"));
+            }
+            _ => panic!("Unexpected element type"),
+        }
+
+        match parser.parse_next().unwrap() {
+            Element::Shape { params, .. } => {
+                assert_eq!(params.string("filename"), Some(r#"a"b.png"#));
+            }
+            _ => panic!("Unexpected element type"),
+        }
+    }
+
+    #[test]
+    fn parse_unknown_directive() {
+        let str = "
+FooBar \"type\" \"float x\" [ 1 ] 2
+Scale 1 2 3
+        ";
+
+        let mut parser = Parser::new(str);
+        assert!(matches!(parser.parse_next(), Err(Error::UnknownDirective)));
+
+        let mut parser = Parser::new(str);
+        parser.set_lenient(true);
+
+        match parser.parse_next().unwrap() {
+            Element::Unknown { keyword, params } => {
+                assert_eq!(keyword, "FooBar");
+                assert_eq!(params.len(), 1);
+                assert_eq!(params.get("x").unwrap().ty, ParamType::Float);
+            }
+            _ => panic!("Unexpected element type"),
+        }
+
+        assert!(matches!(
+            parser.parse_next().unwrap(),
+            Element::Scale { .. }
+        ));
+    }
+
     #[test]
     fn parse_transform() {
         let mut parser = Parser::new("Transform [ 1 0 0 0 0 1 0 0 0 0 1 0 3 1 -4 1 ]");
@@ -581,4 +881,110 @@ LookAt 0 5.5 24 0 11 -10 0 1 0
 
         assert!(matches!(next, Element::ConcatTransform { .. }));
     }
+
+    #[test]
+    fn malformed_numbers() {
+        for (input, token, line, column) in [
+            ("Translate 1.0.0 0 0", "1.0.0", 1, 11),
+            ("Scale 1 --3 1", "--3", 1, 9),
+            ("Shape \"sphere\"\n  \"float radius\" 1.0.0", "1.0.0", 2, 18),
+            (
+                "Film \"rgb\" \"integer xresolution\" [ 400 --3 ]",
+                "--3",
+                1,
+                40,
+            ),
+        ] {
+            let mut parser = Parser::new(input);
+            let err = parser.parse_next().unwrap_err();
+
+            assert!(err.to_string().contains(token));
+            assert!(
+                matches!(err, Error::InvalidNumber { token: ref t, line: l, column: c } if t == token && l == line && c == column),
+                "{input}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn array_too_large() {
+        let mut parser = Parser::new("Shape \"trianglemesh\" \"integer indices\" [ 0 1 2 2 1 3 ]");
+        parser.set_max_array_length(3);
+
+        assert!(matches!(
+            parser.parse_next(),
+            Err(Error::ArrayTooLarge {
+                declared: 6,
+                limit: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_point2_param() -> Result<()> {
+        let mut parser = Parser::new(
+            "Shape \"bilinearmesh\" \"point2 uv\" [ 0 0 1 0 1 1 0 1 ] \"vector2 dir\" [ 1 0 ]",
+        );
+
+        let Element::Shape { params, .. } = parser.parse_next()? else {
+            panic!("Unexpected element");
+        };
+
+        assert_eq!(params.get("uv").unwrap().ty, ParamType::Point2);
+        assert_eq!(params.get("dir").unwrap().ty, ParamType::Vector2);
+        assert_eq!(params.get_point2_array("uv")?.unwrap().len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_number_array() -> Result<()> {
+        let values = (0..10_000)
+            .map(|i| i as f32 * 0.25 - 1000.0)
+            .collect::<Vec<_>>();
+        let array = values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let data =
+            format!("Shape \"trianglemesh\" \"point3 P\" [ {array} ] \"string name\" \"mesh\"");
+        let mut parser = Parser::new(&data);
+
+        let Element::Shape { params, .. } = parser.parse_next()? else {
+            panic!("Unexpected element");
+        };
+
+        assert_eq!(params.floats("P").unwrap().unwrap(), values);
+        assert_eq!(params.string("name"), Some("mesh"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_spanned() -> Result<()> {
+        let data = "WorldBegin # world\n  Shape \"sphere\" \"float radius\" [ 2 ]\nAttributeBegin";
+
+        let mut parser = Parser::new(data);
+        parser.set_file_id(3);
+
+        let world = parser.parse_next_spanned()?;
+        assert_eq!(world.span, 0..10);
+
+        let shape = parser.parse_next_spanned()?;
+        assert!(matches!(
+            shape.element,
+            Element::Shape { name: "sphere", .. }
+        ));
+        assert_eq!(&data[shape.span], "Shape \"sphere\" \"float radius\" [ 2 ]");
+        assert_eq!(shape.file_id, 3);
+
+        let attribute = parser.parse_next_spanned()?;
+        assert_eq!(&data[attribute.span], "AttributeBegin");
+
+        assert!(matches!(parser.parse_next_spanned(), Err(Error::EndOfFile)));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,560 @@
+//! Triangle meshes stored in PLY files, as referenced by the `plymesh` shape.
+
+use std::{fs, path::Path, str::SplitWhitespace};
+
+use crate::{Error, LoadOptions, Result};
+
+/// Triangle mesh read from a PLY file.
+///
+/// Attributes use the same flat layout as [crate::types::Shape::TriangleMesh],
+/// optional attributes are empty if the file doesn't provide them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TriangleMesh {
+    /// Vertex indices, three per triangle.
+    pub indices: Vec<i32>,
+    /// Vertex positions, three floats per vertex.
+    pub positions: Vec<f32>,
+    /// Per-vertex normals.
+    pub normals: Vec<f32>,
+    /// Per-vertex texture coordinates.
+    pub uvs: Vec<f32>,
+    /// Per-vertex colors in `[0, 1]`, three floats per vertex.
+    ///
+    /// Read from the `red`, `green` and `blue` vertex properties, integer values are
+    /// normalized by the maximum of their type.
+    pub colors: Vec<f32>,
+}
+
+impl TriangleMesh {
+    /// Read a mesh from a PLY file at path.
+    pub fn from_ply_file(path: impl AsRef<Path>) -> Result<TriangleMesh> {
+        Self::from_ply(&fs::read(path)?)
+    }
+
+    /// Parse the contents of a PLY file.
+    ///
+    /// ASCII and binary files are supported. Faces with more than three vertices
+    /// are split into triangle fans, elements other than vertices and faces are skipped.
+    pub fn from_ply(data: &[u8]) -> Result<TriangleMesh> {
+        let (header, body) = split_header(data)?;
+        let (format, elements) = parse_header(header)?;
+
+        // Every element takes at least one byte, so counts larger than the data are bogus.
+        if let Some(element) = elements.iter().find(|element| element.count > body.len()) {
+            return Err(Error::InvalidPly(format!(
+                "{} {} elements don't fit in {} bytes of data",
+                element.count,
+                element.name,
+                body.len()
+            )));
+        }
+
+        let mut reader = match format {
+            Format::Ascii => {
+                let body = std::str::from_utf8(body)
+                    .map_err(|_| Error::InvalidPly("ASCII data is not valid UTF-8".to_string()))?;
+                Reader::Ascii(body.split_whitespace())
+            }
+            Format::BinaryLittleEndian => Reader::Binary {
+                data: body,
+                big_endian: false,
+            },
+            Format::BinaryBigEndian => Reader::Binary {
+                data: body,
+                big_endian: true,
+            },
+        };
+
+        let mut mesh = TriangleMesh::default();
+
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => read_vertices(&mut reader, element, &mut mesh)?,
+                "face" => read_faces(&mut reader, element, &mut mesh)?,
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            reader.skip(property)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let vertex_count = mesh.positions.len() / 3;
+        if mesh
+            .indices
+            .iter()
+            .any(|&index| index < 0 || index as usize >= vertex_count)
+        {
+            return Err(Error::ValueOutOfRange("vertex_indices".to_string()));
+        }
+
+        Ok(mesh)
+    }
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn new(name: &str) -> Result<Scalar> {
+        let scalar = match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(Error::InvalidPly(format!("unknown property type {name}"))),
+        };
+
+        Ok(scalar)
+    }
+
+    /// Largest value of integer types, used to normalize colors. Floats are already normalized.
+    fn max_value(self) -> f64 {
+        match self {
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum PropertyType {
+    Scalar(Scalar),
+    /// Count type and item type.
+    List(Scalar, Scalar),
+}
+
+struct Property {
+    name: String,
+    ty: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Split the file into the header text and the element data.
+fn split_header(data: &[u8]) -> Result<(&str, &[u8])> {
+    const END: &[u8] = b"end_header";
+
+    if !data.starts_with(b"ply") {
+        return Err(Error::InvalidPly("missing magic number".to_string()));
+    }
+
+    let end = data
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or_else(|| Error::InvalidPly("missing end_header".to_string()))?;
+
+    // Data starts after the line break following `end_header`.
+    let mut body = end + END.len();
+    while body < data.len() && data[body] != b'\n' {
+        body += 1;
+    }
+
+    let header = std::str::from_utf8(&data[..end])
+        .map_err(|_| Error::InvalidPly("header is not valid UTF-8".to_string()))?;
+
+    Ok((header, data.get(body + 1..).unwrap_or_default()))
+}
+
+fn parse_header(header: &str) -> Result<(Format, Vec<Element>)> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    for line in header.lines().skip(1) {
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("format") => {
+                format = Some(match words.next() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(Error::InvalidPly(format!("unsupported format: {line}"))),
+                });
+            }
+            Some("element") => {
+                let (Some(name), Some(count)) = (words.next(), words.next()) else {
+                    return Err(Error::InvalidPly(format!("invalid element: {line}")));
+                };
+
+                elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse()?,
+                    properties: Vec::new(),
+                });
+            }
+            Some("property") => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| Error::InvalidPly("property outside of element".to_string()))?;
+
+                let ty = match words.next() {
+                    Some("list") => {
+                        let count = Scalar::new(words.next().unwrap_or_default())?;
+                        let item = Scalar::new(words.next().unwrap_or_default())?;
+                        PropertyType::List(count, item)
+                    }
+                    Some(ty) => PropertyType::Scalar(Scalar::new(ty)?),
+                    None => return Err(Error::InvalidPly(format!("invalid property: {line}"))),
+                };
+
+                let name = words
+                    .next()
+                    .ok_or_else(|| Error::InvalidPly(format!("invalid property: {line}")))?;
+
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    ty,
+                });
+            }
+            // Comments, object info and unknown keywords.
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| Error::InvalidPly("missing format".to_string()))?;
+
+    Ok((format, elements))
+}
+
+enum Reader<'a> {
+    Ascii(SplitWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl Reader<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64> {
+        let eof = || Error::InvalidPly("unexpected end of data".to_string());
+
+        match self {
+            Reader::Ascii(values) => Ok(values.next().ok_or_else(eof)?.parse()?),
+            Reader::Binary { data, big_endian } => {
+                if data.len() < ty.size() {
+                    return Err(eof());
+                }
+
+                let (bytes, rest) = data.split_at(ty.size());
+                *data = rest;
+
+                // Reorder to little endian, so a single set of conversions is needed.
+                let mut buffer = [0; 8];
+                buffer[..bytes.len()].copy_from_slice(bytes);
+                if *big_endian {
+                    buffer[..bytes.len()].reverse();
+                }
+
+                let value = match ty {
+                    Scalar::I8 => buffer[0] as i8 as f64,
+                    Scalar::U8 => buffer[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Scalar::I32 => {
+                        i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::U32 => {
+                        u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::F32 => {
+                        f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::F64 => f64::from_le_bytes(buffer),
+                };
+
+                Ok(value)
+            }
+        }
+    }
+
+    /// Read all values of a property, lists yield their items.
+    fn read_property(&mut self, property: &Property) -> Result<Vec<f64>> {
+        match property.ty {
+            PropertyType::Scalar(ty) => Ok(vec![self.read(ty)?]),
+            PropertyType::List(count, item) => {
+                let count = self.read(count)? as usize;
+                (0..count).map(|_| self.read(item)).collect()
+            }
+        }
+    }
+
+    fn skip(&mut self, property: &Property) -> Result<()> {
+        self.read_property(property).map(|_| ())
+    }
+}
+
+fn read_vertices(reader: &mut Reader, element: &Element, mesh: &mut TriangleMesh) -> Result<()> {
+    let has = |names: &[&str]| {
+        element
+            .properties
+            .iter()
+            .any(|property| names.contains(&property.name.as_str()))
+    };
+    let has_normals = has(&["nx"]);
+    let has_uvs = has(&["u", "s", "texture_u", "texture_s"]);
+    let has_colors = has(&["red"]);
+
+    mesh.positions.reserve(reserve_len(element, 3)?);
+
+    for _ in 0..element.count {
+        let mut position = [0.0; 3];
+        let mut normal = [0.0; 3];
+        let mut uv = [0.0; 2];
+        let mut color = [0.0; 3];
+
+        for property in &element.properties {
+            let (value, ty) = match property.ty {
+                PropertyType::Scalar(ty) => (reader.read(ty)? as f32, ty),
+                PropertyType::List(..) => {
+                    reader.skip(property)?;
+                    continue;
+                }
+            };
+            let channel = || value / ty.max_value() as f32;
+
+            match property.name.as_str() {
+                "x" => position[0] = value,
+                "y" => position[1] = value,
+                "z" => position[2] = value,
+                "nx" => normal[0] = value,
+                "ny" => normal[1] = value,
+                "nz" => normal[2] = value,
+                "u" | "s" | "texture_u" | "texture_s" => uv[0] = value,
+                "v" | "t" | "texture_v" | "texture_t" => uv[1] = value,
+                "red" => color[0] = channel(),
+                "green" => color[1] = channel(),
+                "blue" => color[2] = channel(),
+                _ => {}
+            }
+        }
+
+        mesh.positions.extend_from_slice(&position);
+        if has_normals {
+            mesh.normals.extend_from_slice(&normal);
+        }
+        if has_uvs {
+            mesh.uvs.extend_from_slice(&uv);
+        }
+        if has_colors {
+            mesh.colors.extend_from_slice(&color);
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of values to reserve for `per_element` values of every element.
+///
+/// The count comes from the file, so the reservation is bounded by [LoadOptions::DEFAULT_MAX_ARRAY_LENGTH].
+fn reserve_len(element: &Element, per_element: usize) -> Result<usize> {
+    let len = element
+        .count
+        .checked_mul(per_element)
+        .ok_or_else(|| Error::InvalidPly(format!("too many {} elements", element.name)))?;
+
+    Ok(len.min(LoadOptions::DEFAULT_MAX_ARRAY_LENGTH))
+}
+
+fn read_faces(reader: &mut Reader, element: &Element, mesh: &mut TriangleMesh) -> Result<()> {
+    mesh.indices.reserve(reserve_len(element, 3)?);
+
+    for _ in 0..element.count {
+        for property in &element.properties {
+            let values = reader.read_property(property)?;
+
+            if !matches!(property.name.as_str(), "vertex_indices" | "vertex_index") {
+                continue;
+            }
+
+            // Split polygons into a triangle fan around the first vertex.
+            for i in 1..values.len().saturating_sub(1) {
+                mesh.indices.extend_from_slice(&[
+                    values[0] as i32,
+                    values[i] as i32,
+                    values[i + 1] as i32,
+                ]);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ascii() -> Result<()> {
+        let data = "ply
+format ascii 1.0
+comment quad with normals
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1
+1 0 0 0 0 1
+1 1 0 0 0 1
+0 1 0 0 0 1
+4 0 1 2 3
+";
+
+        let mesh = TriangleMesh::from_ply(data.as_bytes())?;
+
+        assert_eq!(mesh.positions.len(), 12);
+        assert_eq!(mesh.normals.len(), 12);
+        assert!(mesh.uvs.is_empty());
+        assert!(mesh.colors.is_empty());
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_vertex_colors() -> Result<()> {
+        let data = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property uchar alpha
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0 255
+1 0 0 0 255 0 255
+0 1 0 0 0 51 255
+3 0 1 2
+";
+
+        let mesh = TriangleMesh::from_ply(data.as_bytes())?;
+
+        assert_eq!(mesh.positions.len(), 9);
+        assert_eq!(
+            mesh.colors,
+            vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.2]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_binary() -> Result<()> {
+        let mut data = b"ply
+format binary_big_endian 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property float u
+property float v
+element face 1
+property list uchar uint vertex_indices
+end_header
+"
+        .to_vec();
+
+        for vertex in [
+            [0.0f32, 0.0, 0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0, 1.0],
+        ] {
+            for value in vertex {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        data.push(3);
+        for index in [0u32, 1, 2] {
+            data.extend_from_slice(&index.to_be_bytes());
+        }
+
+        let mesh = TriangleMesh::from_ply(&data)?;
+
+        assert_eq!(
+            mesh.positions,
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+        );
+        assert_eq!(mesh.uvs, vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+
+        // Truncated data.
+        assert!(matches!(
+            TriangleMesh::from_ply(&data[..data.len() - 2]),
+            Err(Error::InvalidPly(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bogus_element_count() -> Result<()> {
+        for count in ["18446744073709551615", "100000000000"] {
+            let data = format!(
+                "ply
+format binary_little_endian 1.0
+element vertex {count}
+property float x
+property float y
+property float z
+end_header
+"
+            );
+            let mut data = data.into_bytes();
+            data.extend_from_slice(&[0; 12]);
+
+            assert!(matches!(
+                TriangleMesh::from_ply(&data),
+                Err(Error::InvalidPly(_))
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -1,16 +1,37 @@
 //! Scene loader
 
-use std::{collections::HashMap, env, fs, path::Path, slice, str};
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    env,
+    f32::consts::PI,
+    fmt::{self, Write},
+    fs,
+    hash::Hasher,
+    io, mem,
+    path::{Path, PathBuf},
+    slice, str,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use glam::{Mat4, Vec3};
+use glam::{Mat3, Mat4, UVec3, Vec3};
 
 use crate::{
-    param::{Param, ParamList},
+    color::ColorMatching,
+    param::{Param, ParamList, ParamValue, Spectrum},
+    parser::V3_MATERIAL_PARAMS,
+    source::{read_string, AssetSource},
+    tessellate::{tessellate_quadric, tessellated_shape},
     types::{
-        Accelerator, AreaLight, Camera, Film, Integrator, Light, Material, Medium, Options,
-        PixelFilter, Sampler, Shape, Texture,
+        Accelerator, Alpha, AreaLight, Camera, ColorSpace, CoordinateSystem, Film, Integrator,
+        Light, Material, MaterialType, Medium, Options, PixelFilter, Sampler, Shape, Texture,
+        TextureClass,
     },
-    Element, Error, Parser, Result,
+    writer::SceneWriter,
+    Element, Error, Parser, PbrtVersion, Result, TriangleMesh,
 };
 
 /// A number of directives modify the current graphics state.
@@ -23,13 +44,19 @@ struct State<'a> {
     reverse_orientation: bool,
 
     transform_matrix: Mat4,
+    /// Transform directives that produced `transform_matrix`, only recorded when tracing.
+    transform_trace: Vec<TransformOp>,
 
-    current_inside_medium: Option<&'a str>,
-    current_outside_medium: Option<&'a str>,
+    /// Current interior and exterior media, `None` stands for vacuum.
+    current_inside_medium: Option<Cow<'a, str>>,
+    current_outside_medium: Option<Cow<'a, str>>,
 
     material_index: Option<usize>,
     area_light_index: Option<usize>,
 
+    /// Color space set by the `ColorSpace` directive, `None` for the default sRGB.
+    color_space: Option<ColorSpace>,
+
     /// Between `ObjectBegin` and `ObjectEnd` if `Some`.
     active_object: Option<usize>,
     shape_count: usize,
@@ -41,13 +68,321 @@ struct State<'a> {
     texture_params: ParamList<'a>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CameraEntity {
     pub params: Camera,
     pub transform: Mat4,
+    /// The CTM at the `Camera` directive, see [CameraEntity::camera_from_world].
+    parsed_camera_from_world: Mat4,
+    /// Name of the medium the camera is in, `None` for vacuum.
+    pub medium: Option<String>,
+    /// Start of the motion interval of `transform`, from `TransformTimes`.
+    pub transform_start_time: f32,
+    /// End of the motion interval of `transform`, from `TransformTimes`.
+    pub transform_end_time: f32,
+}
+
+/// Camera parameters needed to generate rays, see [CameraEntity::intrinsics].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraIntrinsics {
+    /// Field of view in degrees along the shorter image axis, `None` for non-perspective cameras.
+    pub fov: Option<f32>,
+    /// Film aspect ratio, width divided by height.
+    pub aspect_ratio: f32,
+    /// Extent of the image plane in screen space as `[xmin, xmax, ymin, ymax]`.
+    pub screen_window: [f32; 4],
+    /// The radius of the lens, zero for a pinhole camera.
+    pub lens_radius: f32,
+    /// The distance to the plane of focus.
+    pub focal_distance: f32,
+    /// Camera to world transformation.
+    pub world_from_camera: Mat4,
+}
+
+/// Depth range of clip space, see [CameraEntity::projection_matrix].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipConvention {
+    /// OpenGL, normalized device z is in `[-1, 1]`.
+    OpenGl,
+    /// Vulkan, Direct3D and Metal, normalized device z is in `[0, 1]`.
+    Vulkan,
+}
+
+impl CameraEntity {
+    /// Near and far planes of the projection of perspective cameras, as used by pbrt.
+    const PERSPECTIVE_CLIP: (f32, f32) = (1e-2, 1000.0);
+
+    /// Returns the camera from world transformation, the inverse of `transform`.
+    ///
+    /// While `transform` is unchanged, this is the CTM at the `Camera` directive as parsed,
+    /// so writing the scene doesn't invert `transform` again, which would drift by a rounding
+    /// error on every load and write.
+    pub fn camera_from_world(&self) -> Mat4 {
+        if self.parsed_camera_from_world.inverse() == self.transform {
+            self.parsed_camera_from_world
+        } else {
+            self.transform.inverse()
+        }
+    }
+
+    /// Returns the `(open, close)` times of the camera shutter.
+    pub fn shutter(&self) -> (f32, f32) {
+        self.params.shutter()
+    }
+
+    /// Returns the world space `(forward, up, right)` axes of the camera, normalized.
+    ///
+    /// pbrt's camera space is left-handed: the camera looks down `+z`, `+y` is up and `+x`
+    /// points to the right of the image. The axes are these directions transformed by the
+    /// world from camera transformation, so for a camera placed with `LookAt` `forward` points
+    /// from the eye to the look-at point. Mirroring transforms (like `Scale -1 1 1`) flip `right`.
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let axis = |axis: Vec3| self.transform.transform_vector3(axis).normalize_or_zero();
+
+        (axis(Vec3::Z), axis(Vec3::Y), axis(Vec3::X))
+    }
+
+    /// Resolve the camera parameters against the film.
+    ///
+    /// Perspective cameras report their field of view, orthographic cameras use the screen window
+    /// to define the extent of the view. The realistic camera's aperture diameter is given in mm
+    /// and converted to the lens radius in meters, spherical cameras have no lens.
+    pub fn intrinsics(&self, film: &Film) -> CameraIntrinsics {
+        let aspect_ratio = film.aspect_ratio();
+        let default_window = Camera::default_screen_window(aspect_ratio);
+
+        let (fov, screen_window, lens_radius, focal_distance) = match &self.params {
+            Camera::Perspective {
+                fov,
+                lens_radius,
+                focal_distance,
+                ..
+            } => (Some(*fov), default_window, *lens_radius, *focal_distance),
+            Camera::Orthographic {
+                screen_window,
+                lens_radius,
+                focal_distance,
+                ..
+            } => (
+                None,
+                screen_window.unwrap_or(default_window),
+                *lens_radius,
+                *focal_distance,
+            ),
+            Camera::Realistic {
+                aperture_diameter,
+                focus_distance,
+                ..
+            } => (
+                None,
+                default_window,
+                aperture_diameter * 0.5 * 0.001,
+                *focus_distance,
+            ),
+            Camera::Spherical { .. } => (None, default_window, 0.0, 0.0),
+        };
+
+        CameraIntrinsics {
+            fov,
+            aspect_ratio,
+            screen_window,
+            lens_radius,
+            focal_distance,
+            world_from_camera: self.transform,
+        }
+    }
+
+    /// Projection from camera space to clip space for rasterizing a preview of the camera's view.
+    ///
+    /// The screen window of the resolved [CameraEntity::intrinsics] maps to x and y in `[-1, 1]`,
+    /// with y pointing up (Vulkan previews flip y in the viewport). The near plane maps to the
+    /// smallest normalized device z of `api`, the far plane to 1. Orthographic cameras use their
+    /// `hither` and `yon` distances, perspective cameras pbrt's fixed clipping planes at 0.01 and
+    /// 1000. Realistic and spherical cameras have no linear projection and are approximated by a
+    /// perspective projection with a 90 degree field of view.
+    ///
+    /// Combine with the inverse of [CameraEntity::transform] to project world space points.
+    pub fn projection_matrix(&self, film: &Film, api: ClipConvention) -> Mat4 {
+        let intrinsics = self.intrinsics(film);
+        let [xmin, xmax, ymin, ymax] = intrinsics.screen_window;
+
+        // Scale and offset that map the screen window to [-1, 1].
+        let (sx, ox) = (2.0 / (xmax - xmin), (xmax + xmin) / (xmax - xmin));
+        let (sy, oy) = (2.0 / (ymax - ymin), (ymax + ymin) / (ymax - ymin));
+
+        let rows = match &self.params {
+            Camera::Orthographic {
+                near_clip,
+                far_clip,
+                ..
+            } => {
+                let (n, f) = (*near_clip, *far_clip);
+                let (a, b) = match api {
+                    ClipConvention::OpenGl => (2.0 / (f - n), -(f + n) / (f - n)),
+                    ClipConvention::Vulkan => (1.0 / (f - n), -n / (f - n)),
+                };
+
+                [
+                    [sx, 0.0, 0.0, -ox],
+                    [0.0, sy, 0.0, -oy],
+                    [0.0, 0.0, a, b],
+                    [0.0, 0.0, 0.0, 1.0],
+                ]
+            }
+            _ => {
+                let fov = intrinsics.fov.unwrap_or(90.0);
+                let tan_half_fov = (fov * 0.5).to_radians().tan();
+                let (n, f) = Self::PERSPECTIVE_CLIP;
+                let (a, b) = match api {
+                    ClipConvention::OpenGl => ((f + n) / (f - n), -2.0 * f * n / (f - n)),
+                    ClipConvention::Vulkan => (f / (f - n), -f * n / (f - n)),
+                };
+
+                // Camera space points are divided by z to project them onto the screen.
+                [
+                    [sx / tan_half_fov, 0.0, -ox, 0.0],
+                    [0.0, sy / tan_half_fov, -oy, 0.0],
+                    [0.0, 0.0, a, b],
+                    [0.0, 0.0, 1.0, 0.0],
+                ]
+            }
+        };
+
+        Mat4::from_cols_array_2d(&rows).transpose()
+    }
+
+    /// Generate the world space primary ray through the center of a film pixel.
+    ///
+    /// Returns the ray origin and the normalized direction. Rays are generated like by a pinhole
+    /// camera, the lens radius is ignored. Only perspective and orthographic cameras are supported,
+    /// other cameras return `None`.
+    pub fn generate_ray(&self, film: &Film, pixel_x: u32, pixel_y: u32) -> Option<(Vec3, Vec3)> {
+        let intrinsics = self.intrinsics(film);
+        let [xmin, xmax, ymin, ymax] = intrinsics.screen_window;
+
+        // Raster space y points down, screen space y points up.
+        let u = (pixel_x as f32 + 0.5) / film.xresolution as f32;
+        let v = (pixel_y as f32 + 0.5) / film.yresolution as f32;
+        let screen_x = xmin + u * (xmax - xmin);
+        let screen_y = ymax - v * (ymax - ymin);
+
+        let (origin, direction) = match &self.params {
+            Camera::Perspective { fov, .. } => {
+                let tan_half_fov = (fov * 0.5).to_radians().tan();
+                let direction = Vec3::new(screen_x * tan_half_fov, screen_y * tan_half_fov, 1.0);
+
+                (Vec3::ZERO, direction)
+            }
+            Camera::Orthographic { .. } => (Vec3::new(screen_x, screen_y, 0.0), Vec3::Z),
+            Camera::Realistic { .. } | Camera::Spherical { .. } => return None,
+        };
+
+        let world_from_camera = intrinsics.world_from_camera;
+
+        Some((
+            world_from_camera.transform_point3(origin),
+            world_from_camera.transform_vector3(direction).normalize(),
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LightEntity {
+    pub params: Light,
+    /// Light to world transformation, the CTM at the time the light was defined.
+    pub transform: Mat4,
+    /// Name of the exterior medium at the time the light was defined, `None` for vacuum.
+    pub outside_medium: Option<String>,
+    /// Number of enclosing `AttributeBegin` (and `ObjectBegin`) blocks where the light was defined.
+    pub attribute_depth: u32,
+}
+
+impl LightEntity {
+    /// Returns the world space direction in which the light travels.
+    ///
+    /// Only defined for `distant` and `spot` lights, computed as [Light::direction]
+    /// transformed by the light's transformation.
+    pub fn world_direction(&self) -> Option<Vec3> {
+        let direction = self.params.direction()?;
+        self.transform.transform_vector3(direction).try_normalize()
+    }
+
+    /// Returns the world space position of the light.
+    ///
+    /// `point` and `spot` lights are positioned at `from` transformed by the light's transformation,
+    /// `goniometric` and `projection` lights at the origin of the light's coordinate system.
+    /// Lights at infinity (`distant`, `infinite`) have no position.
+    pub fn world_position(&self) -> Option<Vec3> {
+        let from = match &self.params {
+            Light::Point { from, .. } | Light::Spot { from, .. } => Vec3::from(*from),
+            Light::GonioPhotometric { .. } | Light::Projection { .. } => Vec3::ZERO,
+            Light::Distant { .. } | Light::Infinite { .. } => return None,
+        };
+
+        Some(self.transform.transform_point3(from))
+    }
+
+    /// Returns the world space bounds of the light as `(min, max)`.
+    ///
+    /// Lights with a position have degenerate bounds enclosing just that point,
+    /// lights at infinity are unbounded and return `None`.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        self.world_position().map(|position| (position, position))
+    }
+
+    /// Summarize the light for photometric interchange.
+    ///
+    /// The luminous flux is estimated by treating the luminance of the emission spectrum,
+    /// multiplied by the light's scale, as intensity in candela and integrating it over the
+    /// solid angle the light emits into. Lights at infinity and lights without a spectrum
+    /// have no estimate.
+    pub fn photometric_summary(&self) -> PhotometricSummary {
+        let solid_angle = match &self.params {
+            Light::Point { .. } | Light::GonioPhotometric { .. } => Some(4.0 * PI),
+            Light::Spot {
+                cone_angle,
+                cone_delta,
+                ..
+            } => {
+                // Average of the full intensity and the falloff regions, like pbrt's spot light.
+                let cos_end = cone_angle.to_radians().cos();
+                let cos_start = (cone_angle - cone_delta).to_radians().cos();
+
+                Some(2.0 * PI * (1.0 - 0.5 * (cos_start + cos_end)))
+            }
+            Light::Projection { fov, .. } => {
+                let half_angle = (fov * 0.5).to_radians();
+                Some(4.0 * (half_angle.sin() * half_angle.sin()).asin())
+            }
+            Light::Distant { .. } | Light::Infinite { .. } => None,
+        };
+
+        let intensity = self
+            .params
+            .spectrum()
+            .and_then(Spectrum::luminance)
+            .map(|luminance| luminance * self.params.scale());
+
+        PhotometricSummary {
+            ty: self.params.type_name(),
+            lumens: solid_angle.zip(intensity).map(|(a, i)| a * i),
+            direction: self.world_direction(),
+        }
+    }
+}
+
+/// Photometric description of a light, see [LightEntity::photometric_summary].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhotometricSummary {
+    /// Light type as written in the scene file.
+    pub ty: &'static str,
+    /// Estimated total luminous flux.
+    pub lumens: Option<f32>,
+    /// World space beam direction of `distant` and `spot` lights.
+    pub direction: Option<Vec3>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ShapeEntity {
     pub params: Shape,
     /// If shape is a part of [Object], transform matrix defines the transformation from
@@ -56,6 +391,352 @@ pub struct ShapeEntity {
     pub reverse_orientation: bool,
     pub material_index: Option<usize>,
     pub area_light_index: Option<usize>,
+    /// Opacity mask, either a constant or a float texture.
+    pub alpha: Alpha,
+    /// Name of the medium inside the shape, `None` for vacuum.
+    pub inside_medium: Option<String>,
+    /// Name of the medium outside the shape, `None` for vacuum.
+    pub outside_medium: Option<String>,
+    /// Start of the motion interval of `transform`, from `TransformTimes`.
+    pub transform_start_time: f32,
+    /// End of the motion interval of `transform`, from `TransformTimes`.
+    pub transform_end_time: f32,
+    /// Cached inverse of `transform`, see [Scene::precompute_inverse_transforms].
+    pub inverse_transform: Option<Mat4>,
+    /// Index of `transform` in `Scene::transforms`, see [Scene::deduplicate_transforms].
+    pub transform_index: Option<usize>,
+    /// Number of enclosing `AttributeBegin` (and `ObjectBegin`) blocks where the shape was defined.
+    pub attribute_depth: u32,
+}
+
+/// Shape ready to be rendered, see [Scene::render_primitives].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderPrimitive {
+    /// Index of the shape in `Scene::shapes`.
+    pub shape_index: usize,
+    /// Index of the instance the shape is rendered through, `None` for standalone shapes.
+    pub instance_index: Option<usize>,
+    /// Object to world transformation, composed with the instance's transformation.
+    pub world_transform: Mat4,
+    /// Whether surface normals should be flipped, see [Scene::effective_reverse_orientation].
+    pub reverse_orientation: bool,
+    pub material_index: Option<usize>,
+    pub area_light_index: Option<usize>,
+    /// Name of the medium inside the shape, `None` for vacuum.
+    pub inside_medium: Option<String>,
+    /// Name of the medium outside the shape, `None` for vacuum.
+    pub outside_medium: Option<String>,
+}
+
+/// Shape with its geometry, material and emission resolved, see [Scene::bake].
+#[derive(Debug, Clone)]
+pub struct BakedPrimitive {
+    /// Geometry in object space, `plymesh` shapes are loaded into a [Shape::TriangleMesh].
+    pub shape: Shape,
+    /// Object to world transformation, composed with the instance's transformation.
+    pub world_transform: Mat4,
+    /// Whether surface normals should be flipped, see [Scene::effective_reverse_orientation].
+    pub reverse_orientation: bool,
+    /// The shape's material, pbrt's default material if it has none.
+    pub material: Material,
+    pub area_light: Option<AreaLight>,
+    pub alpha: Alpha,
+    /// Name of the medium inside the shape, `None` for vacuum.
+    pub inside_medium: Option<String>,
+    /// Name of the medium outside the shape, `None` for vacuum.
+    pub outside_medium: Option<String>,
+}
+
+/// Render-ready scene with instances expanded and render settings resolved, see [Scene::bake].
+#[derive(Debug, Clone)]
+pub struct BakedScene {
+    pub film: Film,
+    pub sampler: Sampler,
+    pub integrator: Integrator,
+    pub pixel_filter: PixelFilter,
+    pub cameras: Vec<CameraEntity>,
+    pub lights: Vec<LightEntity>,
+    /// Textures, referenced by materials and alpha masks like in [Scene::textures].
+    pub textures: Vec<Texture>,
+    /// All primitives to render, in the order of [Scene::render_primitives].
+    pub primitives: Vec<BakedPrimitive>,
+}
+
+impl BakedScene {
+    /// Replace the spectral parameters of materials, lights and area lights with RGB values
+    /// in `color_space`, see [Scene::bake_spectra_to_rgb].
+    pub fn bake_spectra_to_rgb(&mut self, color_space: ColorSpace) {
+        let primitives = self.primitives.iter_mut().flat_map(|primitive| {
+            let area_light = match &mut primitive.area_light {
+                Some(AreaLight::Diffuse { spectrum, .. }) => spectrum.as_mut(),
+                None => None,
+            };
+            primitive
+                .material
+                .ty
+                .spectra_mut()
+                .into_iter()
+                .chain(area_light)
+        });
+        let lights = self
+            .lights
+            .iter_mut()
+            .filter_map(|light| light.params.spectrum_mut());
+
+        spectra_to_rgb(primitives.chain(lights), color_space);
+    }
+}
+
+/// Light source of the scene, see [Scene::all_light_sources].
+#[derive(Debug, Clone)]
+pub enum LightSourceRef<'a> {
+    /// Light declared with `LightSource`, with its light to world transform.
+    Light(&'a LightEntity),
+    /// Emissive shape.
+    Area {
+        /// The shape with its world transform.
+        primitive: RenderPrimitive,
+        /// Emission of the shape.
+        area_light: &'a AreaLight,
+    },
+}
+
+impl RenderPrimitive {
+    /// Create a primitive for `shape`, rendered through `instance` with the given index if any.
+    fn new(shape_index: usize, shape: &ShapeEntity, instance: Option<(usize, &Instance)>) -> Self {
+        let world_transform = match instance {
+            Some((_, instance)) => instance.instance_to_world * shape.transform,
+            None => shape.transform,
+        };
+        let swaps_handedness = world_transform.determinant() < 0.0;
+
+        let instance_index = instance.map(|(index, _)| index);
+        let instance = instance.map(|(_, instance)| instance);
+
+        Self {
+            shape_index,
+            instance_index,
+            world_transform,
+            reverse_orientation: shape.reverse_orientation
+                ^ instance.map_or(false, |instance| instance.reverse_orientation)
+                ^ swaps_handedness,
+            material_index: instance
+                .and_then(|instance| instance.material_override)
+                .or(shape.material_index),
+            area_light_index: shape
+                .area_light_index
+                .or(instance.and_then(|instance| instance.area_light_index)),
+            inside_medium: shape.inside_medium.clone(),
+            outside_medium: shape.outside_medium.clone(),
+        }
+    }
+}
+
+/// Node of the hierarchical scene representation, see [Scene::to_node_tree].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneNode {
+    pub kind: SceneNodeKind,
+    /// Transformation from the node's space to its parent's space.
+    pub transform: Mat4,
+    pub children: Vec<SceneNode>,
+}
+
+/// What a [SceneNode] represents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SceneNodeKind {
+    /// Top level node, its transformation is the identity.
+    Root,
+    /// Reusable subtree holding the shapes of an object, indexes `Scene::objects`.
+    Object { object_index: usize, name: String },
+    /// Placement of an object's subtree, indexes `Scene::instances` and `Scene::objects`.
+    Instance {
+        instance_index: usize,
+        object_index: usize,
+    },
+    /// Leaf node, indexes `Scene::shapes`.
+    Shape { shape_index: usize },
+}
+
+impl SceneNode {
+    fn new(kind: SceneNodeKind, transform: Mat4) -> Self {
+        Self {
+            kind,
+            transform,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Transform directive recorded by [Scene::load_with_transform_trace].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransformOp {
+    Identity,
+    Translate([f32; 3]),
+    Scale([f32; 3]),
+    /// Angle in degrees and rotation axis.
+    Rotate(f32, [f32; 3]),
+    LookAt {
+        eye: [f32; 3],
+        look_at: [f32; 3],
+        up: [f32; 3],
+    },
+    Transform([f32; 16]),
+    ConcatTransform([f32; 16]),
+    CoordSysTransform(String),
+}
+
+impl TransformOp {
+    fn from_element(element: &Element) -> Option<Self> {
+        let op = match element {
+            Element::Identity => TransformOp::Identity,
+            Element::Translate { v } => TransformOp::Translate(*v),
+            Element::Scale { v } => TransformOp::Scale(*v),
+            Element::Rotate { angle, v } => TransformOp::Rotate(*angle, *v),
+            Element::LookAt { eye, look_at, up } => TransformOp::LookAt {
+                eye: *eye,
+                look_at: *look_at,
+                up: *up,
+            },
+            Element::Transform { m } => TransformOp::Transform(*m),
+            Element::ConcatTransform { m } => TransformOp::ConcatTransform(*m),
+            Element::CoordSysTransform { name } => TransformOp::CoordSysTransform(name.to_string()),
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    /// Returns `true` if the op replaces the CTM instead of multiplying it.
+    fn resets(&self) -> bool {
+        matches!(
+            self,
+            TransformOp::Identity | TransformOp::Transform(_) | TransformOp::CoordSysTransform(_)
+        )
+    }
+}
+
+impl ShapeEntity {
+    /// Relative tolerance used by [ShapeEntity::has_nonuniform_scale] to compare the entries of
+    /// the transform's metric.
+    pub const SCALE_EPSILON: f32 = 1e-5;
+
+    /// Tessellate a sphere, cylinder or disk into a triangle mesh.
+    ///
+    /// `segments` is the number of steps around the z axis, half as many are used along the
+    /// other direction. Positions and normals are transformed by `transform`, normals are
+    /// flipped for shapes with reverse orientation. The inverse transpose keeps normals on
+    /// the same side of the surface when the transform swaps handedness (has a negative
+    /// determinant), the triangle winding is flipped instead so it agrees with the normals.
+    ///
+    /// Returns `None` for meshes, curves and other shapes that aren't quadrics. Cones,
+    /// paraboloids and hyperboloids only exist in pbrt-v3 and fail to load, so they are
+    /// not handled here.
+    pub fn tessellate(&self, segments: u32) -> Option<TriangleMesh> {
+        let mut mesh = tessellate_quadric(&self.params, segments)?;
+
+        let normal_matrix = self.transform.inverse().transpose();
+        let sign = if self.reverse_orientation { -1.0 } else { 1.0 };
+
+        // Triangles are wound counter-clockwise around their normals.
+        let swaps_handedness = self.transform.determinant() < 0.0;
+        if self.reverse_orientation != swaps_handedness {
+            for triangle in mesh.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+
+        for p in mesh.positions.chunks_exact_mut(3) {
+            let world = self.transform.transform_point3(Vec3::from_slice(p));
+            p.copy_from_slice(&world.to_array());
+        }
+        for n in mesh.normals.chunks_exact_mut(3) {
+            let world = normal_matrix
+                .transform_vector3(Vec3::from_slice(n))
+                .normalize_or_zero();
+            n.copy_from_slice(&(world * sign).to_array());
+        }
+
+        Some(mesh)
+    }
+
+    /// Returns the world (or instance) to object transformation, the inverse of `transform`.
+    ///
+    /// Uses the cached inverse if available, otherwise it's computed on every call.
+    /// The inverse of a near-singular transform loses most of its precision and the one of a
+    /// singular transform is not finite, [Scene::validate] reports such transforms.
+    pub fn world_to_object(&self) -> Mat4 {
+        self.inverse_transform
+            .unwrap_or_else(|| self.transform.inverse())
+    }
+
+    /// Returns `true` if the shape's transform scales axes by different amounts or shears them.
+    ///
+    /// Normals of such shapes must be transformed by the inverse transpose of the transform.
+    pub fn has_nonuniform_scale(&self) -> bool {
+        squared_uniform_scale(&self.transform).is_none()
+    }
+
+    /// Intersect a world space ray with the shape.
+    ///
+    /// Returns the world space distance from `ray_origin` to the nearest hit in front of it.
+    /// Spheres, disks, cylinders and triangle meshes are supported, other shapes never report a hit.
+    pub fn intersect(&self, ray_origin: Vec3, ray_dir: Vec3) -> Option<f32> {
+        let object_from_world = self.transform.inverse();
+
+        // The ray direction isn't normalized in object space,
+        // so `t` is the same in both spaces.
+        let origin = object_from_world.transform_point3(ray_origin);
+        let dir = object_from_world.transform_vector3(ray_dir);
+
+        let t = intersect_shape(&self.params, origin, dir)?;
+
+        Some(t * ray_dir.length())
+    }
+
+    /// Returns the world space surface area of the shape.
+    ///
+    /// Analytic shapes are measured in object space and scaled by the transform,
+    /// so `None` is returned when [ShapeEntity::has_nonuniform_scale] is `true`.
+    /// Shapes whose geometry lives in external files (PLY meshes) and curves also return `None`.
+    pub fn surface_area(&self) -> Option<f32> {
+        world_shape_area(&self.params, &self.transform)
+    }
+}
+
+/// Problem with medium assignments found by [Scene::check_medium_consistency].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediumIssue {
+    /// Shape references a medium that was never declared with `MakeNamedMedium`.
+    UndefinedShapeMedium { shape_index: usize, name: String },
+    /// Light is placed in a medium that was never declared with `MakeNamedMedium`.
+    UndefinedLightMedium { light_index: usize, name: String },
+    /// Shape with an `interface` material has the same medium on both sides,
+    /// so it doesn't separate anything.
+    RedundantInterface { shape_index: usize },
+    /// Light is in a different medium than the camera, but no shape separates the two media.
+    MediumMismatch { light_index: usize },
+}
+
+/// Problem found by [Scene::validate].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// Transform of the entity is singular or not finite, so it can't be inverted.
+    DegenerateTransform(EntityRef),
+    /// Emitted radiance of the area light is negative or not finite.
+    InvalidEmission(EntityRef),
+}
+
+/// Reference to a scene entity by its index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntityRef {
+    /// Index into `Scene::shapes`.
+    Shape(usize),
+    /// Index into `Scene::instances`.
+    Instance(usize),
+    /// Index into `Scene::cameras`.
+    Camera(usize),
+    /// Index into `Scene::area_lights`.
+    AreaLight(usize),
 }
 
 #[derive(Debug, Clone)]
@@ -66,20 +747,179 @@ pub struct Object {
     pub object_to_instance: Mat4,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Instance {
     pub instance_to_world: Mat4,
     pub object_index: usize,
     pub area_light_index: Option<usize>,
+    /// Material that was active at the `ObjectInstance` directive,
+    /// if [LoadOptions::instance_material_override] is set.
+    ///
+    /// Not part of pbrt, where instanced shapes always keep the materials they were defined
+    /// with. Some tools emit a material before `ObjectInstance` to override it, so
+    /// [Scene::render_primitives] prefers this over the shape's material.
+    pub material_override: Option<usize>,
+    /// `ReverseOrientation` setting at the `ObjectInstance` directive.
+    ///
+    /// Combined with the flags of the object's shapes, which keep the setting that was active
+    /// when they were defined, see [Scene::effective_reverse_orientation].
     pub reverse_orientation: bool,
+    /// Start of the motion interval of `instance_to_world`, from `TransformTimes`.
+    pub transform_start_time: f32,
+    /// End of the motion interval of `instance_to_world`, from `TransformTimes`.
+    pub transform_end_time: f32,
+    /// Cached inverse of `instance_to_world`, see [Scene::precompute_inverse_transforms].
+    pub inverse_transform: Option<Mat4>,
+    /// Index of `instance_to_world` in `Scene::transforms`, see [Scene::deduplicate_transforms].
+    pub transform_index: Option<usize>,
 }
 
-#[derive(Default)]
+impl Instance {
+    /// Returns the world to instance transformation.
+    ///
+    /// Uses the cached inverse if available, see [ShapeEntity::world_to_object] for
+    /// precision caveats.
+    pub fn world_to_instance(&self) -> Mat4 {
+        self.inverse_transform
+            .unwrap_or_else(|| self.instance_to_world.inverse())
+    }
+}
+
+/// Scene entities that are declared, but never referenced.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct UnusedReport {
+    /// Indices of materials not assigned to any shape.
+    pub materials: Vec<usize>,
+    /// Indices of textures not referenced by any material.
+    pub textures: Vec<usize>,
+    /// Indices of objects that are never instanced.
+    pub objects: Vec<usize>,
+}
+
+impl UnusedReport {
+    /// Returns `true` when all entities are in use.
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty() && self.textures.is_empty() && self.objects.is_empty()
+    }
+}
+
+/// Dependencies between textures, see [Scene::texture_graph].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TextureGraph {
+    /// For each texture in `Scene::textures`, indices of the textures it references.
+    pub dependencies: Vec<Vec<usize>>,
+}
+
+impl TextureGraph {
+    /// Order textures so that each texture comes after all textures it references.
+    ///
+    /// Fails with [Error::TextureCycle] if textures reference each other in a cycle.
+    pub fn topological_order(&self) -> Result<Vec<usize>> {
+        let count = self.dependencies.len();
+
+        // Number of unresolved dependencies and reverse edges of each texture.
+        let mut pending = vec![0; count];
+        let mut users = vec![Vec::new(); count];
+
+        for (index, dependencies) in self.dependencies.iter().enumerate() {
+            for &dependency in dependencies {
+                pending[index] += 1;
+                users[dependency].push(index);
+            }
+        }
+
+        let mut order = Vec::with_capacity(count);
+        let mut ready = (0..count)
+            .filter(|&index| pending[index] == 0)
+            .collect::<VecDeque<_>>();
+
+        while let Some(index) = ready.pop_front() {
+            order.push(index);
+
+            for &user in &users[index] {
+                pending[user] -= 1;
+                if pending[user] == 0 {
+                    ready.push_back(user);
+                }
+            }
+        }
+
+        match pending.iter().position(|&pending| pending > 0) {
+            Some(index) => Err(Error::TextureCycle(index)),
+            None => Ok(order),
+        }
+    }
+}
+
+/// Differences between two scenes, see [Scene::diff].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SceneDiff {
+    /// Indices of shapes present only in the other scene.
+    pub added_shapes: Vec<usize>,
+    /// Indices of shapes present only in this scene.
+    pub removed_shapes: Vec<usize>,
+    /// Indices of shapes whose type or transform differ.
+    pub changed_shapes: Vec<usize>,
+    /// Indices of materials present only in the other scene.
+    pub added_materials: Vec<usize>,
+    /// Indices of materials present only in this scene.
+    pub removed_materials: Vec<usize>,
+    /// Indices of materials with different parameters.
+    pub changed_materials: Vec<usize>,
+    /// Indices of cameras whose transform differ, or that are present in only one of the scenes.
+    pub changed_cameras: Vec<usize>,
+}
+
+impl SceneDiff {
+    /// Returns `true` if no differences were found.
+    pub fn is_empty(&self) -> bool {
+        self.added_shapes.is_empty()
+            && self.removed_shapes.is_empty()
+            && self.changed_shapes.is_empty()
+            && self.added_materials.is_empty()
+            && self.removed_materials.is_empty()
+            && self.changed_materials.is_empty()
+            && self.changed_cameras.is_empty()
+    }
+}
+
+/// Image tile of a scene, see [Scene::split_into_tiles].
+#[derive(Clone)]
+pub struct SceneTile {
+    /// Scene shared by all tiles.
+    pub scene: Arc<Scene>,
+    /// Film of the scene with its crop window set to the tile.
+    pub film: Film,
+}
+
+impl SceneTile {
+    /// Make a standalone copy of the scene that renders just this tile.
+    ///
+    /// This copies all of the scene's entities, including geometry.
+    pub fn to_scene(&self) -> Scene {
+        Scene {
+            film: Some(self.film.clone()),
+            ..Scene::clone(&self.scene)
+        }
+    }
+}
+
+/// Loaded scene description.
+///
+/// `Scene::default()` produces an empty scene, see [Scene::empty].
+#[derive(Clone)]
 pub struct Scene {
     pub start_time: f32,
     pub end_time: f32,
     pub options: Options,
-    pub camera: Option<CameraEntity>,
+    /// Transformation from the rendering space to world space, selected by the `rendercoordsys` option.
+    ///
+    /// With the default `cameraworld`, the rendering space is world space translated so that
+    /// the camera is at the origin. With `camera` it's the camera space, with `world` the identity.
+    /// Resolved against the first camera, the identity if the scene has no camera.
+    pub world_from_render: Mat4,
+    /// All cameras declared in the scene, in the order of appearance.
+    pub cameras: Vec<CameraEntity>,
     pub film: Option<Film>,
     pub integrator: Option<Integrator>,
     pub pixel_filter: Option<PixelFilter>,
@@ -87,56 +927,1120 @@ pub struct Scene {
     pub sampler: Option<Sampler>,
     pub textures: Vec<Texture>,
     pub materials: Vec<Material>,
-    pub lights: Vec<Light>,
+    pub lights: Vec<LightEntity>,
     pub area_lights: Vec<AreaLight>,
     pub mediums: Vec<Medium>,
     pub shapes: Vec<ShapeEntity>,
     pub objects: Vec<Object>,
     pub instances: Vec<Instance>,
+    /// Distinct transforms of shapes and instances, see [Scene::deduplicate_transforms].
+    pub transforms: Vec<Mat4>,
+    /// Texture name to index in `textures`.
+    pub named_textures: HashMap<String, usize>,
+    /// Material name to index in `materials`.
+    pub named_materials: HashMap<String, usize>,
+    /// Medium name to index in `mediums`.
+    pub named_mediums: HashMap<String, usize>,
+    /// Object name to index in `objects`.
+    pub named_objects: HashMap<String, usize>,
+    /// Non-fatal issues found while loading the scene.
+    pub diagnostics: Vec<String>,
+    /// Bitset of [UNSUPPORTED_FEATURES] encountered while loading.
+    unsupported: u32,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            start_time: 0.0,
+            end_time: 1.0,
+            options: Options::default(),
+            world_from_render: Mat4::IDENTITY,
+            cameras: Vec::new(),
+            film: None,
+            integrator: None,
+            pixel_filter: None,
+            accelerator: None,
+            sampler: None,
+            textures: Vec::new(),
+            materials: Vec::new(),
+            lights: Vec::new(),
+            area_lights: Vec::new(),
+            mediums: Vec::new(),
+            shapes: Vec::new(),
+            objects: Vec::new(),
+            instances: Vec::new(),
+            transforms: Vec::new(),
+            named_textures: HashMap::new(),
+            named_materials: HashMap::new(),
+            named_mediums: HashMap::new(),
+            named_objects: HashMap::new(),
+            diagnostics: Vec::new(),
+            unsupported: 0,
+        }
+    }
+}
+
+/// Cell of [Scene::spatial_buckets] holding shapes without known bounds.
+pub const UNBOUNDED_BUCKET: [u32; 3] = [u32::MAX; 3];
+
+/// Parameter names pbrt-v4 defines for each directive and type.
+///
+/// Entries are `(directive, type, names)`, type is empty for parameters all types of the
+/// directive take, and names are separated by whitespace. Textures are keyed by their class.
+/// Under [LoadOptions::strict_parameters] parameters in this table are accepted even if this
+/// crate doesn't read them, only names pbrt-v4 doesn't define for the type fail.
+const KNOWN_PARAMETERS: &[(&str, &str, &str)] = &[
+    ("Camera", "", "shutteropen shutterclose"),
+    (
+        "Camera",
+        "perspective",
+        "frameaspectratio screenwindow lensradius focaldistance fov",
+    ),
+    (
+        "Camera",
+        "orthographic",
+        "frameaspectratio screenwindow lensradius focaldistance",
+    ),
+    (
+        "Camera",
+        "realistic",
+        "lensfile aperturediameter focusdistance aperture",
+    ),
+    (
+        "Camera",
+        "spherical",
+        "frameaspectratio screenwindow lensradius focaldistance mapping",
+    ),
+    (
+        "Film",
+        "",
+        "xresolution yresolution cropwindow pixelbounds diagonal filename savefp16 iso \
+         exposuretime whitebalance sensor maxcomponentvalue",
+    ),
+    ("Film", "gbuffer", "coordinatesystem"),
+    ("Film", "spectral", "nbuckets lambdamin lambdamax"),
+    ("Integrator", "ambientocclusion", "cossample maxdistance"),
+    (
+        "Integrator",
+        "bdpt",
+        "maxdepth regularize lightsampler visualizestrategies visualizeweights",
+    ),
+    ("Integrator", "lightpath", "maxdepth"),
+    (
+        "Integrator",
+        "mlt",
+        "maxdepth regularize bootstrapsamples chains mutationsperpixel \
+         largestepprobability sigma",
+    ),
+    ("Integrator", "path", "maxdepth regularize lightsampler"),
+    ("Integrator", "randomwalk", "maxdepth"),
+    (
+        "Integrator",
+        "simplepath",
+        "maxdepth samplelights samplebsdf",
+    ),
+    ("Integrator", "simplevolpath", "maxdepth"),
+    (
+        "Integrator",
+        "sppm",
+        "maxdepth photonsperiteration iterations radius seed",
+    ),
+    ("Integrator", "volpath", "maxdepth regularize lightsampler"),
+    ("Accelerator", "bvh", "maxnodeprims splitmethod"),
+    (
+        "Accelerator",
+        "kdtree",
+        "intersectcost traversalcost emptybonus maxprims maxdepth",
+    ),
+    ("PixelFilter", "", "xradius yradius"),
+    ("PixelFilter", "gaussian", "sigma"),
+    ("PixelFilter", "mitchell", "B C"),
+    ("PixelFilter", "sinc", "tau"),
+    ("Sampler", "", "seed"),
+    ("Sampler", "halton", "pixelsamples randomization"),
+    ("Sampler", "independent", "pixelsamples"),
+    ("Sampler", "paddedsobol", "pixelsamples randomization"),
+    ("Sampler", "pmj02bn", "pixelsamples"),
+    ("Sampler", "sobol", "pixelsamples randomization"),
+    ("Sampler", "stratified", "xsamples ysamples jitter"),
+    ("Sampler", "zsobol", "pixelsamples randomization"),
+    ("Texture", "", "scale invert"),
+    (
+        "Texture",
+        "bilerp",
+        "mapping uscale vscale udelta vdelta v1 v2 v00 v01 v10 v11",
+    ),
+    (
+        "Texture",
+        "checkerboard",
+        "mapping uscale vscale udelta vdelta v1 v2 dimension tex1 tex2",
+    ),
+    ("Texture", "constant", "value"),
+    ("Texture", "directionmix", "tex1 tex2 dir"),
+    (
+        "Texture",
+        "dots",
+        "mapping uscale vscale udelta vdelta v1 v2 inside outside",
+    ),
+    ("Texture", "fbm", "octaves roughness"),
+    (
+        "Texture",
+        "imagemap",
+        "mapping uscale vscale udelta vdelta v1 v2 filename filter maxanisotropy wrap \
+         encoding",
+    ),
+    ("Texture", "marble", "octaves roughness variation"),
+    ("Texture", "mix", "tex1 tex2 amount"),
+    ("Texture", "ptex", "filename encoding"),
+    ("Texture", "scale", "tex"),
+    ("Texture", "windy", ""),
+    ("Texture", "wrinkled", "octaves roughness"),
+    ("Material", "", "type displacement normalmap"),
+    (
+        "Material",
+        "coateddiffuse",
+        "reflectance roughness uroughness vroughness remaproughness thickness eta albedo g \
+         maxdepth nsamples",
+    ),
+    (
+        "Material",
+        "coatedconductor",
+        "interface.eta interface.k interface.roughness interface.uroughness \
+         interface.vroughness conductor.eta conductor.k conductor.roughness \
+         conductor.uroughness conductor.vroughness reflectance remaproughness thickness \
+         albedo g maxdepth nsamples",
+    ),
+    (
+        "Material",
+        "conductor",
+        "eta k reflectance roughness uroughness vroughness remaproughness",
+    ),
+    (
+        "Material",
+        "dielectric",
+        "eta roughness uroughness vroughness remaproughness",
+    ),
+    ("Material", "diffuse", "reflectance"),
+    (
+        "Material",
+        "diffusetransmission",
+        "reflectance transmittance scale",
+    ),
+    (
+        "Material",
+        "hair",
+        "sigma_a color eumelanin pheomelanin eta beta_m beta_n alpha",
+    ),
+    ("Material", "interface", ""),
+    ("Material", "measured", "filename"),
+    ("Material", "mix", "materials amount"),
+    (
+        "Material",
+        "subsurface",
+        "name sigma_a sigma_s reflectance mfp scale eta g roughness uroughness vroughness \
+         remaproughness maxdepth nsamples",
+    ),
+    ("Material", "thindielectric", "eta"),
+    ("LightSource", "", "scale"),
+    ("LightSource", "distant", "L from to illuminance"),
+    ("LightSource", "goniometric", "I filename power"),
+    ("LightSource", "infinite", "L filename illuminance portal"),
+    ("LightSource", "point", "I from power"),
+    ("LightSource", "projection", "filename fov power"),
+    (
+        "LightSource",
+        "spot",
+        "I from to coneangle conedeltaangle power",
+    ),
+    (
+        "AreaLightSource",
+        "diffuse",
+        "L twosided filename scale power",
+    ),
+    ("Shape", "", "alpha"),
+    ("Shape", "sphere", "radius zmin zmax phimax"),
+    ("Shape", "cylinder", "radius zmin zmax phimax"),
+    ("Shape", "disk", "height radius innerradius phimax"),
+    (
+        "Shape",
+        "bilinearmesh",
+        "P N uv indices faceIndices emissionfilename",
+    ),
+    (
+        "Shape",
+        "curve",
+        "P N basis degree type width width0 width1 splitdepth",
+    ),
+    (
+        "Shape",
+        "trianglemesh",
+        "P N S uv indices faceIndices edgelength",
+    ),
+    ("Shape", "plymesh", "filename displacement edgelength"),
+    ("Shape", "loopsubdiv", "P indices levels edgelength"),
+];
+
+/// Features that are parsed, but not fully modeled by this crate.
+///
+/// Entries are `(directive, type, feature name)`, type is empty for directives without one.
+const UNSUPPORTED_FEATURES: &[(&str, &str, &str)] = &[
+    ("Import", "", "Import"),
+    ("Texture", "directionmix", "directionmix texture"),
+    ("Texture", "fbm", "fbm texture"),
+    ("Texture", "marble", "marble texture"),
+    ("Texture", "windy", "windy texture"),
+    ("Texture", "wrinkled", "wrinkled texture"),
+    ("Material", "coatedconductor", "coatedconductor material"),
+    ("Material", "hair", "hair material"),
+    ("Material", "measured", "measured material"),
+    ("Material", "mix", "mix material"),
+    ("Material", "subsurface", "subsurface material"),
+    ("Material", "thindielectric", "thindielectric material"),
+    ("MakeNamedMedium", "cloud", "cloud medium"),
+    ("MakeNamedMedium", "homogeneous", "homogeneous medium"),
+    ("MakeNamedMedium", "nanovdb", "nanovdb medium"),
+    ("MakeNamedMedium", "rgbgrid", "rgbgrid medium"),
+    ("MakeNamedMedium", "uniformgrid", "uniformgrid medium"),
+];
+
+/// Function used to rewrite file paths, see [LoadOptions::rewrite_path].
+pub type RewritePathFn = Box<dyn Fn(&Path) -> PathBuf + Send + Sync>;
+
+/// Selects a parameter of a directive to override, see [LoadOptions::overrides].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    /// Directive name, for example `Sampler` or `Integrator`.
+    pub directive: String,
+    /// Parameter name, for example `pixelsamples`.
+    pub name: String,
+}
+
+impl Selector {
+    pub fn new(directive: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            directive: directive.into(),
+            name: name.into(),
+        }
+    }
+}
+
+/// Directory that relative includes are resolved against when a scene is loaded without
+/// a working directory, see [LoadOptions::include_base].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum IncludeBase {
+    /// Fail with [Error::RelativeInclude].
+    #[default]
+    Error,
+    /// Use the current directory of the process.
+    CurrentDir,
+    /// Use the given directory, it's also used to resolve other relative file names.
+    Path(PathBuf),
+}
+
+/// Options to customize scene loading.
+pub struct LoadOptions {
+    /// Hook applied to every external file name referenced by the scene (image and ptex
+    /// textures, PLY meshes) after it has been resolved against the working directory.
+    ///
+    /// Can be used to swap file extensions or redirect directories without editing scene files.
+    pub rewrite_path: Option<RewritePathFn>,
+    /// Skip unrecognized directives instead of failing.
+    /// A diagnostic is recorded in [Scene::diagnostics] for each skipped directive.
+    pub lenient: bool,
+    /// Parameter values to use instead of the ones specified in the scene files.
+    ///
+    /// Overrides are applied to every matching directive before the corresponding entity is constructed,
+    /// parameters missing in the scene file are added.
+    pub overrides: Vec<(Selector, ParamValue)>,
+    /// Maximum number of values in an array parameter.
+    ///
+    /// Guards against malicious scenes declaring huge arrays, loading fails with
+    /// [Error::ArrayTooLarge] when exceeded.
+    pub max_array_length: usize,
+    /// Scene file format, pbrt-v4 by default.
+    pub version: PbrtVersion,
+    /// Convert `bilinearmesh` and `loopsubdiv` shapes to [Shape::TriangleMesh].
+    ///
+    /// These shapes fail to load when disabled.
+    pub tessellate: bool,
+    /// How to resolve relative includes when no working directory is given.
+    pub include_base: IncludeBase,
+    /// Fail with [Error::UnknownParameter] if a directive has parameters that are neither
+    /// read when constructing the entity nor defined by pbrt-v4, for example misspelled names.
+    /// Unknown options are rejected as well instead of being stored in `Options::custom`.
+    ///
+    /// Media are not modeled yet, their parameters are not checked.
+    pub strict_parameters: bool,
+    /// Record the material active at `ObjectInstance` in [Instance::material_override].
+    ///
+    /// Off by default, since pbrt ignores it and instanced shapes keep their own materials.
+    pub instance_material_override: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            rewrite_path: None,
+            lenient: false,
+            overrides: Vec::new(),
+            max_array_length: Self::DEFAULT_MAX_ARRAY_LENGTH,
+            version: PbrtVersion::V4,
+            tessellate: false,
+            include_base: IncludeBase::default(),
+            strict_parameters: false,
+            instance_material_override: false,
+        }
+    }
+}
+
+impl LoadOptions {
+    /// Default value of [LoadOptions::max_array_length].
+    pub const DEFAULT_MAX_ARRAY_LENGTH: usize = 100_000_000;
+
+    /// Construct an entity of type `ty` from `params` with `new`,
+    /// checking for unknown parameters if [LoadOptions::strict_parameters] is set.
+    fn construct<'a, T>(
+        &self,
+        directive: &str,
+        ty: &str,
+        mut params: ParamList<'a>,
+        new: impl FnOnce(ParamList<'a>) -> Result<T>,
+    ) -> Result<T> {
+        if !self.strict_parameters {
+            return new(params);
+        }
+
+        let usage = params.track_usage();
+        let entity = new(params)?;
+
+        // Named materials take the same parameters as materials.
+        let table_directive = match directive {
+            "MakeNamedMaterial" => "Material",
+            directive => directive,
+        };
+        let is_known = |key: &str| {
+            KNOWN_PARAMETERS
+                .iter()
+                .filter(|(name, table_ty, _)| {
+                    *name == table_directive && (table_ty.is_empty() || *table_ty == ty)
+                })
+                .any(|(_, _, known)| known.split_whitespace().any(|name| name == key))
+        };
+
+        match usage.unused().into_iter().find(|key| !is_known(key)) {
+            Some(key) => Err(Error::UnknownParameter {
+                directive: directive.to_string(),
+                key,
+            }),
+            None => Ok(entity),
+        }
+    }
+
+    /// Resolve a file name relative to `working_directory` and apply the rewrite hook.
+    fn resolve_path(&self, filename: &str, working_directory: Option<&Path>) -> PathBuf {
+        let path = match working_directory {
+            Some(directory) => directory.join(filename),
+            None => PathBuf::from(filename),
+        };
+
+        match &self.rewrite_path {
+            Some(rewrite) => rewrite(&path),
+            None => path,
+        }
+    }
 }
 
 impl Scene {
+    /// Tolerance used by [Scene::diff] to compare transforms.
+    pub const DIFF_EPSILON: f32 = 1e-5;
+
+    /// Determinant magnitude, relative to the product of the lengths of the transformed axes,
+    /// below which [Scene::validate] considers a transform singular.
+    ///
+    /// The ratio doesn't depend on the overall scale, it is 1 for rotations and scales
+    /// and goes to 0 as the axes collapse onto a plane or a line.
+    pub const DEGENERATE_EPSILON: f32 = 1e-6;
+
     /// Load a scene from a file at path.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Scene> {
+        Self::from_file_with_options(path, &LoadOptions::default())
+    }
+
+    /// Load a scene from a file at path with custom load options.
+    pub fn from_file_with_options<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<Scene> {
         let path = path.as_ref();
 
         let working_directory = path.parent();
 
         let data = fs::read_to_string(path)?;
-        Self::load(&data, working_directory)
+        Self::load_with_options(&data, working_directory, options)
     }
 
-    /// Load a PBRT v4 scene from a string slice.
+    /// Load a scene from a file at path, unless its [Scene::content_hash] equals `previous_hash`.
     ///
-    /// # Arguments
-    /// - `data` is a string buffer with the file data.
-    /// - `working_directory` is a file's directory path which required for includes
-    /// with relative paths to work.
-    pub fn load(data: &str, working_directory: Option<&Path>) -> Result<Scene> {
-        let mut scene = Scene::default();
+    /// Returns `None` when the loaded scene is identical to the previous one,
+    /// so callers can skip rebuilding their render data.
+    pub fn from_file_if_changed<P: AsRef<Path>>(
+        path: P,
+        previous_hash: u64,
+    ) -> Result<Option<Scene>> {
+        let scene = Self::from_file(path)?;
 
-        let mut parsers = Vec::new();
-        parsers.push(Parser::new(data));
+        if scene.content_hash() == previous_hash {
+            return Ok(None);
+        }
 
-        let mut current_state = State::default();
-        let mut states_stack = Vec::new();
-        let mut is_world_block = false;
+        Ok(Some(scene))
+    }
 
-        let mut named_coord_systems: HashMap<String, Mat4> = HashMap::default();
+    /// Load multiple scene files and merge them into one scene, see [Scene::merge].
+    ///
+    /// Each file is loaded with its own directory as the working directory.
+    pub fn from_manifest(paths: &[PathBuf]) -> Result<Scene> {
+        let mut paths = paths.iter();
+
+        // Scene wide options of the first file are kept.
+        let mut scene = match paths.next() {
+            Some(path) => Scene::from_file(path)?,
+            None => return Ok(Scene::empty()),
+        };
+
+        for path in paths {
+            scene.merge(Scene::from_file(path)?);
+        }
+
+        Ok(scene)
+    }
+
+    /// Compute and cache the inverse transforms of all shapes and instances.
+    ///
+    /// The cache is used by [ShapeEntity::world_to_object] and [Instance::world_to_instance].
+    /// It's cleared by [Scene::apply_transform], but must be refreshed manually when
+    /// transforms are modified directly.
+    pub fn precompute_inverse_transforms(&mut self) {
+        for shape in &mut self.shapes {
+            shape.inverse_transform = Some(shape.transform.inverse());
+        }
+
+        for instance in &mut self.instances {
+            instance.inverse_transform = Some(instance.instance_to_world.inverse());
+        }
+    }
+
+    /// Bake a world-from-scene transform `m` into all world space transforms of the scene.
+    ///
+    /// Shapes, lights, cameras and instances are transformed. Shapes that are part of
+    /// objects are left alone as they are positioned by their instances.
+    pub fn apply_transform(&mut self, m: Mat4) {
+        let is_object_shape = self.object_shape_mask();
+
+        for (shape, is_object_shape) in self.shapes.iter_mut().zip(is_object_shape) {
+            if !is_object_shape {
+                shape.transform = m * shape.transform;
+                shape.inverse_transform = None;
+            }
+
+            shape.transform_index = None;
+        }
+
+        for light in &mut self.lights {
+            light.transform = m * light.transform;
+        }
+
+        for camera in &mut self.cameras {
+            camera.transform = m * camera.transform;
+        }
+
+        for instance in &mut self.instances {
+            instance.instance_to_world = m * instance.instance_to_world;
+            instance.inverse_transform = None;
+            instance.transform_index = None;
+        }
+
+        for texture in &mut self.textures {
+            texture.transform = texture.transform.map(|transform| m * transform);
+        }
+
+        self.transforms.clear();
+    }
+
+    /// Collect the distinct transforms of shapes and instances into [Scene::transforms].
+    ///
+    /// Bitwise identical matrices share one entry, referenced by the `transform_index` of
+    /// shapes and instances. This only builds a lookup table: the `transform` fields are left
+    /// in place, so the scene uses more memory, not less.
+    ///
+    /// The table is a snapshot. [Scene::apply_transform] clears it, but other changes to
+    /// the transforms of shapes or instances leave the indices stale, call this again after them.
+    pub fn deduplicate_transforms(&mut self) {
+        let mut interned = HashMap::new();
+        let mut transforms = Vec::new();
+
+        let mut intern = |transform: &Mat4| {
+            let key = transform.to_cols_array().map(f32::to_bits);
+            *interned.entry(key).or_insert_with(|| {
+                transforms.push(*transform);
+                transforms.len() - 1
+            })
+        };
+
+        for shape in &mut self.shapes {
+            shape.transform_index = Some(intern(&shape.transform));
+        }
+
+        for instance in &mut self.instances {
+            instance.transform_index = Some(intern(&instance.instance_to_world));
+        }
+
+        self.transforms = transforms;
+    }
+
+    /// Remove objects without shapes, along with their instances.
+    ///
+    /// Exporters sometimes emit empty `ObjectBegin`/`ObjectEnd` blocks, instancing them renders
+    /// nothing. `Instance::object_index` and [Scene::named_objects] are remapped to the
+    /// remaining objects.
+    pub fn prune_empty_objects(&mut self) {
+        let mut remap = Vec::with_capacity(self.objects.len());
+        let mut next = 0;
+        for object in &self.objects {
+            if object.shape_count == 0 {
+                remap.push(None);
+            } else {
+                remap.push(Some(next));
+                next += 1;
+            }
+        }
+
+        self.objects.retain(|object| object.shape_count != 0);
+
+        self.instances.retain_mut(|instance| {
+            match remap.get(instance.object_index).copied().flatten() {
+                Some(index) => {
+                    instance.object_index = index;
+                    true
+                }
+                None => false,
+            }
+        });
+
+        self.named_objects
+            .retain(|_, index| match remap.get(*index).copied().flatten() {
+                Some(new_index) => {
+                    *index = new_index;
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// Append all entities of `other` to this scene.
+    ///
+    /// Indices referencing other entities are adjusted. Named entities of `other` replace
+    /// entities with the same name in this scene. Global settings (film, camera parameters,
+    /// sampler, etc) are taken from `other` only if not set in this scene.
+    pub fn merge(&mut self, other: Scene) {
+        let texture_offset = self.textures.len();
+        let material_offset = self.materials.len();
+        let area_light_offset = self.area_lights.len();
+        let medium_offset = self.mediums.len();
+        let shape_offset = self.shapes.len();
+        let object_offset = self.objects.len();
+        let transform_offset = self.transforms.len();
+
+        if self.film.is_none() {
+            self.film = other.film;
+        }
+        if self.integrator.is_none() {
+            self.integrator = other.integrator;
+        }
+        if self.pixel_filter.is_none() {
+            self.pixel_filter = other.pixel_filter;
+        }
+        if self.accelerator.is_none() {
+            self.accelerator = other.accelerator;
+        }
+        if self.sampler.is_none() {
+            self.sampler = other.sampler;
+        }
+
+        self.cameras.extend(other.cameras);
+        self.lights.extend(other.lights);
+        self.textures
+            .extend(other.textures.into_iter().map(|mut texture| {
+                texture.map_texture_references(|index| index + texture_offset);
+                texture
+            }));
+        self.area_lights.extend(other.area_lights);
+        self.mediums.extend(other.mediums);
+
+        self.materials
+            .extend(other.materials.into_iter().map(|mut material| {
+                material.map_texture_references(|index| index + texture_offset);
+                material
+            }));
+
+        self.shapes
+            .extend(other.shapes.into_iter().map(|mut shape| {
+                shape.material_index = shape.material_index.map(|index| index + material_offset);
+                shape.area_light_index = shape
+                    .area_light_index
+                    .map(|index| index + area_light_offset);
+                if let Alpha::Texture(index) = &mut shape.alpha {
+                    *index += texture_offset;
+                }
+                shape.transform_index = shape.transform_index.map(|index| index + transform_offset);
+                shape
+            }));
+
+        self.objects
+            .extend(other.objects.into_iter().map(|mut object| {
+                object.shape_start = object.shape_start.map(|index| index + shape_offset);
+                object
+            }));
+
+        self.instances
+            .extend(other.instances.into_iter().map(|mut instance| {
+                instance.object_index += object_offset;
+                instance.area_light_index = instance
+                    .area_light_index
+                    .map(|index| index + area_light_offset);
+                instance.material_override = instance
+                    .material_override
+                    .map(|index| index + material_offset);
+                instance.transform_index = instance
+                    .transform_index
+                    .map(|index| index + transform_offset);
+                instance
+            }));
+        self.transforms.extend(other.transforms);
+
+        let named = [
+            (
+                &mut self.named_textures,
+                other.named_textures,
+                texture_offset,
+            ),
+            (
+                &mut self.named_materials,
+                other.named_materials,
+                material_offset,
+            ),
+            (&mut self.named_mediums, other.named_mediums, medium_offset),
+            (&mut self.named_objects, other.named_objects, object_offset),
+        ];
+
+        for (names, other_names, offset) in named {
+            names.extend(
+                other_names
+                    .into_iter()
+                    .map(|(name, index)| (name, index + offset)),
+            );
+        }
+
+        self.diagnostics.extend(other.diagnostics);
+        self.unsupported |= other.unsupported;
+    }
+
+    /// Load a scene from the `entry` of a zip archive at path.
+    #[cfg(feature = "archive")]
+    pub fn from_zip<P: AsRef<Path>>(path: P, entry: &str) -> Result<Scene> {
+        let file = fs::File::open(path)?;
+        Self::from_zip_reader(std::io::BufReader::new(file), entry)
+    }
+
+    /// Load a scene from the `entry` of a zip archive read from `reader`.
+    ///
+    /// The entry's directory inside the archive is used as the working directory,
+    /// so included files and textures are resolved against other entries of the archive.
+    #[cfg(feature = "archive")]
+    pub fn from_zip_reader<R: std::io::Read + std::io::Seek>(
+        reader: R,
+        entry: &str,
+    ) -> Result<Scene> {
+        let source = crate::source::ZipAssetSource::new(reader)?;
+        Self::load_with_source(&source, Path::new(entry), &LoadOptions::default())
+    }
+
+    /// Load the scene file at `path`, reading it and all included files from `source`.
+    ///
+    /// Relative include paths are resolved against the directory of `path`,
+    /// see [crate::FsAssetSource] for reading from the file system.
+    pub fn load_with_source(
+        source: &dyn AssetSource,
+        path: &Path,
+        options: &LoadOptions,
+    ) -> Result<Scene> {
+        let data = read_string(source, path)?;
+
+        let mut read_include = |path: &Path| -> Result<IncludeData> {
+            Ok(IncludeData::Heap(read_string(source, path)?))
+        };
+
+        Self::load_with_includes(
+            &data,
+            path.parent(),
+            options,
+            &mut read_include,
+            LoadHooks::default(),
+        )
+    }
+
+    /// Create a scene without any entities, as loaded from an empty file.
+    ///
+    /// Transform times default to `[0, 1]`, like in pbrt. Same as `Scene::default()`.
+    pub fn empty() -> Scene {
+        Scene::default()
+    }
+
+    /// Returns `true` if the scene has no shapes, lights and cameras.
+    ///
+    /// Such a scene can't produce an image, which usually means loading failed silently
+    /// or only the header was loaded, see [Scene::load_header_only].
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty() && self.lights.is_empty() && self.cameras.is_empty()
+    }
+
+    /// Returns the pixel filter, or pbrt's default gaussian filter (radius 1.5, sigma 0.5)
+    /// if the scene has no `PixelFilter` directive.
+    pub fn pixel_filter_or_default(&self) -> PixelFilter {
+        self.pixel_filter.clone().unwrap_or_default()
+    }
+
+    /// Returns the sampler, or pbrt's default `zsobol` sampler with 16 samples per pixel
+    /// if the scene has no `Sampler` directive.
+    pub fn sampler_or_default(&self) -> Sampler {
+        self.sampler.clone().unwrap_or_default()
+    }
+
+    /// Returns the integrator, or pbrt's default `volpath` integrator
+    /// if the scene has no `Integrator` directive.
+    pub fn integrator_or_default(&self) -> Integrator {
+        self.integrator.clone().unwrap_or_default()
+    }
+
+    /// Returns the accelerator, or pbrt's default `bvh` accelerator
+    /// if the scene has no `Accelerator` directive.
+    pub fn accelerator_or_default(&self) -> Accelerator {
+        self.accelerator.clone().unwrap_or_default()
+    }
+
+    /// Number of samples the integrator takes per pixel.
+    ///
+    /// The `mlt` integrator generates its samples by path mutations and ignores the sampler,
+    /// so its `mutationsperpixel` is returned. All other integrators, including `sppm` which runs
+    /// one iteration per pixel sample, use the count of the sampler (or pbrt's default sampler).
+    pub fn effective_samples_per_pixel(&self) -> u32 {
+        let samples = match &self.integrator {
+            Some(Integrator::Mlt {
+                mutations_per_pixel,
+                ..
+            }) => *mutations_per_pixel,
+            _ => self.sampler_or_default().pixel_samples(),
+        };
+
+        samples.max(0) as u32
+    }
+
+    /// Load a PBRT v4 scene from a string slice.
+    ///
+    /// # Arguments
+    /// - `data` is a string buffer with the file data.
+    /// - `working_directory` is a file's directory path which required for includes
+    /// with relative paths to work.
+    pub fn load(data: &str, working_directory: Option<&Path>) -> Result<Scene> {
+        Self::load_with_options(data, working_directory, &LoadOptions::default())
+    }
+
+    /// Load a PBRT v4 scene from a string slice with custom load options.
+    ///
+    /// See [Scene::load] for arguments description.
+    pub fn load_with_options(
+        data: &str,
+        working_directory: Option<&Path>,
+        options: &LoadOptions,
+    ) -> Result<Scene> {
+        Self::load_with_includes(
+            data,
+            working_directory,
+            options,
+            &mut IncludeData::read,
+            LoadHooks::default(),
+        )
+    }
+
+    /// Load only the scene-wide configuration that precedes `WorldBegin`.
+    ///
+    /// Options, camera, film, sampler, integrator, accelerator, pixel filter and
+    /// transform times are captured, everything in the world block is skipped,
+    /// so the returned scene has no shapes, lights, materials or textures.
+    pub fn load_header_only(data: &str, working_directory: Option<&Path>) -> Result<Scene> {
+        Self::load_with_includes(
+            data,
+            working_directory,
+            &LoadOptions::default(),
+            &mut IncludeData::read,
+            LoadHooks {
+                header_only: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Load a scene that can be cancelled from another thread.
+    ///
+    /// `cancel` is checked periodically while parsing, once it's set loading stops and
+    /// [Error::Cancelled] is returned. See [Scene::load] for the other arguments.
+    pub fn load_cancellable(
+        data: &str,
+        working_directory: Option<&Path>,
+        cancel: &AtomicBool,
+    ) -> Result<Scene> {
+        Self::load_with_includes(
+            data,
+            working_directory,
+            &LoadOptions::default(),
+            &mut IncludeData::read,
+            LoadHooks {
+                cancel: Some(cancel),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Load only the object named `object_name` and its instances, for previewing a single asset.
+    ///
+    /// The full scene is parsed, the returned scene keeps the options, render settings and cameras,
+    /// the object's shapes, its instances and the materials, textures and area lights they use.
+    /// Lights and all other shapes, objects, materials and textures are left out.
+    /// Returns [Error::NotFound] if there is no object with that name.
+    pub fn load_object(
+        data: &str,
+        working_directory: Option<&Path>,
+        object_name: &str,
+    ) -> Result<Scene> {
+        let mut scene = Self::load(data, working_directory)?;
+
+        let object_index = *scene
+            .named_objects
+            .get(object_name)
+            .ok_or(Error::NotFound)?;
+        let mut object = scene.objects.swap_remove(object_index);
+
+        let mut shapes = match object.shape_start {
+            Some(start) => scene
+                .shapes
+                .drain(start..start + object.shape_count)
+                .collect(),
+            None => Vec::new(),
+        };
+        object.shape_start = object.shape_start.map(|_| 0);
+
+        let mut instances = mem::take(&mut scene.instances);
+        instances.retain(|instance| instance.object_index == object_index);
+
+        let mut used_materials = vec![false; scene.materials.len()];
+        let mut used_area_lights = vec![false; scene.area_lights.len()];
+
+        let references = shapes
+            .iter()
+            .map(|shape| (shape.material_index, shape.area_light_index))
+            .chain(
+                instances
+                    .iter()
+                    .map(|instance| (instance.material_override, instance.area_light_index)),
+            );
+        for (material_index, area_light_index) in references {
+            if let Some(index) = material_index {
+                used_materials[index] = true;
+            }
+            if let Some(index) = area_light_index {
+                used_area_lights[index] = true;
+            }
+        }
+        let used_textures =
+            used_textures(&scene.textures, &scene.materials, &used_materials, &shapes);
 
-        // Texture name to index.
-        let mut named_textures: HashMap<String, usize> = HashMap::default();
-        let mut named_materials: HashMap<String, usize> = HashMap::default();
-        let mut named_mediums: HashMap<String, usize> = HashMap::default();
-        let mut named_objects: HashMap<String, usize> = HashMap::default();
+        let material_indices = retain_used(&mut scene.materials, &used_materials);
+        let area_light_indices = retain_used(&mut scene.area_lights, &used_area_lights);
+        let texture_indices = retain_used(&mut scene.textures, &used_textures);
+
+        let texture_index = |index: usize| texture_indices[index].unwrap_or_default();
+        for texture in &mut scene.textures {
+            texture.map_texture_references(texture_index);
+        }
+        for material in &mut scene.materials {
+            material.map_texture_references(texture_index);
+        }
+
+        for shape in &mut shapes {
+            shape.material_index = shape.material_index.and_then(|i| material_indices[i]);
+            shape.area_light_index = shape.area_light_index.and_then(|i| area_light_indices[i]);
+            if let Alpha::Texture(index) = &mut shape.alpha {
+                *index = texture_index(*index);
+            }
+        }
+        for instance in &mut instances {
+            instance.object_index = 0;
+            instance.material_override =
+                instance.material_override.and_then(|i| material_indices[i]);
+            instance.area_light_index = instance
+                .area_light_index
+                .and_then(|i| area_light_indices[i]);
+        }
+
+        let rename = |names: &mut HashMap<String, usize>, indices: &[Option<usize>]| {
+            names.retain(|_, index| match indices[*index] {
+                Some(new_index) => {
+                    *index = new_index;
+                    true
+                }
+                None => false,
+            });
+        };
+        rename(&mut scene.named_materials, &material_indices);
+        rename(&mut scene.named_textures, &texture_indices);
+
+        scene.named_objects = HashMap::from([(object.name.clone(), 0)]);
+        scene.objects = vec![object];
+        scene.shapes = shapes;
+        scene.instances = instances;
+        scene.lights.clear();
+
+        Ok(scene)
+    }
+
+    /// Load a scene and record the transform directives applied to each shape.
+    ///
+    /// This is a debugging aid, returns the scene along with one list of [TransformOp] per shape,
+    /// in the order the directives were applied since the CTM was last reset.
+    pub fn load_with_transform_trace(
+        data: &str,
+        working_directory: Option<&Path>,
+    ) -> Result<(Scene, Vec<Vec<TransformOp>>)> {
+        let mut traces = Vec::new();
+
+        let scene = Self::load_with_includes(
+            data,
+            working_directory,
+            &LoadOptions::default(),
+            &mut IncludeData::read,
+            LoadHooks {
+                traces: Some(&mut traces),
+                ..Default::default()
+            },
+        )?;
+
+        Ok((scene, traces))
+    }
+
+    /// Load a scene from a file at path without blocking the async executor.
+    ///
+    /// See [Scene::from_file_async_with_options].
+    #[cfg(feature = "tokio")]
+    pub async fn from_file_async<P: AsRef<Path>>(path: P) -> Result<Scene> {
+        Self::from_file_async_with_options(path, LoadOptions::default()).await
+    }
+
+    /// Load a scene from a file at path with custom load options without blocking
+    /// the async executor.
+    ///
+    /// The scene is parsed on a blocking task, the root file and all included files are read
+    /// with [tokio::fs]. Returns [Error::Cancelled] if the runtime shuts down before loading
+    /// finished.
+    #[cfg(feature = "tokio")]
+    pub async fn from_file_async_with_options<P: AsRef<Path>>(
+        path: P,
+        options: LoadOptions,
+    ) -> Result<Scene> {
+        let path = path.as_ref();
+        let working_directory = path.parent().map(Path::to_path_buf);
+
+        let data = tokio::fs::read_to_string(path).await?;
+        let runtime = tokio::runtime::Handle::current();
+
+        let task = tokio::task::spawn_blocking(move || {
+            // Includes are read when the parser reaches them, blocking this task only.
+            let mut read_include = |path: &Path| -> Result<IncludeData> {
+                let data = runtime.block_on(tokio::fs::read_to_string(path))?;
+                Ok(IncludeData::Heap(data))
+            };
+
+            Self::load_with_includes(
+                &data,
+                working_directory.as_deref(),
+                &options,
+                &mut read_include,
+                LoadHooks::default(),
+            )
+        });
+
+        match task.await {
+            Ok(result) => result,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(_) => Err(Error::Cancelled),
+        }
+    }
+
+    /// Load a scene, reading included files with `read_include`.
+    pub(crate) fn load_with_includes(
+        data: &str,
+        working_directory: Option<&Path>,
+        options: &LoadOptions,
+        read_include: &mut dyn FnMut(&Path) -> Result<IncludeData>,
+        mut hooks: LoadHooks,
+    ) -> Result<Scene> {
+        let mut scene = Scene::empty();
+
+        let working_directory = match (working_directory, &options.include_base) {
+            (None, IncludeBase::Path(base)) => Some(base.as_path()),
+            (working_directory, _) => working_directory,
+        };
+
+        let resolve_path = |filename: &str| options.resolve_path(filename, working_directory);
 
         // Because data from included files might end up in cached parameters,
         // we should keep the file data around until scene loading is done.
-        let mut includes = Vec::new();
+        // Declared before the parsers, so it's dropped after them.
+        let mut includes: Vec<IncludeData> = Vec::new();
+
+        // The top-level file has id 0, included files are numbered in the order they're reached.
+        let new_parser = |data, file_id| {
+            let mut parser = Parser::new(data);
+            parser.set_lenient(options.lenient);
+            parser.set_max_array_length(options.max_array_length);
+            parser.set_version(options.version);
+            parser.set_file_id(file_id);
+            parser
+        };
+
+        let mut parsers = Vec::new();
+        parsers.push(new_parser(data, 0));
+
+        let mut current_state = State::default();
+        let mut states_stack = Vec::new();
+        let mut transforms_stack = Vec::new();
+        let mut is_world_block = false;
+
+        let mut named_coord_systems: HashMap<String, Mat4> = HashMap::default();
+
+        // Number of shapes in the scene, including skipped ones.
+        let mut shape_total = 0;
+
+        // Number of parsed elements, to poll the cancel flag every so often.
+        let mut element_count = 0_usize;
 
         while let Some(parser) = parsers.last_mut() {
+            if let Some(cancel) = hooks.cancel {
+                if element_count % CANCEL_CHECK_INTERVAL == 0 && cancel.load(Ordering::Relaxed) {
+                    return Err(Error::Cancelled);
+                }
+                element_count += 1;
+            }
+
             // Fetch next element.
-            let element = match parser.parse_next() {
+            let mut element = match parser.parse_next() {
                 Ok(element) => element,
                 Err(err) if matches!(err, Error::EndOfFile) => {
                     // Remove parser from the stack.
@@ -147,21 +2051,68 @@ impl Scene {
             };
             // eprintln!("parse element: {element:?}");
 
+            // Apply user provided parameter overrides.
+            if let Some((directive, params)) = element_params(&mut element) {
+                for (selector, value) in &options.overrides {
+                    if selector.directive == directive {
+                        params.set(value.to_param(&selector.name));
+                    }
+                }
+
+                if let Some(color_space) = current_state.color_space {
+                    params.set_color_space(color_space);
+                }
+            }
+
+            if !is_world_block {
+                if let Some(directive) = world_only_directive(&element) {
+                    return Err(Error::ElementBeforeWorldBegin(directive));
+                }
+            }
+
+            if hooks.traces.is_some() {
+                if let Some(op) = TransformOp::from_element(&element) {
+                    if op.resets() {
+                        current_state.transform_trace.clear();
+                    }
+                    current_state.transform_trace.push(op);
+                } else if matches!(element, Element::WorldBegin) {
+                    current_state.transform_trace.clear();
+                }
+            }
+
             match element {
                 Element::AttributeBegin => {
                     states_stack.push(current_state.clone());
                 }
                 Element::AttributeEnd => match states_stack.pop() {
-                    Some(state) => current_state = state,
+                    // Shapes of the object being defined are counted across attribute blocks.
+                    Some(state) => {
+                        current_state = State {
+                            shape_count: current_state.shape_count,
+                            ..state
+                        }
+                    }
                     None => return Err(Error::TooManyEndAttributes),
                 },
+                // pbrt-v3 transform blocks only save and restore the CTM.
+                Element::TransformBegin => {
+                    transforms_stack.push(current_state.transform_matrix);
+                }
+                Element::TransformEnd => match transforms_stack.pop() {
+                    Some(transform) => current_state.transform_matrix = transform,
+                    None => return Err(Error::TooManyEndTransforms),
+                },
                 Element::Attribute { target, params } => match target {
                     "shape" => current_state.shape_params.extend(&params),
                     "light" => current_state.light_params.extend(&params),
                     "material" => current_state.material_params.extend(&params),
                     "medium" => current_state.medium_params.extend(&params),
                     "texture" => current_state.texture_params.extend(&params),
-                    _ => unimplemented!(),
+                    _ if options.lenient => scene
+                        .diagnostics
+                        .push(format!("Skipped attribute with unknown target: {target}")),
+                    _ => return Err(Error::UnknownAttributeTarget(target.to_string())),
                 },
                 Element::ReverseOrientation => {
                     current_state.reverse_orientation = !current_state.reverse_orientation;
@@ -173,6 +2124,7 @@ impl Scene {
                     current_state.transform_matrix = Mat4::IDENTITY;
                 }
                 // Transform resets the CTM to the specified matrix.
+                // Matrices are given in column-major order, see `Element::Transform`.
                 Element::Transform { m } => {
                     current_state.transform_matrix = Mat4::from_cols_array(&m);
                 }
@@ -184,7 +2136,8 @@ impl Scene {
                     current_state.transform_matrix *= Mat4::from_scale(Vec3::from(v));
                 }
                 Element::Rotate { angle, v } => {
-                    current_state.transform_matrix *= Mat4::from_axis_angle(Vec3::from(v), angle);
+                    current_state.transform_matrix *=
+                        Mat4::from_axis_angle(Vec3::from(v).normalize(), angle.to_radians());
                 }
                 Element::LookAt { eye, look_at, up } => {
                     current_state.transform_matrix *=
@@ -196,7 +2149,7 @@ impl Scene {
                 }
                 // The CTM can later be reset to the recorded transformation using CoordSysTransform.
                 Element::CoordSysTransform { name } => {
-                    match named_coord_systems.get(name).copied() {
+                    match named_coord_systems.get(name.as_ref()).copied() {
                         Some(mat) => current_state.transform_matrix = mat,
                         None => {
                             // TODO: Material not found, return error.
@@ -216,40 +2169,75 @@ impl Scene {
                     // TODO: Fix key
                     named_coord_systems.insert("camera".to_string(), world_from_camera);
 
-                    let camera = Camera::new(ty, params)?;
+                    let camera = options
+                        .construct("Camera", ty, params, |params| Camera::new(ty, params))?;
 
                     let entity = CameraEntity {
                         params: camera,
                         transform: world_from_camera,
+                        parsed_camera_from_world: camera_from_world,
+                        medium: current_state
+                            .current_outside_medium
+                            .as_deref()
+                            .map(str::to_string),
+                        transform_start_time: scene.start_time,
+                        transform_end_time: scene.end_time,
                     };
 
-                    scene.camera = Some(entity);
+                    scene.cameras.push(entity);
                 }
                 Element::Film { ty, params } => {
                     debug_assert!(scene.film.is_none());
-                    let film = Film::new(ty, params)?;
+                    let film =
+                        options.construct("Film", ty, params, |params| Film::new(ty, params))?;
                     scene.film = Some(film);
                 }
                 Element::Integrator { ty, params } => {
                     debug_assert!(scene.integrator.is_none());
-                    let integrator = Integrator::new(ty, params)?;
+                    let integrator = options.construct("Integrator", ty, params, |params| {
+                        Integrator::new(ty, params)
+                    })?;
+
+                    if integrator.is_high_variance() {
+                        scene.diagnostics.push(format!(
+                            "Integrator {ty} has both samplelights and samplebsdf disabled, \
+                             uniform sampling is used and the image will be very noisy"
+                        ));
+                    }
+
                     scene.integrator = Some(integrator);
                 }
                 Element::Accelerator { ty, params } => {
                     debug_assert!(scene.accelerator.is_none());
-                    let accelerator = Accelerator::new(ty, params)?;
+                    let accelerator = options.construct("Accelerator", ty, params, |params| {
+                        Accelerator::new(ty, params)
+                    })?;
                     scene.accelerator = Some(accelerator);
                 }
                 Element::PixelFilter { ty, params } => {
                     debug_assert!(scene.pixel_filter.is_none());
-                    let filter = PixelFilter::new(ty, params)?;
+                    let filter = options.construct("PixelFilter", ty, params, |params| {
+                        PixelFilter::new(ty, params)
+                    })?;
                     scene.pixel_filter = Some(filter);
                 }
-                Element::ColorSpace { .. } => {
-                    todo!("Support color space");
+                Element::ColorSpace { ty } => {
+                    current_state.color_space = Some(ty.parse()?);
                 }
                 Element::Sampler { ty, params } => {
-                    let sampler = Sampler::new(ty, params)?;
+                    let requested = params.integer("pixelsamples", 0)?;
+                    let sampler = options
+                        .construct("Sampler", ty, params, |params| Sampler::new(ty, params))?;
+
+                    if let Sampler::Stratified { .. } = sampler {
+                        let effective = sampler.pixel_samples();
+                        if requested > 0 && requested != effective {
+                            scene.diagnostics.push(format!(
+                                "Stratified sampler ignores pixelsamples {requested}, \
+                                 taking xsamples * ysamples = {effective} samples per pixel"
+                            ));
+                        }
+                    }
 
                     debug_assert!(scene.sampler.is_none());
                     scene.sampler = Some(sampler);
@@ -274,26 +2262,8 @@ impl Scene {
                 // the specified file is parsed in its entirety, and only then does parsing of the current file resume.
                 // Its effect is equivalent to direct text substitution of the included file.
                 Element::Include(path) => {
-                    // If the filename given to a Include or Import statement is not an absolute path,
-                    // its path is interpreted as being relative to the directory of the initial file being parsed as
-                    // specified with pbrt's command-line arguments.
-                    let path = Path::new(path);
-
-                    let full_path;
-
-                    let path = if path.is_absolute() {
-                        path
-                    } else {
-                        full_path = match working_directory {
-                            Some(directory) => directory.join(path),
-                            // Use current working directory if not provided
-                            None => env::current_dir()?.join(path),
-                        };
-
-                        full_path.as_path()
-                    };
-
-                    let data = fs::read_to_string(path)?;
+                    let path = include_path(&path, working_directory, &options.include_base)?;
+                    let data = read_include(&path)?;
 
                     // Included files may be compressed using gzip.
                     // If a scene file name has a ".gz" suffix, then pbrt will automatically decompress it as it is read from disk.
@@ -305,28 +2275,51 @@ impl Scene {
 
                     // In Rust, String is heap allocated type, so it's safe to keep a pointer to
                     // the raw data and move the String object (like push it to the vector).
-                    let raw = data.as_bytes();
+                    // The same applies to memory mapped files, moving the mapping doesn't change its address.
+                    let raw = data.as_str().as_bytes();
                     let raw_len = raw.len();
                     let raw_ptr = raw.as_ptr();
 
                     includes.push(data);
 
                     // TODO: is there a better way?
-                    let parser = Parser::new(unsafe {
-                        let byte_slice = slice::from_raw_parts(raw_ptr, raw_len);
-                        str::from_utf8_unchecked(byte_slice)
-                    });
+                    let parser = new_parser(
+                        unsafe {
+                            let byte_slice = slice::from_raw_parts(raw_ptr, raw_len);
+                            str::from_utf8_unchecked(byte_slice)
+                        },
+                        includes.len(),
+                    );
                     parsers.push(parser);
                 }
-                Element::Import(..) => {
-                    todo!("Support imports")
+                Element::Import(path) => {
+                    if !options.lenient {
+                        todo!("Support imports")
+                    }
+
+                    // Imported files are skipped in lenient mode.
+                    scene.note_feature("Import", "");
+                    scene.diagnostics.push(format!("Skipped import: {path}"));
                 }
                 Element::WorldBegin => {
                     is_world_block = true;
+
+                    if hooks.header_only {
+                        break;
+                    }
+
                     current_state.transform_matrix = Mat4::IDENTITY;
                 }
                 Element::Option(param) => {
+                    let name = param.name;
                     scene.options.apply(param)?;
+
+                    if options.strict_parameters && scene.options.custom.contains_key(name) {
+                        return Err(Error::UnknownParameter {
+                            directive: "Option".to_string(),
+                            key: name.to_string(),
+                        });
+                    }
                 }
                 Element::Texture {
                     name,
@@ -335,19 +2328,37 @@ impl Scene {
                     mut params,
                 } => {
                     params.extend(&current_state.texture_params);
-                    let texture = Texture::new(name, ty, class, params)?;
+                    scene.note_feature("Texture", class);
+                    let texture = options.construct("Texture", class, params, |params| {
+                        Texture::new(
+                            &name,
+                            ty,
+                            class,
+                            params,
+                            current_state.transform_matrix,
+                            &scene.named_textures,
+                            &resolve_path,
+                        )
+                    })?;
 
                     let index = scene.textures.len();
                     scene.textures.push(texture);
 
-                    named_textures.insert(name.to_string(), index);
+                    scene.named_textures.insert(name.to_string(), index);
                 }
                 // The Material directive specifies the current material, which then applies for all subsequent
                 // shape definitions (until the end of the current attribute scope or until a new material is defined.
                 Element::Material { ty, mut params } => {
                     params.extend(&current_state.material_params);
                     params.add(Param::new("string type", ty)?)?;
-                    let material = Material::new("", params, &named_textures)?;
+                    scene.note_feature("Material", ty);
+                    if options.version == PbrtVersion::V3Compat {
+                        scene.note_v3_material_params(&params);
+                    }
+                    let mut material = options.construct("Material", ty, params, |params| {
+                        Material::new("", params, &scene.named_textures, &resolve_path)
+                    })?;
+                    material.attribute_depth = states_stack.len() as u32;
 
                     let index = scene.materials.len();
                     scene.materials.push(material);
@@ -356,16 +2367,28 @@ impl Scene {
                 }
                 Element::MakeNamedMaterial { name, mut params } => {
                     params.extend(&current_state.material_params);
-                    let material = Material::new(name, params, &named_textures)?;
+                    let ty = params.string("type").unwrap_or_default().to_string();
+                    scene.note_feature("Material", &ty);
+                    if options.version == PbrtVersion::V3Compat {
+                        scene.note_v3_material_params(&params);
+                    }
+                    let mut material =
+                        options.construct("MakeNamedMaterial", &ty, params, |params| {
+                            Material::new(&name, params, &scene.named_textures, &resolve_path)
+                        })?;
+                    material.attribute_depth = states_stack.len() as u32;
 
                     let index = scene.materials.len();
                     scene.materials.push(material);
 
-                    named_materials.insert(name.to_string(), index);
+                    scene.named_materials.insert(name.to_string(), index);
                 }
-                Element::NamedMaterial { name } => match named_materials.get(name) {
+                Element::NamedMaterial { name } => match scene.named_materials.get(name.as_ref()) {
                     Some(index) => current_state.material_index = Some(*index),
-                    None => unimplemented!("Material name: {name:?} not found"),
+                    None if options.lenient => scene
+                        .diagnostics
+                        .push(format!("Skipped undefined named material: {name}")),
+                    None => return Err(Error::UndefinedMaterial(name.to_string())),
                 },
                 Element::LightSource { ty, params } => {
                     // When a light source is created, the current exterior medium is used for rays leaving the light
@@ -374,17 +2397,31 @@ impl Scene {
                     // The user is responsible for specifying media in a way such that rays reaching lights are in the same medium
                     // as rays leaving those lights.
 
-                    // TODO: Handle current_outside_medium
+                    let light = options.construct("LightSource", ty, params, |params| {
+                        Light::new(ty, params, &resolve_path)
+                    })?;
+
+                    let entity = LightEntity {
+                        params: light,
+                        transform: current_state.transform_matrix,
+                        outside_medium: current_state
+                            .current_outside_medium
+                            .as_deref()
+                            .map(str::to_string),
+                        attribute_depth: states_stack.len() as u32,
+                    };
 
-                    let light = Light::new(ty, params)?;
-                    scene.lights.push(light);
+                    scene.lights.push(entity);
                 }
                 // After an AreaLightSource directive, all subsequent shapes emit light
                 // from their surfaces according to the distribution defined by the given
                 // area light implementation.
                 Element::AreaLightSource { ty, mut params } => {
                     params.extend(&current_state.light_params);
-                    let area_light = AreaLight::new(ty, params)?;
+                    let area_light =
+                        options.construct("AreaLightSource", ty, params, |params| {
+                            AreaLight::new(ty, params, &resolve_path)
+                        })?;
 
                     let index = scene.area_lights.len();
                     scene.area_lights.push(area_light);
@@ -398,22 +2435,50 @@ impl Scene {
                     name: ty,
                     mut params,
                 } => {
-                    params.extend(&current_state.shape_params);
-                    let shape = Shape::new(ty, params)?;
+                    shape_total += 1;
+                    scene.note_feature("Shape", ty);
 
-                    // When a shape is created, the current interior medium is assumed to be the medium inside the shape,
-                    // and the current exterior medium is assumed to be the medium outside the shape.
-                    // TODO: handle mediums
+                    if !hooks.skip_shapes {
+                        params.extend(&current_state.shape_params);
+                        let alpha = Alpha::new(&params, &scene.named_textures)?;
+                        let shape = options.construct("Shape", ty, params, |params| {
+                            if options.tessellate {
+                                tessellated_shape(ty, params, &resolve_path, &mut scene.diagnostics)
+                            } else {
+                                Shape::new(ty, params, &resolve_path)
+                            }
+                        })?;
 
-                    let entity = ShapeEntity {
-                        params: shape,
-                        transform: current_state.transform_matrix,
-                        reverse_orientation: current_state.reverse_orientation,
-                        material_index: current_state.material_index,
-                        area_light_index: current_state.area_light_index,
-                    };
+                        // When a shape is created, the current interior medium is assumed to be the medium inside the shape,
+                        // and the current exterior medium is assumed to be the medium outside the shape.
+                        let entity = ShapeEntity {
+                            params: shape,
+                            transform: current_state.transform_matrix,
+                            reverse_orientation: current_state.reverse_orientation,
+                            material_index: current_state.material_index,
+                            area_light_index: current_state.area_light_index,
+                            alpha,
+                            inside_medium: current_state
+                                .current_inside_medium
+                                .as_deref()
+                                .map(str::to_string),
+                            outside_medium: current_state
+                                .current_outside_medium
+                                .as_deref()
+                                .map(str::to_string),
+                            transform_start_time: scene.start_time,
+                            transform_end_time: scene.end_time,
+                            inverse_transform: None,
+                            transform_index: None,
+                            attribute_depth: states_stack.len() as u32,
+                        };
+
+                        scene.shapes.push(entity);
+                    }
 
-                    scene.shapes.push(entity);
+                    if let Some(traces) = hooks.traces.as_mut() {
+                        traces.push(current_state.transform_trace.clone());
+                    }
 
                     // If inside of ObjectBegin/ObjectEnd, count the number of shapes.
                     if current_state.active_object.is_some() {
@@ -439,7 +2504,7 @@ impl Scene {
                     scene.objects.push(object);
 
                     current_state.active_object = Some(index);
-                    named_objects.insert(name.to_string(), index);
+                    scene.named_objects.insert(name.to_string(), index);
                 }
                 Element::ObjectEnd => {
                     let object_index = current_state
@@ -452,7 +2517,7 @@ impl Scene {
                     object.shape_count = current_state.shape_count;
 
                     if object.shape_count > 0 {
-                        object.shape_start = Some(scene.shapes.len() - object.shape_count)
+                        object.shape_start = Some(shape_total - object.shape_count)
                     }
 
                     current_state.shape_count = 0;
@@ -464,7 +2529,7 @@ impl Scene {
                     }
                 }
                 Element::ObjectInstance { name } => {
-                    let Some(object_index) = named_objects.get(name).copied() else {
+                    let Some(object_index) = scene.named_objects.get(name.as_ref()).copied() else {
                         return Err(Error::NotFound)
                     };
 
@@ -473,7 +2538,14 @@ impl Scene {
                         instance_to_world: current_state.transform_matrix,
                         object_index,
                         area_light_index: current_state.area_light_index,
+                        material_override: current_state
+                            .material_index
+                            .filter(|_| options.instance_material_override),
                         reverse_orientation: current_state.reverse_orientation,
+                        transform_start_time: scene.start_time,
+                        transform_end_time: scene.end_time,
+                        inverse_transform: None,
+                        transform_index: None,
                     };
 
                     scene.instances.push(instance);
@@ -481,18 +2553,27 @@ impl Scene {
                 // MakeNamedMedium associates a user-specified name with medium scattering characteristics.
                 Element::MakeNamedMedium { name, mut params } => {
                     params.extend(&current_state.medium_params);
+                    scene
+                        .note_feature("MakeNamedMedium", params.string("type").unwrap_or_default());
                     let medium = Medium::new(params)?;
 
                     let index = scene.mediums.len();
                     scene.mediums.push(medium);
 
-                    named_mediums.insert(name.to_string(), index);
+                    scene.named_mediums.insert(name.to_string(), index);
                 }
                 // MediumInterface directive can be used to specify the current "interior" and "exterior" media.
-                // A vacuum—no participating media—is represented by empty string "".
+                // A vacuum—no participating media—is represented by empty string "",
+                // normalized to `None` here, so `MediumInterface "" ""` clears both media.
                 Element::MediumInterface { interior, exterior } => {
-                    current_state.current_inside_medium = Some(interior);
-                    current_state.current_outside_medium = Some(exterior);
+                    current_state.current_inside_medium = medium_name(interior);
+                    current_state.current_outside_medium = medium_name(exterior);
+                }
+                // Only emitted in lenient mode, skip it.
+                Element::Unknown { keyword, .. } => {
+                    scene
+                        .diagnostics
+                        .push(format!("Skipped unknown directive: {keyword}"));
                 }
             }
         }
@@ -500,81 +2581,5630 @@ impl Scene {
         debug_assert!(states_stack.is_empty());
         debug_assert!(is_world_block);
 
+        let aspect_ratio = match &scene.film {
+            Some(film) => film.aspect_ratio(),
+            None => Film::default().aspect_ratio(),
+        };
+
+        if let Some([_, x1, _, y1]) = scene.options.sample_bounds {
+            let film = scene.film.clone().unwrap_or_default();
+            if x1 > film.xresolution || y1 > film.yresolution {
+                return Err(Error::ValueOutOfRange("samplebounds".to_string()));
+            }
+        }
+
+        for camera in &mut scene.cameras {
+            camera.params.resolve_screen_window(aspect_ratio);
+
+            // Motion blur samples times within the shutter, which must be covered by the
+            // transform times for the animated transforms to be well defined.
+            let (start, end) = (camera.transform_start_time, camera.transform_end_time);
+            if start <= end && camera.params.clamp_shutter(start, end) {
+                let (open, close) = camera.params.shutter();
+                scene.diagnostics.push(format!(
+                    "Camera shutter interval clamped to transform times [{start}, {end}]: [{open}, {close}]"
+                ));
+            }
+        }
+
+        if !hooks.skip_shapes {
+            scene.resolve_area_light_power();
+        }
+
+        if let Some(camera) = scene.cameras.first() {
+            scene.world_from_render = match scene.options.render_coord_sys {
+                CoordinateSystem::Camera => camera.transform,
+                CoordinateSystem::CameraWorld => {
+                    Mat4::from_translation(camera.transform.transform_point3(Vec3::ZERO))
+                }
+                CoordinateSystem::World => Mat4::IDENTITY,
+            };
+        }
+
         Ok(scene)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Split the image into `nx` by `ny` tiles.
+    ///
+    /// The film crop window of each tile covers one tile of the scene's crop window,
+    /// tiles are ordered row by row. Scenes without a film use the default one.
+    /// The scene is copied once and shared by all tiles, use [SceneTile::to_scene] where
+    /// a separate scene per tile is needed, at the cost of copying the whole scene for each.
+    pub fn split_into_tiles(&self, nx: usize, ny: usize) -> Result<Vec<SceneTile>> {
+        let scene = Arc::new(self.clone());
+        let film = self.film.clone().unwrap_or_default();
+        let [x0, x1, y0, y1] = film.crop_window;
 
-    use tempdir::TempDir;
+        let lerp = |min: f32, max: f32, t: usize, n: usize| min + (max - min) * t as f32 / n as f32;
 
-    #[test]
-    fn test_includes() -> Result<()> {
-        let temp_dir = TempDir::new("pbrt-includes-")?;
-        let temp_path = temp_dir.path();
+        let mut tiles = Vec::with_capacity(nx * ny);
 
-        fs::write(temp_path.join("1.pbrt"), "Shape \"sphere\"")?;
-        fs::write(temp_path.join("2.pbrt"), "Include \"1.pbrt\" ")?;
-        fs::write(temp_path.join("3.pbrt"), "Include \"2.pbrt\" ")?;
-        fs::write(temp_path.join("4.pbrt"), "Include \"3.pbrt\" ")?;
+        for y in 0..ny {
+            for x in 0..nx {
+                let mut film = film.clone();
+                film.set_crop_window([
+                    lerp(x0, x1, x, nx),
+                    lerp(x0, x1, x + 1, nx),
+                    lerp(y0, y1, y, ny),
+                    lerp(y0, y1, y + 1, ny),
+                ])?;
 
-        fs::write(
+                tiles.push(SceneTile {
+                    scene: Arc::clone(&scene),
+                    film,
+                });
+            }
+        }
+
+        Ok(tiles)
+    }
+
+    /// Build the graph of texture to texture references.
+    pub fn texture_graph(&self) -> TextureGraph {
+        TextureGraph {
+            dependencies: self
+                .textures
+                .iter()
+                .map(Texture::texture_references)
+                .collect(),
+        }
+    }
+
+    /// Find a texture by name.
+    pub fn texture_by_name(&self, name: &str) -> Option<&Texture> {
+        self.named_textures
+            .get(name)
+            .and_then(|index| self.textures.get(*index))
+    }
+
+    /// Find a material declared with `MakeNamedMaterial` by name.
+    pub fn material_by_name(&self, name: &str) -> Option<&Material> {
+        self.named_materials
+            .get(name)
+            .and_then(|index| self.materials.get(*index))
+    }
+
+    /// Indices of textures referenced by the material at `material_index`.
+    pub fn material_textures(&self, material_index: usize) -> Vec<usize> {
+        self.materials
+            .get(material_index)
+            .map(Material::texture_references)
+            .unwrap_or_default()
+    }
+
+    /// Find a medium declared with `MakeNamedMedium` by name.
+    pub fn medium_by_name(&self, name: &str) -> Option<&Medium> {
+        self.named_mediums
+            .get(name)
+            .and_then(|index| self.mediums.get(*index))
+    }
+
+    /// Find an object declared with `ObjectBegin` by name.
+    pub fn object_by_name(&self, name: &str) -> Option<&Object> {
+        self.named_objects
+            .get(name)
+            .and_then(|index| self.objects.get(*index))
+    }
+
+    /// Compute a rough estimate of the total power emitted by all lights in the scene.
+    ///
+    /// Point, spot, distant and uniform infinite lights are converted from their
+    /// intensity/radiance to power the same way pbrt does. Distant and infinite
+    /// lights depend on the scene's bounding sphere, which is approximated from the
+    /// shapes' world space bounds.
+    ///
+    /// Area lights contribute `π * L * area` (doubled when two sided) for every shape
+    /// they are attached to, as long as the shape's area can be computed.
+    ///
+    /// Lights that require external files (image based infinite lights, goniometric
+    /// and projection lights, textured emission) are skipped.
+    pub fn total_emitted_power(&self) -> f32 {
+        let scene_radius = self.world_bounds().map_or(0.0, |(min, max)| {
+            let diagonal: Vec3 = max - min;
+            diagonal.length() * 0.5
+        });
+
+        let mut power = 0.0;
+
+        for light in &self.lights {
+            power += light.params.scale()
+                * match &light.params {
+                    Light::Point { spectrum, .. } => 4.0 * PI * spectrum_power(spectrum.as_ref()),
+                    Light::Spot {
+                        spectrum,
+                        cone_angle,
+                        cone_delta,
+                        ..
+                    } => {
+                        let cos_falloff_end = cone_angle.to_radians().cos();
+                        let cos_falloff_start = (cone_angle - cone_delta).to_radians().cos();
+
+                        spectrum_power(spectrum.as_ref())
+                            * 2.0
+                            * PI
+                            * ((1.0 - cos_falloff_start)
+                                + (cos_falloff_start - cos_falloff_end) / 2.0)
+                    }
+                    Light::Distant { spectrum, .. } => {
+                        spectrum_power(spectrum.as_ref()) * PI * scene_radius * scene_radius
+                    }
+                    Light::Infinite {
+                        filename: None,
+                        spectrum,
+                        ..
+                    } => {
+                        4.0 * PI
+                            * PI
+                            * scene_radius
+                            * scene_radius
+                            * spectrum_power(spectrum.as_ref())
+                    }
+                    _ => 0.0,
+                };
+        }
+
+        for shape in &self.shapes {
+            let Some(area_light) = shape
+                .area_light_index
+                .and_then(|index| self.area_lights.get(index))
+            else {
+                continue;
+            };
+
+            let AreaLight::Diffuse {
+                filename: None,
+                two_sided,
+                spectrum,
+                scale,
+                ..
+            } = area_light
+            else {
+                continue;
+            };
+
+            let Some(area) = shape_area(&shape.params, &shape.transform) else {
+                continue;
+            };
+
+            let sides = if *two_sided { 2.0 } else { 1.0 };
+
+            power += PI * sides * area * scale * spectrum_power(spectrum.as_ref());
+        }
+
+        power
+    }
+
+    /// Normalize the scale of area lights specified by `power`.
+    ///
+    /// An area light shared by several shapes emits the requested power from all of them
+    /// together, so the scale is divided by the total area of the emitting shapes, counting
+    /// every instance of shapes in objects. Like in pbrt, two-sided lights emit from both sides,
+    /// which doubles the emitting area and halves the resulting radiance.
+    ///
+    /// Areas are measured in world space, see [ShapeEntity::surface_area]. Called when loading
+    /// a scene. Lights with a shape whose area can't be computed (non-uniformly scaled analytic
+    /// shapes, curves and PLY meshes) are left as is and reported in `Scene::diagnostics`.
+    pub fn resolve_area_light_power(&mut self) {
+        let areas = self.area_light_areas();
+        self.apply_area_light_power(&areas);
+    }
+
+    /// Total world space area of the shapes emitting each area light, `None` if any of them
+    /// has no computable area.
+    pub(crate) fn area_light_areas(&self) -> Vec<Option<f32>> {
+        let mut areas = vec![Some(0.0); self.area_lights.len()];
+        for primitive in self.render_primitives() {
+            let Some(index) = primitive.area_light_index else {
+                continue;
+            };
+
+            let shape = &self.shapes[primitive.shape_index].params;
+            areas[index] = areas[index]
+                .zip(world_shape_area(shape, &primitive.world_transform))
+                .map(|(total, area)| total + area);
+        }
+
+        areas
+    }
+
+    /// Normalize area lights specified by `power` with the areas from [Scene::area_light_areas].
+    ///
+    /// Split from [Scene::resolve_area_light_power] for loads that share their shapes.
+    pub(crate) fn apply_area_light_power(&mut self, areas: &[Option<f32>]) {
+        for (index, (area_light, &area)) in self.area_lights.iter_mut().zip(areas).enumerate() {
+            let AreaLight::Diffuse {
+                two_sided,
+                spectrum,
+                scale,
+                power,
+                ..
+            } = area_light;
+
+            let Some(phi) = *power else {
+                continue;
+            };
+
+            let Some(area) = area else {
+                self.diagnostics.push(format!(
+                    "Area light {index} has a shape without a computable area, its power is ignored"
+                ));
+                continue;
+            };
+
+            let sides = if *two_sided { 2.0 } else { 1.0 };
+            let radiance = spectrum.as_ref().and_then(Spectrum::average).unwrap_or(1.0);
+            let k_e = sides * area * PI * radiance;
+
+            if k_e > 0.0 {
+                *scale *= phi / k_e;
+                *power = None;
+            }
+        }
+    }
+
+    /// Replace the spectral parameters of materials, lights and area lights with RGB values in `color_space`.
+    ///
+    /// The color matching tables are built once for the whole pass. RGB values are converted from
+    /// the color space they were given in and are annotated with `color_space`. Texture references and named spectra without built-in data (such as measured metals)
+    /// are left unchanged.
+    pub fn bake_spectra_to_rgb(&mut self, color_space: ColorSpace) {
+        let materials = self
+            .materials
+            .iter_mut()
+            .flat_map(|material| material.ty.spectra_mut());
+        let lights = self
+            .lights
+            .iter_mut()
+            .filter_map(|light| light.params.spectrum_mut());
+        let area_lights = self
+            .area_lights
+            .iter_mut()
+            .filter_map(|AreaLight::Diffuse { spectrum, .. }| spectrum.as_mut());
+
+        spectra_to_rgb(materials.chain(lights).chain(area_lights), color_space);
+    }
+
+    /// Flatten the scene into a [BakedScene] for handing it to a renderer.
+    ///
+    /// Instances are expanded, every primitive gets its own copy of the shape's geometry,
+    /// material and area light, shapes without material get pbrt's default material and
+    /// render settings that aren't specified get pbrt's defaults. The PLY files of `plymesh`
+    /// shapes are read from `source`, at the paths resolved and rewritten while loading.
+    /// Spectra are kept, use [BakedScene::bake_spectra_to_rgb] to convert them.
+    ///
+    /// This is expensive: each PLY file is read once, but geometry is copied for every primitive,
+    /// so memory grows with the number of instances times the size of the instanced objects.
+    /// For heavily instanced scenes prefer [Scene::render_primitives], which keeps shapes shared.
+    pub fn bake(&self, source: &dyn AssetSource) -> Result<BakedScene> {
+        let mut meshes: HashMap<PathBuf, Shape> = HashMap::new();
+        let mut primitives = Vec::new();
+
+        for primitive in self.render_primitives() {
+            let entity = &self.shapes[primitive.shape_index];
+
+            let shape = match &entity.params {
+                Shape::PlyMesh { filename } => match meshes.get(filename) {
+                    Some(shape) => shape.clone(),
+                    None => {
+                        let shape = mesh_shape(TriangleMesh::from_ply(&source.read(filename)?)?);
+                        meshes.insert(filename.clone(), shape.clone());
+                        shape
+                    }
+                },
+                shape => shape.clone(),
+            };
+
+            primitives.push(BakedPrimitive {
+                shape,
+                world_transform: primitive.world_transform,
+                reverse_orientation: primitive.reverse_orientation,
+                material: primitive
+                    .material_index
+                    .and_then(|index| self.materials.get(index))
+                    .cloned()
+                    .unwrap_or_default(),
+                area_light: primitive
+                    .area_light_index
+                    .and_then(|index| self.area_lights.get(index))
+                    .cloned(),
+                alpha: entity.alpha,
+                inside_medium: primitive.inside_medium,
+                outside_medium: primitive.outside_medium,
+            });
+        }
+
+        Ok(BakedScene {
+            film: self.film.clone().unwrap_or_default(),
+            sampler: self.sampler_or_default(),
+            integrator: self.integrator_or_default(),
+            pixel_filter: self.pixel_filter_or_default(),
+            cameras: self.cameras.clone(),
+            lights: self.lights.clone(),
+            textures: self.textures.clone(),
+            primitives,
+        })
+    }
+
+    /// Copy the shapes of an object, placed in the world with `instance_to_world`.
+    ///
+    /// The returned shapes are standalone, their transformation is `instance_to_world * shape.transform`.
+    /// Returns an empty list if there is no object at `object_index`.
+    pub fn expand_object(&self, object_index: usize, instance_to_world: Mat4) -> Vec<ShapeEntity> {
+        let Some(object) = self.objects.get(object_index) else {
+            return Vec::new();
+        };
+
+        let Some(start) = object.shape_start else {
+            return Vec::new();
+        };
+
+        self.shapes
+            .get(start..start + object.shape_count)
+            .unwrap_or_default()
+            .iter()
+            .map(|shape| ShapeEntity {
+                transform: instance_to_world * shape.transform,
+                inverse_transform: None,
+                transform_index: None,
+                ..shape.clone()
+            })
+            .collect()
+    }
+
+    /// Resolve whether the surface normals of a shape should be flipped.
+    ///
+    /// The result combines the shape's own `ReverseOrientation` setting with the one of the
+    /// instance it's rendered through (if any) and whether the composed object to world
+    /// transformation changes the handedness of the coordinate system (negative determinant),
+    /// all three are XOR'ed together.
+    ///
+    /// When `instance_index` is `Some`, the shape must belong to the object referenced by the
+    /// instance, the composed transformation is then `instance_to_world * shape.transform`.
+    ///
+    /// Returns `None` if either index is out of range or the shape is not part of the instanced object.
+    pub fn effective_reverse_orientation(
+        &self,
+        shape_index: usize,
+        instance_index: Option<usize>,
+    ) -> Option<bool> {
+        let shape = self.shapes.get(shape_index)?;
+
+        let (transform, instance_flag) = match instance_index {
+            Some(index) => {
+                let instance = self.instances.get(index)?;
+                let object = self.objects.get(instance.object_index)?;
+
+                let start = object.shape_start?;
+                if !(start..start + object.shape_count).contains(&shape_index) {
+                    return None;
+                }
+
+                (
+                    instance.instance_to_world * shape.transform,
+                    instance.reverse_orientation,
+                )
+            }
+            None => (shape.transform, false),
+        };
+
+        let swaps_handedness = transform.determinant() < 0.0;
+
+        Some(shape.reverse_orientation ^ instance_flag ^ swaps_handedness)
+    }
+
+    /// Export instance transformations using glTF conventions.
+    ///
+    /// pbrt uses a left-handed coordinate system, while glTF is right-handed (+Y up, +Z forward).
+    /// Each instance's `instance_to_world` matrix `M` is converted by flipping the Z axis on
+    /// both sides: `M' = S * M * S`, where `S = diag(1, 1, -1, 1)`. This negates the Z
+    /// translation component and the elements of the rotation/scale part that mix Z with X or Y,
+    /// while keeping the determinant unchanged.
+    ///
+    /// Returns one entry per instance, named after the instanced object, with
+    /// matrices in column-major order (`m[column][row]`), as expected by glTF.
+    pub fn to_gltf_transforms(&self) -> Vec<(String, [[f32; 4]; 4])> {
+        let flip_z = Mat4::from_scale(Vec3::new(1.0, 1.0, -1.0));
+
+        self.instances
+            .iter()
+            .map(|instance| {
+                let name = self
+                    .objects
+                    .get(instance.object_index)
+                    .map(|object| object.name.clone())
+                    .unwrap_or_default();
+
+                let matrix = flip_z * instance.instance_to_world * flip_z;
+
+                (name, matrix.to_cols_array_2d())
+            })
+            .collect()
+    }
+
+    /// Write the scene in pbrt's format, in a canonical form that is stable across re-exports.
+    ///
+    /// Directives are written in a fixed order (options, render settings, cameras, then textures,
+    /// named materials, lights, shapes, objects and instances), one per line with one parameter
+    /// per line, parameters sorted by name and floats in their shortest round-trip representation.
+    /// Every parameter the scene stores is written, including defaults. Media and parameters this
+    /// crate doesn't parse are not written.
+    pub fn write_canonical<W: io::Write>(&self, w: W) -> Result<()> {
+        SceneWriter::new(w).write_scene(self)
+    }
+
+    /// External files needed to render the shape at `shape_index`, for loading assets on demand.
+    ///
+    /// Includes the `plymesh` file, the material's normal map and the image and ptex files of
+    /// all textures reachable from the material's parameters, its displacement and the shape's
+    /// alpha texture (following references between textures). Paths are resolved like at load
+    /// time, each file is listed once. Returns an empty list if there is no shape at `shape_index`.
+    pub fn shape_dependencies(&self, shape_index: usize) -> Vec<PathBuf> {
+        let Some(shape) = self.shapes.get(shape_index) else {
+            return Vec::new();
+        };
+
+        let mut files = Vec::new();
+        let mut pending = Vec::new();
+
+        if let Shape::PlyMesh { filename } = &shape.params {
+            files.push(filename.clone());
+        }
+
+        if let Alpha::Texture(index) = shape.alpha {
+            pending.push(index);
+        }
+
+        if let Some(material) = shape.material_index.and_then(|i| self.materials.get(i)) {
+            files.extend(material.normal_map.clone());
+            pending.extend(material.texture_references());
+            pending.extend(material.displacement);
+        }
+
+        let mut visited = vec![false; self.textures.len()];
+        while let Some(index) = pending.pop() {
+            let Some(texture) = self.textures.get(index) else {
+                continue;
+            };
+
+            if mem::replace(&mut visited[index], true) {
+                continue;
+            }
+
+            if let TextureClass::ImageMap { filename } | TextureClass::Ptex { filename, .. } =
+                &texture.class
+            {
+                if !files.contains(filename) {
+                    files.push(filename.clone());
+                }
+            }
+
+            pending.extend(texture.texture_references());
+        }
+
+        files
+    }
+
+    /// Find materials, textures and objects that are not referenced by anything else in the scene.
+    ///
+    /// Materials are considered used when assigned to at least one shape or instance, textures when
+    /// referenced by a used material's parameters or displacement, a shape's alpha or another used
+    /// texture, and objects when instanced at least once.
+    pub fn unused_entities(&self) -> UnusedReport {
+        let mut used_materials = vec![false; self.materials.len()];
+        let shape_materials = self.shapes.iter().filter_map(|shape| shape.material_index);
+        let instance_materials = self
+            .instances
+            .iter()
+            .filter_map(|instance| instance.material_override);
+        for index in shape_materials.chain(instance_materials) {
+            used_materials[index] = true;
+        }
+
+        let used_textures = used_textures(
+            &self.textures,
+            &self.materials,
+            &used_materials,
+            &self.shapes,
+        );
+
+        let mut used_objects = vec![false; self.objects.len()];
+        for instance in &self.instances {
+            used_objects[instance.object_index] = true;
+        }
+
+        let unused = |used: Vec<bool>| -> Vec<usize> {
+            used.into_iter()
+                .enumerate()
+                .filter_map(|(index, used)| (!used).then_some(index))
+                .collect()
+        };
+
+        UnusedReport {
+            materials: unused(used_materials),
+            textures: unused(used_textures),
+            objects: unused(used_objects),
+        }
+    }
+
+    /// Check the scene for problems that prevent it from rendering correctly.
+    ///
+    /// Transforms whose relative determinant is within [Scene::DEGENERATE_EPSILON] of zero are reported,
+    /// as well as camera transforms that are not finite because the singular CTM was inverted at load.
+    /// Area lights must have a finite, non-negative scale and `L` (for RGB and sampled spectra).
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let is_degenerate = |transform: &Mat4| {
+            let linear = Mat3::from_mat4(*transform);
+            let axes = linear.x_axis.length() * linear.y_axis.length() * linear.z_axis.length();
+
+            !transform.is_finite()
+                || axes == 0.0
+                || linear.determinant().abs() / axes <= Self::DEGENERATE_EPSILON
+        };
+
+        let shapes = self
+            .shapes
+            .iter()
+            .map(|shape| &shape.transform)
+            .enumerate()
+            .map(|(index, transform)| (EntityRef::Shape(index), transform));
+        let instances = self
+            .instances
+            .iter()
+            .map(|instance| &instance.instance_to_world)
+            .enumerate()
+            .map(|(index, transform)| (EntityRef::Instance(index), transform));
+        let cameras = self
+            .cameras
+            .iter()
+            .map(|camera| &camera.transform)
+            .enumerate()
+            .map(|(index, transform)| (EntityRef::Camera(index), transform));
+
+        shapes
+            .chain(instances)
+            .chain(cameras)
+            .filter(|(_, transform)| is_degenerate(transform))
+            .map(|(entity, _)| ValidationIssue::DegenerateTransform(entity))
+            .chain(
+                self.area_lights
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, area_light)| !has_valid_emission(area_light))
+                    .map(|(index, _)| {
+                        ValidationIssue::InvalidEmission(EntityRef::AreaLight(index))
+                    }),
+            )
+            .collect()
+    }
+
+    /// Check that media referenced by shapes and lights are consistent.
+    ///
+    /// Only problems that can be detected statically are reported, pbrt still relies on the user
+    /// to specify media such that rays reaching lights are in the same medium as rays leaving them.
+    pub fn check_medium_consistency(&self) -> Vec<MediumIssue> {
+        let mut issues = Vec::new();
+
+        let is_undefined = |name: &Option<String>| -> bool {
+            name.as_ref()
+                .map_or(false, |name| !self.named_mediums.contains_key(name))
+        };
+
+        for (shape_index, shape) in self.shapes.iter().enumerate() {
+            for name in [&shape.inside_medium, &shape.outside_medium] {
+                if is_undefined(name) {
+                    issues.push(MediumIssue::UndefinedShapeMedium {
+                        shape_index,
+                        name: name.clone().unwrap_or_default(),
+                    });
+                }
+            }
+
+            let is_interface = shape
+                .material_index
+                .and_then(|index| self.materials.get(index))
+                .map_or(false, |material| {
+                    matches!(material.ty, MaterialType::Interface)
+                });
+
+            if is_interface && shape.inside_medium == shape.outside_medium {
+                issues.push(MediumIssue::RedundantInterface { shape_index });
+            }
+        }
+
+        let camera_medium = self
+            .cameras
+            .first()
+            .and_then(|camera| camera.medium.as_ref());
+        let is_separated = |a: Option<&String>, b: Option<&String>| -> bool {
+            self.shapes.iter().any(|shape| {
+                let inside = shape.inside_medium.as_ref();
+                let outside = shape.outside_medium.as_ref();
+                (inside == a && outside == b) || (inside == b && outside == a)
+            })
+        };
+
+        for (light_index, light) in self.lights.iter().enumerate() {
+            if is_undefined(&light.outside_medium) {
+                issues.push(MediumIssue::UndefinedLightMedium {
+                    light_index,
+                    name: light.outside_medium.clone().unwrap_or_default(),
+                });
+            }
+
+            let light_medium = light.outside_medium.as_ref();
+            if light_medium != camera_medium && !is_separated(light_medium, camera_medium) {
+                issues.push(MediumIssue::MediumMismatch { light_index });
+            }
+        }
+
+        issues
+    }
+
+    /// Compare this scene against `other`.
+    ///
+    /// Entities are matched by their index, so the scenes are expected to declare them in the same order.
+    /// Shapes are compared by type and transform, materials by all their parameters and cameras by transform.
+    /// Transforms are considered equal if all matrix elements differ by less than [Scene::DIFF_EPSILON].
+    pub fn diff(&self, other: &Scene) -> SceneDiff {
+        let mut diff = SceneDiff::default();
+
+        let same_transform = |a: &Mat4, b: &Mat4| -> bool { a.abs_diff_eq(*b, Self::DIFF_EPSILON) };
+
+        for (index, (a, b)) in self.shapes.iter().zip(&other.shapes).enumerate() {
+            let same_type = mem::discriminant(&a.params) == mem::discriminant(&b.params);
+            if !same_type || !same_transform(&a.transform, &b.transform) {
+                diff.changed_shapes.push(index);
+            }
+        }
+
+        diff.added_shapes = (self.shapes.len()..other.shapes.len()).collect();
+        diff.removed_shapes = (other.shapes.len()..self.shapes.len()).collect();
+
+        for (index, (a, b)) in self.materials.iter().zip(&other.materials).enumerate() {
+            if a != b {
+                diff.changed_materials.push(index);
+            }
+        }
+
+        diff.added_materials = (self.materials.len()..other.materials.len()).collect();
+        diff.removed_materials = (other.materials.len()..self.materials.len()).collect();
+
+        let cameras = self.cameras.len().max(other.cameras.len());
+        for index in 0..cameras {
+            let same = match (self.cameras.get(index), other.cameras.get(index)) {
+                (Some(a), Some(b)) => same_transform(&a.transform, &b.transform),
+                _ => false,
+            };
+
+            if !same {
+                diff.changed_cameras.push(index);
+            }
+        }
+
+        diff
+    }
+
+    /// Find shapes that are copies of an earlier shape, for removing accidental duplicates.
+    ///
+    /// Shapes are duplicates when they have the same type and parameters, the same material,
+    /// area light, alpha, media and orientation, and transforms equal within [Scene::DIFF_EPSILON].
+    /// Shapes are only compared with shapes of the same object, or with other shapes outside
+    /// of objects. Returns sorted `(original, duplicate)` pairs, where the original is the first
+    /// shape the duplicate copies.
+    pub fn duplicate_shapes(&self) -> Vec<(usize, usize)> {
+        let mut owners = vec![None; self.shapes.len()];
+        for (object_index, object) in self.objects.iter().enumerate() {
+            if let Some(start) = object.shape_start {
+                let end = (start + object.shape_count).min(owners.len());
+                owners[start.min(end)..end].fill(Some(object_index));
+            }
+        }
+
+        // Group shapes by a hash of their parameters, so only likely duplicates are compared.
+        let mut groups: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (index, shape) in self.shapes.iter().enumerate() {
+            let mut hasher = DefaultHasher::new();
+            let _ = write!(
+                HashWriter(&mut hasher),
+                "{:?}{:?}",
+                owners[index],
+                shape.params
+            );
+            groups.entry(hasher.finish()).or_default().push(index);
+        }
+
+        let is_duplicate = |a: usize, b: usize| {
+            let (a, b) = (&self.shapes[a], &self.shapes[b]);
+            a.params == b.params
+                && a.material_index == b.material_index
+                && a.area_light_index == b.area_light_index
+                && a.alpha == b.alpha
+                && a.inside_medium == b.inside_medium
+                && a.outside_medium == b.outside_medium
+                && a.reverse_orientation == b.reverse_orientation
+                && a.transform.abs_diff_eq(b.transform, Self::DIFF_EPSILON)
+        };
+
+        let mut duplicates = Vec::new();
+        for indices in groups.values() {
+            let mut originals: Vec<usize> = Vec::new();
+            for &index in indices {
+                let original = originals.iter().copied().find(|&original| {
+                    owners[original] == owners[index] && is_duplicate(original, index)
+                });
+
+                match original {
+                    Some(original) => duplicates.push((original, index)),
+                    None => originals.push(index),
+                }
+            }
+        }
+
+        duplicates.sort_unstable();
+        duplicates
+    }
+
+    /// World space bounds of all bounded light sources.
+    ///
+    /// Unions [LightEntity::bounds] of every light with the bounds of emissive shapes.
+    /// Shapes that are part of instanced objects are not included.
+    /// Returns `None` when the scene has no bounded lights.
+    pub fn light_bounds(&self) -> Option<(Vec3, Vec3)> {
+        let mut bounds = None;
+
+        for light in &self.lights {
+            if let Some((min, max)) = light.bounds() {
+                bounds = union_bounds(bounds, min);
+                bounds = union_bounds(bounds, max);
+            }
+        }
+
+        let is_object_shape = self.object_shape_mask();
+
+        for (shape, is_object_shape) in self.shapes.iter().zip(is_object_shape) {
+            if shape.area_light_index.is_none() || is_object_shape {
+                continue;
+            }
+
+            for point in shape_points(&shape.params) {
+                bounds = union_bounds(bounds, shape.transform.transform_point3(point));
+            }
+        }
+
+        bounds
+    }
+
+    /// Returns the features used by the scene that are not fully modeled by this crate.
+    ///
+    /// Such features are loaded as placeholders, for example texture classes without parameters,
+    /// or skipped altogether, like `Import` directives in lenient mode.
+    pub fn unsupported_features(&self) -> Vec<&'static str> {
+        UNSUPPORTED_FEATURES
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.unsupported & (1 << index) != 0)
+            .map(|(_, (_, _, feature))| *feature)
+            .collect()
+    }
+
+    /// Remember that the scene uses `ty` of `directive`, if it's in [UNSUPPORTED_FEATURES].
+    fn note_feature(&mut self, directive: &str, ty: &str) {
+        if let Some(index) = UNSUPPORTED_FEATURES
+            .iter()
+            .position(|(d, t, _)| *d == directive && *t == ty)
+        {
+            self.unsupported |= 1 << index;
+        }
+    }
+
+    /// Record a diagnostic for each pbrt-v3 parameter of a material that has no pbrt-v4 equivalent.
+    fn note_v3_material_params(&mut self, params: &ParamList) {
+        let ty = params.string("type").unwrap_or_default();
+
+        let mut names = params
+            .iter()
+            .map(|param| param.name)
+            .filter(|name| V3_MATERIAL_PARAMS.contains(name))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+
+        for name in names {
+            self.diagnostics.push(format!(
+                "Ignored pbrt-v3 parameter {name} of material {ty}, it has no pbrt-v4 equivalent"
+            ));
+        }
+    }
+
+    /// Hash of the scene contents: transform times, options, the rendering space, render settings,
+    /// cameras, lights, textures, materials, media, shapes with their transformations, objects and
+    /// instances.
+    ///
+    /// Hashes are only comparable between scenes hashed by the same build of the crate.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        // Entities don't implement `Hash` because of the floats they contain,
+        // their debug representation is exact, so hash that instead.
+        let _ = write!(
+            HashWriter(&mut hasher),
+            "{:?}{:?}{:?}{:?}",
+            self.start_time,
+            self.end_time,
+            self.options,
+            self.world_from_render
+        );
+        let _ = write!(
+            HashWriter(&mut hasher),
+            "{:?}{:?}{:?}{:?}{:?}",
+            self.film,
+            self.integrator,
+            self.pixel_filter,
+            self.accelerator,
+            self.sampler
+        );
+        let _ = write!(
+            HashWriter(&mut hasher),
+            "{:?}{:?}{:?}{:?}",
+            self.cameras,
+            self.lights,
+            self.area_lights,
+            self.textures
+        );
+        let _ = write!(
+            HashWriter(&mut hasher),
+            "{:?}{:?}{:?}",
+            self.materials,
+            self.mediums,
+            self.objects
+        );
+
+        // Cached inverses and transform indices are derived from the transforms,
+        // they must not change the hash.
+        for shape in &self.shapes {
+            let ShapeEntity {
+                params,
+                transform,
+                reverse_orientation,
+                material_index,
+                area_light_index,
+                alpha,
+                inside_medium,
+                outside_medium,
+                transform_start_time,
+                transform_end_time,
+                inverse_transform: _,
+                transform_index: _,
+                attribute_depth,
+            } = shape;
+
+            let _ = write!(
+                HashWriter(&mut hasher),
+                "{:?}",
+                (
+                    params,
+                    transform,
+                    reverse_orientation,
+                    material_index,
+                    area_light_index,
+                    alpha,
+                    inside_medium,
+                    outside_medium,
+                    transform_start_time,
+                    transform_end_time,
+                    attribute_depth,
+                )
+            );
+        }
+
+        for instance in &self.instances {
+            let Instance {
+                instance_to_world,
+                object_index,
+                area_light_index,
+                material_override,
+                reverse_orientation,
+                transform_start_time,
+                transform_end_time,
+                inverse_transform: _,
+                transform_index: _,
+            } = instance;
+
+            let _ = write!(
+                HashWriter(&mut hasher),
+                "{:?}",
+                (
+                    instance_to_world,
+                    object_index,
+                    area_light_index,
+                    material_override,
+                    reverse_orientation,
+                    transform_start_time,
+                    transform_end_time,
+                )
+            );
+        }
+
+        hasher.finish()
+    }
+
+    /// Group shape indices by material, for batching draw calls.
+    ///
+    /// Shapes without a material are collected under `None`.
+    /// Shapes of objects are listed once with the material they were defined with,
+    /// which is the material of every instance of the object.
+    pub fn shapes_by_material(&self) -> HashMap<Option<usize>, Vec<usize>> {
+        let mut batches: HashMap<Option<usize>, Vec<usize>> = HashMap::new();
+
+        for (index, shape) in self.shapes.iter().enumerate() {
+            batches.entry(shape.material_index).or_default().push(index);
+        }
+
+        batches
+    }
+
+    /// Returns the pixel region to render as `[xmin, xmax, ymin, ymax]`, max exclusive.
+    ///
+    /// The region is the film's crop window in pixels, intersected with the `samplebounds`
+    /// option if set. Returns `None` if the two don't overlap.
+    pub fn render_region(&self) -> Option<[i32; 4]> {
+        let film = self.film.clone().unwrap_or_default();
+        let [mut x0, mut x1, mut y0, mut y1] = film.pixel_bounds();
+
+        if let Some([sx0, sx1, sy0, sy1]) = self.options.sample_bounds {
+            x0 = x0.max(sx0);
+            x1 = x1.min(sx1);
+            y0 = y0.max(sy0);
+            y1 = y1.min(sy1);
+        }
+
+        (x0 < x1 && y0 < y1).then_some([x0, x1, y0, y1])
+    }
+
+    /// Returns the material of `shape`, or pbrt's default material if it has none.
+    ///
+    /// The default is a diffuse material with a reflectance of 0.5, see [Material::default].
+    pub fn resolved_material(&self, shape: &ShapeEntity) -> Material {
+        shape
+            .material_index
+            .and_then(|index| self.materials.get(index))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if light can't pass through any surface or volume of the scene.
+    ///
+    /// Scenes with participating media, transmissive materials (`dielectric`, `thindielectric`,
+    /// `diffusetransmission`, `subsurface` and `interface`) or shapes with an alpha mask
+    /// other than a constant 1 are not fully opaque.
+    pub fn is_fully_opaque(&self) -> bool {
+        if !self.mediums.is_empty() {
+            return false;
+        }
+
+        let transmissive = self.materials.iter().any(|material| {
+            matches!(
+                material.ty,
+                MaterialType::Dielectric { .. }
+                    | MaterialType::ThinDielectric
+                    | MaterialType::DiffuseTransmission { .. }
+                    | MaterialType::Subsurface
+                    | MaterialType::Interface
+            )
+        });
+
+        !transmissive
+            && self
+                .shapes
+                .iter()
+                .all(|shape| shape.alpha == Alpha::Constant(1.0))
+    }
+
+    /// Read the PLY files of all `plymesh` shapes concurrently.
+    ///
+    /// Files are read from `source`, at the paths resolved and rewritten while loading.
+    /// Every file is read once, shapes referencing the same file share the mesh.
+    /// Returns a map from shape index to the loaded mesh.
+    #[cfg(feature = "rayon")]
+    pub fn load_meshes_parallel(
+        &self,
+        source: &(dyn AssetSource + Sync),
+    ) -> Result<HashMap<usize, Arc<TriangleMesh>>> {
+        use rayon::prelude::*;
+
+        let shapes = self
+            .shapes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, shape)| match &shape.params {
+                Shape::PlyMesh { filename } => Some((index, filename)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut paths = shapes.iter().map(|(_, path)| *path).collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+
+        let meshes = paths
+            .into_par_iter()
+            .map(|path| Ok((path, Arc::new(TriangleMesh::from_ply(&source.read(path)?)?))))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(shapes
+            .iter()
+            .map(|(index, path)| (*index, Arc::clone(&meshes[path])))
+            .collect())
+    }
+
+    /// Iterate over all light sources of the scene.
+    ///
+    /// Yields the lights declared with `LightSource` first, followed by every emissive shape
+    /// (instances expanded, like [Scene::render_primitives]).
+    pub fn all_light_sources(&self) -> impl Iterator<Item = LightSourceRef<'_>> + '_ {
+        let lights = self.lights.iter().map(LightSourceRef::Light);
+
+        let area_lights = self.render_primitives().filter_map(|primitive| {
+            let area_light = self.area_lights.get(primitive.area_light_index?)?;
+
+            Some(LightSourceRef::Area {
+                primitive,
+                area_light,
+            })
+        });
+
+        lights.chain(area_lights)
+    }
+
+    /// Walk all primitives to render, with instances expanded.
+    ///
+    /// Shapes outside of objects are yielded first, followed by the shapes of each instance.
+    /// Every item is an owned [RenderPrimitive] with medium names copied,
+    /// so iterating allocates per item.
+    pub fn render_primitives(&self) -> impl Iterator<Item = RenderPrimitive> + '_ {
+        let standalone = self
+            .shapes
+            .iter()
+            .zip(self.object_shape_mask())
+            .enumerate()
+            .filter(|(_, (_, is_object_shape))| !is_object_shape)
+            .map(|(shape_index, (shape, _))| RenderPrimitive::new(shape_index, shape, None));
+
+        let instanced =
+            self.instances
+                .iter()
+                .enumerate()
+                .flat_map(move |(instance_index, instance)| {
+                    let range = self
+                        .objects
+                        .get(instance.object_index)
+                        .and_then(|object| {
+                            object
+                                .shape_start
+                                .map(|start| start..start + object.shape_count)
+                        })
+                        .unwrap_or(0..0);
+
+                    range.filter_map(move |shape_index| {
+                        let shape = self.shapes.get(shape_index)?;
+
+                        Some(RenderPrimitive::new(
+                            shape_index,
+                            shape,
+                            Some((instance_index, instance)),
+                        ))
+                    })
+                });
+
+        standalone.chain(instanced)
+    }
+
+    /// Build a scene graph out of the flat entity lists.
+    ///
+    /// Root's children are the object subtrees first, then standalone shapes, then instances.
+    /// An instance node doesn't duplicate the object's shapes, it references the object subtree
+    /// by index. The world transformation of an instanced shape is the instance node's transform
+    /// composed with the shape node's transform.
+    pub fn to_node_tree(&self) -> SceneNode {
+        let mut root = SceneNode::new(SceneNodeKind::Root, Mat4::IDENTITY);
+
+        for (object_index, object) in self.objects.iter().enumerate() {
+            let mut node = SceneNode::new(
+                SceneNodeKind::Object {
+                    object_index,
+                    name: object.name.clone(),
+                },
+                Mat4::IDENTITY,
+            );
+
+            if let Some(start) = object.shape_start {
+                for shape_index in start..(start + object.shape_count).min(self.shapes.len()) {
+                    node.children.push(SceneNode::new(
+                        SceneNodeKind::Shape { shape_index },
+                        self.shapes[shape_index].transform,
+                    ));
+                }
+            }
+
+            root.children.push(node);
+        }
+
+        for ((shape_index, shape), is_object_shape) in
+            self.shapes.iter().enumerate().zip(self.object_shape_mask())
+        {
+            if !is_object_shape {
+                root.children.push(SceneNode::new(
+                    SceneNodeKind::Shape { shape_index },
+                    shape.transform,
+                ));
+            }
+        }
+
+        for (instance_index, instance) in self.instances.iter().enumerate() {
+            root.children.push(SceneNode::new(
+                SceneNodeKind::Instance {
+                    instance_index,
+                    object_index: instance.object_index,
+                },
+                instance.instance_to_world,
+            ));
+        }
+
+        root
+    }
+
+    /// Returns `true` for each shape that's a part of an [Object].
+    fn object_shape_mask(&self) -> Vec<bool> {
+        let mut mask = vec![false; self.shapes.len()];
+
+        for object in &self.objects {
+            if let Some(start) = object.shape_start {
+                let end = (start + object.shape_count).min(mask.len());
+                mask[start.min(end)..end].fill(true);
+            }
+        }
+
+        mask
+    }
+
+    /// Group shapes into the cells of a coarse grid over the scene, for tiled or out-of-core loading.
+    ///
+    /// The grid spans the world space bounds of all shapes and has `grid` cells along each axis
+    /// (at least one). Each shape is placed into the cell containing the centroid of its bounds.
+    /// Shapes without known bounds (`plymesh`) are put into [UNBOUNDED_BUCKET], shapes that are
+    /// part of instanced objects are not included. Returns shape indices per cell, empty cells
+    /// are omitted.
+    pub fn spatial_buckets(&self, grid: [u32; 3]) -> HashMap<[u32; 3], Vec<usize>> {
+        let grid = UVec3::from(grid).max(UVec3::ONE);
+        let is_object_shape = self.object_shape_mask();
+
+        let shape_bounds = self
+            .shapes
+            .iter()
+            .zip(is_object_shape)
+            .enumerate()
+            .filter(|(_, (_, is_object_shape))| !is_object_shape)
+            .map(|(index, (shape, _))| {
+                let bounds = shape_points(&shape.params)
+                    .into_iter()
+                    .map(|point| shape.transform.transform_point3(point))
+                    .fold(None, union_bounds);
+
+                (index, bounds)
+            })
+            .collect::<Vec<_>>();
+
+        let scene_bounds = shape_bounds
+            .iter()
+            .filter_map(|(_, bounds)| *bounds)
+            .flat_map(|(min, max)| [min, max])
+            .fold(None, union_bounds);
+
+        let mut buckets: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+
+        for (index, bounds) in shape_bounds {
+            let cell = match (bounds, scene_bounds) {
+                (Some((min, max)), Some((scene_min, scene_max))) => {
+                    let extent = (scene_max - scene_min).max(Vec3::splat(f32::MIN_POSITIVE));
+                    let relative = ((min + max) * 0.5 - scene_min) / extent;
+                    let cell = (relative * grid.as_vec3()).as_uvec3();
+
+                    cell.min(grid - UVec3::ONE).to_array()
+                }
+                _ => UNBOUNDED_BUCKET,
+            };
+
+            buckets.entry(cell).or_default().push(index);
+        }
+
+        buckets
+    }
+
+    /// Bounding sphere of all shapes in the scene, including instanced ones, as `(center, radius)`.
+    ///
+    /// The sphere encloses the world space bounding box of the shapes.
+    /// Returns `None` if the scene has no shapes with known bounds.
+    pub fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        let (min, max) = self.world_bounds()?;
+
+        Some(((min + max) * 0.5, (max - min).length() * 0.5))
+    }
+
+    /// World space bounds of an instance as `(min, max)`, for building a top-level BVH.
+    ///
+    /// The instance space bounds of the object's shapes are computed first, the transformations of
+    /// the shapes already include the object's `object_to_instance`. The eight corners of that box
+    /// are then transformed by `instance_to_world` and enclosed again.
+    /// `plymesh` shapes are skipped since their vertices live in external files. Returns `None` if
+    /// there is no instance at `instance_index` or none of the object's shapes have known bounds.
+    pub fn instance_bounds(&self, instance_index: usize) -> Option<(Vec3, Vec3)> {
+        let instance = self.instances.get(instance_index)?;
+        let object = self.objects.get(instance.object_index)?;
+        let start = object.shape_start?;
+
+        let mut local = None;
+        for shape in self.shapes.get(start..start + object.shape_count)? {
+            for point in shape_points(&shape.params) {
+                local = union_bounds(local, shape.transform.transform_point3(point));
+            }
+        }
+
+        let (min, max) = local?;
+        let mut bounds = None;
+        for corner in 0..8 {
+            let point = Vec3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            );
+            bounds = union_bounds(bounds, instance.instance_to_world.transform_point3(point));
+        }
+
+        bounds
+    }
+
+    /// Approximate world space bounds of all shapes in the scene, with instances expanded.
+    fn world_bounds(&self) -> Option<(Vec3, Vec3)> {
+        let mut bounds = None;
+
+        for primitive in self.render_primitives() {
+            for point in shape_points(&self.shapes[primitive.shape_index].params) {
+                bounds = union_bounds(bounds, primitive.world_transform.transform_point3(point));
+            }
+        }
+
+        bounds
+    }
+}
+
+/// Feeds formatted text into a hasher.
+struct HashWriter<'a>(&'a mut DefaultHasher);
+
+impl fmt::Write for HashWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Replace `spectra` with RGB values in `color_space`.
+///
+/// Texture references and named spectra without built-in data are left unchanged.
+fn spectra_to_rgb<'a>(spectra: impl Iterator<Item = &'a mut Spectrum>, color_space: ColorSpace) {
+    // Building the color matching tables is expensive, do it once for all spectra.
+    let matching = ColorMatching::new();
+
+    for spectrum in spectra {
+        if let Some(rgb) = matching.to_rgb(spectrum, &color_space) {
+            *spectrum = Spectrum::Rgb(rgb, Some(color_space));
+        }
+    }
+}
+
+/// Convert a mesh read from a PLY file to a shape.
+fn mesh_shape(mesh: TriangleMesh) -> Shape {
+    Shape::TriangleMesh {
+        alpha: 1.0,
+        indices: mesh.indices,
+        positions: mesh.positions,
+        normals: mesh.normals,
+        tangents: None,
+        uvs: mesh.uvs,
+        colors: (!mesh.colors.is_empty()).then(|| {
+            mesh.colors
+                .chunks_exact(3)
+                .map(|c| [c[0], c[1], c[2]])
+                .collect()
+        }),
+        face_indices: None,
+        edge_length: None,
+    }
+}
+
+/// Flag the textures referenced by the `used_materials` and the alpha masks of `shapes`,
+/// along with the textures they reference in turn.
+fn used_textures(
+    textures: &[Texture],
+    materials: &[Material],
+    used_materials: &[bool],
+    shapes: &[ShapeEntity],
+) -> Vec<bool> {
+    let mut used = vec![false; textures.len()];
+
+    for (material, _) in materials
+        .iter()
+        .zip(used_materials)
+        .filter(|(_, used)| **used)
+    {
+        for index in material
+            .texture_references()
+            .into_iter()
+            .chain(material.displacement)
+        {
+            used[index] = true;
+        }
+    }
+
+    for shape in shapes {
+        if let Alpha::Texture(index) = shape.alpha {
+            used[index] = true;
+        }
+    }
+
+    // Textures only reference textures defined before them.
+    for index in (0..textures.len()).rev() {
+        if used[index] {
+            for texture in textures[index].texture_references() {
+                used[texture] = true;
+            }
+        }
+    }
+
+    used
+}
+
+/// Keep the `used` items in their original order.
+///
+/// Returns the new index of each item, `None` for removed items.
+fn retain_used<T>(items: &mut Vec<T>, used: &[bool]) -> Vec<Option<usize>> {
+    let mut next = 0;
+    let indices = used
+        .iter()
+        .map(|&used| {
+            used.then(|| {
+                next += 1;
+                next - 1
+            })
+        })
+        .collect();
+
+    let mut used = used.iter();
+    items.retain(|_| *used.next().unwrap_or(&false));
+    indices
+}
+
+/// Grow `bounds` to include `point`.
+fn union_bounds(bounds: Option<(Vec3, Vec3)>, point: Vec3) -> Option<(Vec3, Vec3)> {
+    Some(match bounds {
+        Some((min, max)) => (min.min(point), max.max(point)),
+        None => (point, point),
+    })
+}
+
+/// Internal knobs for [Scene::load_with_includes].
+#[derive(Default)]
+pub(crate) struct LoadHooks<'t> {
+    /// Record the transform directives of each shape.
+    pub traces: Option<&'t mut Vec<Vec<TransformOp>>>,
+    /// Don't construct shapes, `Scene::shapes` is left empty.
+    pub skip_shapes: bool,
+    /// Stop loading at `WorldBegin`.
+    pub header_only: bool,
+    /// Abort loading with [Error::Cancelled] once set.
+    pub cancel: Option<&'t AtomicBool>,
+}
+
+/// Number of elements parsed between checks of [LoadHooks::cancel].
+const CANCEL_CHECK_INTERVAL: usize = 1024;
+
+/// Convert a medium name from `MediumInterface`, empty string stands for vacuum.
+fn medium_name(name: Cow<str>) -> Option<Cow<str>> {
+    (!name.is_empty()).then_some(name)
+}
+
+/// Returns the directive name of elements that pbrt only accepts inside the world block.
+fn world_only_directive(element: &Element) -> Option<&'static str> {
+    let directive = match element {
+        Element::Shape { .. } => "Shape",
+        Element::LightSource { .. } => "LightSource",
+        Element::AreaLightSource { .. } => "AreaLightSource",
+        Element::Material { .. } => "Material",
+        Element::MakeNamedMaterial { .. } => "MakeNamedMaterial",
+        Element::NamedMaterial { .. } => "NamedMaterial",
+        Element::Texture { .. } => "Texture",
+        Element::ObjectBegin { .. } => "ObjectBegin",
+        Element::ObjectInstance { .. } => "ObjectInstance",
+        _ => return None,
+    };
+
+    Some(directive)
+}
+
+/// Returns the directive name and parameters of elements that accept a parameter list.
+fn element_params<'a, 'b>(
+    element: &'b mut Element<'a>,
+) -> Option<(&'static str, &'b mut ParamList<'a>)> {
+    let res = match element {
+        Element::Film { params, .. } => ("Film", params),
+        Element::Camera { params, .. } => ("Camera", params),
+        Element::Sampler { params, .. } => ("Sampler", params),
+        Element::Integrator { params, .. } => ("Integrator", params),
+        Element::Accelerator { params, .. } => ("Accelerator", params),
+        Element::PixelFilter { params, .. } => ("PixelFilter", params),
+        Element::Attribute { params, .. } => ("Attribute", params),
+        Element::LightSource { params, .. } => ("LightSource", params),
+        Element::AreaLightSource { params, .. } => ("AreaLightSource", params),
+        Element::Material { params, .. } => ("Material", params),
+        Element::MakeNamedMaterial { params, .. } => ("MakeNamedMaterial", params),
+        Element::Texture { params, .. } => ("Texture", params),
+        Element::Shape { params, .. } => ("Shape", params),
+        Element::MakeNamedMedium { params, .. } => ("MakeNamedMedium", params),
+        _ => return None,
+    };
+
+    Some(res)
+}
+
+/// Resolve a path given to an `Include` directive.
+///
+/// If the filename given to a Include or Import statement is not an absolute path,
+/// its path is interpreted as being relative to the directory of the initial file being parsed as
+/// specified with pbrt's command-line arguments.
+/// Without a working directory, `base` decides how relative paths are handled.
+fn include_path(
+    path: &str,
+    working_directory: Option<&Path>,
+    base: &IncludeBase,
+) -> Result<PathBuf> {
+    let path = Path::new(path);
+
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+
+    let full_path = match (working_directory, base) {
+        (Some(directory), _) => directory.join(path),
+        (None, IncludeBase::Path(directory)) => directory.join(path),
+        (None, IncludeBase::CurrentDir) => env::current_dir()?.join(path),
+        (None, IncludeBase::Error) => return Err(Error::RelativeInclude(path.to_path_buf())),
+    };
+
+    Ok(full_path)
+}
+
+/// Contents of an included file.
+pub(crate) enum IncludeData {
+    /// File data read into a heap allocated string.
+    Heap(String),
+    /// Memory mapped file, validated to be UTF-8.
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl IncludeData {
+    /// Read a file at `path`.
+    ///
+    /// With `mmap` feature enabled the file is memory mapped instead of being read
+    /// into the heap. If mapping fails (for instance, for empty files or on
+    /// file systems that don't support it), falls back to [fs::read_to_string].
+    pub(crate) fn read(path: &Path) -> Result<Self> {
+        #[cfg(feature = "mmap")]
+        {
+            let file = fs::File::open(path)?;
+
+            // SAFETY: the mapping is read-only and kept alive until the scene loading is done.
+            // The file must not be modified by other processes while the scene is loading.
+            if let Ok(mmap) = unsafe { memmap2::Mmap::map(&file) } {
+                if let Err(err) = str::from_utf8(&mmap) {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, err).into());
+                }
+
+                return Ok(IncludeData::Mapped(mmap));
+            }
+        }
+
+        Ok(IncludeData::Heap(fs::read_to_string(path)?))
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            IncludeData::Heap(data) => data.as_str(),
+            // SAFETY: validated in `IncludeData::read`.
+            #[cfg(feature = "mmap")]
+            IncludeData::Mapped(mmap) => unsafe { str::from_utf8_unchecked(mmap) },
+        }
+    }
+}
+
+/// Reduce an optional spectrum to a single scalar value, see [Spectrum::average].
+fn spectrum_power(spectrum: Option<&Spectrum>) -> f32 {
+    spectrum.and_then(Spectrum::average).unwrap_or(0.0)
+}
+
+/// Object space points that enclose a shape.
+fn shape_points(shape: &Shape) -> Vec<Vec3> {
+    let corners = |radius: f32, zmin: f32, zmax: f32| {
+        let min = Vec3::new(-radius, -radius, zmin);
+        let max = Vec3::new(radius, radius, zmax);
+
+        vec![min, max]
+    };
+
+    match shape {
+        Shape::Sphere {
+            radius, zmin, zmax, ..
+        } => corners(*radius, *zmin, *zmax),
+        Shape::Cylinder {
+            radius, zmin, zmax, ..
+        } => corners(*radius, *zmin, *zmax),
+        Shape::Disk { radius, height, .. } => corners(*radius, *height, *height),
+        Shape::TriangleMesh { positions, .. } | Shape::Curve { positions, .. } => {
+            positions.chunks_exact(3).map(Vec3::from_slice).collect()
+        }
+        Shape::PlyMesh { .. } => Vec::new(),
+    }
+}
+
+/// Compute the surface area of a shape, if possible.
+///
+/// Like in pbrt, the area of analytic shapes is computed in object space,
+/// while meshes are measured in world space.
+fn shape_area(shape: &Shape, transform: &Mat4) -> Option<f32> {
+    let area = match shape {
+        Shape::Sphere {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => {
+            let radius = radius.abs();
+            let zmin = zmin.min(*zmax).max(-radius);
+            let zmax = zmax.max(zmin).min(radius);
+
+            phimax.clamp(0.0, 360.0).to_radians() * radius * (zmax - zmin).max(0.0)
+        }
+        Shape::Cylinder {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => (zmax - zmin).abs() * radius * phimax.clamp(0.0, 360.0).to_radians(),
+        Shape::Disk {
+            radius,
+            innerradius,
+            phimax,
+            ..
+        } => {
+            phimax.clamp(0.0, 360.0).to_radians()
+                * 0.5
+                * (radius * radius - innerradius * innerradius)
+        }
+        Shape::TriangleMesh {
+            indices, positions, ..
+        } => {
+            let vertex = |index: i32| -> Option<Vec3> {
+                let start = usize::try_from(index).ok()? * 3;
+                let p = positions.get(start..start + 3)?;
+                Some(transform.transform_point3(Vec3::from_slice(p)))
+            };
+
+            let mut area = 0.0;
+            for triangle in indices.chunks_exact(3) {
+                let p0 = vertex(triangle[0])?;
+                let p1 = vertex(triangle[1])?;
+                let p2 = vertex(triangle[2])?;
+
+                area += 0.5 * (p1 - p0).cross(p2 - p0).length();
+            }
+
+            area
+        }
+        Shape::Curve { .. } | Shape::PlyMesh { .. } => return None,
+    };
+
+    Some(area)
+}
+
+/// Compute the world space surface area of a shape with the given object to world transform.
+///
+/// Analytic shapes can only be measured under uniform scale, see [ShapeEntity::surface_area].
+fn world_shape_area(shape: &Shape, transform: &Mat4) -> Option<f32> {
+    let area = shape_area(shape, transform)?;
+    if let Shape::TriangleMesh { .. } = shape {
+        return Some(area);
+    }
+
+    squared_uniform_scale(transform).map(|k| area * k)
+}
+
+/// Returns `k` if the upper 3x3 matrix `M` of `transform` satisfies `MᵀM ≈ k·I`.
+///
+/// That is the case exactly when `M` is a rotation (or reflection) followed by a uniform scale
+/// of `√k`. Comparing scale factors alone isn't enough, a non-uniform scale followed by a
+/// rotation can shear the axes while keeping their lengths equal.
+fn squared_uniform_scale(transform: &Mat4) -> Option<f32> {
+    let m = Mat3::from_mat4(*transform);
+    let metric = m.transpose() * m;
+
+    let k = (metric.x_axis.x + metric.y_axis.y + metric.z_axis.z) / 3.0;
+    let deviation = (metric - Mat3::from_diagonal(Vec3::splat(k)))
+        .to_cols_array()
+        .into_iter()
+        .fold(0.0_f32, |max, value| max.max(value.abs()));
+
+    (deviation <= ShapeEntity::SCALE_EPSILON * k).then_some(k)
+}
+
+/// Returns `true` if the radiance emitted by `area_light` is finite and non-negative.
+fn has_valid_emission(area_light: &AreaLight) -> bool {
+    let AreaLight::Diffuse {
+        spectrum, scale, ..
+    } = area_light;
+
+    let is_valid = |value: f32| value.is_finite() && value >= 0.0;
+
+    let values = match spectrum {
+        Some(Spectrum::Rgb(rgb, _)) => rgb.to_vec(),
+        Some(Spectrum::Sampled(samples)) => samples.iter().map(|[_, value]| *value).collect(),
+        _ => Vec::new(),
+    };
+
+    is_valid(*scale) && values.into_iter().all(|value| is_valid(value * scale))
+}
+
+/// Returns the smallest positive `t` at which the object space ray `origin + t * dir` hits the shape.
+fn intersect_shape(shape: &Shape, origin: Vec3, dir: Vec3) -> Option<f32> {
+    // Check the azimuth of a hit point against the maximum phi in degrees.
+    let within_phi = |p: Vec3, phimax: f32| {
+        let mut phi = p.y.atan2(p.x);
+        if phi < 0.0 {
+            phi += 2.0 * PI;
+        }
+        phi <= phimax.to_radians()
+    };
+
+    // Nearest positive root of `a * t^2 + b * t + c` accepted by `hit`.
+    let quadratic = |a: f32, b: f32, c: f32, hit: &dyn Fn(Vec3) -> bool| {
+        let discriminant = b * b - 4.0 * a * c;
+        if a == 0.0 || discriminant < 0.0 {
+            return None;
+        }
+
+        let root = discriminant.sqrt();
+        let (t0, t1) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+        let (t0, t1) = (t0.min(t1), t0.max(t1));
+
+        [t0, t1]
+            .into_iter()
+            .find(|&t| t > 0.0 && hit(origin + dir * t))
+    };
+
+    match shape {
+        Shape::Sphere {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => quadratic(
+            dir.length_squared(),
+            2.0 * origin.dot(dir),
+            origin.length_squared() - radius * radius,
+            &|p| (*zmin..=*zmax).contains(&p.z) && within_phi(p, *phimax),
+        ),
+        Shape::Cylinder {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => quadratic(
+            dir.x * dir.x + dir.y * dir.y,
+            2.0 * (origin.x * dir.x + origin.y * dir.y),
+            origin.x * origin.x + origin.y * origin.y - radius * radius,
+            &|p| (*zmin..=*zmax).contains(&p.z) && within_phi(p, *phimax),
+        ),
+        Shape::Disk {
+            height,
+            radius,
+            innerradius,
+            phimax,
+            ..
+        } => {
+            if dir.z == 0.0 {
+                return None;
+            }
+
+            let t = (height - origin.z) / dir.z;
+            let p = origin + dir * t;
+            let distance = (p.x * p.x + p.y * p.y).sqrt();
+
+            (t > 0.0 && (*innerradius..=*radius).contains(&distance) && within_phi(p, *phimax))
+                .then_some(t)
+        }
+        Shape::TriangleMesh {
+            indices, positions, ..
+        } => {
+            let vertex = |index: i32| -> Option<Vec3> {
+                let start = usize::try_from(index).ok()? * 3;
+                positions.get(start..start + 3).map(Vec3::from_slice)
+            };
+
+            let mut nearest: Option<f32> = None;
+
+            for triangle in indices.chunks_exact(3) {
+                let (Some(p0), Some(p1), Some(p2)) = (
+                    vertex(triangle[0]),
+                    vertex(triangle[1]),
+                    vertex(triangle[2]),
+                ) else {
+                    continue;
+                };
+
+                // Möller–Trumbore ray-triangle intersection.
+                let e1 = p1 - p0;
+                let e2 = p2 - p0;
+                let pvec = dir.cross(e2);
+                let det = e1.dot(pvec);
+                if det.abs() < f32::EPSILON {
+                    continue;
+                }
+
+                let tvec = origin - p0;
+                let u = tvec.dot(pvec) / det;
+                if !(0.0..=1.0).contains(&u) {
+                    continue;
+                }
+
+                let qvec = tvec.cross(e1);
+                let v = dir.dot(qvec) / det;
+                if v < 0.0 || u + v > 1.0 {
+                    continue;
+                }
+
+                let t = e2.dot(qvec) / det;
+                if t > 0.0 && nearest.map_or(true, |nearest| t < nearest) {
+                    nearest = Some(t);
+                }
+            }
+
+            nearest
+        }
+        Shape::Curve { .. } | Shape::PlyMesh { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        param::ParamType,
+        types::{FloatOrSpectrum, LayerDepth, MaterialType, TextureInput},
+        FsAssetSource,
+    };
+
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_includes() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-includes-")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(temp_path.join("1.pbrt"), "Shape \"sphere\"")?;
+        fs::write(temp_path.join("2.pbrt"), "Include \"1.pbrt\" ")?;
+        fs::write(temp_path.join("3.pbrt"), "Include \"2.pbrt\" ")?;
+        fs::write(temp_path.join("4.pbrt"), "Include \"3.pbrt\" ")?;
+
+        fs::write(
+            temp_path.join("main.pbrt"),
+            r#"
+WorldBegin
+
+Include "4.pbrt" # Include file with nexted includes
+Include "1.pbrt" # Include shap directly
+
+        "#,
+        )?;
+
+        let scene = Scene::from_file(temp_path.join("main.pbrt"))?;
+
+        debug_assert_eq!(scene.shapes.len(), 2);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn load_meshes_parallel() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-ply-")?;
+        let temp_path = temp_dir.path();
+
+        let ply = |vertex_count: usize| {
+            let mut data = format!(
+                "ply\nformat ascii 1.0\nelement vertex {vertex_count}\n\
+                 property float x\nproperty float y\nproperty float z\n\
+                 element face 1\nproperty list uchar int vertex_indices\nend_header\n"
+            );
+            for i in 0..vertex_count {
+                data += &format!("{i} {} 0\n", i % 2);
+            }
+            data + "3 0 1 2\n"
+        };
+
+        fs::write(temp_path.join("a.ply"), ply(3))?;
+        fs::write(temp_path.join("b.ply"), ply(4))?;
+
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Shape "plymesh" "string filename" "a.ply"
+Shape "sphere"
+Shape "plymesh" "string filename" "b.ply"
+Shape "plymesh" "string filename" "a.ply"
+            "#,
+            Some(temp_path),
+        )?;
+
+        let meshes = scene.load_meshes_parallel(&FsAssetSource)?;
+
+        assert_eq!(meshes.len(), 3);
+        assert_eq!(meshes[&0].positions.len(), 9);
+        assert_eq!(meshes[&2].positions.len(), 12);
+        assert!(Arc::ptr_eq(&meshes[&0], &meshes[&3]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn relative_include_base() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-include-base-")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(temp_path.join("shape.pbrt"), "Shape \"sphere\"")?;
+
+        let data = "WorldBegin\nInclude \"shape.pbrt\"\n";
+
+        // Relative includes need a base directory by default.
+        let result = Scene::load(data, None);
+        assert!(
+            matches!(&result, Err(Error::RelativeInclude(path)) if path == Path::new("shape.pbrt"))
+        );
+
+        let options = LoadOptions {
+            include_base: IncludeBase::Path(temp_path.to_path_buf()),
+            ..Default::default()
+        };
+        let scene = Scene::load_with_options(data, None, &options)?;
+        assert_eq!(scene.shapes.len(), 1);
+
+        // An explicit working directory takes precedence.
+        let scene = Scene::load(data, Some(temp_path))?;
+        assert_eq!(scene.shapes.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_instancing() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+ObjectBegin "foo"
+Shape "sphere"
+Shape "sphere"
+ObjectEnd
+
+ObjectInstance "foo"
+Translate 1 0 0
+ObjectInstance "foo"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!(scene.shapes.len(), 2);
+
+        assert!(matches!(scene.shapes[0].params, Shape::Sphere { .. }));
+        assert!(matches!(scene.shapes[0].params, Shape::Sphere { .. }));
+
+        assert_eq!(scene.objects.len(), 1);
+
+        let object = &scene.objects[0];
+        assert_eq!(&object.name, "foo");
+        assert_eq!(object.shape_start, Some(0));
+        assert_eq!(object.shape_count, 2);
+
+        assert_eq!(scene.instances.len(), 2);
+
+        let inst1 = &scene.instances[0];
+        assert_eq!(inst1.object_index, 0);
+
+        let inst2 = &scene.instances[1];
+        assert_eq!(inst1.object_index, 0);
+        assert_ne!(inst1.instance_to_world, inst2.instance_to_world);
+
+        Ok(())
+    }
+
+    #[test]
+    fn instancing_attribute_blocks() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+ObjectBegin "foo"
+AttributeBegin
+    Shape "sphere"
+AttributeEnd
+Shape "disk"
+ObjectEnd
+ObjectInstance "foo"
+            "#,
+            None,
+        )?;
+
+        // Shapes inside attribute blocks still belong to the object.
+        let object = &scene.objects[0];
+        assert_eq!(object.shape_start, Some(0));
+        assert_eq!(object.shape_count, 2);
+        assert_eq!(scene.render_primitives().count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn total_emitted_power() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+LightSource "point" "rgb I" [ 2 2 2 ]
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        let expected = 4.0 * PI * 2.0;
+        assert!((scene.total_emitted_power() - expected).abs() < 1e-4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interface_material() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+Material "interface"
+Shape "sphere"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!(scene.materials.len(), 1);
+        assert!(matches!(scene.materials[0].ty, MaterialType::Interface));
+        assert_eq!(scene.shapes[0].material_index, Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_orientation_plain_shape() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+Shape "sphere"
+
+AttributeBegin
+ReverseOrientation
+Shape "sphere"
+AttributeEnd
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!(scene.effective_reverse_orientation(0, None), Some(false));
+        assert_eq!(scene.effective_reverse_orientation(1, None), Some(true));
+        assert_eq!(scene.effective_reverse_orientation(2, None), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_orientation_instance() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+ObjectBegin "foo"
+Shape "sphere"
+ObjectEnd
+
+ObjectInstance "foo"
+
+ReverseOrientation
+ObjectInstance "foo"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!(scene.effective_reverse_orientation(0, Some(0)), Some(false));
+        assert_eq!(scene.effective_reverse_orientation(0, Some(1)), Some(true));
+        assert_eq!(scene.effective_reverse_orientation(0, Some(2)), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_orientation_object_scope() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+Shape "sphere"
+
+ObjectBegin "foo"
+ReverseOrientation
+Shape "sphere"
+ObjectEnd
+
+Shape "sphere"
+ObjectInstance "foo"
+
+ReverseOrientation
+ObjectBegin "bar"
+Shape "sphere"
+ObjectEnd
+ObjectInstance "bar"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        let flags = scene
+            .shapes
+            .iter()
+            .map(|shape| shape.reverse_orientation)
+            .collect::<Vec<_>>();
+        // The flag set inside "foo" doesn't leak out of the object,
+        // shapes of "bar" inherit the flag set before `ObjectBegin`.
+        assert_eq!(flags, vec![false, true, false, true]);
+
+        assert!(!scene.instances[0].reverse_orientation);
+        assert!(scene.instances[1].reverse_orientation);
+
+        assert_eq!(scene.effective_reverse_orientation(1, Some(0)), Some(true));
+        // Like the CTM, the flag active at `ObjectBegin` is recorded by the shapes and applies
+        // again through the instance, so the two cancel each other out.
+        assert_eq!(scene.effective_reverse_orientation(3, Some(1)), Some(false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_orientation_mirror_transform() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+ObjectBegin "foo"
+ReverseOrientation
+Shape "sphere"
+ObjectEnd
+
+Scale -1 1 1
+ObjectInstance "foo"
+
+ReverseOrientation
+ObjectInstance "foo"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        // Shape flag and mirror transform cancel each other out.
+        assert_eq!(scene.effective_reverse_orientation(0, Some(0)), Some(false));
+        // All three flags are set.
+        assert_eq!(scene.effective_reverse_orientation(0, Some(1)), Some(true));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_includes() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-mmap-")?;
+        let temp_path = temp_dir.path();
+
+        let count = 100_000;
+
+        let mut geometry = String::new();
+        for i in 0..count {
+            geometry.push_str(&format!("Shape \"sphere\" \"float radius\" [ {i} ]\n"));
+        }
+
+        fs::write(temp_path.join("geometry.pbrt"), geometry)?;
+        fs::write(
             temp_path.join("main.pbrt"),
+            "WorldBegin\nInclude \"geometry.pbrt\"\n",
+        )?;
+
+        let data = IncludeData::read(&temp_path.join("geometry.pbrt"))?;
+        assert!(matches!(data, IncludeData::Mapped(_)));
+
+        let scene = Scene::from_file(temp_path.join("main.pbrt"))?;
+        assert_eq!(scene.shapes.len(), count);
+
+        let Shape::Sphere { radius, .. } = scene.shapes[count - 1].params else {
+            panic!("Unexpected shape type");
+        };
+        assert_eq!(radius, (count - 1) as f32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn gltf_transforms() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+ObjectBegin "foo"
+Shape "sphere"
+ObjectEnd
+
+Translate 1 2 3
+ObjectInstance "foo"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        let transforms = scene.to_gltf_transforms();
+        assert_eq!(transforms.len(), 1);
+
+        let (name, matrix) = &transforms[0];
+        assert_eq!(name, "foo");
+
+        let expected = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [1.0, 2.0, -3.0, 1.0],
+        ];
+        assert_eq!(matrix, &expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_cameras() -> Result<()> {
+        let data = r#"
+LookAt 0 0 -5 0 0 0 0 1 0
+Camera "perspective" "float fov" [ 45 ]
+
+LookAt 0 0 0 1 0 0 0 1 0
+Camera "orthographic"
+
+WorldBegin
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!(scene.cameras.len(), 2);
+        assert!(matches!(
+            scene.cameras[0].params,
+            Camera::Perspective { .. }
+        ));
+        assert!(matches!(
+            scene.cameras[1].params,
+            Camera::Orthographic { .. }
+        ));
+        assert_ne!(scene.cameras[0].transform, scene.cameras[1].transform);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unused_entities() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+Texture "checks" "spectrum" "checkerboard"
+Texture "orphan" "spectrum" "checkerboard"
+
+MakeNamedMaterial "orphan" "string type" "diffuse"
+MakeNamedMaterial "used" "string type" "diffuse" "texture reflectance" "checks"
+
+NamedMaterial "used"
+Shape "sphere"
+
+ObjectBegin "foo"
+Shape "sphere"
+ObjectEnd
+
+ObjectBegin "bar"
+Shape "sphere"
+ObjectEnd
+
+ObjectInstance "bar"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+        let report = scene.unused_entities();
+
+        assert_eq!(report.materials, vec![0]);
+        assert_eq!(report.textures, vec![1]);
+        assert_eq!(report.objects, vec![0]);
+        assert!(!report.is_empty());
+
+        let data = r#"
+WorldBegin
+
+Texture "a" "spectrum" "checkerboard"
+Texture "orphan" "spectrum" "checkerboard"
+Texture "b" "spectrum" "checkerboard"
+Texture "amount" "float" "checkerboard"
+Texture "mix" "spectrum" "mix" "texture tex1" "a" "texture tex2" "b" "texture amount" "amount"
+Texture "bumps" "float" "checkerboard"
+Texture "roughness" "float" "checkerboard"
+
+Material "coateddiffuse" "texture reflectance" "mix" "texture roughness" "roughness"
+    "texture displacement" "bumps"
+Shape "sphere"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+        assert_eq!(scene.unused_entities().textures, vec![1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lookup_by_name() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+Material "diffuse"
+MakeNamedMaterial "gold" "string type" "conductor"
+
+ObjectBegin "foo"
+Shape "sphere"
+ObjectEnd
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!(scene.named_materials.get("gold"), Some(&1));
+
+        let material = scene.material_by_name("gold").unwrap();
+        assert_eq!(material.name, "gold");
+        assert!(matches!(material.ty, MaterialType::Conductor { .. }));
+
+        assert!(scene.material_by_name("silver").is_none());
+        assert_eq!(scene.object_by_name("foo").unwrap().name, "foo");
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_canonical() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+            LookAt 0 1 -5  0 0 0  0 1 0
+            Camera "perspective" "float fov" 45
+            Film "rgb" "integer yresolution" 256 "integer xresolution" 512
+            Sampler "halton" "integer pixelsamples" 64
+            WorldBegin
+            LightSource "infinite" "rgb L" [0.4 0.45 0.5]
+            Texture "checks" "spectrum" "checkerboard"
+                "float uscale" 8 "float vscale" 8
+                "rgb tex1" [1 0 0] "rgb tex2" [0 0 1]
+            MakeNamedMaterial "floor" "string type" "diffuse" "texture reflectance" "checks"
+            ColorSpace "rec2020"
+            AttributeBegin
+                NamedMaterial "floor"
+                Translate 0 -1 0
+                Shape "trianglemesh" "point3 P" [-10 0 -10  10 0 -10  10 0 10  -10 0 10]
+                    "integer indices" [0 1 2  0 2 3]
+            AttributeEnd
+            AttributeBegin
+                AreaLightSource "diffuse" "rgb L" [4 4 4]
+                Material "dielectric" "float eta" 1.33
+                ReverseOrientation
+                Shape "sphere" "float radius" 0.25
+            AttributeEnd
+            ObjectBegin "pebble"
+                Shape "sphere" "float radius" 0.1
+            ObjectEnd
+            Translate 1 0 0
+            ObjectInstance "pebble"
+            "#,
+            None,
+        )?;
+
+        let write = |scene: &Scene| -> Result<String> {
+            let mut out = Vec::new();
+            scene.write_canonical(&mut out)?;
+            Ok(String::from_utf8(out).unwrap())
+        };
+
+        let first = write(&scene)?;
+        assert_eq!(first, write(&scene)?);
+
+        // Parameters are sorted, colors in other color spaces are tagged.
+        assert!(first.contains(
+            "Film \"rgb\"\n    \"float cropwindow\" [ 0.0 1.0 0.0 1.0 ]\n    \"float diagonal\" 35.0\n"
+        ));
+        assert!(first.contains("ColorSpace \"rec2020\"\n"));
+        assert!(first.contains("    ReverseOrientation\n"));
+
+        // The written scene loads back to the same scene.
+        let reloaded = Scene::load(&first, None)?;
+        assert_eq!(reloaded.shapes.len(), scene.shapes.len());
+        assert_eq!(reloaded.instances.len(), 1);
+        assert_eq!(reloaded.objects[0].shape_count, 1);
+        assert_eq!(write(&reloaded)?, first);
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_object() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+LightSource "point"
+Texture "unused" "float" "constant"
+Texture "checks" "spectrum" "checkerboard"
+MakeNamedMaterial "unrelated" "string type" "conductor"
+MakeNamedMaterial "stone" "string type" "diffuse" "texture reflectance" "checks"
+Shape "disk"
+
+ObjectBegin "foo"
+NamedMaterial "stone"
+Shape "sphere"
+Shape "sphere"
+ObjectEnd
+
+ObjectBegin "bar"
+Shape "cylinder"
+ObjectEnd
+
+ObjectInstance "foo"
+Translate 1 0 0
+ObjectInstance "foo"
+ObjectInstance "bar"
+        "#;
+
+        let scene = Scene::load_object(data, None, "foo")?;
+
+        assert_eq!(scene.shapes.len(), 2);
+        assert!(scene
+            .shapes
+            .iter()
+            .all(|shape| matches!(shape.params, Shape::Sphere { .. })
+                && shape.material_index == Some(0)));
+
+        assert_eq!(scene.objects.len(), 1);
+        assert_eq!(scene.objects[0].shape_start, Some(0));
+        assert_eq!(scene.objects[0].shape_count, 2);
+        assert_eq!(scene.object_by_name("foo").map(|o| o.shape_count), Some(2));
+
+        assert_eq!(scene.instances.len(), 2);
+        assert!(scene
+            .instances
+            .iter()
+            .all(|instance| instance.object_index == 0));
+
+        assert!(scene.lights.is_empty());
+        assert_eq!(scene.materials.len(), 1);
+        assert_eq!(
+            scene
+                .material_by_name("stone")
+                .map(|m| m.texture_references()),
+            Some(vec![0])
+        );
+        assert!(scene.material_by_name("unrelated").is_none());
+        assert_eq!(scene.textures.len(), 1);
+        assert_eq!(scene.textures[0].name, "checks");
+
+        assert!(matches!(
+            Scene::load_object(data, None, "baz"),
+            Err(Error::NotFound)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_shapes() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+AttributeBegin
+Translate 1 2 3
+Shape "sphere" "float radius" 2
+AttributeEnd
+AttributeBegin
+Translate 1 2 3
+Shape "sphere" "float radius" 2
+AttributeEnd
+Shape "sphere" "float radius" 2
+AttributeBegin
+Translate 1 2 3
+Shape "sphere" "float radius" 3
+AttributeEnd
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.duplicate_shapes(), vec![(0, 1)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn effective_samples_per_pixel() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Sampler "halton" "integer pixelsamples" 16
+Integrator "mlt" "integer mutationsperpixel" 256 "integer chains" 8
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        assert!(matches!(
+            scene.integrator,
+            Some(Integrator::Mlt {
+                mutations_per_pixel: 256,
+                chains: 8,
+                ..
+            })
+        ));
+        assert_eq!(scene.effective_samples_per_pixel(), 256);
+
+        let scene = Scene::load(
+            r#"
+Sampler "halton" "integer pixelsamples" 16
+Integrator "sppm" "integer photonsperiteration" 1000
+WorldBegin
+            "#,
+            None,
+        )?;
+        assert_eq!(scene.effective_samples_per_pixel(), 16);
+
+        let scene = Scene::load("WorldBegin", None)?;
+        assert_eq!(scene.effective_samples_per_pixel(), 16);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bake() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+ObjectBegin "foo"
+Shape "sphere"
+Shape "sphere"
+ObjectEnd
+
+ObjectInstance "foo"
+Translate 1 0 0
+ObjectInstance "foo"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+        let baked = scene.bake(&FsAssetSource)?;
+
+        assert_eq!(baked.primitives.len(), 4);
+        assert!(baked
+            .primitives
+            .iter()
+            .all(|primitive| primitive.material == Material::default()
+                && matches!(primitive.shape, Shape::Sphere { .. })));
+        assert_eq!(
+            baked.primitives[2].world_transform,
+            Mat4::from_translation(Vec3::X)
+        );
+        assert!(matches!(baked.integrator, Integrator::VolPath { .. }));
+
+        let mut baked = Scene::load(
+            r#"
+WorldBegin
+Material "conductor" "spectrum eta" "glass-BK7"
+Shape "sphere"
+            "#,
+            None,
+        )?
+        .bake(&FsAssetSource)?;
+        baked.bake_spectra_to_rgb(ColorSpace::Srgb);
+        assert!(matches!(
+            baked.primitives[0].material.ty,
+            MaterialType::Conductor {
+                eta: Spectrum::Rgb(_, Some(ColorSpace::Srgb)),
+                ..
+            }
+        ));
+
+        let scene = Scene::load(
+            "WorldBegin\nShape \"plymesh\" \"string filename\" \"missing.ply\"",
+            None,
+        )?;
+        assert!(matches!(scene.bake(&FsAssetSource), Err(Error::Io(_))));
+
+        // Mesh paths are already relative to the working directory the scene was loaded with.
+        let scene = Scene::load(
+            "WorldBegin\nShape \"plymesh\" \"string filename\" \"m.ply\"",
+            Some(Path::new("scn")),
+        )?;
+        let source = HashMap::from([(
+            PathBuf::from("scn/m.ply"),
+            b"ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+              property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+              end_header\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n"
+                .to_vec(),
+        )]);
+        let baked = scene.bake(&source)?;
+        assert!(matches!(
+            &baked.primitives[0].shape,
+            Shape::TriangleMesh { indices, .. } if indices == &[0, 1, 2]
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shape_dependencies() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Texture "wood" "spectrum" "imagemap" "string filename" "wood.png"
+Texture "dirt" "spectrum" "imagemap" "string filename" "dirt.png"
+Texture "mask" "float" "imagemap" "string filename" "mask.png"
+Texture "blend" "spectrum" "mix" "texture tex1" "wood" "texture tex2" "dirt"
+Texture "unused" "spectrum" "imagemap" "string filename" "unused.png"
+
+AttributeBegin
+Material "diffuse" "texture reflectance" "blend"
+Shape "plymesh" "string filename" "mesh.ply" "texture alpha" "mask"
+AttributeEnd
+
+Material "diffuse" "texture reflectance" "wood"
+Shape "sphere"
+            "#,
+            Some(Path::new("scene")),
+        )?;
+
+        let mut files = scene.shape_dependencies(0);
+        files.sort();
+        assert_eq!(
+            files,
+            ["dirt.png", "mask.png", "mesh.ply", "wood.png"].map(|f| Path::new("scene").join(f))
+        );
+
+        assert_eq!(
+            scene.shape_dependencies(1),
+            vec![PathBuf::from("scene/wood.png")]
+        );
+        assert!(scene.shape_dependencies(2).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rewrite_path() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+Texture "wood" "spectrum" "imagemap" "string filename" "textures/wood.tga"
+Shape "plymesh" "string filename" "geometry/mesh.ply"
+LightSource "infinite" "string filename" "sky.tga"
+AreaLightSource "diffuse" "string filename" "emission.tga"
+Shape "sphere"
+        "#;
+
+        let options = LoadOptions {
+            rewrite_path: Some(Box::new(|path: &Path| {
+                if path.extension().map_or(false, |ext| ext == "tga") {
+                    path.with_extension("exr")
+                } else {
+                    path.to_path_buf()
+                }
+            })),
+            ..Default::default()
+        };
+
+        let scene = Scene::load_with_options(data, Some(Path::new("scene")), &options)?;
+
+        let TextureClass::ImageMap { filename } = &scene.textures[0].class else {
+            panic!("Unexpected texture class");
+        };
+        assert_eq!(filename, Path::new("scene/textures/wood.exr"));
+
+        let Shape::PlyMesh { filename } = &scene.shapes[0].params else {
+            panic!("Unexpected shape type");
+        };
+        assert_eq!(filename, Path::new("scene/geometry/mesh.ply"));
+
+        let Light::Infinite { filename, .. } = &scene.lights[0].params else {
+            panic!("Unexpected light type");
+        };
+        assert_eq!(filename.as_deref(), Some(Path::new("scene/sky.exr")));
+
+        let AreaLight::Diffuse { filename, .. } = &scene.area_lights[0];
+        assert_eq!(filename.as_deref(), Some(Path::new("scene/emission.exr")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_unknown_directive() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+FutureDirective "foo" "float bar" [ 1 ]
+Shape "sphere"
+        "#;
+
+        assert!(matches!(
+            Scene::load(data, None),
+            Err(Error::UnknownDirective)
+        ));
+
+        let options = LoadOptions {
+            lenient: true,
+            ..Default::default()
+        };
+
+        let scene = Scene::load_with_options(data, None, &options)?;
+
+        assert_eq!(scene.shapes.len(), 1);
+        assert_eq!(scene.diagnostics.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_undefined_names() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+Attribute "camera" "float fov" [ 45 ]
+NamedMaterial "missing"
+Shape "sphere"
+        "#;
+
+        assert!(matches!(
+            Scene::load(data, None),
+            Err(Error::UnknownAttributeTarget(target)) if target == "camera"
+        ));
+        assert!(matches!(
+            Scene::load(&data.replace("Attribute", "# Attribute"), None),
+            Err(Error::UndefinedMaterial(name)) if name == "missing"
+        ));
+
+        let options = LoadOptions {
+            lenient: true,
+            ..Default::default()
+        };
+
+        let scene = Scene::load_with_options(data, None, &options)?;
+
+        assert_eq!(scene.shapes.len(), 1);
+        assert_eq!(scene.shapes[0].material_index, None);
+        assert_eq!(scene.diagnostics.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distant_light_direction() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+AttributeBegin
+Rotate 90 0 1 0
+LightSource "distant" "point3 from" [ 0 0 0 ] "point3 to" [ 0 0 1 ]
+AttributeEnd
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        let light = &scene.lights[0];
+        assert!(light.world_position().is_none());
+
+        let direction = light.world_direction().unwrap();
+        assert!(direction.abs_diff_eq(Vec3::X, 1e-5), "{direction}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn distant_light_from_to() -> Result<()> {
+        let data = r#"
+WorldBegin
+Scale 2 1 1
+LightSource "distant" "point3 from" [ 0 4 0 ] "point3 to" [ 1 4 1 ] "float scale" 3
+    "rgb L" [ 1 0.5 0.25 ]
+LightSource "distant"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        let light = &scene.lights[0];
+        assert_eq!(light.params.scale(), 3.0);
+        assert!(matches!(
+            light.params,
+            Light::Distant {
+                spectrum: Some(Spectrum::Rgb([1.0, 0.5, 0.25], None)),
+                ..
+            }
+        ));
+
+        let direction = light.params.direction().unwrap();
+        assert!(direction.abs_diff_eq(Vec3::new(1.0, 0.0, 1.0).normalize(), 1e-5));
+
+        let direction = light.world_direction().unwrap();
+        assert!(
+            direction.abs_diff_eq(Vec3::new(2.0, 0.0, 1.0).normalize(), 1e-5),
+            "{direction}"
+        );
+
+        // Defaults to shining along +z.
+        assert_eq!(scene.lights[1].params.direction(), Some(Vec3::Z));
+
+        Ok(())
+    }
+
+    #[test]
+    fn point_light_position() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+Translate 1 2 3
+LightSource "point" "point3 from" [ 0 1 0 ]
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        let light = &scene.lights[0];
+        assert!(light.world_direction().is_none());
+
+        let position = light.world_position().unwrap();
+        assert!(
+            position.abs_diff_eq(Vec3::new(1.0, 3.0, 3.0), 1e-5),
+            "{position}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn override_params() -> Result<()> {
+        let data = r#"
+Sampler "halton" "integer pixelsamples" 16
+Integrator "volpath"
+
+WorldBegin
+        "#;
+
+        let options = LoadOptions {
+            overrides: vec![
+                (
+                    Selector::new("Sampler", "pixelsamples"),
+                    ParamValue::new(ParamType::Integer, "256"),
+                ),
+                (
+                    Selector::new("Integrator", "maxdepth"),
+                    ParamValue::new(ParamType::Integer, "2"),
+                ),
+            ],
+            ..Default::default()
+        };
+
+        let scene = Scene::load_with_options(data, None, &options)?;
+
+        assert!(matches!(
+            scene.sampler,
+            Some(Sampler::Halton { pixel_samples: 256 })
+        ));
+        assert!(matches!(
+            scene.integrator,
+            Some(Integrator::VolPath { max_depth: 2, .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_scenes() -> Result<()> {
+        let data = r#"
+Camera "perspective"
+
+WorldBegin
+
+Material "diffuse"
+Shape "sphere"
+
+AttributeBegin
+Translate 0 1 0
+Shape "disk"
+AttributeEnd
+        "#;
+
+        let scene = Scene::load(data, None)?;
+        assert!(scene.diff(&Scene::load(data, None)?).is_empty());
+
+        let other = Scene::load(&data.replace("Translate 0 1 0", "Translate 0 2 0"), None)?;
+
+        let diff = scene.diff(&other);
+        assert_eq!(
+            diff,
+            SceneDiff {
+                changed_shapes: vec![1],
+                ..Default::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn undefined_medium() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+MakeNamedMedium "fog" "string type" "homogeneous"
+
+AttributeBegin
+MediumInterface "smoke" ""
+Material "interface"
+Shape "sphere"
+AttributeEnd
+
+AttributeBegin
+MediumInterface "fog" ""
+Material "interface"
+Shape "sphere"
+AttributeEnd
+            "#,
+            None,
+        )?;
+
+        assert_eq!(
+            scene.check_medium_consistency(),
+            vec![MediumIssue::UndefinedShapeMedium {
+                shape_index: 0,
+                name: "smoke".to_string(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn load_file_async() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt4_async")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(
+            temp_path.join("object.pbrt"),
+            "ObjectBegin \"foo\"\nShape \"sphere\"\nShape \"sphere\"\nObjectEnd\n",
+        )?;
+        fs::write(
+            temp_path.join("scene.pbrt"),
+            r#"
+WorldBegin
+
+Include "object.pbrt"
+
+ObjectInstance "foo"
+Translate 1 0 0
+ObjectInstance "foo"
+            "#,
+        )?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+
+        let scene = runtime.block_on(Scene::from_file_async(temp_path.join("scene.pbrt")))?;
+        assert_eq!(scene.shapes.len(), 2);
+        assert_eq!(scene.instances.len(), 2);
+
+        let options = LoadOptions {
+            rewrite_path: Some(Box::new(|path: &Path| path.with_extension("ply"))),
+            ..Default::default()
+        };
+        fs::write(
+            temp_path.join("mesh.pbrt"),
+            "WorldBegin\nShape \"plymesh\" \"string filename\" \"mesh.obj\"\n",
+        )?;
+        let scene = runtime.block_on(Scene::from_file_async_with_options(
+            temp_path.join("mesh.pbrt"),
+            options,
+        ))?;
+        assert!(matches!(
+            &scene.shapes[0].params,
+            Shape::PlyMesh { filename } if filename.ends_with("mesh.ply")
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rotate_degrees() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Rotate 90 0 0 2
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        // The angle is in degrees and the axis doesn't need to be normalized.
+        let expected = Mat4::from_rotation_z(PI / 2.0);
+        assert!(scene.shapes[0].transform.abs_diff_eq(expected, 1e-6));
+
+        Ok(())
+    }
+
+    #[test]
+    fn nonuniform_scale() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+AttributeBegin
+Scale 2 2 2
+Rotate 45 0 1 0
+Shape "sphere"
+AttributeEnd
+
+AttributeBegin
+Scale 1 2 1
+Shape "sphere"
+AttributeEnd
+
+# Shears the axes while keeping their lengths equal.
+AttributeBegin
+Scale 1 2.6457513 2
+Rotate 45 0 0 1
+Shape "sphere"
+AttributeEnd
+            "#,
+            None,
+        )?;
+
+        assert!(!scene.shapes[0].has_nonuniform_scale());
+        assert!(scene.shapes[1].has_nonuniform_scale());
+        assert!(scene.shapes[2].has_nonuniform_scale());
+
+        let area = scene.shapes[0].surface_area().unwrap();
+        assert!((area - 16.0 * PI).abs() < 1e-3);
+        assert_eq!(scene.shapes[2].surface_area(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn material_textures() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+Texture "grid" "spectrum" "imagemap" "string filename" "grid.png"
+Texture "checks" "spectrum" "checkerboard"
+
+Material "diffuse" "texture reflectance" "checks"
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.material_textures(0), vec![1]);
+        assert!(scene.material_textures(1).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn orthographic_camera() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Film "rgb" "integer xresolution" 400 "integer yresolution" 200
+Camera "orthographic" "float screenwindow" [-1 1 -1 1] "float lensradius" 0.5
+
+AttributeBegin
+Camera "orthographic"
+AttributeEnd
+
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        let Camera::Orthographic {
+            lens_radius,
+            focal_distance,
+            screen_window,
+            ..
+        } = scene.cameras[0].params
+        else {
+            panic!("Unexpected camera type");
+        };
+
+        assert_eq!(lens_radius, 0.5);
+        assert_eq!(focal_distance, 1e6);
+        assert_eq!(screen_window, Some([-1.0, 1.0, -1.0, 1.0]));
+
+        let Camera::Orthographic { screen_window, .. } = scene.cameras[1].params else {
+            panic!("Unexpected camera type");
+        };
+
+        assert_eq!(screen_window, Some([-2.0, 2.0, -1.0, 1.0]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_trace() -> Result<()> {
+        let (scene, traces) = Scene::load_with_transform_trace(
+            r#"
+Translate 0 0 -5
+
+WorldBegin
+
+Shape "sphere"
+
+AttributeBegin
+Translate 1 2 3
+Rotate 90 0 0 1
+Shape "sphere"
+AttributeEnd
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.shapes.len(), 2);
+        assert_eq!(
+            traces,
+            vec![
+                vec![],
+                vec![
+                    TransformOp::Translate([1.0, 2.0, 3.0]),
+                    TransformOp::Rotate(90.0, [0.0, 0.0, 1.0]),
+                ],
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn camera_basis() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+LookAt 0 0 -5  0 0 0  0 1 0
+Camera "perspective"
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        let camera = &scene.cameras[0];
+        let (forward, up, right) = camera.basis();
+
+        let eye = camera.transform.transform_point3(Vec3::ZERO);
+        assert!(eye.abs_diff_eq(Vec3::new(0.0, 0.0, -5.0), 1e-5));
+        assert!(forward.abs_diff_eq(-eye.normalize(), 1e-5), "{forward}");
+        assert!(up.abs_diff_eq(Vec3::Y, 1e-5), "{up}");
+        assert!(right.abs_diff_eq(Vec3::X, 1e-5), "{right}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn camera_intrinsics() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Film "rgb" "integer xresolution" 800 "integer yresolution" 400
+Translate 0 0 5
+Camera "perspective" "float fov" 60 "float lensradius" 0.1 "float focaldistance" 4
+
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        let film = scene.film.as_ref().unwrap();
+        let intrinsics = scene.cameras[0].intrinsics(film);
+
+        assert_eq!(
+            intrinsics,
+            CameraIntrinsics {
+                fov: Some(60.0),
+                aspect_ratio: 2.0,
+                screen_window: [-2.0, 2.0, -1.0, 1.0],
+                lens_radius: 0.1,
+                focal_distance: 4.0,
+                world_from_camera: Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0)),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn projection_matrix() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Film "rgb" "integer xresolution" 800 "integer yresolution" 400
+Camera "perspective" "float fov" 60
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        let film = scene.film.as_ref().unwrap();
+        let camera = &scene.cameras[0];
+
+        let near = Vec3::new(0.0, 0.0, 0.01);
+        let far = Vec3::new(0.0, 0.0, 1000.0);
+
+        let gl = camera.projection_matrix(film, ClipConvention::OpenGl);
+        assert!((gl.project_point3(near).z + 1.0).abs() < 1e-4);
+        assert!((gl.project_point3(far).z - 1.0).abs() < 1e-4);
+
+        let vulkan = camera.projection_matrix(film, ClipConvention::Vulkan);
+        assert!(vulkan.project_point3(near).z.abs() < 1e-4);
+        assert!((vulkan.project_point3(far).z - 1.0).abs() < 1e-4);
+
+        // The top of the field of view maps to the top of the image.
+        let top = Vec3::new(0.0, (30.0f32).to_radians().tan(), 1.0);
+        assert!((vulkan.project_point3(top).y - 1.0).abs() < 1e-4);
+        let right = Vec3::new(2.0 * (30.0f32).to_radians().tan(), 0.0, 1.0);
+        assert!((gl.project_point3(right).x - 1.0).abs() < 1e-4);
+
+        let scene = Scene::load(
+            r#"
+Film "rgb" "integer xresolution" 400 "integer yresolution" 400
+Camera "orthographic" "float hither" 0.5 "float yon" 10
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        let film = scene.film.as_ref().unwrap();
+        let camera = &scene.cameras[0];
+
+        let near = Vec3::new(1.0, 0.0, 0.5);
+        let gl = camera
+            .projection_matrix(film, ClipConvention::OpenGl)
+            .project_point3(near);
+        assert!(gl.abs_diff_eq(Vec3::new(1.0, 0.0, -1.0), 1e-5));
+
+        let vulkan = camera.projection_matrix(film, ClipConvention::Vulkan);
+        assert!(vulkan
+            .project_point3(near)
+            .abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), 1e-5));
+        assert!((vulkan.project_point3(Vec3::new(0.0, 0.0, 10.0)).z - 1.0).abs() < 1e-5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_manifest() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt4_manifest")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(
+            temp_path.join("sphere.pbrt"),
+            "WorldBegin\nMaterial \"diffuse\"\nShape \"sphere\"\n",
+        )?;
+        fs::write(
+            temp_path.join("disk.pbrt"),
+            "WorldBegin\nMaterial \"conductor\"\nShape \"disk\"\n",
+        )?;
+
+        let scene =
+            Scene::from_manifest(&[temp_path.join("sphere.pbrt"), temp_path.join("disk.pbrt")])?;
+
+        assert_eq!(scene.shapes.len(), 2);
+        assert!(matches!(scene.shapes[1].params, Shape::Disk { .. }));
+        assert_eq!(scene.shapes[1].material_index, Some(1));
+        assert!(matches!(
+            scene.materials[1].ty,
+            MaterialType::Conductor { .. }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn clear_medium_interface() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+MakeNamedMedium "fog" "string type" "homogeneous"
+
+MediumInterface "fog" ""
+Shape "sphere"
+
+MediumInterface "" ""
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.shapes[0].inside_medium.as_deref(), Some("fog"));
+        assert_eq!(scene.shapes[0].outside_medium, None);
+
+        assert_eq!(scene.shapes[1].inside_medium, None);
+        assert_eq!(scene.shapes[1].outside_medium, None);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "archive")]
+    #[test]
+    fn load_zip() -> Result<()> {
+        use std::io::{Cursor, Write};
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+        let mut add = |name: &str, data: &str| -> zip::result::ZipResult<()> {
+            writer.start_file(name, options)?;
+            writer.write_all(data.as_bytes())?;
+            Ok(())
+        };
+
+        add(
+            "scenes/main.pbrt",
+            "WorldBegin\nShape \"sphere\"\nInclude \"geometry/disk.pbrt\"\n",
+        )
+        .map_err(std::io::Error::from)?;
+        add("scenes/geometry/disk.pbrt", "Shape \"disk\"\n").map_err(std::io::Error::from)?;
+
+        let archive = writer.finish().map_err(std::io::Error::from)?;
+
+        let scene = Scene::from_zip_reader(archive, "scenes/main.pbrt")?;
+
+        assert_eq!(scene.shapes.len(), 2);
+        assert!(matches!(scene.shapes[1].params, Shape::Disk { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_with_memory_source() -> Result<()> {
+        let mut files = HashMap::new();
+        files.insert(
+            PathBuf::from("scene/main.pbrt"),
+            b"WorldBegin\nInclude \"shapes.pbrt\"\n".to_vec(),
+        );
+        files.insert(
+            PathBuf::from("scene/shapes.pbrt"),
+            b"Shape \"sphere\"\nShape \"disk\"\n".to_vec(),
+        );
+
+        let scene = Scene::load_with_source(
+            &files,
+            Path::new("scene/main.pbrt"),
+            &LoadOptions::default(),
+        )?;
+
+        assert_eq!(scene.shapes.len(), 2);
+
+        assert!(matches!(
+            Scene::load_with_source(&files, Path::new("missing.pbrt"), &LoadOptions::default()),
+            Err(Error::Io(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_transform() -> Result<()> {
+        let mut scene = Scene::load(
+            r#"
+LookAt 0 1 -5  0 0 0  0 1 0
+Camera "perspective"
+WorldBegin
+
+ObjectBegin "foo"
+Translate 0 1 0
+Shape "sphere"
+ObjectEnd
+
+Translate 1 0 0
+Shape "sphere"
+ObjectInstance "foo"
+            "#,
+            None,
+        )?;
+
+        let object_shape_transform = scene.shapes[0].transform;
+        let camera_from_world = scene.cameras[0].camera_from_world();
+        assert_eq!(camera_from_world.inverse(), scene.cameras[0].transform);
+
+        scene.apply_transform(Mat4::from_translation(Vec3::new(0.0, 0.0, 5.0)));
+
+        // The parsed matrix is stale once the camera moves.
+        let camera = &scene.cameras[0];
+        assert_eq!(camera.camera_from_world(), camera.transform.inverse());
+        assert_ne!(camera.camera_from_world(), camera_from_world);
+
+        let position = scene.shapes[1].transform.transform_point3(Vec3::ZERO);
+        assert!(position.abs_diff_eq(Vec3::new(1.0, 0.0, 5.0), 1e-6));
+
+        assert_eq!(scene.shapes[0].transform, object_shape_transform);
+
+        let position = scene.instances[0]
+            .instance_to_world
+            .transform_point3(Vec3::ZERO);
+        assert!(position.abs_diff_eq(Vec3::new(1.0, 0.0, 5.0), 1e-6));
+
+        Ok(())
+    }
+
+    #[test]
+    fn dots_texture() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+Texture "red" "spectrum" "constant" "rgb value" [1 0 0]
+Texture "blue" "spectrum" "constant" "rgb value" [0 0 1]
+Texture "dots" "spectrum" "dots" "texture inside" "red" "texture outside" "blue"
+            "#,
+            None,
+        )?;
+
+        let TextureClass::Dots {
+            inside, outside, ..
+        } = &scene.texture_by_name("dots").unwrap().class
+        else {
+            panic!("Unexpected texture class");
+        };
+
+        assert_eq!(inside, &TextureInput::Texture(0));
+        assert_eq!(outside, &TextureInput::Texture(1));
+
+        let result = Scene::load(
+            r#"
+WorldBegin
+Texture "dots" "float" "dots" "texture inside" "missing"
+            "#,
+            None,
+        );
+        assert!(matches!(result, Err(Error::UndefinedTexture(name)) if name == "missing"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn light_bounds() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Translate 1 2 3
+LightSource "point" "point3 from" [ 1 0 0 ]
+LightSource "distant" "point3 from" [ 0 0 0 ] "point3 to" [ 0 0 1 ]
+            "#,
+            None,
+        )?;
+
+        let point = Vec3::new(2.0, 2.0, 3.0);
+        assert_eq!(scene.lights[0].bounds(), Some((point, point)));
+        assert_eq!(scene.lights[1].bounds(), None);
+        assert_eq!(scene.light_bounds(), Some((point, point)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn light_bounds_include_area_lights() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+LightSource "point" "point3 from" [ 0 0 -5 ]
+AttributeBegin
+    AreaLightSource "diffuse"
+    Translate 0 0 5
+    Shape "sphere" "float radius" 1
+AttributeEnd
+Shape "sphere" "float radius" 10
+            "#,
+            None,
+        )?;
+
+        assert_eq!(
+            scene.light_bounds(),
+            Some((Vec3::new(-1.0, -1.0, -5.0), Vec3::new(1.0, 1.0, 6.0)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spatial_buckets() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Shape "sphere"
+Translate 100 0 50
+Shape "sphere"
+Shape "plymesh" "string filename" "mesh.ply"
+ObjectBegin "foo"
+Shape "sphere"
+ObjectEnd
+            "#,
+            None,
+        )?;
+
+        let buckets = scene.spatial_buckets([4, 4, 4]);
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[&[0, 2, 0]], vec![0]);
+        assert_eq!(buckets[&[3, 2, 3]], vec![1]);
+        assert_eq!(buckets[&UNBOUNDED_BUCKET], vec![2]);
+
+        // Degenerate grids have a single cell.
+        let buckets = scene.spatial_buckets([0, 0, 0]);
+        assert_eq!(buckets[&[0, 0, 0]], vec![0, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn high_variance_integrator() -> Result<()> {
+        let data = r#"
+Integrator "simplepath" "bool samplelights" false "bool samplebsdf" false
+WorldBegin
+        "#;
+
+        let scene = Scene::load(data, None)?;
+        assert_eq!(scene.diagnostics.len(), 1);
+        assert!(scene.diagnostics[0].contains("samplelights and samplebsdf"));
+
+        let scene = Scene::load(
+            r#"
+Integrator "simplepath" "bool samplelights" false
+WorldBegin
+            "#,
+            None,
+        )?;
+        assert!(scene.diagnostics.is_empty());
+
+        // The settings are valid, strict loading only reports them as well.
+        let options = LoadOptions {
+            strict_parameters: true,
+            ..Default::default()
+        };
+        let scene = Scene::load_with_options(data, None, &options)?;
+        assert_eq!(scene.diagnostics.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_matrix_order() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+AttributeBegin
+Transform [ 1 0 0 0  0 1 0 0  0 0 1 0  3 1 -4 1 ]
+Shape "sphere"
+AttributeEnd
+Scale 2 2 2
+ConcatTransform [ 0 1 0 0  -1 0 0 0  0 0 1 0  1 0 0 1 ]
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        // The last four values are the translation.
+        let point = scene.shapes[0].transform.transform_point3(Vec3::ONE);
+        assert_eq!(point, Vec3::new(4.0, 2.0, -3.0));
+
+        // Rotation by 90° around z followed by the translation, applied after the scale.
+        let point = scene.shapes[1].transform.transform_point3(Vec3::X);
+        assert_eq!(point, Vec3::new(2.0, 2.0, 0.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn render_settings_defaults() -> Result<()> {
+        let scene = Scene::load("WorldBegin", None)?;
+
+        assert!(scene.pixel_filter.is_none());
+        assert!(matches!(
+            scene.pixel_filter_or_default(),
+            PixelFilter::Gaussian { xradius, yradius, sigma }
+                if xradius == 1.5 && yradius == 1.5 && sigma == 0.5
+        ));
+        assert!(matches!(
+            scene.sampler_or_default(),
+            Sampler::ZSobol {
+                pixel_samples: 16,
+                ..
+            }
+        ));
+        assert!(matches!(
+            scene.integrator_or_default(),
+            Integrator::VolPath { max_depth: 5, .. }
+        ));
+        assert!(matches!(
+            scene.accelerator_or_default(),
+            Accelerator::Bvh {
+                max_node_prims: 4,
+                ..
+            }
+        ));
+
+        let scene = Scene::load(
+            r#"
+PixelFilter "box"
+Integrator "path"
+WorldBegin
+            "#,
+            None,
+        )?;
+        assert!(matches!(
+            scene.pixel_filter_or_default(),
+            PixelFilter::Box { .. }
+        ));
+        assert!(matches!(
+            scene.integrator_or_default(),
+            Integrator::Path { .. }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shape_before_world_begin() {
+        assert!(matches!(
+            Scene::load("Shape \"sphere\"\nWorldBegin", None),
+            Err(Error::ElementBeforeWorldBegin("Shape"))
+        ));
+        assert!(matches!(
+            Scene::load("LightSource \"point\"\nWorldBegin", None),
+            Err(Error::ElementBeforeWorldBegin("LightSource"))
+        ));
+        assert!(matches!(
+            Scene::load("Material \"diffuse\"\nWorldBegin", None),
+            Err(Error::ElementBeforeWorldBegin("Material"))
+        ));
+    }
+
+    #[test]
+    fn empty_scene() -> Result<()> {
+        assert!(Scene::default().is_empty());
+        assert!(Scene::empty().is_empty());
+        assert_eq!(Scene::default().end_time, 1.0);
+        assert_eq!(Scene::from_manifest(&[])?.end_time, 1.0);
+        assert_eq!(
+            Scene::empty().end_time,
+            Scene::load("WorldBegin", None)?.end_time
+        );
+
+        let scene = Scene::load("WorldBegin\nShape \"sphere\"", None)?;
+        assert!(!scene.is_empty());
+
+        let scene = Scene::load_header_only("Camera \"perspective\"\nWorldBegin", None)?;
+        assert!(!scene.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_cancellable() -> Result<()> {
+        let data = format!("WorldBegin\n{}", "Shape \"sphere\"\n".repeat(200_000));
+
+        let cancel = AtomicBool::new(false);
+        let scene = Scene::load_cancellable("WorldBegin\nShape \"sphere\"", None, &cancel)?;
+        assert_eq!(scene.shapes.len(), 1);
+
+        // Cancel from another thread while the scene is being parsed.
+        let result = std::thread::scope(|scope| {
+            let load = scope.spawn(|| Scene::load_cancellable(&data, None, &cancel));
+            cancel.store(true, Ordering::Relaxed);
+            load.join().unwrap()
+        });
+        assert!(matches!(result, Err(Error::Cancelled)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_header_only() -> Result<()> {
+        let scene = Scene::load_header_only(
+            r#"
+Camera "perspective" "float fov" 45
+Film "rgb" "integer xresolution" 400 "integer yresolution" 200
+Sampler "halton"
+WorldBegin
+LightSource "point"
+Shape "sphere"
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.cameras.len(), 1);
+        assert!(scene.film.is_some());
+        assert!(scene.sampler.is_some());
+        assert!(scene.shapes.is_empty());
+        assert!(scene.lights.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mix_texture() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+Texture "red" "spectrum" "constant" "rgb value" [1 0 0]
+Texture "amount" "float" "constant" "float value" 0.25
+Texture "constant" "spectrum" "mix" "texture tex1" "red" "rgb tex2" [0 0 1] "float amount" 0.75
+Texture "textured" "spectrum" "mix" "texture tex1" "red" "texture amount" "amount"
+            "#,
+            None,
+        )?;
+
+        let TextureClass::Mix { tex1, tex2, amount } =
+            &scene.texture_by_name("constant").unwrap().class
+        else {
+            panic!("Unexpected texture class");
+        };
+
+        assert_eq!(tex1, &TextureInput::Texture(0));
+        assert_eq!(
+            tex2,
+            &TextureInput::Constant(FloatOrSpectrum::Spectrum(Spectrum::Rgb(
+                [0.0, 0.0, 1.0],
+                None
+            )))
+        );
+        assert_eq!(
+            amount,
+            &TextureInput::Constant(FloatOrSpectrum::Float(0.75))
+        );
+
+        let TextureClass::Mix { tex2, amount, .. } =
+            &scene.texture_by_name("textured").unwrap().class
+        else {
+            panic!("Unexpected texture class");
+        };
+
+        assert_eq!(
+            tex2,
+            &TextureInput::Constant(FloatOrSpectrum::Spectrum(Spectrum::Rgb([1.0; 3], None)))
+        );
+        assert_eq!(amount, &TextureInput::Texture(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_features() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Texture "noise" "float" "fbm"
+Material "diffuse"
+Material "hair"
+MakeNamedMedium "smoke" "string type" "nanovdb"
+            "#,
+            None,
+        )?;
+
+        assert_eq!(
+            scene.unsupported_features(),
+            vec!["fbm texture", "hair material", "nanovdb medium"]
+        );
+
+        let scene = Scene::load(
+            "WorldBegin\nShape \"sphere\"\nShape \"plymesh\" \"string filename\" \"mesh.ply\"\n",
+            None,
+        )?;
+        assert!(scene.unsupported_features().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn max_array_length() -> Result<()> {
+        let positions = vec!["0"; 3000].join(" ");
+        let data = format!("WorldBegin\nShape \"trianglemesh\" \"point3 P\" [ {positions} ]\n");
+
+        let options = LoadOptions {
+            max_array_length: 16,
+            ..Default::default()
+        };
+
+        let result = Scene::load_with_options(&data, None, &options);
+        assert!(matches!(
+            result,
+            Err(Error::ArrayTooLarge {
+                declared: 3000,
+                limit: 16
+            })
+        ));
+
+        let scene = Scene::load(&data, None)?;
+        assert_eq!(scene.shapes.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn camera_shutter() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Camera "perspective" "float shutteropen" 0.25 "float shutterclose" 0.75
+Camera "spherical"
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.cameras[0].shutter(), (0.25, 0.75));
+        assert_eq!(scene.cameras[1].shutter(), (0.0, 1.0));
+
+        let result = Scene::load(
+            r#"
+Camera "realistic" "float shutteropen" 1 "float shutterclose" 0.5
+WorldBegin
+            "#,
+            None,
+        );
+        assert!(matches!(result, Err(Error::ValueOutOfRange(name)) if name == "shutterclose"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn texture_graph() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Texture "white" "spectrum" "constant" "rgb value" [1 1 1]
+Texture "checks" "spectrum" "checkerboard" "texture tex1" "white" "rgb tex2" [0 0 0]
+Texture "half" "float" "constant" "float value" 0.5
+Texture "scaled" "spectrum" "scale" "texture tex" "checks" "texture scale" "half"
+            "#,
+            None,
+        )?;
+
+        let graph = scene.texture_graph();
+        assert_eq!(
+            graph.dependencies,
+            vec![vec![], vec![0], vec![], vec![1, 2]]
+        );
+        assert_eq!(graph.topological_order()?, vec![0, 2, 1, 3]);
+
+        let graph = TextureGraph {
+            dependencies: vec![vec![2], vec![], vec![1]],
+        };
+        assert_eq!(graph.topological_order()?, vec![1, 2, 0]);
+
+        let graph = TextureGraph {
+            dependencies: vec![vec![], vec![2], vec![1]],
+        };
+        assert!(matches!(
+            graph.topological_order(),
+            Err(Error::TextureCycle(1))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn expand_object() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+ObjectBegin "foo"
+Shape "sphere"
+Translate 0 2 0
+Shape "sphere"
+ObjectEnd
+
+ObjectInstance "foo"
+            "#,
+            None,
+        )?;
+
+        let shapes = scene.expand_object(0, Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0)));
+        assert_eq!(shapes.len(), 2);
+
+        let centers = shapes
+            .iter()
+            .map(|shape| shape.transform.transform_point3(Vec3::ZERO))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            centers,
+            vec![Vec3::new(5.0, 0.0, 0.0), Vec3::new(5.0, 2.0, 0.0)]
+        );
+
+        assert!(scene.expand_object(1, Mat4::IDENTITY).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn instance_bounds() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+Translate 0 0 1
+ObjectBegin "foo"
+Shape "sphere"
+Translate 0 2 0
+Shape "sphere" "float radius" 0.5
+ObjectEnd
+
+Identity
+ObjectInstance "foo"
+Translate 3 0 0
+Rotate 90 0 0 1
+ObjectInstance "foo"
+
+ObjectBegin "mesh"
+Shape "plymesh" "string filename" "mesh.ply"
+ObjectEnd
+ObjectInstance "mesh"
+            "#,
+            None,
+        )?;
+
+        let (min, max) = scene.instance_bounds(0).unwrap();
+        assert!(min.abs_diff_eq(Vec3::new(-1.0, -1.0, 0.0), 1e-5), "{min}");
+        assert!(max.abs_diff_eq(Vec3::new(1.0, 2.5, 2.0), 1e-5), "{max}");
+
+        // Rotated by 90° around z, then offset along x.
+        let (min, max) = scene.instance_bounds(1).unwrap();
+        assert!(min.abs_diff_eq(Vec3::new(0.5, -1.0, 0.0), 1e-5), "{min}");
+        assert!(max.abs_diff_eq(Vec3::new(4.0, 1.0, 2.0), 1e-5), "{max}");
+
+        assert_eq!(scene.instance_bounds(2), None);
+        assert_eq!(scene.instance_bounds(3), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn solid_texture_transform() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Translate 1 2 3
+Texture "stone" "spectrum" "marble"
+Texture "grid" "spectrum" "checkerboard" "integer dimension" 3
+Texture "flat" "spectrum" "checkerboard"
+            "#,
+            None,
+        )?;
+
+        let translation = Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0));
+
+        assert_eq!(scene.textures[0].transform, Some(translation));
+        assert_eq!(scene.textures[1].transform, Some(translation));
+        assert_eq!(scene.textures[2].transform, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn split_into_tiles() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Film "rgb" "integer xresolution" 200 "integer yresolution" 100
+WorldBegin
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        let tiles = scene.split_into_tiles(2, 2)?;
+        assert_eq!(tiles.len(), 4);
+
+        let windows = tiles
+            .iter()
+            .map(|tile| tile.film.crop_window)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            windows,
+            vec![
+                [0.0, 0.5, 0.0, 0.5],
+                [0.5, 1.0, 0.0, 0.5],
+                [0.0, 0.5, 0.5, 1.0],
+                [0.5, 1.0, 0.5, 1.0],
+            ]
+        );
+
+        assert!(tiles
+            .iter()
+            .all(|tile| Arc::ptr_eq(&tile.scene, &tiles[0].scene)));
+
+        let tile = tiles[3].to_scene();
+        assert_eq!(tile.shapes.len(), 1);
+        assert_eq!(tile.film.unwrap().crop_window, [0.5, 1.0, 0.5, 1.0]);
+
+        let mut film = Film::default();
+        assert!(film.set_crop_window([0.5, 0.25, 0.0, 1.0]).is_err());
+        assert!(film.set_crop_window([0.0, 1.0, -0.5, 1.0]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn stratified_sampler_diagnostics() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Sampler "stratified" "integer xsamples" 2 "integer ysamples" 3 "integer pixelsamples" 16
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.sampler.as_ref().unwrap().pixel_samples(), 6);
+        assert_eq!(scene.diagnostics.len(), 1);
+        assert!(scene.diagnostics[0].contains("pixelsamples 16"));
+
+        let scene = Scene::load(
+            r#"
+Sampler "stratified" "integer xsamples" 2 "integer ysamples" 2 "integer pixelsamples" 4
+WorldBegin
+            "#,
+            None,
+        )?;
+        assert!(scene.diagnostics.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn render_primitives() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+Shape "disk"
+
+ObjectBegin "foo"
+Shape "sphere"
+Shape "sphere"
+ObjectEnd
+
+ObjectInstance "foo"
+Translate 1 0 0
+ObjectInstance "foo"
+            "#,
+            None,
+        )?;
+
+        let primitives = scene.render_primitives().collect::<Vec<_>>();
+        assert_eq!(primitives.len(), 5);
+
+        assert_eq!(primitives[0].shape_index, 0);
+        assert_eq!(primitives[0].instance_index, None);
+
+        for primitive in &primitives[1..] {
+            let instance = &scene.instances[primitive.instance_index.unwrap()];
+            let shape = &scene.shapes[primitive.shape_index];
+
+            assert_eq!(
+                primitive.world_transform,
+                instance.instance_to_world * shape.transform
+            );
+        }
+
+        assert_eq!(
+            primitives[4].world_transform.transform_point3(Vec3::ZERO),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn content_hash() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-hash-")?;
+        let path = temp_dir.path().join("scene.pbrt");
+
+        fs::write(&path, "WorldBegin\nShape \"sphere\" \"float radius\" 2\n")?;
+
+        let first = Scene::from_file(&path)?;
+        let second = Scene::from_file(&path)?;
+        assert_eq!(first.content_hash(), second.content_hash());
+
+        let hash = first.content_hash();
+        assert!(Scene::from_file_if_changed(&path, hash)?.is_none());
+
+        fs::write(&path, "WorldBegin\nShape \"sphere\" \"float radius\" 3\n")?;
+        let changed = Scene::from_file_if_changed(&path, hash)?;
+        assert!(changed.map_or(false, |scene| scene.content_hash() != hash));
+
+        let mut scene = Scene::load(
+            "WorldBegin\nObjectBegin \"foo\"\nShape \"sphere\"\nObjectEnd\nObjectInstance \"foo\"\n",
+            None,
+        )?;
+        let hash = scene.content_hash();
+        scene.precompute_inverse_transforms();
+        scene.deduplicate_transforms();
+        assert_eq!(scene.content_hash(), hash);
+
+        // Options, transform times and the rendering space are part of the contents.
+        let sphere = "WorldBegin\nShape \"sphere\" \"float radius\" 2\n";
+        let hash = first.content_hash();
+        assert_eq!(Scene::load(sphere, None)?.content_hash(), hash);
+
+        let options = Scene::load(
+            &format!("Option \"bool disablepixeljitter\" true\n{sphere}"),
+            None,
+        )?;
+        assert_ne!(options.content_hash(), hash);
+
+        let mut scene = first.clone();
+        scene.end_time = 2.0;
+        assert_ne!(scene.content_hash(), hash);
+
+        let mut scene = first.clone();
+        scene.world_from_render = Mat4::from_translation(Vec3::X);
+        assert_ne!(scene.content_hash(), hash);
+
+        Ok(())
+    }
+
+    #[test]
+    fn entity_transform_times() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+TransformTimes 0.25 0.5
+Camera "perspective"
+WorldBegin
+Shape "sphere"
+ObjectBegin "foo"
+Shape "sphere"
+ObjectEnd
+ObjectInstance "foo"
+            "#,
+            None,
+        )?;
+
+        assert_eq!((scene.start_time, scene.end_time), (0.25, 0.5));
+
+        let shape = &scene.shapes[0];
+        assert_eq!(
+            (shape.transform_start_time, shape.transform_end_time),
+            (0.25, 0.5)
+        );
+
+        let instance = &scene.instances[0];
+        assert_eq!(
+            (instance.transform_start_time, instance.transform_end_time),
+            (0.25, 0.5)
+        );
+
+        let camera = &scene.cameras[0];
+        assert_eq!(
+            (camera.transform_start_time, camera.transform_end_time),
+            (0.25, 0.5)
+        );
+
+        let scene = Scene::load("WorldBegin\nShape \"sphere\"\n", None)?;
+        let shape = &scene.shapes[0];
+        assert_eq!(
+            (shape.transform_start_time, shape.transform_end_time),
+            (0.0, 1.0)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn inverse_transforms() -> Result<()> {
+        let mut scene = Scene::load(
+            r#"
+WorldBegin
+ObjectBegin "obj"
+Shape "sphere"
+ObjectEnd
+Translate 1 2 3
+Rotate 30 0 1 1
+Shape "sphere"
+ObjectInstance "obj"
+            "#,
+            None,
+        )?;
+
+        let shape = &scene.shapes[1];
+        assert!((shape.world_to_object() * shape.transform).abs_diff_eq(Mat4::IDENTITY, 1e-5));
+
+        scene.precompute_inverse_transforms();
+
+        let shape = &scene.shapes[1];
+        assert!(shape.inverse_transform.is_some());
+        assert!((shape.world_to_object() * shape.transform).abs_diff_eq(Mat4::IDENTITY, 1e-5));
+
+        let instance = &scene.instances[0];
+        assert!((instance.world_to_instance() * instance.instance_to_world)
+            .abs_diff_eq(Mat4::IDENTITY, 1e-5));
+
+        scene.apply_transform(Mat4::from_scale(Vec3::splat(2.0)));
+        assert!(scene.shapes[1].inverse_transform.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tessellate_sphere() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Shape "sphere"
+Translate 0 0 5
+Scale 2 2 2
+Shape "sphere" "float zmin" 0
+Shape "trianglemesh" "integer indices" [ 0 1 2 ] "point3 P" [ 0 0 0 1 0 0 0 1 0 ]
+            "#,
+            None,
+        )?;
+
+        let mesh = scene.shapes[0].tessellate(16).unwrap();
+        assert_eq!(mesh.positions.len(), 17 * 9 * 3);
+        assert_eq!(mesh.indices.len(), 16 * 8 * 6);
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+        assert!(mesh
+            .positions
+            .chunks_exact(3)
+            .all(|p| (Vec3::from_slice(p).length() - 1.0).abs() < 1e-5));
+
+        // Upper hemisphere of radius 2 around (0, 0, 5).
+        let mesh = scene.shapes[1].tessellate(16).unwrap();
+        let center = Vec3::new(0.0, 0.0, 5.0);
+        assert!(mesh.positions.chunks_exact(3).all(|p| {
+            let p = Vec3::from_slice(p);
+            (p.distance(center) - 2.0).abs() < 1e-4 && p.z >= 5.0 - 1e-4
+        }));
+
+        assert!(scene.shapes[2].tessellate(16).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tessellate_orientation() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Shape "sphere"
+AttributeBegin
+    Scale -1 1 1
+    Shape "sphere"
+AttributeEnd
+AttributeBegin
+    ReverseOrientation
+    Shape "sphere"
+AttributeEnd
+AttributeBegin
+    ReverseOrientation
+    Scale 1 1 -2
+    Shape "sphere"
+AttributeEnd
+            "#,
+            None,
+        )?;
+
+        // Returns the sign of the normals and of the triangle windings relative to the
+        // outward direction, `None` if they are mixed.
+        let orientation = |index: usize| {
+            let mesh = scene.shapes[index].tessellate(16).unwrap();
+            let point = |i: i32| Vec3::from_slice(&mesh.positions[i as usize * 3..][..3]);
+            let normal = |i: i32| Vec3::from_slice(&mesh.normals[i as usize * 3..][..3]);
+
+            let normals = mesh
+                .indices
+                .iter()
+                .map(|&i| normal(i).dot(point(i)).signum())
+                .collect::<Vec<_>>();
+            let windings = mesh
+                .indices
+                .chunks_exact(3)
+                .filter_map(|t| {
+                    let (p0, p1, p2) = (point(t[0]), point(t[1]), point(t[2]));
+                    let face = (p1 - p0).cross(p2 - p0);
+                    (face.length() > 1e-6).then(|| face.dot(p0 + p1 + p2).signum())
+                })
+                .collect::<Vec<_>>();
+
+            let sign = |signs: Vec<f32>| signs.iter().all(|s| *s == signs[0]).then(|| signs[0]);
+            (sign(normals), sign(windings))
+        };
+
+        assert_eq!(orientation(0), (Some(1.0), Some(1.0)));
+        assert_eq!(orientation(1), (Some(1.0), Some(1.0)));
+        assert_eq!(orientation(2), (Some(-1.0), Some(-1.0)));
+        assert_eq!(orientation(3), (Some(-1.0), Some(-1.0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn instance_material_override() -> Result<()> {
+        let data = r#"
+WorldBegin
+MakeNamedMaterial "red" "string type" "diffuse" "rgb reflectance" [ 1 0 0 ]
+MakeNamedMaterial "metal" "string type" "conductor"
+ObjectBegin "ball"
+NamedMaterial "red"
+Shape "sphere"
+ObjectEnd
+ObjectInstance "ball"
+NamedMaterial "metal"
+ObjectInstance "ball"
+            "#;
+
+        let options = LoadOptions {
+            instance_material_override: true,
+            ..Default::default()
+        };
+        let scene = Scene::load_with_options(data, None, &options)?;
+
+        assert_eq!(scene.instances[0].material_override, None);
+        assert_eq!(scene.instances[1].material_override, Some(1));
+
+        let materials = scene
+            .render_primitives()
+            .map(|primitive| primitive.material_index)
+            .collect::<Vec<_>>();
+        assert_eq!(materials, vec![Some(0), Some(1)]);
+
+        // Like in pbrt, instanced shapes keep their own materials by default.
+        let scene = Scene::load(data, None)?;
+        assert_eq!(scene.instances[1].material_override, None);
+
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Material "diffuse"
+Shape "disk"
+ObjectBegin "tree"
+Material "conductor"
+Shape "sphere"
+ObjectEnd
+ObjectInstance "tree"
+            "#,
+            None,
+        )?;
+
+        let primitive = scene.render_primitives().last().unwrap();
+        let material = &scene.materials[primitive.material_index.unwrap()];
+        assert!(matches!(material.ty, MaterialType::Conductor { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn render_region() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Option "integer samplebounds" [ 100 300 0 50 ]
+Film "rgb" "integer xresolution" 400 "integer yresolution" 200
+    "float cropwindow" [ 0 0.5 0.1 1 ]
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.options.sample_bounds, Some([100, 300, 0, 50]));
+        assert_eq!(scene.render_region(), Some([100, 200, 20, 50]));
+
+        let scene = Scene::load("WorldBegin\n", None)?;
+        assert_eq!(scene.render_region(), Some([0, 1280, 0, 720]));
+
+        // Bounds must lie within the film.
+        assert!(matches!(
+            Scene::load(
+                "Option \"integer samplebounds\" [ 0 2000 0 10 ]\nWorldBegin\n",
+                None
+            ),
+            Err(Error::ValueOutOfRange(_))
+        ));
+        assert!(matches!(
+            Scene::load(
+                "Option \"integer samplebounds\" [ 10 10 0 10 ]\nWorldBegin\n",
+                None
+            ),
+            Err(Error::ValueOutOfRange(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolved_material() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Shape "sphere"
+Material "conductor"
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        let material = scene.resolved_material(&scene.shapes[0]);
+        assert_eq!(
+            material.ty,
+            MaterialType::Diffuse {
+                reflectance: Spectrum::Rgb([0.5; 3], None)
+            }
+        );
+
+        let material = scene.resolved_material(&scene.shapes[1]);
+        assert!(matches!(material.ty, MaterialType::Conductor { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn strict_parameters() -> Result<()> {
+        let data = r#"
+WorldBegin
+Material "diffuse" "rgb reflectance" [ 0.5 0.5 0.5 ] "float roughnes" 0.1
+Shape "sphere" "float radius" 2
+            "#;
+
+        let strict = LoadOptions {
+            strict_parameters: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            Scene::load_with_options(data, None, &strict),
+            Err(Error::UnknownParameter { directive, key })
+                if directive == "Material" && key == "roughnes"
+        ));
+        assert!(matches!(
+            Scene::load_with_options(
+                "WorldBegin\nMakeNamedMaterial \"a\" \"string type\" \"diffuse\" \"float sigma\" 1\n",
+                None,
+                &strict
+            ),
+            Err(Error::UnknownParameter { directive, key })
+                if directive == "MakeNamedMaterial" && key == "sigma"
+        ));
+
+        let scene = Scene::load(data, None)?;
+        assert_eq!(scene.materials.len(), 1);
+
+        // Known parameters pass.
+        let scene = Scene::load_with_options(
+            r#"
+LookAt 0 0 -5  0 0 0  0 1 0
+Camera "perspective" "float fov" 45
+Sampler "halton" "integer pixelsamples" 16
+Integrator "path" "integer maxdepth" 4
+WorldBegin
+LightSource "point" "rgb I" [ 1 1 1 ]
+Material "conductor" "float uroughness" 0.1
+Shape "sphere" "float radius" 2
+            "#,
+            None,
+            &strict,
+        )?;
+        assert_eq!(scene.shapes.len(), 1);
+
+        // Valid pbrt-v4 parameters this crate doesn't read pass as well.
+        let scene = Scene::load_with_options(
+            r#"
+Sampler "halton" "integer pixelsamples" 16 "integer seed" 7
+WorldBegin
+LightSource "point" "rgb I" [ 1 1 1 ] "float power" 100
+Texture "grid" "spectrum" "imagemap" "string filename" "grid.png" "string wrap" "clamp"
+Material "coateddiffuse" "float roughness" 0.2
+Shape "sphere" "float radius" 2
+            "#,
+            None,
+            &strict,
+        )?;
+        assert_eq!(scene.shapes.len(), 1);
+
+        // Parameters of other types of the same directive fail.
+        for (data, directive, key) in [
+            (
+                r#"Material "diffuse" "float roughness" 0.1"#,
+                "Material",
+                "roughness",
+            ),
+            (
+                r#"LightSource "point" "float coneangle" 30"#,
+                "LightSource",
+                "coneangle",
+            ),
+            (r#"Shape "sphere" "float height" 1"#, "Shape", "height"),
+        ] {
+            assert!(matches!(
+                Scene::load_with_options(&format!("WorldBegin\n{data}\n"), None, &strict),
+                Err(Error::UnknownParameter { directive: d, key: k })
+                    if d == directive && k == key
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn attribute_depth() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+LightSource "point"
+Shape "sphere"
+AttributeBegin
+  Material "diffuse"
+  AttributeBegin
+    LightSource "point"
+    Shape "disk"
+  AttributeEnd
+AttributeEnd
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        let depths = scene
+            .shapes
+            .iter()
+            .map(|shape| shape.attribute_depth)
+            .collect::<Vec<_>>();
+        assert_eq!(depths, vec![0, 2, 0]);
+
+        assert_eq!(scene.lights[0].attribute_depth, 0);
+        assert_eq!(scene.lights[1].attribute_depth, 2);
+        assert_eq!(scene.materials[0].attribute_depth, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn prune_empty_objects() -> Result<()> {
+        let mut scene = Scene::load(
+            r#"
+WorldBegin
+ObjectBegin "empty"
+ObjectEnd
+ObjectBegin "ball"
+Shape "sphere"
+ObjectEnd
+ObjectInstance "empty"
+ObjectInstance "ball"
+Translate 1 0 0
+ObjectInstance "ball"
+            "#,
+            None,
+        )?;
+
+        scene.prune_empty_objects();
+
+        assert_eq!(scene.objects.len(), 1);
+        assert_eq!(scene.objects[0].name, "ball");
+        assert_eq!(scene.named_objects.len(), 1);
+        assert_eq!(scene.named_objects["ball"], 0);
+
+        assert_eq!(scene.instances.len(), 2);
+        assert!(scene
+            .instances
+            .iter()
+            .all(|instance| instance.object_index == 0));
+        assert_eq!(scene.render_primitives().count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deduplicate_transforms() -> Result<()> {
+        let mut scene = Scene::load(
+            r#"
+WorldBegin
+Translate 1 0 0
+Shape "sphere"
+Identity
+Transform [ 1 0 0 0  0 1 0 0  0 0 1 0  1 0 0 1 ]
+Shape "disk"
+Scale 2 2 2
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        scene.deduplicate_transforms();
+
+        assert_eq!(scene.transforms.len(), 2);
+        assert_eq!(scene.shapes[0].transform_index, Some(0));
+        assert_eq!(scene.shapes[1].transform_index, Some(0));
+        assert_eq!(scene.shapes[2].transform_index, Some(1));
+        assert_eq!(scene.transforms[0], scene.shapes[0].transform);
+
+        scene.apply_transform(Mat4::IDENTITY);
+        assert!(scene.transforms.is_empty());
+        assert_eq!(scene.shapes[0].transform_index, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn integrator_and_layer_depth() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Integrator "path" "integer maxdepth" 7
+WorldBegin
+Material "coateddiffuse" "integer maxdepth" 3
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        assert!(matches!(
+            scene.integrator,
+            Some(Integrator::Path { max_depth: 7, .. })
+        ));
+        assert!(matches!(
+            scene.materials[0].ty,
+            MaterialType::CoatedDiffuse {
+                maxdepth: LayerDepth(3),
+                ..
+            }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn all_light_sources() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+LightSource "point" "rgb I" [ 1 1 1 ]
+Shape "disk"
+AttributeBegin
+Translate 0 0 2
+AreaLightSource "diffuse" "rgb L" [ 4 4 4 ]
+Shape "sphere"
+AttributeEnd
+            "#,
+            None,
+        )?;
+
+        let sources = scene.all_light_sources().collect::<Vec<_>>();
+        assert_eq!(sources.len(), 2);
+
+        assert!(matches!(
+            sources[0],
+            LightSourceRef::Light(LightEntity {
+                params: Light::Point { .. },
+                ..
+            })
+        ));
+
+        let LightSourceRef::Area {
+            primitive,
+            area_light,
+        } = &sources[1]
+        else {
+            panic!("Expected an area light");
+        };
+        assert_eq!(primitive.shape_index, 1);
+        assert_eq!(
+            primitive.world_transform,
+            Mat4::from_translation(Vec3::new(0.0, 0.0, 2.0))
+        );
+        assert!(matches!(area_light, AreaLight::Diffuse { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn fully_opaque() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Material "diffuse" "rgb reflectance" [ 0.5 0.5 0.5 ]
+Shape "sphere"
+Shape "disk"
+            "#,
+            None,
+        )?;
+        assert!(scene.is_fully_opaque());
+
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Material "diffuse"
+Shape "sphere"
+Material "dielectric" "float eta" 1.5
+Shape "disk"
+            "#,
+            None,
+        )?;
+        assert!(!scene.is_fully_opaque());
+
+        let scene = Scene::load("WorldBegin\nShape \"sphere\" \"float alpha\" 0.5\n", None)?;
+        assert!(!scene.is_fully_opaque());
+
+        Ok(())
+    }
+
+    #[test]
+    fn clamp_camera_shutter() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+TransformTimes 0 1
+Camera "perspective" "float shutteropen" -0.5 "float shutterclose" 2
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.cameras[0].params.shutter(), (0.0, 1.0));
+        assert!(scene
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.contains("shutter")));
+
+        let scene = Scene::load(
+            r#"
+Camera "perspective" "float shutteropen" 0.25 "float shutterclose" 0.75
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.cameras[0].params.shutter(), (0.25, 0.75));
+        assert!(scene.diagnostics.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn intersect_shapes() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Shape "sphere"
+Translate 0 0 5
+Shape "trianglemesh" "integer indices" [ 0 1 2 ] "point3 P" [ -1 -1 0  1 -1 0  0 1 0 ]
+            "#,
+            None,
+        )?;
+
+        let sphere = &scene.shapes[0];
+
+        let hit = sphere.intersect(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+        assert_eq!(hit, Some(4.0));
+
+        // Distances are measured in world space, regardless of the direction length.
+        let hit = sphere.intersect(Vec3::new(0.0, 0.0, -5.0), Vec3::Z * 2.0);
+        assert_eq!(hit, Some(4.0));
+
+        // Ray starting inside hits the far side.
+        assert_eq!(sphere.intersect(Vec3::ZERO, Vec3::X), Some(1.0));
+
+        assert_eq!(sphere.intersect(Vec3::new(0.0, 2.0, -5.0), Vec3::Z), None);
+        assert_eq!(sphere.intersect(Vec3::new(0.0, 0.0, -5.0), -Vec3::Z), None);
+
+        let triangle = &scene.shapes[1];
+        assert_eq!(
+            triangle.intersect(Vec3::new(0.0, 0.0, 8.0), -Vec3::Z),
+            Some(3.0)
+        );
+        assert_eq!(triangle.intersect(Vec3::new(2.0, 0.0, 8.0), -Vec3::Z), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn node_tree() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+
+ObjectBegin "foo"
+Shape "sphere"
+Shape "sphere"
+ObjectEnd
+
+Shape "disk"
+
+ObjectInstance "foo"
+Translate 1 0 0
+ObjectInstance "foo"
+            "#,
+            None,
+        )?;
+
+        let root = scene.to_node_tree();
+        assert_eq!(root.kind, SceneNodeKind::Root);
+        assert_eq!(root.children.len(), 4);
+
+        let object = &root.children[0];
+        assert_eq!(
+            object.kind,
+            SceneNodeKind::Object {
+                object_index: 0,
+                name: "foo".to_string()
+            }
+        );
+        assert_eq!(object.children.len(), 2);
+
+        assert_eq!(
+            root.children[1].kind,
+            SceneNodeKind::Shape { shape_index: 2 }
+        );
+
+        let instances = &root.children[2..];
+        for (instance_index, node) in instances.iter().enumerate() {
+            assert_eq!(
+                node.kind,
+                SceneNodeKind::Instance {
+                    instance_index,
+                    object_index: 0
+                }
+            );
+            assert!(node.children.is_empty());
+        }
+
+        assert_eq!(
+            instances[1].transform * object.children[0].transform,
+            Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn material_displacement() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Texture "bumps" "float" "constant" "float value" 0.1
+Material "diffuse" "texture displacement" "bumps"
+Material "diffuse"
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.materials[0].displacement, Some(0));
+        assert_eq!(scene.materials[1].displacement, None);
+
+        let result = Scene::load(
             r#"
 WorldBegin
+Material "conductor" "texture displacement" "missing"
+            "#,
+            None,
+        );
+        assert!(matches!(result, Err(Error::UndefinedTexture(name)) if name == "missing"));
 
-Include "4.pbrt" # Include file with nexted includes
-Include "1.pbrt" # Include shap directly
+        Ok(())
+    }
 
-        "#,
+    #[test]
+    fn bounding_sphere() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+ObjectBegin "ball"
+Shape "sphere"
+ObjectEnd
+
+AttributeBegin
+Translate -1 0 0
+Shape "sphere"
+AttributeEnd
+
+Translate 1 0 0
+ObjectInstance "ball"
+            "#,
+            None,
         )?;
 
-        let scene = Scene::from_file(temp_path.join("main.pbrt"))?;
+        let (center, radius) = scene.bounding_sphere().unwrap();
+        assert!(center.abs_diff_eq(Vec3::ZERO, 1e-6));
+        assert!(radius >= 2.0);
 
-        debug_assert_eq!(scene.shapes.len(), 2);
+        let scene = Scene::load("WorldBegin\nLightSource \"point\"\n", None)?;
+        assert!(scene.bounding_sphere().is_none());
 
         Ok(())
     }
 
     #[test]
-    fn test_instancing() -> Result<()> {
+    fn world_from_render() -> Result<()> {
+        let data = |coord_sys: &str| {
+            format!(
+                r#"
+Option "string rendercoordsys" "{coord_sys}"
+LookAt 1 2 3  0 0 0  0 1 0
+Camera "perspective"
+WorldBegin
+                "#
+            )
+        };
+
+        let scene = Scene::load(&data("camera"), None)?;
+        assert_eq!(scene.options.render_coord_sys, CoordinateSystem::Camera);
+        assert_eq!(scene.world_from_render, scene.cameras[0].transform);
+
+        let scene = Scene::load(&data("cameraworld"), None)?;
+        assert!(scene
+            .world_from_render
+            .abs_diff_eq(Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0)), 1e-5));
+
+        let scene = Scene::load(&data("world"), None)?;
+        assert_eq!(scene.world_from_render, Mat4::IDENTITY);
+
+        Ok(())
+    }
+
+    #[test]
+    fn pbrt_v3_compat() -> Result<()> {
         let data = r#"
 WorldBegin
+TransformBegin
+Translate 1 0 0
+Material "matte" "rgb Kd" [0.1 0.2 0.3] "float sigma" 20
+Shape "sphere"
+TransformEnd
+MakeNamedMaterial "glass" "string type" "glass" "float index" 1.33 "rgb Kr" [1 1 1]
+Shape "sphere"
+        "#;
 
-ObjectBegin "foo"
+        assert!(matches!(
+            Scene::load(data, None),
+            Err(Error::UnknownDirective)
+        ));
+
+        let options = LoadOptions {
+            version: PbrtVersion::V3Compat,
+            ..Default::default()
+        };
+        let scene = Scene::load_with_options(data, None, &options)?;
+
+        assert_eq!(scene.shapes.len(), 2);
+        assert_eq!(
+            scene.shapes[0].transform,
+            Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0))
+        );
+        assert_eq!(scene.shapes[1].transform, Mat4::IDENTITY);
+        assert_eq!(
+            scene.materials[0].ty,
+            MaterialType::Diffuse {
+                reflectance: Spectrum::Rgb([0.1, 0.2, 0.3], None),
+            }
+        );
+        assert!(matches!(
+            scene.materials[1].ty,
+            MaterialType::Dielectric {
+                eta: FloatOrSpectrum::Float(eta),
+                ..
+            } if eta == 1.33
+        ));
+        assert_eq!(
+            scene.diagnostics,
+            vec![
+                "Ignored pbrt-v3 parameter sigma of material diffuse, \
+                 it has no pbrt-v4 equivalent",
+                "Ignored pbrt-v3 parameter Kr of material dielectric, \
+                 it has no pbrt-v4 equivalent",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn shape_surface_area() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Shape "sphere"
+AttributeBegin
+    Scale 2 2 2
+    Shape "sphere"
+AttributeEnd
+AttributeBegin
+    Scale 1 2 1
+    Shape "sphere"
+AttributeEnd
+AttributeBegin
+    Scale 3 3 3
+    Shape "trianglemesh"
+        "integer indices" [ 0 1 2 0 2 3 ]
+        "point3 P" [ 0 0 0  1 0 0  1 1 0  0 1 0 ]
+AttributeEnd
+Shape "plymesh" "string filename" "mesh.ply"
+"#,
+            None,
+        )?;
+
+        let area = |index: usize| scene.shapes[index].surface_area();
+        let pi = std::f32::consts::PI;
+
+        assert!((area(0).unwrap() - 4.0 * pi).abs() < 1e-4);
+        assert!((area(1).unwrap() - 16.0 * pi).abs() < 1e-3);
+        assert_eq!(area(2), None);
+        assert!((area(3).unwrap() - 9.0).abs() < 1e-4);
+        assert_eq!(area(4), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn material_normal_map() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Material "diffuse" "string normalmap" "textures/normal.png"
+Material "diffuse"
+            "#,
+            Some(Path::new("assets")),
+        )?;
+
+        assert_eq!(
+            scene.materials[0].normal_map,
+            Some(Path::new("assets").join("textures/normal.png"))
+        );
+        assert_eq!(scene.materials[1].normal_map, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn shapes_by_material() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
 Shape "sphere"
+MakeNamedMaterial "red" "string type" "diffuse" "rgb reflectance" [ 1 0 0 ]
+NamedMaterial "red"
+Shape "sphere"
+ObjectBegin "ball"
 Shape "sphere"
 ObjectEnd
+ObjectInstance "ball"
+            "#,
+            None,
+        )?;
 
-ObjectInstance "foo"
-Translate 1 0 0
-ObjectInstance "foo"
+        let batches = scene.shapes_by_material();
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[&None], vec![0]);
+        assert_eq!(batches[&Some(0)], vec![1, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn subdivision_edge_length() -> Result<()> {
+        let options = LoadOptions {
+            tessellate: true,
+            ..Default::default()
+        };
+        let triangle_count = |edge_length: f32| -> Result<usize> {
+            let data = format!(
+                r#"
+WorldBegin
+Shape "loopsubdiv" "integer levels" 0 "float edgelength" {edge_length}
+    "integer indices" [ 0 1 2 ]
+    "point3 P" [ 0 0 0  1 0 0  0 1 0 ]
+                "#
+            );
+            let scene = Scene::load_with_options(&data, None, &options)?;
+
+            let Shape::TriangleMesh {
+                indices,
+                edge_length: Some(target),
+                ..
+            } = &scene.shapes[0].params
+            else {
+                panic!("Unexpected shape");
+            };
+            assert_eq!(*target, edge_length);
+
+            Ok(indices.len() / 3)
+        };
+
+        let coarse = triangle_count(1.0)?;
+        let fine = triangle_count(0.1)?;
+        assert_eq!(coarse, 4);
+        assert!(fine > coarse);
+
+        // Refinement is bounded.
+        assert_eq!(triangle_count(1e-6)?, 4usize.pow(6));
+
+        let levels = |levels: i32| {
+            Scene::load_with_options(
+                &format!(
+                    r#"
+WorldBegin
+Shape "loopsubdiv" "integer levels" {levels}
+    "integer indices" [ 0 1 2 ]
+    "point3 P" [ 0 0 0  1 0 0  0 1 0 ]
+                    "#
+                ),
+                None,
+                &options,
+            )
+        };
+        assert!(levels(6)?.diagnostics.is_empty());
+        assert!(matches!(levels(-1), Err(Error::ValueOutOfRange(name)) if name == "levels"));
+
+        // Levels above the maximum are clamped.
+        for clamped in [7, 20] {
+            let scene = levels(clamped)?;
+            assert_eq!(scene.diagnostics.len(), 1);
+
+            let Shape::TriangleMesh { indices, .. } = &scene.shapes[0].params else {
+                panic!("Unexpected shape");
+            };
+            assert_eq!(indices.len() / 3, 4usize.pow(6));
+        }
+
+        let result = Scene::load_with_options(
+            r#"
+WorldBegin
+Shape "trianglemesh" "float edgelength" 0
+    "integer indices" [ 0 1 2 ]
+    "point3 P" [ 0 0 0  1 0 0  0 1 0 ]
+            "#,
+            None,
+            &options,
+        );
+        assert!(matches!(result, Err(Error::ValueOutOfRange(name)) if name == "edgelength"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn tessellate_shapes() -> Result<()> {
+        let data = r#"
+WorldBegin
+Shape "bilinearmesh" "point3 P" [ 0 0 0  1 0 0  0 1 0  1 1 0 ]
+Shape "loopsubdiv" "integer levels" 1
+    "integer indices" [ 0 1 2 ]
+    "point3 P" [ 0 0 0  1 0 0  0 1 0 ]
         "#;
 
+        let result = Scene::load(data, None);
+        assert!(matches!(result, Err(Error::InvalidObjectType(ty)) if ty == "bilinearmesh"));
+
+        let options = LoadOptions {
+            tessellate: true,
+            ..Default::default()
+        };
+        let scene = Scene::load_with_options(data, None, &options)?;
+
+        let Shape::TriangleMesh { indices, .. } = &scene.shapes[0].params else {
+            panic!("Unexpected shape");
+        };
+        assert_eq!(indices, &[0, 1, 3, 0, 3, 2]);
+        assert!((scene.shapes[0].surface_area().unwrap() - 1.0).abs() < 1e-6);
+
+        let Shape::TriangleMesh {
+            indices, positions, ..
+        } = &scene.shapes[1].params
+        else {
+            panic!("Unexpected shape");
+        };
+        assert_eq!(indices.len(), 4 * 3);
+        assert_eq!(positions.len(), 6 * 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn texture_scale() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Texture "wood" "spectrum" "imagemap" "string filename" "wood.png" "float scale" 0.5
+Texture "checks" "float" "checkerboard" "float scale" 2
+Texture "plain" "spectrum" "imagemap" "string filename" "plain.png"
+Texture "tinted" "spectrum" "scale" "texture tex" "wood" "float scale" 0.25
+            "#,
+            None,
+        )?;
+
+        let wood = scene.texture_by_name("wood").unwrap();
+        assert!(matches!(wood.class, TextureClass::ImageMap { .. }));
+        assert_eq!(wood.scale, 0.5);
+        assert_eq!(wood.scale(), 0.5);
+
+        let checks = scene.texture_by_name("checks").unwrap();
+        assert!(matches!(checks.class, TextureClass::Checkerboard { .. }));
+        assert_eq!(checks.scale(), 2.0);
+
+        assert_eq!(scene.texture_by_name("plain").unwrap().scale(), 1.0);
+
+        // The "scale" class keeps the parameter as its factor.
+        let tinted = scene.texture_by_name("tinted").unwrap();
+        assert_eq!(tinted.scale, 1.0);
+        assert_eq!(tinted.scale(), 0.25);
+
+        Ok(())
+    }
+
+    #[test]
+    fn scale_texture() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Texture "white" "spectrum" "constant" "rgb value" [1 1 1]
+Texture "half" "float" "constant" "float value" 0.5
+Texture "tinted" "spectrum" "scale" "texture tex" "white" "float scale" 0.5
+Texture "scaled" "spectrum" "scale" "texture tex" "white" "texture scale" "half"
+Texture "default" "float" "scale" "float tex" 2
+            "#,
+            None,
+        )?;
+
+        let TextureClass::Scale { tex, scale } = &scene.texture_by_name("tinted").unwrap().class
+        else {
+            panic!("Unexpected texture class");
+        };
+        assert_eq!(tex, &TextureInput::Texture(0));
+        assert_eq!(scale, &TextureInput::Constant(FloatOrSpectrum::Float(0.5)));
+
+        let TextureClass::Scale { tex, scale } = &scene.texture_by_name("scaled").unwrap().class
+        else {
+            panic!("Unexpected texture class");
+        };
+        assert_eq!(tex, &TextureInput::Texture(0));
+        assert_eq!(scale, &TextureInput::Texture(1));
+
+        let TextureClass::Scale { tex, scale } = &scene.texture_by_name("default").unwrap().class
+        else {
+            panic!("Unexpected texture class");
+        };
+        assert_eq!(tex, &TextureInput::Constant(FloatOrSpectrum::Float(2.0)));
+        assert_eq!(scale, &TextureInput::Constant(FloatOrSpectrum::Float(1.0)));
+
+        assert_eq!(scene.texture_graph().dependencies[3], vec![0, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn photometric_summary() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+LightSource "point" "rgb I" [ 10 10 10 ]
+Rotate 90 1 0 0
+LightSource "spot" "point3 to" [ 0 0 1 ] "float coneangle" 30 "float conedelta" 0
+LightSource "infinite" "rgb L" [ 1 1 1 ]
+            "#,
+            None,
+        )?;
+
+        let point = scene.lights[0].photometric_summary();
+        assert_eq!(point.ty, "point");
+        assert!((point.lumens.unwrap() - 40.0 * PI).abs() < 1e-3);
+        assert_eq!(point.direction, None);
+
+        let spot = scene.lights[1].photometric_summary();
+        assert_eq!(spot.ty, "spot");
+        assert!(spot.lumens.unwrap() > 0.0);
+        assert!(spot.direction.unwrap().abs_diff_eq(Vec3::NEG_Y, 1e-5));
+
+        let infinite = scene.lights[2].photometric_summary();
+        assert_eq!(infinite.lumens, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn camera_generate_ray() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Film "rgb" "integer xresolution" 200 "integer yresolution" 100
+LookAt 0 0 5  0 0 0  0 1 0
+Camera "perspective" "float fov" 90
+Identity
+LookAt 0 0 5  0 0 0  0 1 0
+Camera "orthographic"
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        let film = scene.film.as_ref().unwrap();
+
+        // The center of the image looks at the origin.
+        let (origin, direction) = scene.cameras[0].generate_ray(film, 100, 50).unwrap();
+        assert!(origin.abs_diff_eq(Vec3::new(0.0, 0.0, 5.0), 1e-5));
+        assert!(direction.abs_diff_eq(Vec3::NEG_Z, 1e-2));
+
+        // Top left corner is at 45 degrees along the shorter axis.
+        let (_, direction) = scene.cameras[0].generate_ray(film, 0, 0).unwrap();
+        assert!(direction.y > 0.0);
+        assert!((direction.y / -direction.z - 0.99).abs() < 1e-4);
+
+        let (origin, direction) = scene.cameras[1].generate_ray(film, 199, 50).unwrap();
+        assert!((origin.x.abs() - 1.99).abs() < 1e-4);
+        assert!(direction.abs_diff_eq(Vec3::NEG_Z, 1e-5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shape_alpha() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+Texture "leaf" "float" "imagemap" "string filename" "leaf.png"
+Shape "sphere"
+Shape "sphere" "float alpha" 0.25
+Shape "sphere" "texture alpha" "leaf"
+            "#,
+            None,
+        )?;
+
+        assert_eq!(scene.shapes[0].alpha, Alpha::Constant(1.0));
+        assert_eq!(scene.shapes[1].alpha, Alpha::Constant(0.25));
+        assert_eq!(scene.shapes[2].alpha, Alpha::Texture(0));
+
+        let Shape::Sphere { alpha, .. } = scene.shapes[1].params else {
+            panic!("Unexpected shape");
+        };
+        assert_eq!(alpha, 0.25);
+
+        assert!(scene.unused_entities().textures.is_empty());
+
+        let result = Scene::load(
+            r#"
+WorldBegin
+Shape "sphere" "texture alpha" "missing"
+            "#,
+            None,
+        );
+        assert!(matches!(result, Err(Error::UndefinedTexture(name)) if name == "missing"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn area_light_power() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+AttributeBegin
+    AreaLightSource "diffuse" "rgb L" [ 2 2 2 ] "float power" 10
+    Shape "trianglemesh"
+        "integer indices" [ 0 1 2 0 2 3 ]
+        "point3 P" [ 0 0 0  1 0 0  1 1 0  0 1 0 ]
+AttributeEnd
+AttributeBegin
+    AreaLightSource "diffuse" "rgb L" [ 1 1 1 ] "float power" 10 "bool twosided" true
+    Shape "trianglemesh"
+        "integer indices" [ 0 1 2 0 2 3 ]
+        "point3 P" [ 0 0 0  1 0 0  1 1 0  0 1 0 ]
+AttributeEnd
+            "#,
+            None,
+        )?;
+
+        let AreaLight::Diffuse { scale, power, .. } = &scene.area_lights[0];
+        assert!((scale - 10.0 / (2.0 * PI)).abs() < 1e-5);
+        assert_eq!(*power, None);
+
+        let AreaLight::Diffuse { scale, .. } = &scene.area_lights[1];
+        assert!((scale - 10.0 / (2.0 * PI)).abs() < 1e-5);
+
+        // The emitted power matches the requested one.
+        assert!((scene.total_emitted_power() - 20.0).abs() < 1e-3);
+
+        // Areas are measured in world space.
+        let scene = Scene::load(
+            r#"
+WorldBegin
+AttributeBegin
+    Scale 2 2 2
+    AreaLightSource "diffuse" "float power" 10
+    Shape "sphere"
+AttributeEnd
+AttributeBegin
+    Scale 1 2 1
+    AreaLightSource "diffuse" "float power" 10
+    Shape "sphere"
+AttributeEnd
+            "#,
+            None,
+        )?;
+
+        let AreaLight::Diffuse { scale, .. } = &scene.area_lights[0];
+        assert!((scale - 10.0 / (PI * 16.0 * PI)).abs() < 1e-5);
+
+        // Non-uniformly scaled spheres have no closed form area.
+        let AreaLight::Diffuse { scale, power, .. } = &scene.area_lights[1];
+        assert_eq!((*scale, *power), (1.0, Some(10.0)));
+        assert!(scene
+            .diagnostics
+            .iter()
+            .any(|d| d.starts_with("Area light 1")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn options_flags() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Option "bool disabletexturecache" true
+Option "bool disableimagetextures" "true"
+Option "bool wavefront" false
+WorldBegin
+            "#,
+            None,
+        )?;
+
+        assert!(scene.options.disable_texture_cache);
+        assert!(scene.options.disable_image_textures);
+        assert!(!scene.options.wavefront);
+
+        let result = Scene::load(
+            r#"
+Option "bool disabletexturecache" "yes"
+WorldBegin
+            "#,
+            None,
+        );
+        assert!(matches!(result, Err(Error::ParseBool(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn custom_options() -> Result<()> {
+        let data = r#"
+Option "string preview" "thumbnails/scene.png"
+Option "integer exporterversion" 3
+Option "bool wavefront" true
+WorldBegin
+            "#;
+
         let scene = Scene::load(data, None)?;
 
-        assert_eq!(scene.shapes.len(), 2);
+        assert_eq!(
+            scene.options.custom.get("preview").map(String::as_str),
+            Some("thumbnails/scene.png")
+        );
+        assert_eq!(
+            scene
+                .options
+                .custom
+                .get("exporterversion")
+                .map(String::as_str),
+            Some("3")
+        );
+        assert!(!scene.options.custom.contains_key("wavefront"));
 
-        assert!(matches!(scene.shapes[0].params, Shape::Sphere { .. }));
-        assert!(matches!(scene.shapes[0].params, Shape::Sphere { .. }));
+        let strict = LoadOptions {
+            strict_parameters: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Scene::load_with_options(data, None, &strict),
+            Err(Error::UnknownParameter { directive, key })
+                if directive == "Option" && key == "preview"
+        ));
 
-        assert_eq!(scene.objects.len(), 1);
+        Ok(())
+    }
 
-        let object = &scene.objects[0];
-        assert_eq!(&object.name, "foo");
-        assert_eq!(object.shape_start, Some(0));
-        assert_eq!(object.shape_count, 2);
+    #[test]
+    fn validate_degenerate_transforms() -> Result<()> {
+        let scene = Scene::load(
+            r#"
+Scale 1 0 1
+Camera "perspective"
+WorldBegin
+Shape "sphere"
+AttributeBegin
+    Scale 0 0 0
+    Shape "sphere"
+AttributeEnd
+ObjectBegin "ball"
+Shape "sphere"
+ObjectEnd
+AttributeBegin
+    Scale 1 1 0
+    ObjectInstance "ball"
+AttributeEnd
+AttributeBegin
+    Scale 1e-4 1e-4 1e-4
+    Shape "sphere"
+AttributeEnd
+AttributeBegin
+    Transform [ 1 0 0 0  1 1e-9 0 0  0 0 1 0  0 0 0 1 ]
+    Shape "sphere"
+AttributeEnd
+            "#,
+            None,
+        )?;
 
-        assert_eq!(scene.instances.len(), 2);
+        assert_eq!(
+            scene.validate(),
+            vec![
+                ValidationIssue::DegenerateTransform(EntityRef::Shape(1)),
+                ValidationIssue::DegenerateTransform(EntityRef::Shape(4)),
+                ValidationIssue::DegenerateTransform(EntityRef::Instance(0)),
+                ValidationIssue::DegenerateTransform(EntityRef::Camera(0)),
+            ]
+        );
 
-        let inst1 = &scene.instances[0];
-        assert_eq!(inst1.object_index, 0);
+        Ok(())
+    }
 
-        let inst2 = &scene.instances[1];
-        assert_eq!(inst1.object_index, 0);
-        assert_ne!(inst1.instance_to_world, inst2.instance_to_world);
+    #[test]
+    fn validate_emission() -> Result<()> {
+        let mut scene = Scene::load(
+            r#"
+WorldBegin
+AreaLightSource "diffuse" "rgb L" [ 1 1 1 ]
+Shape "sphere"
+AreaLightSource "diffuse" "rgb L" [ 1 -1 1 ]
+Shape "sphere"
+            "#,
+            None,
+        )?;
+
+        scene.area_lights.push(AreaLight::Diffuse {
+            filename: None,
+            two_sided: false,
+            spectrum: Some(Spectrum::Rgb([1.0, f32::NAN, 1.0], None)),
+            scale: 1.0,
+            n_samples: 1,
+            power: None,
+        });
+
+        assert_eq!(
+            scene.validate(),
+            vec![
+                ValidationIssue::InvalidEmission(EntityRef::AreaLight(1)),
+                ValidationIssue::InvalidEmission(EntityRef::AreaLight(2)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rgb_color_space() -> Result<()> {
+        let mut scene = Scene::load(
+            r#"
+WorldBegin
+AttributeBegin
+ColorSpace "rec2020"
+Material "diffuse" "rgb reflectance" [ 0.2 0.4 0.6 ]
+AttributeEnd
+Material "diffuse" "rgb reflectance" [ 0.2 0.4 0.6 ]
+            "#,
+            None,
+        )?;
+
+        let reflectance = |scene: &Scene, index: usize| match &scene.materials[index].ty {
+            MaterialType::Diffuse { reflectance } => reflectance.clone(),
+            _ => panic!("Unexpected material"),
+        };
+
+        assert_eq!(
+            reflectance(&scene, 0),
+            Spectrum::Rgb([0.2, 0.4, 0.6], Some(ColorSpace::Rec2020))
+        );
+        // The color space is part of the graphics state.
+        assert_eq!(reflectance(&scene, 1), Spectrum::Rgb([0.2, 0.4, 0.6], None));
+
+        // Values already in the target color space are kept.
+        scene.bake_spectra_to_rgb(ColorSpace::Rec2020);
+        let Spectrum::Rgb(rgb, _) = reflectance(&scene, 0) else {
+            panic!("Unexpected spectrum");
+        };
+        assert!(Vec3::from(rgb).abs_diff_eq(Vec3::new(0.2, 0.4, 0.6), 1e-4));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bake_spectra_to_rgb() -> Result<()> {
+        let mut scene = Scene::load(
+            r#"
+WorldBegin
+LightSource "point" "spectrum I" "stdillum-D65"
+LightSource "spot" "blackbody I" 3000
+AttributeBegin
+    AreaLightSource "diffuse" "spectrum L" "stdillum-A"
+    Shape "sphere"
+AttributeEnd
+Material "dielectric" "spectrum eta" "glass-BK7"
+Material "conductor" "spectrum eta" [ 300 0.2  800 0.2 ] "spectrum k" "metal-Au-k"
+Texture "checks" "spectrum" "checkerboard"
+Material "diffuse" "texture reflectance" "checks"
+            "#,
+            None,
+        )?;
+
+        scene.bake_spectra_to_rgb(ColorSpace::Srgb);
+
+        let is_rgb = |spectrum: Option<&Spectrum>| matches!(spectrum, Some(Spectrum::Rgb(_, _)));
+
+        assert!(scene
+            .lights
+            .iter()
+            .all(|light| is_rgb(light.params.spectrum())));
+
+        let AreaLight::Diffuse { spectrum, .. } = &scene.area_lights[0];
+        assert!(is_rgb(spectrum.as_ref()));
+
+        let MaterialType::Dielectric {
+            eta: FloatOrSpectrum::Spectrum(Spectrum::Rgb([r, g, b], _)),
+            ..
+        } = scene.materials[0].ty
+        else {
+            panic!("Unexpected material");
+        };
+        assert!(r < g && g < b);
+
+        let MaterialType::Conductor { eta, k, .. } = &scene.materials[1].ty else {
+            panic!("Unexpected material");
+        };
+        assert!(is_rgb(Some(eta)));
+        // No data for measured metals.
+        assert_eq!(k, &Spectrum::Named("metal-Au-k".to_string()));
+
+        let MaterialType::Diffuse { reflectance } = &scene.materials[2].ty else {
+            panic!("Unexpected material");
+        };
+        assert_eq!(reflectance, &Spectrum::Texture("checks".to_string()));
 
         Ok(())
     }
@@ -0,0 +1,102 @@
+//! Abstraction over the storage scene files are read from.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "archive")]
+use std::{
+    io::{Read, Seek},
+    path::Component,
+    sync::Mutex,
+};
+
+use crate::Result;
+
+/// Source of files referenced by a scene, such as included scene files, PLY meshes and IES profiles.
+pub trait AssetSource {
+    /// Read the whole file at `path`.
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+}
+
+/// Reads files from the file system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FsAssetSource;
+
+impl AssetSource for FsAssetSource {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(path)?)
+    }
+}
+
+/// In-memory files keyed by their path.
+impl AssetSource for HashMap<PathBuf, Vec<u8>> {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match self.get(path) {
+            Some(data) => Ok(data.clone()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, path.display().to_string()).into()),
+        }
+    }
+}
+
+/// Read a UTF-8 text file from `source`.
+pub(crate) fn read_string(source: &dyn AssetSource, path: &Path) -> Result<String> {
+    String::from_utf8(source.read(path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+}
+
+/// Reads files from a zip archive.
+///
+/// Paths are relative to the archive root, `.` and `..` components are resolved.
+#[cfg(feature = "archive")]
+pub struct ZipAssetSource<R> {
+    archive: Mutex<zip::ZipArchive<R>>,
+}
+
+#[cfg(feature = "archive")]
+impl<R: Read + Seek> ZipAssetSource<R> {
+    pub fn new(reader: R) -> Result<Self> {
+        let archive = zip::ZipArchive::new(reader).map_err(io::Error::from)?;
+
+        Ok(Self {
+            archive: Mutex::new(archive),
+        })
+    }
+
+    /// Convert a path to the name of an entry in the archive.
+    fn entry_name(path: &Path) -> String {
+        let mut components: Vec<String> = Vec::new();
+
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+                Component::ParentDir => {
+                    components.pop();
+                }
+                Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            }
+        }
+
+        components.join("/")
+    }
+}
+
+#[cfg(feature = "archive")]
+impl<R: Read + Seek> AssetSource for ZipAssetSource<R> {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let mut archive = self
+            .archive
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let mut file = archive
+            .by_name(&Self::entry_name(path))
+            .map_err(io::Error::from)?;
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,412 @@
+//! Conversion of bilinear patches and subdivision surfaces to triangle meshes.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use glam::Vec3;
+
+use crate::{param::ParamList, types::Shape, Error, Result, TriangleMesh};
+
+/// Maximum number of Loop subdivision levels, including the refinement to a target edge length.
+///
+/// Every level quadruples the triangle count.
+const MAX_SUBDIVISION_LEVELS: u32 = 6;
+
+/// Create a shape of type `ty`, converting shapes that aren't natively supported to
+/// [Shape::TriangleMesh].
+///
+/// `bilinearmesh` patches are split into two triangles each, and `loopsubdiv` control meshes
+/// are refined with Loop subdivision to the requested number of `levels`. Levels above
+/// [MAX_SUBDIVISION_LEVELS] are clamped to it, which is reported in `diagnostics`. If an
+/// `edgelength` is given, subdivision surfaces are refined further until no edge is longer than
+/// it, up to [MAX_SUBDIVISION_LEVELS] in total.
+/// All other shapes are created with [Shape::new].
+pub(crate) fn tessellated_shape(
+    ty: &str,
+    params: ParamList,
+    resolve_path: &dyn Fn(&str) -> PathBuf,
+    diagnostics: &mut Vec<String>,
+) -> Result<Shape> {
+    let alpha = Shape::constant_alpha(&params)?;
+
+    match ty {
+        "bilinearmesh" => {
+            let positions = params.floats("P")?.ok_or(Error::MissingRequiredParameter)?;
+
+            // A single patch may omit the indices.
+            let indices = match params.integers("indices")? {
+                Some(indices) => indices,
+                None if positions.len() == 12 => vec![0, 1, 2, 3],
+                None => return Err(Error::MissingRequiredParameter),
+            };
+
+            let vertex_count = positions.len() / 3;
+            if indices.len() % 4 != 0 {
+                return Err(Error::AttributeCountMismatch);
+            }
+            if indices
+                .iter()
+                .any(|&index| index < 0 || index as usize >= vertex_count)
+            {
+                return Err(Error::ValueOutOfRange("indices".to_string()));
+            }
+
+            // Patch vertices are ordered (0, 0), (1, 0), (0, 1), (1, 1) in parametric space.
+            let indices = indices
+                .chunks_exact(4)
+                .flat_map(|p| [p[0], p[1], p[3], p[0], p[3], p[2]])
+                .collect();
+
+            Ok(Shape::TriangleMesh {
+                alpha,
+                indices,
+                positions,
+                normals: params.floats("N")?.unwrap_or_default(),
+                uvs: params.floats("uv")?.unwrap_or_default(),
+                tangents: None,
+                colors: None,
+                face_indices: None,
+                edge_length: Shape::edge_length(&params)?,
+            })
+        }
+        "loopsubdiv" => {
+            let levels = u32::try_from(params.integer("levels", 3)?)
+                .map_err(|_| Error::ValueOutOfRange("levels".to_string()))?;
+            if levels > MAX_SUBDIVISION_LEVELS {
+                diagnostics.push(format!(
+                    "Clamped loopsubdiv levels from {levels} to {MAX_SUBDIVISION_LEVELS}"
+                ));
+            }
+            let levels = levels.min(MAX_SUBDIVISION_LEVELS);
+
+            let positions = params.floats("P")?.ok_or(Error::MissingRequiredParameter)?;
+            let indices = params
+                .integers("indices")?
+                .ok_or(Error::MissingRequiredParameter)?;
+
+            let vertex_count = positions.len() / 3;
+            if indices.len() % 3 != 0 {
+                return Err(Error::AttributeCountMismatch);
+            }
+
+            let indices = indices
+                .iter()
+                .map(|&index| match usize::try_from(index) {
+                    Ok(index) if index < vertex_count => Ok(index),
+                    _ => Err(Error::ValueOutOfRange("indices".to_string())),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let positions = positions.chunks_exact(3).map(Vec3::from_slice).collect();
+
+            let edge_length = Shape::edge_length(&params)?;
+            let (mut positions, mut indices) = loop_subdivide(positions, indices, levels);
+
+            if let Some(edge_length) = edge_length {
+                let mut level = levels;
+                while level < MAX_SUBDIVISION_LEVELS
+                    && longest_edge(&positions, &indices) > edge_length
+                {
+                    (positions, indices) = loop_subdivide(positions, indices, 1);
+                    level += 1;
+                }
+            }
+
+            Ok(Shape::TriangleMesh {
+                alpha,
+                indices: indices.into_iter().map(|index| index as i32).collect(),
+                positions: positions.iter().flat_map(|p| p.to_array()).collect(),
+                normals: Vec::new(),
+                uvs: Vec::new(),
+                tangents: None,
+                colors: None,
+                face_indices: None,
+                edge_length,
+            })
+        }
+        _ => Shape::new(ty, params, resolve_path),
+    }
+}
+
+/// Length of the longest edge of the triangles.
+fn longest_edge(positions: &[Vec3], indices: &[usize]) -> f32 {
+    indices
+        .chunks_exact(3)
+        .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+        .map(|(a, b)| positions[a].distance(positions[b]))
+        .fold(0.0, f32::max)
+}
+
+/// Tessellate a sphere, cylinder or disk into a triangle mesh in object space.
+///
+/// The surface is sampled on a regular grid of `segments` steps around the z axis
+/// and `segments / 2` steps along the other parametric direction, honoring
+/// `phimax` and the shape's z or radius bounds. Triangles wind counter-clockwise
+/// around the outward normal. Returns `None` for other shapes.
+pub(crate) fn tessellate_quadric(shape: &Shape, segments: u32) -> Option<TriangleMesh> {
+    let nu = segments.max(3) as usize;
+    let nv = (segments as usize / 2).max(1);
+
+    // Position and normal at parametric coordinates `(u, v)`.
+    let surface: Box<dyn Fn(f32, f32) -> (Vec3, Vec3)> = match *shape {
+        Shape::Sphere {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => {
+            let theta = |z: f32| (z / radius).clamp(-1.0, 1.0).acos();
+            let (theta_min, theta_max) = (theta(zmin.min(zmax)), theta(zmin.max(zmax)));
+            let phimax = phimax.clamp(0.0, 360.0).to_radians();
+
+            Box::new(move |u, v| {
+                let phi = u * phimax;
+                let theta = theta_min + v * (theta_max - theta_min);
+                let normal = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+
+                (normal * radius, normal)
+            })
+        }
+        Shape::Cylinder {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => {
+            let (zmin, zmax) = (zmin.min(zmax), zmin.max(zmax));
+            let phimax = phimax.clamp(0.0, 360.0).to_radians();
+
+            Box::new(move |u, v| {
+                let phi = u * phimax;
+                let normal = Vec3::new(phi.cos(), phi.sin(), 0.0);
+                let z = zmin + v * (zmax - zmin);
+
+                (Vec3::new(normal.x * radius, normal.y * radius, z), normal)
+            })
+        }
+        Shape::Disk {
+            height,
+            radius,
+            innerradius,
+            phimax,
+            ..
+        } => {
+            let phimax = phimax.clamp(0.0, 360.0).to_radians();
+
+            Box::new(move |u, v| {
+                let phi = u * phimax;
+                let r = radius + v * (innerradius - radius);
+
+                (Vec3::new(r * phi.cos(), r * phi.sin(), height), Vec3::Z)
+            })
+        }
+        _ => return None,
+    };
+
+    let mut mesh = TriangleMesh::default();
+
+    for j in 0..=nv {
+        for i in 0..=nu {
+            let (u, v) = (i as f32 / nu as f32, j as f32 / nv as f32);
+            let (position, normal) = surface(u, v);
+
+            mesh.positions.extend_from_slice(&position.to_array());
+            mesh.normals.extend_from_slice(&normal.to_array());
+            mesh.uvs.extend_from_slice(&[u, v]);
+        }
+    }
+
+    let vertex = |i: usize, j: usize| (j * (nu + 1) + i) as i32;
+    for j in 0..nv {
+        for i in 0..nu {
+            mesh.indices.extend_from_slice(&[
+                vertex(i, j),
+                vertex(i + 1, j),
+                vertex(i + 1, j + 1),
+                vertex(i, j),
+                vertex(i + 1, j + 1),
+                vertex(i, j + 1),
+            ]);
+        }
+    }
+
+    Some(mesh)
+}
+
+/// Edge of a triangle mesh, shared by one (boundary) or two triangles.
+struct Edge {
+    vertices: [usize; 2],
+    /// Vertices opposite to the edge in the adjacent triangles.
+    opposite: Vec<usize>,
+}
+
+/// Refine a triangle mesh `levels` times using Loop's subdivision rules.
+///
+/// Boundary edges and vertices use the crease rules, so open meshes keep their outline.
+fn loop_subdivide(
+    mut positions: Vec<Vec3>,
+    mut indices: Vec<usize>,
+    levels: u32,
+) -> (Vec<Vec3>, Vec<usize>) {
+    for _ in 0..levels {
+        // Collect edges in triangle order, so results don't depend on hashing.
+        let mut edge_map = HashMap::new();
+        let mut edges: Vec<Edge> = Vec::new();
+        let mut triangle_edges = Vec::with_capacity(indices.len());
+
+        for triangle in indices.chunks_exact(3) {
+            for k in 0..3 {
+                let a = triangle[k];
+                let b = triangle[(k + 1) % 3];
+                let c = triangle[(k + 2) % 3];
+
+                let edge = *edge_map.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    edges.push(Edge {
+                        vertices: [a, b],
+                        opposite: Vec::new(),
+                    });
+                    edges.len() - 1
+                });
+
+                edges[edge].opposite.push(c);
+                triangle_edges.push(edge);
+            }
+        }
+
+        let mut neighbors = vec![Vec::new(); positions.len()];
+        let mut boundary = vec![Vec::new(); positions.len()];
+        for edge in &edges {
+            let [a, b] = edge.vertices;
+
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+
+            if edge.opposite.len() == 1 {
+                boundary[a].push(b);
+                boundary[b].push(a);
+            }
+        }
+
+        // Reposition existing vertices.
+        let mut new_positions = positions
+            .iter()
+            .enumerate()
+            .map(|(vertex, &p)| {
+                let ring = &neighbors[vertex];
+
+                match boundary[vertex].as_slice() {
+                    [a, b] => 0.75 * p + 0.125 * (positions[*a] + positions[*b]),
+                    [] if !ring.is_empty() => {
+                        let n = ring.len() as f32;
+                        let beta = if ring.len() == 3 {
+                            3.0 / 16.0
+                        } else {
+                            3.0 / (8.0 * n)
+                        };
+                        let sum = ring.iter().map(|&v| positions[v]).sum::<Vec3>();
+
+                        (1.0 - n * beta) * p + beta * sum
+                    }
+                    // Isolated and non-manifold vertices stay in place.
+                    _ => p,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Insert a vertex on every edge.
+        let edge_start = new_positions.len();
+        new_positions.extend(edges.iter().map(|edge| {
+            let [a, b] = edge.vertices;
+
+            match edge.opposite.as_slice() {
+                [c, d] => {
+                    0.375 * (positions[a] + positions[b]) + 0.125 * (positions[*c] + positions[*d])
+                }
+                _ => 0.5 * (positions[a] + positions[b]),
+            }
+        }));
+
+        // Split every triangle into four.
+        let mut new_indices = Vec::with_capacity(indices.len() * 4);
+        for (triangle, edge) in indices.chunks_exact(3).zip(triangle_edges.chunks_exact(3)) {
+            let [v0, v1, v2] = [triangle[0], triangle[1], triangle[2]];
+            let [e01, e12, e20] = [
+                edge_start + edge[0],
+                edge_start + edge[1],
+                edge_start + edge[2],
+            ];
+
+            new_indices
+                .extend_from_slice(&[v0, e01, e20, v1, e12, e01, v2, e20, e12, e01, e12, e20]);
+        }
+
+        positions = new_positions;
+        indices = new_indices;
+    }
+
+    (positions, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tessellate_partial_cylinder() {
+        let cylinder = Shape::Cylinder {
+            alpha: 1.0,
+            radius: 2.0,
+            zmin: -1.0,
+            zmax: 1.0,
+            phimax: 90.0,
+        };
+
+        let mesh = tessellate_quadric(&cylinder, 8).unwrap();
+        assert_eq!(mesh.indices.len(), 8 * 4 * 6);
+
+        for p in mesh.positions.chunks_exact(3) {
+            let p = Vec3::from_slice(p);
+            assert!((p.truncate().length() - 2.0).abs() < 1e-5);
+            assert!(p.x >= -1e-5 && p.y >= -1e-5);
+            assert!((-1.0..=1.0).contains(&p.z));
+        }
+
+        // Outward facing triangles.
+        let p = |index: i32| Vec3::from_slice(&mesh.positions[index as usize * 3..]);
+        let [a, b, c] = [mesh.indices[0], mesh.indices[1], mesh.indices[2]].map(p);
+        assert!((b - a).cross(c - a).dot(a.truncate().extend(0.0)) > 0.0);
+    }
+
+    #[test]
+    fn loop_subdivide_triangle() {
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::Y];
+
+        let (positions, indices) = loop_subdivide(positions, vec![0, 1, 2], 2);
+
+        assert_eq!(indices.len(), 16 * 3);
+        assert_eq!(positions.len(), 15);
+
+        // Boundary vertices of a flat triangle stay in its plane.
+        assert!(positions.iter().all(|p| p.z == 0.0));
+    }
+
+    #[test]
+    fn loop_subdivide_tetrahedron() {
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z];
+        let indices = vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3];
+
+        let (positions, indices) = loop_subdivide(positions, indices, 1);
+
+        // 4 vertices + 6 edges, each face is split into 4.
+        assert_eq!(positions.len(), 10);
+        assert_eq!(indices.len(), 16 * 3);
+
+        // Closed surfaces shrink towards the centroid.
+        let centroid = Vec3::splat(0.25);
+        assert!(positions[..4].iter().all(|p| p.distance(centroid) < 0.75));
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt;
 use std::str::FromStr;
 
@@ -54,13 +55,44 @@ impl<'a> Token<'a> {
     }
 
     /// Return a string without quotes or `None` if the string is not a quoted string.
-    pub fn unquote(&self) -> Option<&'a str> {
-        if self.is_quote() {
-            let len = self.str.len();
-            Some(&self.str[1..len - 1])
-        } else {
-            None
+    ///
+    /// Escape sequences are decoded like in pbrt: `\"`, `\'`, `\\`, `\b`, `\f`, `\n`, `\r`
+    /// and `\t`, a backslash followed by any other character is kept as is.
+    /// Strings without escape sequences are borrowed.
+    pub fn unquote(&self) -> Option<Cow<'a, str>> {
+        if !self.is_quote() {
+            return None;
         }
+
+        let str = &self.str[1..self.str.len() - 1];
+        if !str.contains('\\') {
+            return Some(Cow::Borrowed(str));
+        }
+
+        let mut unescaped = String::with_capacity(str.len());
+        let mut chars = str.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '\\' {
+                unescaped.push(ch);
+                continue;
+            }
+
+            match chars.next() {
+                Some('b') => unescaped.push('\u{8}'),
+                Some('f') => unescaped.push('\u{c}'),
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some('t') => unescaped.push('\t'),
+                Some(ch @ ('"' | '\'' | '\\')) => unescaped.push(ch),
+                Some(ch) => {
+                    unescaped.push('\\');
+                    unescaped.push(ch);
+                }
+                None => unescaped.push('\\'),
+            }
+        }
+
+        Some(Cow::Owned(unescaped))
     }
 
     /// Check whether token is valid.
@@ -132,6 +164,11 @@ pub enum Directive {
     AttributeEnd,
     Attribute,
 
+    /// pbrt-v3 only.
+    TransformBegin,
+    /// pbrt-v3 only.
+    TransformEnd,
+
     Shape,
     ReverseOrientation,
     ObjectBegin,
@@ -184,6 +221,8 @@ impl FromStr for Directive {
             "AttributeBegin" => Directive::AttributeBegin,
             "AttributeEnd" => Directive::AttributeEnd,
             "Attribute" => Directive::Attribute,
+            "TransformBegin" => Directive::TransformBegin,
+            "TransformEnd" => Directive::TransformEnd,
             "Shape" => Directive::Shape,
             "ReverseOrientation" => Directive::ReverseOrientation,
             "ObjectBegin" => Directive::ObjectBegin,
@@ -205,7 +244,7 @@ impl FromStr for Directive {
 
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
+    use std::{borrow::Cow, str::FromStr};
 
     use crate::token::Directive;
 
@@ -224,8 +263,22 @@ mod tests {
 
     #[test]
     fn unquote_str() {
-        assert_eq!(Token::new("\"foo\"").unquote(), Some("foo"));
-        assert_eq!(Token::new("\"\"").unquote(), Some(""));
+        assert_eq!(Token::new("\"foo\"").unquote().as_deref(), Some("foo"));
+        assert_eq!(Token::new("\"\"").unquote().as_deref(), Some(""));
+        assert!(matches!(
+            Token::new("\"foo\"").unquote(),
+            Some(Cow::Borrowed(_))
+        ));
+
+        // Escape sequences.
+        assert_eq!(
+            Token::new(r#""a\"b.png""#).unquote().as_deref(),
+            Some("a\"b.png")
+        );
+        assert_eq!(
+            Token::new(r#""C:\\scenes\tx\n\q""#).unquote().as_deref(),
+            Some("C:\\scenes\tx\n\\q")
+        );
 
         assert_eq!(Token::new("").unquote(), None);
         assert_eq!(Token::new("\"").unquote(), None);
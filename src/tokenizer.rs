@@ -70,6 +70,58 @@ impl<'a> Tokenizer<'a> {
     pub fn offset(&self) -> usize {
         self.offset
     }
+
+    /// Skip the rest of an array that only contains plain numbers.
+    ///
+    /// Must be called right after the opening `[`. Returns the number of values and moves past
+    /// the closing `]`, or returns `None` without moving if the array contains anything
+    /// else (strings, comments, etc.), in which case it has to be read token by token.
+    pub fn skip_number_array(&mut self) -> Option<usize> {
+        let bytes = self.str.as_bytes().get(self.offset..)?;
+
+        let mut count = 0;
+        let mut in_value = false;
+
+        for (pos, byte) in bytes.iter().enumerate() {
+            match byte {
+                b' ' | b'\n' | b'\t' | b'\r' => in_value = false,
+                b'0'..=b'9' | b'+' | b'-' | b'.' | b'e' | b'E' => {
+                    if !in_value {
+                        count += 1;
+                        in_value = true;
+                    }
+                }
+                b']' => {
+                    self.offset += pos + 1;
+                    return Some(count);
+                }
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    /// Return the byte offset of `str`, which must be a slice of the tokenized string.
+    pub fn offset_of(&self, str: &str) -> usize {
+        (str.as_ptr() as usize)
+            .saturating_sub(self.str.as_ptr() as usize)
+            .min(self.str.len())
+    }
+
+    /// Return 1-based line and column of `str`, which must be a slice of the tokenized string.
+    pub fn location(&self, str: &str) -> (usize, usize) {
+        let offset = self.offset_of(str);
+        let before = &self.str[..offset];
+
+        let line = before.matches('\n').count() + 1;
+        let column = match before.rfind('\n') {
+            Some(pos) => offset - pos,
+            None => offset + 1,
+        };
+
+        (line, column)
+    }
 }
 
 impl<'a> Iterator for Tokenizer<'a> {
@@ -85,11 +137,22 @@ impl<'a> Iterator for Tokenizer<'a> {
                 '[' | ']' => self.token(start, start + 1),
                 ' ' | '\n' | '\t' | '\r' => continue,
                 '"' => {
-                    let mut end = self.rewind_until(&['"']);
+                    let mut end = start;
 
-                    // Consume remaining "
-                    if let Some((pos, _)) = self.next_char() {
+                    // Read until closing quote, everything in between (including `#`) is a part of the string.
+                    while let Some((pos, ch)) = self.next_char() {
                         end = pos;
+
+                        match ch {
+                            // Skip escaped char, so `\"` doesn't terminate the string.
+                            '\\' => {
+                                if let Some((pos, _)) = self.next_char() {
+                                    end = pos;
+                                }
+                            }
+                            '"' => break,
+                            _ => {}
+                        }
                     }
 
                     self.token(start, end + 1)
@@ -154,6 +217,22 @@ mod tests {
         assert_eq!(t.next(), None);
     }
 
+    #[test]
+    fn number_array() {
+        let mut t = Tokenizer::new("[ 1 -2.5 3e-2\n4 ] Scale");
+        t.next();
+
+        assert_eq!(t.skip_number_array(), Some(4));
+        assert_eq!(t.next(), Some(Token::new("Scale")));
+
+        // Strings and comments are left for the regular tokenizer.
+        let mut t = Tokenizer::new("[ 1 # comment\n 2 ]");
+        t.next();
+
+        assert_eq!(t.skip_number_array(), None);
+        assert_eq!(t.next(), Some(Token::new("1")));
+    }
+
     #[test]
     fn brackets() {
         let mut t = Tokenizer::new("[ abc ]");
@@ -242,6 +321,24 @@ Scale
         assert_eq!(t.next(), None);
     }
 
+    #[test]
+    fn quotes_with_hash() {
+        let mut t = Tokenizer::new(r#""foo #1.exr" Scale"#);
+
+        assert_eq!(t.next(), Some(Token::new("\"foo #1.exr\"")));
+        assert_eq!(t.next(), Some(Token::new("Scale")));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn quotes_escaped() {
+        let mut t = Tokenizer::new(r#""foo \"bar\"" Scale"#);
+
+        assert_eq!(t.next(), Some(Token::new(r#""foo \"bar\"""#)));
+        assert_eq!(t.next(), Some(Token::new("Scale")));
+        assert_eq!(t.next(), None);
+    }
+
     #[test]
     fn parse_scale() {
         let mut t = Tokenizer::new("Scale -1 1 1");
@@ -1,14 +1,23 @@
 //! Data structures that can be deserialized from a parameter list.
 
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::PI,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use glam::{Mat3, Mat4, Vec3};
 
 use crate::{
+    color::{self, NamedSpectrum},
     param::{Param, ParamList, ParamType, Spectrum},
-    Error, Result,
+    source::read_string,
+    AssetSource, Error, GonioDistribution, Result,
 };
 
 /// The coordinate system.
-#[derive(Debug, Default, Eq, PartialEq)]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub enum CoordinateSystem {
     /// Translate the scene so that the camera is at the origin.
     #[default]
@@ -32,11 +41,76 @@ impl FromStr for CoordinateSystem {
     }
 }
 
+/// RGB color spaces built into pbrt.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum ColorSpace {
+    /// sRGB primaries with a D65 white point, the default.
+    #[default]
+    Srgb,
+    /// ITU-R BT.2020 wide gamut primaries with a D65 white point.
+    Rec2020,
+    /// ACES AP0 primaries with a D60 white point.
+    Aces2065_1,
+    /// DCI-P3 primaries with a D65 white point.
+    DciP3,
+}
+
+impl FromStr for ColorSpace {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "srgb" => Ok(ColorSpace::Srgb),
+            "rec2020" => Ok(ColorSpace::Rec2020),
+            "aces2065-1" => Ok(ColorSpace::Aces2065_1),
+            "dci-p3" => Ok(ColorSpace::DciP3),
+            _ => Err(Error::UnknownColorSpace(s.to_string())),
+        }
+    }
+}
+
+impl ColorSpace {
+    /// Returns the xy chromaticities of the red, green and blue primaries and of the white point.
+    pub fn chromaticities(&self) -> [[f32; 2]; 4] {
+        const D65: [f32; 2] = [0.3127, 0.3290];
+
+        match self {
+            ColorSpace::Srgb => [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06], D65],
+            ColorSpace::Rec2020 => [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046], D65],
+            ColorSpace::Aces2065_1 => [
+                [0.7347, 0.2653],
+                [0.0, 1.0],
+                [0.0001, -0.077],
+                [0.32168, 0.33767],
+            ],
+            ColorSpace::DciP3 => [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060], D65],
+        }
+    }
+
+    /// Matrix converting CIE XYZ to linear RGB in this color space.
+    ///
+    /// The white point with `Y = 1` maps to `[1, 1, 1]`.
+    pub fn xyz_to_rgb(&self) -> Mat3 {
+        let xyz = |[x, y]: [f32; 2]| Vec3::new(x / y, 1.0, (1.0 - x - y) / y);
+        let [r, g, b, white] = self.chromaticities();
+
+        let primaries = Mat3::from_cols(xyz(r), xyz(g), xyz(b));
+        let scale = primaries.inverse() * xyz(white);
+        let rgb_to_xyz = primaries * Mat3::from_diagonal(scale);
+
+        rgb_to_xyz.inverse()
+    }
+}
+
 /// Scene-wide rendering options.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Options {
     /// Forces all pixel samples to be through the center of the pixel area.
     pub disable_pixel_jitter: bool,
+    /// Bypasses the texture tile cache and keeps image textures fully in memory.
+    pub disable_texture_cache: bool,
+    /// Replaces all image textures with a constant value, useful to profile geometry-bound scenes.
+    pub disable_image_textures: bool,
     /// Forces point sampling at the finest MIP level for all texture lookups.
     pub disable_texture_filtering: bool,
     /// Forces all samples within each pixel to sample the same wavelengths.
@@ -51,29 +125,69 @@ pub struct Options {
     pub mse_reference_out: Option<String>,
     /// Specifies the coordinate system to use for rendering computation.
     pub render_coord_sys: CoordinateSystem,
+    /// Render with the wavefront integrator instead of the default CPU path.
+    pub wavefront: bool,
+    /// Pixel region to render as `[xmin, xmax, ymin, ymax]`, used to distribute
+    /// rendering of a single image, see [crate::Scene::render_region].
+    pub sample_bounds: Option<[i32; 4]>,
+    /// Options not known to pbrt, for example metadata added by exporters,
+    /// with their values as written in the scene file.
+    pub custom: HashMap<String, String>,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Options {
             disable_pixel_jitter: false,
+            disable_texture_cache: false,
+            disable_image_textures: false,
             disable_texture_filtering: false,
             disable_wavelength_jitter: false,
             displacement_edge_scale: 1.0,
             mse_reference_image: None,
             mse_reference_out: None,
             render_coord_sys: CoordinateSystem::CameraWorld,
+            wavefront: false,
+            sample_bounds: None,
+            custom: HashMap::new(),
         }
     }
 }
 
 impl Options {
-    pub fn apply(&mut self, _option: Param) -> Result<()> {
+    /// Apply an `Option` directive, unknown options are stored in `custom`.
+    pub fn apply(&mut self, option: Param) -> Result<()> {
+        match option.name {
+            "disablepixeljitter" => self.disable_pixel_jitter = option.single()?,
+            "disabletexturecache" => self.disable_texture_cache = option.single()?,
+            "disableimagetextures" => self.disable_image_textures = option.single()?,
+            "disabletexturefiltering" => self.disable_texture_filtering = option.single()?,
+            "disablewavelengthjitter" => self.disable_wavelength_jitter = option.single()?,
+            "displacementedgescale" => self.displacement_edge_scale = option.single()?,
+            "msereferenceimage" => self.mse_reference_image = Some(option.string().to_string()),
+            "msereferenceout" => self.mse_reference_out = Some(option.string().to_string()),
+            "rendercoordsys" => self.render_coord_sys = option.single()?,
+            "wavefront" => self.wavefront = option.single()?,
+            "samplebounds" => {
+                let bounds: [i32; 4] = option.vec()?.try_into().map_err(|_| Error::ParseSlice)?;
+                let [x0, x1, y0, y1] = bounds;
+                if x0 < 0 || y0 < 0 || x0 >= x1 || y0 >= y1 {
+                    return Err(Error::ValueOutOfRange("samplebounds".to_string()));
+                }
+
+                self.sample_bounds = Some(bounds);
+            }
+            _ => {
+                self.custom
+                    .insert(option.name.to_string(), option.string().to_string());
+            }
+        }
+
         Ok(())
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub enum FilmType {
     /// Stores RGB images using the current color space when the [Film] directive is encountered.
     #[default]
@@ -95,8 +209,52 @@ pub enum FilmType {
     },
 }
 
+/// Camera sensor response curves built into pbrt.
+pub const SENSORS: &[&str] = &[
+    "cie1931",
+    "canon_eos_100d",
+    "canon_eos_1dx_mkii",
+    "canon_eos_200d",
+    "canon_eos_200d_mkii",
+    "canon_eos_5d",
+    "canon_eos_5d_mkii",
+    "canon_eos_5d_mkiii",
+    "canon_eos_5d_mkiv",
+    "canon_eos_5ds",
+    "canon_eos_m",
+    "hasselblad_l1d_20c",
+    "nikon_d810",
+    "nikon_d850",
+    "sony_ilce_6400",
+    "sony_ilce_7m3",
+    "sony_ilce_7rm3",
+    "sony_ilce_9",
+];
+
+/// Sensor response used by the film.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Sensor {
+    /// CIE 1931 spectral response curves.
+    #[default]
+    Cie1931,
+    /// Measured response of a camera sensor, one of [SENSORS].
+    Camera(String),
+}
+
+impl FromStr for Sensor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cie1931" => Ok(Sensor::Cie1931),
+            _ if SENSORS.contains(&s) => Ok(Sensor::Camera(s.to_string())),
+            _ => Err(Error::UnknownSensor(s.to_string())),
+        }
+    }
+}
+
 /// Film specifies the characteristics of the image being generated by the renderer.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Film {
     /// The number of pixels in the x direction.
     pub xresolution: i32,
@@ -118,8 +276,9 @@ pub struct Film {
     pub white_balance: f32,
     /// Characterizes the sensor's response for red, green, and blue colors.
     /// The default corresponds to using the CIE 1931 spectral response curves.
-    pub sensor: String,
+    pub sensor: Sensor,
     /// Image sample values with luminance greater than this value are clamped to have this luminance.
+    /// Infinity by default, which disables clamping.
     pub max_component_value: f32,
     /// Film type.
     pub ty: FilmType,
@@ -136,14 +295,47 @@ impl Default for Film {
             save_fp16: true,
             iso: 100.0,
             white_balance: 0.0,
-            sensor: String::from("cie1931"),
-            max_component_value: f32::MAX,
+            sensor: Sensor::Cie1931,
+            max_component_value: f32::INFINITY,
             ty: FilmType::Rgb,
         }
     }
 }
 
 impl Film {
+    /// Film aspect ratio, width divided by height.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.xresolution as f32 / self.yresolution as f32
+    }
+
+    /// Pixel region covered by the crop window as `[xmin, xmax, ymin, ymax]`, max exclusive.
+    pub fn pixel_bounds(&self) -> [i32; 4] {
+        let [x0, x1, y0, y1] = self.crop_window;
+        let (x, y) = (self.xresolution as f32, self.yresolution as f32);
+
+        [
+            (x * x0).ceil() as i32,
+            (x * x1).ceil() as i32,
+            (y * y0).ceil() as i32,
+            (y * y1).ceil() as i32,
+        ]
+    }
+
+    /// Set the crop window as `[xmin, xmax, ymin, ymax]` in NDC space.
+    ///
+    /// The window must be non-empty and lie within `[0, 1]` on both axes.
+    pub fn set_crop_window(&mut self, crop_window: [f32; 4]) -> Result<()> {
+        let [x0, x1, y0, y1] = crop_window;
+        let valid = |min: f32, max: f32| 0.0 <= min && min < max && max <= 1.0;
+
+        if !valid(x0, x1) || !valid(y0, y1) {
+            return Err(Error::ValueOutOfRange("cropwindow".to_string()));
+        }
+
+        self.crop_window = crop_window;
+        Ok(())
+    }
+
     pub fn new(ty: &str, params: ParamList) -> Result<Film> {
         let ty = match ty {
             "rgb" => FilmType::Rgb,
@@ -174,8 +366,8 @@ impl Film {
             save_fp16: params.boolean("savefp16", true)?,
             iso: params.float("iso", 100.0)?,
             white_balance: params.float("whitebalance", 0.0)?,
-            sensor: params.string("sensor").unwrap_or("cie1931").to_owned(),
-            max_component_value: params.float("maxcomponentvalue", f32::MAX)?,
+            sensor: Sensor::from_str(params.string("sensor").unwrap_or("cie1931"))?,
+            max_component_value: params.float("maxcomponentvalue", f32::INFINITY)?,
             ty,
         };
 
@@ -183,21 +375,36 @@ impl Film {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Camera {
     Orthographic {
         /// The time at which the virtual camera shutter opens.
         shutter_open: f32,
         /// The time at which the virtual camera shutter closes.
         shutter_close: f32,
+        /// The radius of the lens, zero for a pinhole camera.
+        lens_radius: f32,
+        /// The distance to the plane of focus.
+        focal_distance: f32,
+        /// Extent of the image plane in screen space as `[xmin, xmax, ymin, ymax]`.
+        /// If not specified, resolved from the film aspect ratio when the scene is loaded.
+        screen_window: Option<[f32; 4]>,
+        /// Distance to the near clipping plane.
+        near_clip: f32,
+        /// Distance to the far clipping plane.
+        far_clip: f32,
     },
     Perspective {
         /// The time at which the virtual camera shutter opens.
         shutter_open: f32,
         /// The time at which the virtual camera shutter closes.
         shutter_close: f32,
-        /// Specifies the field of view for the perspective camera.
+        /// Specifies the field of view for the perspective camera, in degrees.
         fov: f32,
+        /// The radius of the lens, zero for a pinhole camera.
+        lens_radius: f32,
+        /// The distance to the plane of focus.
+        focal_distance: f32,
     },
     /// The `RealisticCamera` simulates imaging from light rays passing through complex lens systems.
     Realistic {
@@ -234,16 +441,29 @@ impl Camera {
         // Two parameters that set the camera's shutter open times are common to all cameras in pbrt.
         let shutter_open = params.float("shutteropen", 0.0)?;
         let shutter_close = params.float("shutterclose", 1.0)?;
+        if shutter_close < shutter_open {
+            return Err(Error::ValueOutOfRange("shutterclose".to_string()));
+        }
 
         let camera = match ty {
             "orthographic" => Camera::Orthographic {
                 shutter_open,
                 shutter_close,
+                lens_radius: params.float("lensradius", 0.0)?,
+                focal_distance: params.float("focaldistance", 1e6)?,
+                screen_window: params
+                    .floats("screenwindow")?
+                    .map(|window| window.try_into().map_err(|_| Error::ParseSlice))
+                    .transpose()?,
+                near_clip: params.float("hither", 1e-3)?,
+                far_clip: params.float("yon", 1e30)?,
             },
             "perspective" => Camera::Perspective {
                 shutter_open,
                 shutter_close,
                 fov: params.float("fov", 90.0)?,
+                lens_radius: params.float("lensradius", 0.0)?,
+                focal_distance: params.float("focaldistance", 1e6)?,
             },
             "realistic" => Camera::Realistic {
                 shutter_open,
@@ -263,6 +483,89 @@ impl Camera {
 
         Ok(camera)
     }
+
+    /// Returns the `(open, close)` times of the camera shutter.
+    pub fn shutter(&self) -> (f32, f32) {
+        match self {
+            Camera::Orthographic {
+                shutter_open,
+                shutter_close,
+                ..
+            }
+            | Camera::Perspective {
+                shutter_open,
+                shutter_close,
+                ..
+            }
+            | Camera::Realistic {
+                shutter_open,
+                shutter_close,
+                ..
+            }
+            | Camera::Spherical {
+                shutter_open,
+                shutter_close,
+                ..
+            } => (*shutter_open, *shutter_close),
+        }
+    }
+
+    /// Clamp the shutter interval to `[start, end]`.
+    ///
+    /// Returns `true` if the shutter times were changed.
+    pub(crate) fn clamp_shutter(&mut self, start: f32, end: f32) -> bool {
+        let (shutter_open, shutter_close) = match self {
+            Camera::Orthographic {
+                shutter_open,
+                shutter_close,
+                ..
+            }
+            | Camera::Perspective {
+                shutter_open,
+                shutter_close,
+                ..
+            }
+            | Camera::Realistic {
+                shutter_open,
+                shutter_close,
+                ..
+            }
+            | Camera::Spherical {
+                shutter_open,
+                shutter_close,
+                ..
+            } => (shutter_open, shutter_close),
+        };
+
+        let (open, close) = (
+            shutter_open.clamp(start, end),
+            shutter_close.clamp(start, end),
+        );
+        let clamped = (open, close) != (*shutter_open, *shutter_close);
+
+        *shutter_open = open;
+        *shutter_close = close;
+
+        clamped
+    }
+
+    /// Default screen window for a film with the given aspect ratio.
+    ///
+    /// The shorter image axis spans `[-1, 1]`, the longer one is scaled by the aspect ratio.
+    pub fn default_screen_window(aspect_ratio: f32) -> [f32; 4] {
+        if aspect_ratio > 1.0 {
+            [-aspect_ratio, aspect_ratio, -1.0, 1.0]
+        } else {
+            [-1.0, 1.0, -1.0 / aspect_ratio, 1.0 / aspect_ratio]
+        }
+    }
+
+    /// Fill in the screen window if it was not specified explicitly.
+    pub(crate) fn resolve_screen_window(&mut self, aspect_ratio: f32) {
+        if let Camera::Orthographic { screen_window, .. } = self {
+            screen_window.get_or_insert_with(|| Self::default_screen_window(aspect_ratio));
+        }
+    }
 }
 
 /// The integrator implements the light transport algorithm that computes radiance
@@ -271,56 +574,191 @@ impl Camera {
 /// Many of these integrators are present only for pedagogical purposes or for use in debugging
 /// more complex integrators through computing images using much simpler integration algorithms.
 /// For rendering high quality images, one should almost always use one of `bdpt`, `mlt`, `sppm`, or `volpath`.
-#[derive(Debug)]
+///
+/// The `max_depth` of integrators limits the number of bounces of a light path through the scene,
+/// the `maxdepth` of coated materials is a [LayerDepth] which only applies within the material.
+#[derive(Debug, Clone)]
 pub enum Integrator {
     /// Ambient occlusion (accessibility over the hemisphere).
     AmbientOcclusion,
     /// Bidirectional path tracing.
     Bdpt,
     /// Path tracing starting from the light sources.
-    LightPath,
+    LightPath {
+        /// Maximum length of a light-carrying path sampled by the integrator.
+        max_depth: i32,
+    },
     /// Metropolis light transport using bidirectional path tracing.
-    Mlt,
+    Mlt {
+        /// Maximum length of a light-carrying path sampled by the integrator.
+        max_depth: i32,
+        /// Number of samples to take during the "bootstrap" phase.
+        bootstrap_samples: i32,
+        /// Number of unique Markov chains to run.
+        chains: i32,
+        /// Number of path mutations to apply per pixel in the image.
+        mutations_per_pixel: i32,
+        /// Probability of discarding the current path and generating a new random path.
+        large_step_probability: f32,
+        /// Standard deviation of the perturbation applied to random samples for small path mutations.
+        sigma: f32,
+    },
     /// Path tracing.
-    Path,
+    ///
+    /// Russian roulette is not configurable in pbrt-v4, paths are terminated
+    /// based on their throughput after the first bounce.
+    Path {
+        /// Maximum length of a light-carrying path sampled by the integrator.
+        max_depth: i32,
+        /// Whether near-specular BSDFs are roughened after the first non-specular bounce.
+        regularize: bool,
+    },
     /// Rendering using a simple random walk without any explicit light sampling.
     RandomWalk,
     /// Path tracing with very basic sampling algorithms.
-    SimplePath,
+    SimplePath {
+        /// Maximum length of a light-carrying path sampled by the integrator.
+        max_depth: i32,
+        /// Whether light sources are sampled directly.
+        sample_lights: bool,
+        /// Whether the BSDF is sampled to choose new path directions.
+        sample_bsdf: bool,
+    },
     /// Volumetric path tracing with very basic sampling algorithms.
-    SimpleVolPath,
+    SimpleVolPath {
+        /// Maximum length of a light-carrying path sampled by the integrator.
+        max_depth: i32,
+    },
     /// Stochastic progressive photon mapping
-    Sppm,
+    Sppm {
+        /// Maximum length of a light-carrying path sampled by the integrator.
+        max_depth: i32,
+        /// Number of photons to shoot from light sources in each iteration,
+        /// `-1` uses the number of pixels in the film.
+        photons_per_iteration: i32,
+        /// Initial photon search radius.
+        radius: f32,
+        /// Seed for the random number generator.
+        seed: i32,
+    },
     /// Volumetric path tracing.
+    ///
+    /// Uses the same Russian roulette termination as [Integrator::Path].
     VolPath {
         /// Maximum length of a light-carrying path sampled by the integrator.
         max_depth: i32,
+        /// Whether near-specular BSDFs are roughened after the first non-specular bounce.
+        regularize: bool,
     },
 }
 
+impl Default for Integrator {
+    /// pbrt's default, volumetric path tracing with a maximum depth of 5.
+    fn default() -> Self {
+        Integrator::VolPath {
+            max_depth: 5,
+            regularize: false,
+        }
+    }
+}
+
 impl Integrator {
     pub fn new(ty: &str, params: ParamList) -> Result<Integrator> {
         let integ = match ty {
             "ambientocclusion" => Integrator::AmbientOcclusion,
             "bdpt" => Integrator::Bdpt,
-            "lightpath" => Integrator::LightPath,
-            "mlt" => Integrator::Mlt,
-            "path" => Integrator::Path,
+            "lightpath" => Integrator::LightPath {
+                max_depth: params.integer("maxdepth", 5)?,
+            },
+            "mlt" => {
+                let large_step_probability = params.float("largestepprobability", 0.3)?;
+                if !(0.0..=1.0).contains(&large_step_probability) {
+                    return Err(Error::ValueOutOfRange("largestepprobability".to_string()));
+                }
+
+                Integrator::Mlt {
+                    max_depth: params.integer("maxdepth", 5)?,
+                    bootstrap_samples: params.integer("bootstrapsamples", 100000)?,
+                    chains: params.integer("chains", 1000)?,
+                    mutations_per_pixel: params.integer("mutationsperpixel", 100)?,
+                    large_step_probability,
+                    sigma: params.float("sigma", 0.01)?,
+                }
+            }
+            "path" => Integrator::Path {
+                max_depth: Self::max_depth(&params)?,
+                regularize: params.boolean("regularize", false)?,
+            },
             "randomwalk" => Integrator::RandomWalk,
-            "simplepath" => Integrator::SimplePath,
-            "simplevolpath" => Integrator::SimpleVolPath,
-            "sppm" => Integrator::Sppm,
+            "simplepath" => Integrator::SimplePath {
+                max_depth: Self::max_depth(&params)?,
+                sample_lights: params.boolean("samplelights", true)?,
+                sample_bsdf: params.boolean("samplebsdf", true)?,
+            },
+            "simplevolpath" => Integrator::SimpleVolPath {
+                max_depth: Self::max_depth(&params)?,
+            },
+            "sppm" => Integrator::Sppm {
+                max_depth: Self::max_depth(&params)?,
+                photons_per_iteration: params.integer("photonsperiteration", -1)?,
+                radius: params.float("radius", 1.0)?,
+                seed: params.integer("seed", 0)?,
+            },
             "volpath" => Integrator::VolPath {
-                max_depth: params.integer("maxdepth", 5)?,
+                max_depth: Self::max_depth(&params)?,
+                regularize: params.boolean("regularize", false)?,
             },
             _ => unimplemented!("Unsupported integrator type {ty}"),
         };
 
         Ok(integ)
     }
+
+    /// Returns `true` if the integrator is set up to converge very slowly.
+    ///
+    /// This is the case for `simplepath` with both `samplelights` and `samplebsdf` disabled:
+    /// pbrt then samples directions uniformly, which still converges, but with high variance.
+    pub fn is_high_variance(&self) -> bool {
+        matches!(
+            self,
+            Integrator::SimplePath {
+                sample_lights: false,
+                sample_bsdf: false,
+                ..
+            }
+        )
+    }
+
+    /// Progressive photon mapping search radius used in iteration `i`, counting from zero.
+    ///
+    /// The radius shrinks following Knaus and Zwicker, `r(i+1)^2 = r(i)^2 * (i + alpha) / (i + 1)`
+    /// with `alpha = 2/3`. Returns zero for integrators other than `sppm`, they don't gather photons.
+    pub fn radius_at_iteration(&self, i: u32) -> f32 {
+        const ALPHA: f32 = 2.0 / 3.0;
+
+        let Integrator::Sppm { radius, .. } = self else {
+            return 0.0;
+        };
+
+        let mut radius_sqr = radius * radius;
+        for iteration in 1..=i {
+            radius_sqr *= (iteration as f32 + ALPHA) / (iteration as f32 + 1.0);
+        }
+
+        radius_sqr.sqrt()
+    }
+
+    fn max_depth(params: &ParamList) -> Result<i32> {
+        let max_depth = params.integer("maxdepth", 5)?;
+        if max_depth < 0 {
+            return Err(Error::ValueOutOfRange("maxdepth".to_string()));
+        }
+
+        Ok(max_depth)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PixelFilter {
     Box {
         xradius: f32,
@@ -356,6 +794,22 @@ impl PixelFilter {
                 let yradius = params.float("yradius", 0.5)?;
                 PixelFilter::Box { xradius, yradius }
             }
+            "gaussian" => PixelFilter::Gaussian {
+                xradius: params.float("xradius", 1.5)?,
+                yradius: params.float("yradius", 1.5)?,
+                sigma: params.float("sigma", 0.5)?,
+            },
+            "mitchell" => PixelFilter::Mitchell {
+                xradius: params.float("xradius", 2.0)?,
+                yradius: params.float("yradius", 2.0)?,
+                b: params.float("B", 1.0 / 3.0)?,
+                c: params.float("C", 1.0 / 3.0)?,
+            },
+            "sinc" => PixelFilter::Sinc {
+                xradius: params.float("xradius", 4.0)?,
+                yradius: params.float("yradius", 4.0)?,
+                tau: params.float("tau", 3.0)?,
+            },
             "triangle" => {
                 let xradius = params.float("xradius", 0.5)?;
                 let yradius = params.float("yradius", 0.5)?;
@@ -366,6 +820,84 @@ impl PixelFilter {
 
         Ok(filter)
     }
+
+    /// Evaluate the filter at offset `(x, y)` from the pixel sample position.
+    ///
+    /// Values are not normalized and are zero outside of the filter radius.
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        match *self {
+            PixelFilter::Box { xradius, yradius } => {
+                if x.abs() <= xradius && y.abs() <= yradius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            PixelFilter::Gaussian {
+                xradius,
+                yradius,
+                sigma,
+            } => {
+                let gaussian = |v: f32, radius: f32| {
+                    (Self::gaussian(v, sigma) - Self::gaussian(radius, sigma)).max(0.0)
+                };
+                gaussian(x, xradius) * gaussian(y, yradius)
+            }
+            PixelFilter::Mitchell {
+                xradius,
+                yradius,
+                b,
+                c,
+            } => Self::mitchell(2.0 * x / xradius, b, c) * Self::mitchell(2.0 * y / yradius, b, c),
+            PixelFilter::Sinc {
+                xradius,
+                yradius,
+                tau,
+            } => Self::windowed_sinc(x, xradius, tau) * Self::windowed_sinc(y, yradius, tau),
+            PixelFilter::Triangle { xradius, yradius } => {
+                (xradius - x.abs()).max(0.0) * (yradius - y.abs()).max(0.0)
+            }
+        }
+    }
+
+    fn gaussian(x: f32, sigma: f32) -> f32 {
+        (-(x * x) / (2.0 * sigma * sigma)).exp() / (2.0 * PI * sigma * sigma).sqrt()
+    }
+
+    fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+        let x = x.abs();
+
+        if x <= 1.0 {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b))
+                / 6.0
+        } else if x <= 2.0 {
+            ((-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            0.0
+        }
+    }
+
+    fn windowed_sinc(x: f32, radius: f32, tau: f32) -> f32 {
+        let sinc = |x: f32| {
+            if x.abs() < 1e-5 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            }
+        };
+
+        if x.abs() > radius {
+            0.0
+        } else {
+            sinc(x) * sinc(x / tau)
+        }
+    }
 }
 
 impl Default for PixelFilter {
@@ -378,7 +910,7 @@ impl Default for PixelFilter {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub enum BvhSplitMethod {
     /// Denotes the surface area heuristic.
     #[default]
@@ -391,7 +923,7 @@ pub enum BvhSplitMethod {
     Hlbvh,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Accelerator {
     Bvh {
         /// Maximum number of primitives to allow in a node in the tree.
@@ -442,99 +974,530 @@ impl Accelerator {
     }
 }
 
+impl Default for Accelerator {
+    /// pbrt's default, a BVH with the surface area heuristic and up to 4 primitives per node.
+    fn default() -> Self {
+        Accelerator::Bvh {
+            max_node_prims: 4,
+            split_method: BvhSplitMethod::Sah,
+        }
+    }
+}
+
+/// Randomization strategy used by the Sobol-based samplers.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum SobolRandomization {
+    /// No randomization.
+    None,
+    /// Random digit permutation.
+    PermuteDigits,
+    /// Fast Owen scrambling.
+    #[default]
+    FastOwen,
+    /// Owen scrambling.
+    Owen,
+}
+
+impl FromStr for SobolRandomization {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(SobolRandomization::None),
+            "permutedigits" => Ok(SobolRandomization::PermuteDigits),
+            "fastowen" => Ok(SobolRandomization::FastOwen),
+            "owen" => Ok(SobolRandomization::Owen),
+            _ => Err(Error::InvalidString),
+        }
+    }
+}
+
 // The Sampler generates samples for the image, time, lens, and Monte Carlo integration.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone)]
 pub enum Sampler {
-    Halton,
-    Independent,
-    PaddedSobol,
-    Sobol,
-    Stratified,
-    #[default]
-    ZSobol,
+    Halton {
+        /// Number of samples to take in each pixel.
+        pixel_samples: i32,
+    },
+    Independent {
+        /// Number of samples to take in each pixel.
+        pixel_samples: i32,
+    },
+    PaddedSobol {
+        /// Number of samples to take in each pixel.
+        pixel_samples: i32,
+        randomization: SobolRandomization,
+    },
+    Sobol {
+        /// Number of samples to take in each pixel.
+        pixel_samples: i32,
+        randomization: SobolRandomization,
+    },
+    /// Stratified sampling over an `xsamples` by `ysamples` grid of pixel strata.
+    Stratified {
+        /// Number of strata in the x direction.
+        x_samples: i32,
+        /// Number of strata in the y direction.
+        y_samples: i32,
+        /// Whether to jitter the samples within the strata.
+        jitter: bool,
+    },
+    ZSobol {
+        /// Number of samples to take in each pixel.
+        pixel_samples: i32,
+        randomization: SobolRandomization,
+    },
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler::ZSobol {
+            pixel_samples: 16,
+            randomization: SobolRandomization::default(),
+        }
+    }
 }
 
 impl Sampler {
-    pub fn new(ty: &str, _params: ParamList) -> Result<Sampler> {
+    pub fn new(ty: &str, params: ParamList) -> Result<Sampler> {
+        let pixel_samples = params.integer("pixelsamples", 16)?;
+
+        let randomization = || -> Result<SobolRandomization> {
+            match params.string("randomization") {
+                Some(value) => SobolRandomization::from_str(value),
+                None => Ok(SobolRandomization::default()),
+            }
+        };
+
         let sampler = match ty {
-            "halton" => Sampler::Halton,
-            "independent" => Sampler::Independent,
-            "paddedsobol" => Sampler::PaddedSobol,
-            "sobol" => Sampler::Sobol,
-            "stratified" => Sampler::Stratified,
-            "zsobol" => Sampler::ZSobol,
+            "halton" => Sampler::Halton { pixel_samples },
+            "independent" => Sampler::Independent { pixel_samples },
+            "paddedsobol" => Sampler::PaddedSobol {
+                pixel_samples,
+                randomization: randomization()?,
+            },
+            "sobol" => Sampler::Sobol {
+                pixel_samples,
+                randomization: randomization()?,
+            },
+            "stratified" => {
+                let x_samples = params.integer("xsamples", 4)?;
+                let y_samples = params.integer("ysamples", 4)?;
+
+                if x_samples < 1 {
+                    return Err(Error::ValueOutOfRange("xsamples".to_string()));
+                }
+                if y_samples < 1 {
+                    return Err(Error::ValueOutOfRange("ysamples".to_string()));
+                }
+
+                Sampler::Stratified {
+                    x_samples,
+                    y_samples,
+                    jitter: params.boolean("jitter", true)?,
+                }
+            }
+            "zsobol" => Sampler::ZSobol {
+                pixel_samples,
+                randomization: randomization()?,
+            },
             _ => return Err(Error::InvalidObjectType(ty.to_string())),
         };
 
         Ok(sampler)
     }
+
+    /// Number of samples taken in each pixel.
+    ///
+    /// The stratified sampler takes one sample per stratum, its `pixelsamples` parameter is ignored.
+    pub fn pixel_samples(&self) -> i32 {
+        match self {
+            Sampler::Halton { pixel_samples }
+            | Sampler::Independent { pixel_samples }
+            | Sampler::PaddedSobol { pixel_samples, .. }
+            | Sampler::Sobol { pixel_samples, .. }
+            | Sampler::ZSobol { pixel_samples, .. } => *pixel_samples,
+            Sampler::Stratified {
+                x_samples,
+                y_samples,
+                ..
+            } => x_samples * y_samples,
+        }
+    }
 }
 
 /// Light sources cast illumination in the scene.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Light {
     /// The "distant" light source represents a directional light source "at infinity";
     /// In other words, it illuminates the scene with light arriving from a single direction.
     Distant {
         spectrum: Option<Spectrum>,
+        /// Scale factor that modulates the amount of light that the light source emits.
+        scale: f32,
         from: [f32; 3],
         to: [f32; 3],
     },
-    GonioPhotometric,
+    /// The "goniometric" light represents a point light source with directionally-varying
+    /// emission specified by a goniometric diagram.
+    GonioPhotometric {
+        /// Spectral distribution of the light's emitted radiant intensity.
+        spectrum: Option<Spectrum>,
+        /// Scale factor that modulates the amount of light that the light source emits.
+        /// When `power` is specified, the scale is normalized so that the light emits that power.
+        scale: f32,
+        /// Total power emitted by the light, if specified.
+        power: Option<f32>,
+        /// File with the goniometric diagram, an IES profile or an equal-area image.
+        /// See [Light::load_goniometric_distribution].
+        filename: Option<PathBuf>,
+    },
     /// The "infinite" light represents an infinitely far away light source that
     /// potentially casts illumination from all directions.
     Infinite {
         /// The environment map to use for the infinite area light.
         /// If no filename is provided, the light will emit the same amount of radiance from every direction.
-        filename: Option<String>,
+        filename: Option<PathBuf>,
         /// The spectral distribution of emission from the light.
         spectrum: Option<Spectrum>,
+        /// Scale factor that modulates the amount of light that the light source emits.
+        scale: f32,
+        /// Four corners of a quadrilateral "portal" through which the environment map's light enters the scene.
+        /// The corners must lie in a plane, see [Light::portal_normal_and_area].
+        portal: Option<[Vec3; 4]>,
+    },
+    /// The "point" light is an isotropic point light source that emits the same
+    /// amount of light in all directions.
+    Point {
+        /// Spectral distribution of the light's emitted radiant intensity.
+        spectrum: Option<Spectrum>,
+        /// Scale factor that modulates the amount of light that the light source emits.
+        scale: f32,
+        /// Light position.
+        from: [f32; 3],
+    },
+    /// The "projection" light acts like a slide projector.
+    Projection {
+        /// Spectral distribution of the light's emitted radiant intensity.
+        spectrum: Option<Spectrum>,
+        /// Scale factor that modulates the amount of light that the light source emits.
+        /// When `power` is specified, the scale is normalized so that the light emits that power.
+        scale: f32,
+        /// Total power emitted by the light, if specified.
+        power: Option<f32>,
+        /// The field of view of the projection, in degrees.
+        fov: f32,
+    },
+    /// The "spot" light is a point light source that emits light in a cone of directions.
+    Spot {
+        /// Spectral distribution of the light's emitted radiant intensity.
+        spectrum: Option<Spectrum>,
+        /// Scale factor that modulates the amount of light that the light source emits.
+        scale: f32,
+        /// Light position.
+        from: [f32; 3],
+        /// The point the spotlight is aimed at.
+        to: [f32; 3],
+        /// The angle that the spotlight's cone makes with its primary axis, in degrees.
+        cone_angle: f32,
+        /// The angle at which the spotlight intensity starts to fall off at the edges, in degrees.
+        cone_delta: f32,
     },
-    Point,
-    Projection,
-    Spot,
 }
 
 impl Light {
-    const ILLUMINANT: Spectrum = Spectrum::Rgb([1.0; 3]); // todo
+    const ILLUMINANT: Spectrum = Spectrum::Rgb([1.0; 3], None); // todo
 
-    pub fn new(ty: &str, params: ParamList) -> Result<Light> {
+    /// Relative tolerance used to check that portal corners are coplanar.
+    pub const PORTAL_EPSILON: f32 = 1e-4;
+
+    /// Create a light of type `ty`.
+    ///
+    /// External file names are converted to paths with `resolve_path`.
+    pub fn new(
+        ty: &str,
+        params: ParamList,
+        resolve_path: &dyn Fn(&str) -> PathBuf,
+    ) -> Result<Light> {
         let light = match ty {
             "distant" => Light::Distant {
                 spectrum: params.spectrum("L", Self::ILLUMINANT).ok(),
+                scale: params.float("scale", 1.0)?,
                 from: params.point3("from", [0.0, 0.0, 0.0])?,
                 to: params.point3("to", [0.0, 0.0, 1.0])?,
             },
-            "goniometric" => Light::GonioPhotometric,
-            "infinite" => Light::Infinite {
-                filename: params.string("filename").map(|s| s.to_string()),
-                spectrum: params.spectrum("L", Self::ILLUMINANT).ok(),
+            "goniometric" => {
+                let spectrum = params.spectrum("I", Self::ILLUMINANT).ok();
+                let power = Self::power(&params)?;
+
+                // Without the goniometric diagram, the emission is assumed to be uniform over the sphere.
+                let solid_angle = 4.0 * PI;
+
+                Light::GonioPhotometric {
+                    scale: Self::normalized_scale(&params, spectrum.as_ref(), power, solid_angle)?,
+                    spectrum,
+                    power,
+                    filename: params.string("filename").map(resolve_path),
+                }
+            }
+            "infinite" => {
+                // Either a constant radiance or an environment map, but not both.
+                let filename = params.string("filename");
+                if filename.is_some() && params.get("L").is_some() {
+                    return Err(Error::ConflictingLightParameters);
+                }
+
+                let portal = match params.floats("portal")? {
+                    Some(portal) => {
+                        let portal: [f32; 12] = portal.try_into().map_err(|_| Error::ParseSlice)?;
+                        let portal = [0, 3, 6, 9].map(|i| Vec3::from_slice(&portal[i..]));
+
+                        if !Self::is_planar(&portal) {
+                            return Err(Error::InvalidPortal);
+                        }
+
+                        Some(portal)
+                    }
+                    None => None,
+                };
+
+                Light::Infinite {
+                    filename: filename.map(resolve_path),
+                    spectrum: params.spectrum("L", Self::ILLUMINANT).ok(),
+                    scale: params.float("scale", 1.0)?,
+                    portal,
+                }
+            }
+            "point" => Light::Point {
+                spectrum: params.spectrum("I", Self::ILLUMINANT).ok(),
+                scale: params.float("scale", 1.0)?,
+                from: params.point3("from", [0.0, 0.0, 0.0])?,
+            },
+            "projection" => {
+                let spectrum = params.spectrum("I", Self::ILLUMINANT).ok();
+                let power = Self::power(&params)?;
+                let fov = params.float("fov", 90.0)?;
+
+                // Solid angle subtended by the projected image, assuming a square image.
+                let half_angle = (fov * 0.5).to_radians();
+                let solid_angle = 4.0 * (half_angle.sin() * half_angle.sin()).asin();
+
+                Light::Projection {
+                    scale: Self::normalized_scale(&params, spectrum.as_ref(), power, solid_angle)?,
+                    spectrum,
+                    power,
+                    fov,
+                }
+            }
+            "spot" => Light::Spot {
+                spectrum: params.spectrum("I", Self::ILLUMINANT).ok(),
+                scale: params.float("scale", 1.0)?,
+                from: params.point3("from", [0.0, 0.0, 0.0])?,
+                to: params.point3("to", [0.0, 0.0, 1.0])?,
+                cone_angle: params.float("coneangle", 30.0)?,
+                cone_delta: params.float("conedelta", 5.0)?,
             },
-            "point" => Light::Point,
-            "projection" => Light::Projection,
-            "spot" => Light::Spot,
             _ => unimplemented!(),
         };
 
         Ok(light)
     }
-}
 
-/// Area lights have geometry associated with them.
-#[derive(Debug)]
-pub enum AreaLight {
-    Diffuse {
-        /// Filename for an image that describes spatially-varying emission over the surface of the emitter.
-        /// The emitting shape's default (u,v) parameterization is used to map the image to the surface.
-        filename: Option<String>,
-        /// Determines whether the light source emits light from just the side of the surface
-        /// where the surface normal points or both sides.
-        two_sided: bool,
-        /// Spectral distribution of the light's emitted radiance.
-        spectrum: Option<Spectrum>,
-        /// Scale factor that modulates the amount of light that the light source emits into the scene.
-        scale: f32,
-    },
+    /// Returns the spot light's cone angle in radians, `None` for other lights.
+    pub fn cone_angle_radians(&self) -> Option<f32> {
+        match self {
+            Light::Spot { cone_angle, .. } => Some(cone_angle.to_radians()),
+            _ => None,
+        }
+    }
+
+    /// Returns the spot light's falloff angle in radians, `None` for other lights.
+    pub fn cone_delta_radians(&self) -> Option<f32> {
+        match self {
+            Light::Spot { cone_delta, .. } => Some(cone_delta.to_radians()),
+            _ => None,
+        }
+    }
+
+    /// Read the goniometric diagram of a `goniometric` light.
+    ///
+    /// The file is read from `source` at the path resolved when the light was created.
+    /// Only IES profiles are parsed, images must be loaded by the caller and result
+    /// in [Error::InvalidIes].
+    pub fn load_goniometric_distribution(
+        &self,
+        source: &dyn AssetSource,
+    ) -> Result<GonioDistribution> {
+        let Light::GonioPhotometric { filename, .. } = self else {
+            return Err(Error::InvalidObjectType(self.type_name().to_string()));
+        };
+        let path = filename.as_deref().ok_or(Error::MissingRequiredParameter)?;
+
+        let is_ies = path
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("ies"));
+        if !is_ies {
+            return Err(Error::InvalidIes(format!(
+                "unsupported file {}",
+                path.display()
+            )));
+        }
+
+        GonioDistribution::from_ies(&read_string(source, path)?)
+    }
+
+    /// Returns the normalized direction `to - from` in light space.
+    ///
+    /// Only defined for `distant` and `spot` lights, `None` if `from` and `to` coincide.
+    pub fn direction(&self) -> Option<Vec3> {
+        let (from, to) = match self {
+            Light::Distant { from, to, .. } | Light::Spot { from, to, .. } => (from, to),
+            _ => return None,
+        };
+
+        (Vec3::from(*to) - Vec3::from(*from)).try_normalize()
+    }
+
+    /// Returns the light type as written in the scene file.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Light::Distant { .. } => "distant",
+            Light::GonioPhotometric { .. } => "goniometric",
+            Light::Infinite { .. } => "infinite",
+            Light::Point { .. } => "point",
+            Light::Projection { .. } => "projection",
+            Light::Spot { .. } => "spot",
+        }
+    }
+
+    /// Scale factor applied to the light's emission.
+    pub fn scale(&self) -> f32 {
+        match self {
+            Light::Distant { scale, .. }
+            | Light::GonioPhotometric { scale, .. }
+            | Light::Infinite { scale, .. }
+            | Light::Point { scale, .. }
+            | Light::Projection { scale, .. }
+            | Light::Spot { scale, .. } => *scale,
+        }
+    }
+
+    /// Mutable access to the light's emission spectrum, see [Light::spectrum].
+    pub fn spectrum_mut(&mut self) -> Option<&mut Spectrum> {
+        match self {
+            Light::Distant { spectrum, .. }
+            | Light::GonioPhotometric { spectrum, .. }
+            | Light::Infinite { spectrum, .. }
+            | Light::Point { spectrum, .. }
+            | Light::Projection { spectrum, .. }
+            | Light::Spot { spectrum, .. } => spectrum.as_mut(),
+        }
+    }
+
+    /// Spectral distribution of the light's emission, radiance or intensity depending on the light type.
+    pub fn spectrum(&self) -> Option<&Spectrum> {
+        match self {
+            Light::Distant { spectrum, .. }
+            | Light::GonioPhotometric { spectrum, .. }
+            | Light::Infinite { spectrum, .. }
+            | Light::Point { spectrum, .. }
+            | Light::Projection { spectrum, .. }
+            | Light::Spot { spectrum, .. } => spectrum.as_ref(),
+        }
+    }
+
+    /// Average emitted radiance or intensity with the light's scale applied.
+    ///
+    /// Returns `None` if the emission is defined by a texture or missing.
+    pub fn effective_intensity(&self) -> Option<f32> {
+        self.spectrum()
+            .and_then(Spectrum::average)
+            .map(|average| average * self.scale())
+    }
+
+    /// Returns the unit normal and the area of an infinite light's portal, if it has one.
+    ///
+    /// The normal follows the winding of the portal corners.
+    pub fn portal_normal_and_area(&self) -> Option<(Vec3, f32)> {
+        let Light::Infinite {
+            portal: Some([p0, p1, p2, p3]),
+            ..
+        } = self
+        else {
+            return None;
+        };
+
+        // Cross product of the diagonals is twice the area of a planar quadrilateral.
+        let cross = (*p2 - *p0).cross(*p3 - *p1);
+
+        Some((cross.normalize(), cross.length() * 0.5))
+    }
+
+    /// Returns `true` if the portal corners are in one plane and don't collapse to a line or a point.
+    fn is_planar(portal: &[Vec3; 4]) -> bool {
+        let [p0, p1, p2, p3] = *portal;
+
+        let normal = (p1 - p0).cross(p2 - p0);
+        let size = (p1 - p0).length().max((p2 - p0).length());
+
+        if normal.length() <= Self::PORTAL_EPSILON * size * size {
+            return false;
+        }
+
+        normal.normalize().dot(p3 - p0).abs() <= Self::PORTAL_EPSILON * size
+    }
+
+    /// Returns the `power` parameter, if specified and positive.
+    fn power(params: &ParamList) -> Result<Option<f32>> {
+        let power = params.float("power", -1.0)?;
+        Ok((power > 0.0).then_some(power))
+    }
+
+    /// Compute the light's scale factor.
+    ///
+    /// If `power` is given, the user provided scale is normalized so that a light emitting
+    /// `spectrum` over `solid_angle` steradians emits the requested power.
+    fn normalized_scale(
+        params: &ParamList,
+        spectrum: Option<&Spectrum>,
+        power: Option<f32>,
+        solid_angle: f32,
+    ) -> Result<f32> {
+        let scale = params.float("scale", 1.0)?;
+
+        let Some(power) = power else {
+            return Ok(scale);
+        };
+
+        match spectrum.and_then(Spectrum::average) {
+            Some(intensity) if intensity > 0.0 && solid_angle > 0.0 => {
+                Ok(scale * power / (intensity * solid_angle))
+            }
+            _ => Ok(scale),
+        }
+    }
+}
+
+/// Area lights have geometry associated with them.
+#[derive(Debug, Clone)]
+pub enum AreaLight {
+    Diffuse {
+        /// Filename for an image that describes spatially-varying emission over the surface of the emitter.
+        /// The emitting shape's default (u,v) parameterization is used to map the image to the surface.
+        filename: Option<PathBuf>,
+        /// Determines whether the light source emits light from just the side of the surface
+        /// where the surface normal points or both sides.
+        two_sided: bool,
+        /// Spectral distribution of the light's emitted radiance.
+        spectrum: Option<Spectrum>,
+        /// Scale factor that modulates the amount of light that the light source emits into the scene.
+        scale: f32,
+        /// Number of samples to take when computing direct lighting from this light.
+        n_samples: u32,
+        /// Total power emitted by the light, if specified.
+        /// Cleared once `scale` is normalized by [Scene::resolve_area_light_power](crate::Scene::resolve_area_light_power).
+        power: Option<f32>,
+    },
 }
 
 impl Default for AreaLight {
@@ -544,63 +1507,445 @@ impl Default for AreaLight {
             two_sided: false,
             spectrum: None,
             scale: 1.0,
+            n_samples: 1,
+            power: None,
         }
     }
 }
 
 impl AreaLight {
-    pub fn new(ty: &str, params: ParamList) -> Result<AreaLight> {
+    /// Create an area light of type `ty`.
+    ///
+    /// External file names are converted to paths with `resolve_path`.
+    pub fn new(
+        ty: &str,
+        params: ParamList,
+        resolve_path: &dyn Fn(&str) -> PathBuf,
+    ) -> Result<AreaLight> {
         // pbrt currently only includes a single area light implementation, "diffuse".
         if ty != "diffuse" {
             return Err(Error::InvalidParamType(ty.to_string()));
         }
+
+        let n_samples = params.integer("nsamples", 1)?;
+        if n_samples < 1 {
+            return Err(Error::ValueOutOfRange("nsamples".to_string()));
+        }
+
         Ok(AreaLight::Diffuse {
-            filename: params.string("filename").map(|s| s.to_string()),
+            filename: params.string("filename").map(resolve_path),
             two_sided: params.boolean("twosided", false)?,
             spectrum: params.get("L").map(|l| l.spectrum()).transpose()?,
             scale: params.float("scale", 1.0)?,
+            n_samples: n_samples as u32,
+            power: Light::power(&params)?,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TextureType {
     Float,
     Spectrum,
 }
 
-#[derive(Debug)]
+/// Value that can be either float or spectrum, such as a texture value depending on the texture type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FloatOrSpectrum {
+    Float(f32),
+    Spectrum(Spectrum),
+}
+
+impl FloatOrSpectrum {
+    /// Read a texture parameter as float or spectrum depending on the texture type.
+    fn new(params: &ParamList, name: &str, ty: &TextureType, default: f32) -> Result<Self> {
+        let value = match ty {
+            TextureType::Float => FloatOrSpectrum::Float(params.float(name, default)?),
+            TextureType::Spectrum => {
+                FloatOrSpectrum::Spectrum(params.spectrum(name, Spectrum::Rgb([default; 3], None))?)
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Evaluate the value at `lambda` nm, as used for the `eta` of dielectrics.
+    ///
+    /// Named spectra resolve to the built-in dispersion curves (`glass-BK7` and others).
+    /// Returns `None` for values that can't be evaluated per wavelength: textures, blackbody
+    /// emitters, RGB values that aren't gray and named spectra that aren't bundled.
+    pub fn eval(&self, lambda: f32) -> Option<f32> {
+        match self {
+            FloatOrSpectrum::Float(value) => Some(*value),
+            FloatOrSpectrum::Spectrum(spectrum) => match spectrum {
+                Spectrum::Rgb([r, g, b], _) if r == g && g == b => Some(*r),
+                Spectrum::Sampled(samples) => Some(color::sample(samples, lambda)),
+                Spectrum::Named(name) => NamedSpectrum::new(name)?.ior(lambda),
+                Spectrum::Rgb(_, _) | Spectrum::Blackbody(_) | Spectrum::Texture(_) => None,
+            },
+        }
+    }
+
+    /// Returns `true` if the value varies with wavelength.
+    ///
+    /// For a dielectric `eta` this means light is dispersed, pbrt then terminates
+    /// all but one wavelength of a path at refraction. Named spectra are assumed to be
+    /// dispersion curves, textures are never dispersive.
+    pub fn is_dispersive(&self) -> bool {
+        match self {
+            FloatOrSpectrum::Float(_) => false,
+            FloatOrSpectrum::Spectrum(spectrum) => match spectrum {
+                Spectrum::Rgb([r, g, b], _) => r != g || g != b,
+                Spectrum::Sampled(samples) => {
+                    samples.windows(2).any(|pair| pair[0][1] != pair[1][1])
+                }
+                Spectrum::Blackbody(_) | Spectrum::Named(_) => true,
+                Spectrum::Texture(_) => false,
+            },
+        }
+    }
+}
+
+/// Texture parameter that is either a reference to another texture or a constant value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureInput {
+    /// Index of the referenced texture in `Scene::textures`.
+    Texture(usize),
+    Constant(FloatOrSpectrum),
+}
+
+impl TextureInput {
+    /// Read a texture parameter, resolving texture references with `texture_map`.
+    fn new(
+        params: &ParamList,
+        name: &str,
+        ty: &TextureType,
+        default: f32,
+        texture_map: &HashMap<String, usize>,
+    ) -> Result<Self> {
+        match params.get(name) {
+            Some(param) if param.ty == ParamType::Texture => {
+                let texture = params.string(name).unwrap_or_default();
+                match texture_map.get(texture) {
+                    Some(index) => Ok(TextureInput::Texture(*index)),
+                    None => Err(Error::UndefinedTexture(texture.to_string())),
+                }
+            }
+            _ => Ok(TextureInput::Constant(FloatOrSpectrum::new(
+                params, name, ty, default,
+            )?)),
+        }
+    }
+}
+
+/// Mapping from surface points to 2D texture coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureMapping {
+    /// Use the surface (u, v) coordinates, scaled and offset.
+    Uv {
+        uscale: f32,
+        vscale: f32,
+        udelta: f32,
+        vdelta: f32,
+    },
+    /// Spherical mapping around the texture space origin.
+    Spherical,
+    /// Cylindrical mapping around the texture space z axis.
+    Cylindrical,
+    /// Projection onto the plane spanned by `v1` and `v2`.
+    Planar {
+        v1: [f32; 3],
+        v2: [f32; 3],
+        udelta: f32,
+        vdelta: f32,
+    },
+}
+
+impl TextureMapping {
+    pub fn new(params: &ParamList) -> Result<Self> {
+        let mapping = match params.string("mapping").unwrap_or("uv") {
+            "uv" => TextureMapping::Uv {
+                uscale: params.float("uscale", 1.0)?,
+                vscale: params.float("vscale", 1.0)?,
+                udelta: params.float("udelta", 0.0)?,
+                vdelta: params.float("vdelta", 0.0)?,
+            },
+            "spherical" => TextureMapping::Spherical,
+            "cylindrical" => TextureMapping::Cylindrical,
+            "planar" => TextureMapping::Planar {
+                v1: params.point3("v1", [1.0, 0.0, 0.0])?,
+                v2: params.point3("v2", [0.0, 1.0, 0.0])?,
+                udelta: params.float("udelta", 0.0)?,
+                vdelta: params.float("vdelta", 0.0)?,
+            },
+            _ => return Err(Error::InvalidString),
+        };
+
+        Ok(mapping)
+    }
+}
+
+/// Texture class along with its class specific parameters.
+#[derive(Debug, Clone)]
+pub enum TextureClass {
+    /// Bilinear interpolation between four corner values.
+    Bilerp {
+        v00: FloatOrSpectrum,
+        v01: FloatOrSpectrum,
+        v10: FloatOrSpectrum,
+        v11: FloatOrSpectrum,
+        mapping: TextureMapping,
+    },
+    /// Alternating checks of two textures.
+    Checkerboard {
+        /// Value of the even checks.
+        tex1: TextureInput,
+        /// Value of the odd checks.
+        tex2: TextureInput,
+        /// Either 2 for a planar checkerboard or 3 for a solid one.
+        dimension: i32,
+        mapping: TextureMapping,
+    },
+    /// Texture that returns the same value everywhere.
+    Constant {
+        /// Constant value, float or spectrum depending on the texture type.
+        value: FloatOrSpectrum,
+    },
+    DirectionMix,
+    /// Polka dots pattern.
+    Dots {
+        /// Value inside of the dots.
+        inside: TextureInput,
+        /// Value outside of the dots.
+        outside: TextureInput,
+        mapping: TextureMapping,
+    },
+    Fbm,
+    /// Texture loaded from an image file.
+    ImageMap {
+        /// Path to the image file.
+        filename: PathBuf,
+    },
+    Marble,
+    /// Linear interpolation between two textures.
+    Mix {
+        /// Value used when `amount` is 0.
+        tex1: TextureInput,
+        /// Value used when `amount` is 1.
+        tex2: TextureInput,
+        /// Float blend factor.
+        amount: TextureInput,
+    },
+    /// Per-face texture stored in a Ptex file.
+    /// The Ptex data itself is not decoded.
+    Ptex {
+        /// Path to the `.ptx` file.
+        filename: PathBuf,
+        /// Scale factor to apply to the texture values.
+        scale: f32,
+    },
+    /// Product of a texture and a float scale, commonly used to tint textures.
+    Scale {
+        /// Texture to scale, of the same type as the scale texture itself.
+        tex: TextureInput,
+        /// Float scale factor, either a constant or a float texture.
+        scale: TextureInput,
+    },
+    Windy,
+    Wrinkled,
+}
+
+#[derive(Debug, Clone)]
 pub struct Texture {
     pub name: String,
     pub ty: TextureType,
-    pub class: String,
+    pub class: TextureClass,
+    /// Texture to world transformation of solid (3D) textures,
+    /// the CTM at the time the texture was defined. `None` for 2D textures.
+    pub transform: Option<Mat4>,
+    /// Multiplier of the texture's values from the `scale` parameter, 1 by default.
+    /// Always 1 for the "scale" class, which uses the parameter as its factor, see [Texture::scale].
+    pub scale: f32,
 }
 
 impl Texture {
-    pub fn new(name: &str, ty: &str, class: &str, _params: ParamList) -> Result<Texture> {
+    pub fn new(
+        name: &str,
+        ty: &str,
+        class: &str,
+        params: ParamList,
+        transform: Mat4,
+        texture_map: &HashMap<String, usize>,
+        resolve_path: &dyn Fn(&str) -> PathBuf,
+    ) -> Result<Texture> {
         let ty = match ty {
             "spectrum" => TextureType::Spectrum,
             "float" => TextureType::Float,
             _ => return Err(Error::InvalidObjectType(ty.to_string())),
         };
 
+        // Any texture can be scaled, except for the "scale" class where it's the class parameter.
+        let scale = match class {
+            "scale" => 1.0,
+            _ => params.float("scale", 1.0)?,
+        };
+
         // TODO: Parse parameters.
+        let class = match class {
+            "bilerp" => TextureClass::Bilerp {
+                v00: FloatOrSpectrum::new(&params, "v00", &ty, 0.0)?,
+                v01: FloatOrSpectrum::new(&params, "v01", &ty, 1.0)?,
+                v10: FloatOrSpectrum::new(&params, "v10", &ty, 0.0)?,
+                v11: FloatOrSpectrum::new(&params, "v11", &ty, 1.0)?,
+                mapping: TextureMapping::new(&params)?,
+            },
+            "checkerboard" => TextureClass::Checkerboard {
+                tex1: TextureInput::new(&params, "tex1", &ty, 1.0, texture_map)?,
+                tex2: TextureInput::new(&params, "tex2", &ty, 0.0, texture_map)?,
+                dimension: match params.integer("dimension", 2)? {
+                    dimension @ (2 | 3) => dimension,
+                    _ => return Err(Error::ValueOutOfRange("dimension".to_string())),
+                },
+                mapping: TextureMapping::new(&params)?,
+            },
+            "constant" => TextureClass::Constant {
+                value: FloatOrSpectrum::new(&params, "value", &ty, 1.0)?,
+            },
+            "directionmix" => TextureClass::DirectionMix,
+            "dots" => TextureClass::Dots {
+                inside: TextureInput::new(&params, "inside", &ty, 1.0, texture_map)?,
+                outside: TextureInput::new(&params, "outside", &ty, 0.0, texture_map)?,
+                mapping: TextureMapping::new(&params)?,
+            },
+            "fbm" => TextureClass::Fbm,
+            "imagemap" => TextureClass::ImageMap {
+                filename: resolve_path(
+                    params
+                        .string("filename")
+                        .ok_or(Error::MissingRequiredParameter)?,
+                ),
+            },
+            "marble" => TextureClass::Marble,
+            "mix" => TextureClass::Mix {
+                tex1: TextureInput::new(&params, "tex1", &ty, 0.0, texture_map)?,
+                tex2: TextureInput::new(&params, "tex2", &ty, 1.0, texture_map)?,
+                amount: TextureInput::new(
+                    &params,
+                    "amount",
+                    &TextureType::Float,
+                    0.5,
+                    texture_map,
+                )?,
+            },
+            "ptex" => TextureClass::Ptex {
+                filename: resolve_path(
+                    params
+                        .string("filename")
+                        .ok_or(Error::MissingRequiredParameter)?,
+                ),
+                scale: params.float("scale", 1.0)?,
+            },
+            "scale" => TextureClass::Scale {
+                tex: TextureInput::new(&params, "tex", &ty, 1.0, texture_map)?,
+                scale: TextureInput::new(&params, "scale", &TextureType::Float, 1.0, texture_map)?,
+            },
+            "windy" => TextureClass::Windy,
+            "wrinkled" => TextureClass::Wrinkled,
+            _ => return Err(Error::InvalidObjectType(class.to_string())),
+        };
+
+        // Solid textures are positioned in space by the CTM.
+        let is_solid = match class {
+            TextureClass::Checkerboard { dimension, .. } => dimension == 3,
+            TextureClass::Fbm
+            | TextureClass::Marble
+            | TextureClass::Windy
+            | TextureClass::Wrinkled => true,
+            _ => false,
+        };
 
         Ok(Texture {
             name: name.to_string(),
             ty,
-            class: class.to_string(),
+            class,
+            transform: is_solid.then_some(transform),
+            scale,
         })
     }
+
+    /// Multiplier applied to the texture's values.
+    ///
+    /// The `scale` parameter that all texture classes accept, for the "scale" class
+    /// its constant factor, 1 if the factor is given by a texture.
+    pub fn scale(&self) -> f32 {
+        match &self.class {
+            TextureClass::Scale {
+                scale: TextureInput::Constant(FloatOrSpectrum::Float(scale)),
+                ..
+            } => *scale,
+            TextureClass::Scale { .. } => 1.0,
+            _ => self.scale,
+        }
+    }
+
+    /// Indices of the textures this texture references, in order of the class parameters.
+    pub fn texture_references(&self) -> Vec<usize> {
+        let inputs = match &self.class {
+            TextureClass::Checkerboard { tex1, tex2, .. } => vec![tex1, tex2],
+            TextureClass::Dots {
+                inside, outside, ..
+            } => vec![inside, outside],
+            TextureClass::Mix { tex1, tex2, amount } => vec![tex1, tex2, amount],
+            TextureClass::Scale { tex, scale } => vec![tex, scale],
+            _ => Vec::new(),
+        };
+
+        inputs
+            .into_iter()
+            .filter_map(|input| match input {
+                TextureInput::Texture(index) => Some(*index),
+                TextureInput::Constant(_) => None,
+            })
+            .collect()
+    }
+
+    /// Update texture references when the scene's textures are moved,
+    /// `map` returns the new index of a texture.
+    pub(crate) fn map_texture_references(&mut self, map: impl Fn(usize) -> usize) {
+        let inputs = match &mut self.class {
+            TextureClass::Checkerboard { tex1, tex2, .. } => vec![tex1, tex2],
+            TextureClass::Dots {
+                inside, outside, ..
+            } => vec![inside, outside],
+            TextureClass::Mix { tex1, tex2, amount } => vec![tex1, tex2, amount],
+            TextureClass::Scale { tex, scale } => vec![tex, scale],
+            _ => Vec::new(),
+        };
+
+        for input in inputs {
+            if let TextureInput::Texture(index) = input {
+                *index = map(*index);
+            }
+        }
+    }
 }
 
+/// Maximum number of scattering events between the layers of a coated material,
+/// from the material's `maxdepth` parameter.
+///
+/// Unrelated to the integrator's `maxdepth`, which limits the length of light paths
+/// through the scene (see [Integrator]), a separate type keeps the two apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerDepth(pub i32);
+
 /// Materials specify the light scattering properties of surfaces in the scene.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MaterialType {
     CoatedDiffuse {
         albedo: Spectrum,
         g: f32,
-        maxdepth: i32,
+        /// Random walk depth within the layers, not the integrator's path depth.
+        maxdepth: LayerDepth,
         nsamples: i32,
         thickness: f32,
         reflectance: Spectrum,
@@ -610,14 +1955,14 @@ pub enum MaterialType {
     },
     CoatedConductor,
     Conductor {
-        eta: [f32; 3],
-        k: [f32; 3],
+        eta: Spectrum,
+        k: Spectrum,
         uroughness: f32,
         vroughness: f32,
         remaproughness: bool,
     },
     Dielectric {
-        eta: f32,
+        eta: FloatOrSpectrum,
         uroughness: f32,
         vroughness: f32,
         remaproughness: bool,
@@ -625,8 +1970,14 @@ pub enum MaterialType {
     Diffuse {
         reflectance: Spectrum,
     },
-    DiffuseTransmission,
+    DiffuseTransmission {
+        reflectance: Spectrum,
+        transmittance: Spectrum,
+    },
     Hair,
+    /// The "interface" material is an empty material that marks the boundary between
+    /// two participating media. Shapes using it don't scatter light, so no BSDF setup
+    /// is required for them.
     Interface,
     Measured,
     Mix,
@@ -634,63 +1985,180 @@ pub enum MaterialType {
     ThinDielectric,
 }
 
-#[derive(Debug)]
+impl MaterialType {
+    /// Returns the names of the textures referenced by the material's parameters.
+    pub fn texture_names(&self) -> Vec<&str> {
+        let spectra = match self {
+            MaterialType::CoatedDiffuse {
+                albedo,
+                reflectance,
+                ..
+            } => vec![albedo, reflectance],
+            MaterialType::Conductor { eta, k, .. } => vec![eta, k],
+            MaterialType::Dielectric {
+                eta: FloatOrSpectrum::Spectrum(eta),
+                ..
+            } => vec![eta],
+            MaterialType::Diffuse { reflectance } => vec![reflectance],
+            MaterialType::DiffuseTransmission {
+                reflectance,
+                transmittance,
+            } => vec![reflectance, transmittance],
+            _ => Vec::new(),
+        };
+
+        spectra
+            .into_iter()
+            .filter_map(|spectrum| match spectrum {
+                Spectrum::Texture(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns mutable references to all spectral parameters of the material.
+    pub fn spectra_mut(&mut self) -> Vec<&mut Spectrum> {
+        match self {
+            MaterialType::CoatedDiffuse {
+                albedo,
+                reflectance,
+                ..
+            } => vec![albedo, reflectance],
+            MaterialType::Conductor { eta, k, .. } => vec![eta, k],
+            MaterialType::Dielectric {
+                eta: FloatOrSpectrum::Spectrum(eta),
+                ..
+            } => vec![eta],
+            MaterialType::Diffuse { reflectance } => vec![reflectance],
+            MaterialType::DiffuseTransmission {
+                reflectance,
+                transmittance,
+            } => vec![reflectance, transmittance],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Material {
     pub name: String,
     pub ty: MaterialType,
+    /// Index of the float texture that displaces the surface along its normal.
+    pub displacement: Option<usize>,
+    /// Path to an image that perturbs shading normals, from the `normalmap` parameter.
+    pub normal_map: Option<PathBuf>,
+    /// Number of enclosing `AttributeBegin` (and `ObjectBegin`) blocks where the material was
+    /// defined, zero if created outside of a scene.
+    pub attribute_depth: u32,
+    /// Sorted indices of textures referenced by the material parameters.
+    textures: Vec<usize>,
+}
+
+impl Default for Material {
+    /// The material pbrt uses for shapes without one, diffuse with a reflectance of 0.5.
+    fn default() -> Self {
+        Material {
+            name: String::new(),
+            ty: MaterialType::Diffuse {
+                reflectance: Spectrum::Rgb([0.5; 3], None),
+            },
+            displacement: None,
+            normal_map: None,
+            attribute_depth: 0,
+            textures: Vec::new(),
+        }
+    }
 }
 
 impl Material {
+    /// Create a material from its parameter list.
+    ///
+    /// The `normalmap` file name is converted to a path with `resolve_path`.
     pub fn new(
         name: &str,
         params: ParamList,
-        _texture_map: &HashMap<String, usize>,
+        texture_map: &HashMap<String, usize>,
+        resolve_path: &dyn Fn(&str) -> PathBuf,
     ) -> Result<Material> {
+        let mut textures = params
+            .iter()
+            .filter(|param| param.ty == ParamType::Texture)
+            .filter_map(|param| params.string(param.name))
+            .filter_map(|name| texture_map.get(name).copied())
+            .collect::<Vec<_>>();
+
+        textures.sort_unstable();
+        textures.dedup();
+
+        let displacement = match params.get("displacement") {
+            Some(param) if param.ty == ParamType::Texture => {
+                let name = params.string("displacement").unwrap_or_default();
+                match texture_map.get(name) {
+                    Some(index) => Some(*index),
+                    None => return Err(Error::UndefinedTexture(name.to_string())),
+                }
+            }
+            _ => None,
+        };
+
+        let normal_map = params.string("normalmap").map(resolve_path);
+
         // Parameters to materials are distinctive in that textures can be used to
         // specify spatially-varying values for the parameters.
         let ty = match params.string("type") {
             Some(ty) => match ty {
                 "coateddiffuse" => MaterialType::CoatedDiffuse {
-                    albedo: params.spectrum("albedo", Spectrum::Rgb([0.0; 3]))?,
+                    albedo: params.spectrum("albedo", Spectrum::Rgb([0.0; 3], None))?,
                     g: params.float("g", 0.0)?,
-                    maxdepth: params.integer("maxdepth", 10)?,
-                    nsamples: params.integer("nsamples", 1)?,
+                    maxdepth: LayerDepth(Self::layer_count(&params, "maxdepth", 10)?),
+                    nsamples: Self::layer_count(&params, "nsamples", 1)?,
                     thickness: params.float("thickness", 0.01)?,
-                    reflectance: params.spectrum("reflectance", Spectrum::Rgb([0.5; 3]))?,
+                    reflectance: params.spectrum("reflectance", Spectrum::Rgb([0.5; 3], None))?,
                     uroughness: params.float("uroughness", 0.0)?,
                     vroughness: params.float("vroughness", 0.0)?,
                     remaproughness: params.boolean("remaproughness", true)?,
                 },
-                "coatedconductor" => MaterialType::CoatedConductor,
+                "coatedconductor" => {
+                    Self::layer_count(&params, "maxdepth", 10)?;
+                    Self::layer_count(&params, "nsamples", 1)?;
+                    MaterialType::CoatedConductor
+                }
                 "conductor" => MaterialType::Conductor {
-                    eta: params.rgb("eta", [0.236, 0.432, 1.1])?,
-                    k: params.rgb("k", [3.42, 3.13, 2.43])?,
+                    eta: params.spectrum("eta", Spectrum::Rgb([0.236, 0.432, 1.1], None))?,
+                    k: params.spectrum("k", Spectrum::Rgb([3.42, 3.13, 2.43], None))?,
                     uroughness: params.float("uroughness", 0.0)?,
                     vroughness: params.float("vroughness", 0.0)?,
                     remaproughness: params.boolean("remaproughness", true)?,
                 },
                 "dielectric" => MaterialType::Dielectric {
-                    eta: {
-                        let parm = params.get("eta").ok_or(Error::MissingRequiredParameter)?;
-                        match parm.ty {
-                            ParamType::Float => parm.single()?,
-                            ParamType::Spectrum => todo!(),
-                            _ => unreachable!(),
+                    eta: match params.get("eta") {
+                        Some(parm) if parm.ty == ParamType::Float => {
+                            FloatOrSpectrum::Float(parm.single()?)
                         }
+                        Some(parm) => FloatOrSpectrum::Spectrum(parm.spectrum()?),
+                        None => FloatOrSpectrum::Float(1.5),
                     },
                     uroughness: params.float("uroughness", 0.0)?,
                     vroughness: params.float("vroughness", 0.0)?,
                     remaproughness: params.boolean("remaproughness", true)?,
                 },
                 "diffuse" => MaterialType::Diffuse {
-                    reflectance: params.spectrum("reflectance", Spectrum::Rgb([0.5; 3]))?,
+                    reflectance: params.spectrum("reflectance", Spectrum::Rgb([0.5; 3], None))?,
+                },
+                "diffusetransmission" => MaterialType::DiffuseTransmission {
+                    reflectance: params.spectrum("reflectance", Spectrum::Rgb([0.25; 3], None))?,
+                    transmittance: params
+                        .spectrum("transmittance", Spectrum::Rgb([0.25; 3], None))?,
                 },
-                "diffusetransmission" => MaterialType::DiffuseTransmission,
                 "hair" => MaterialType::Hair,
                 "interface" => MaterialType::Interface,
                 "measured" => MaterialType::Measured,
                 "mix" => MaterialType::Mix,
-                "subsurface" => MaterialType::Subsurface,
+                "subsurface" => {
+                    Self::layer_count(&params, "maxdepth", 1)?;
+                    Self::layer_count(&params, "nsamples", 1)?;
+                    MaterialType::Subsurface
+                }
                 "thindielectric" => MaterialType::ThinDielectric,
                 _ => return Err(Error::InvalidMaterialType(ty.to_string())),
             },
@@ -700,11 +2168,114 @@ impl Material {
         Ok(Material {
             name: name.to_string(),
             ty,
+            displacement,
+            normal_map,
+            attribute_depth: 0,
+            textures,
         })
     }
+
+    /// Read an integer parameter of layered materials that must be at least 1.
+    fn layer_count(params: &ParamList, name: &str, default: i32) -> Result<i32> {
+        let value = params.integer(name, default)?;
+        if value < 1 {
+            return Err(Error::InvalidMaterialParameter(name.to_string()));
+        }
+
+        Ok(value)
+    }
+
+    /// Returns microfacet `(alpha_u, alpha_v)` of materials with rough interfaces.
+    ///
+    /// When `remaproughness` is set, roughness values are mapped with [remap_roughness],
+    /// otherwise they're used as alpha directly.
+    pub fn effective_alpha(&self) -> Option<(f32, f32)> {
+        let (uroughness, vroughness, remap) = match self.ty {
+            MaterialType::CoatedDiffuse {
+                uroughness,
+                vroughness,
+                remaproughness,
+                ..
+            }
+            | MaterialType::Conductor {
+                uroughness,
+                vroughness,
+                remaproughness,
+                ..
+            }
+            | MaterialType::Dielectric {
+                uroughness,
+                vroughness,
+                remaproughness,
+                ..
+            } => (uroughness, vroughness, remaproughness),
+            _ => return None,
+        };
+
+        if remap {
+            Some((remap_roughness(uroughness), remap_roughness(vroughness)))
+        } else {
+            Some((uroughness, vroughness))
+        }
+    }
+
+    /// Indices of all textures the material references, in any of its parameters.
+    pub fn texture_references(&self) -> Vec<usize> {
+        self.textures.clone()
+    }
+
+    /// Update texture references when the scene's textures are moved,
+    /// `map` returns the new index of a texture.
+    pub(crate) fn map_texture_references(&mut self, map: impl Fn(usize) -> usize) {
+        for index in &mut self.textures {
+            *index = map(*index);
+        }
+
+        if let Some(index) = &mut self.displacement {
+            *index = map(*index);
+        }
+    }
+}
+
+/// Map perceptual roughness in `[0, 1]` to microfacet distribution alpha.
+///
+/// Same as pbrt-v4's `TrowbridgeReitzDistribution::RoughnessToAlpha`, which is `sqrt(roughness)`.
+pub fn remap_roughness(roughness: f32) -> f32 {
+    roughness.sqrt()
+}
+
+/// Opacity mask of a shape, from its `alpha` parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alpha {
+    /// Constant opacity, 1 for fully opaque surfaces.
+    Constant(f32),
+    /// Index of the float texture in `Scene::textures` that defines the opacity.
+    Texture(usize),
+}
+
+impl Default for Alpha {
+    fn default() -> Self {
+        Alpha::Constant(1.0)
+    }
+}
+
+impl Alpha {
+    /// Read the `alpha` parameter, resolving texture references with `texture_map`.
+    pub fn new(params: &ParamList, texture_map: &HashMap<String, usize>) -> Result<Alpha> {
+        match params.get("alpha") {
+            Some(param) if param.ty == ParamType::Texture => {
+                let name = param.string();
+                match texture_map.get(name) {
+                    Some(index) => Ok(Alpha::Texture(*index)),
+                    None => Err(Error::UndefinedTexture(name.to_string())),
+                }
+            }
+            _ => Ok(Alpha::Constant(Shape::constant_alpha(params)?)),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Shape {
     /// Curve shape for hair, fur, and grass
     Curve {
@@ -767,21 +2338,69 @@ pub enum Shape {
         positions: Vec<f32>,
         /// Per-vertex normals.
         normals: Vec<f32>,
-        /// Per-vertex tangents.
-        tangents: Vec<f32>,
+        /// Per-vertex tangents (`S`), used for normal mapping.
+        /// If not provided, consumers are expected to compute tangents themselves.
+        tangents: Option<Vec<Vec3>>,
         /// Per-vertex texture coordinates.
         uvs: Vec<f32>,
+        /// Per-vertex colors (`Cs`), not used by pbrt's materials but handy for debugging
+        /// and vertex color shading.
+        colors: Option<Vec<[f32; 3]>>,
+        /// Per-face material indices (`faceIndices`), one per triangle.
+        ///
+        /// Used by exporters to assign multiple materials to a single mesh,
+        /// see [Shape::split_by_face_indices].
+        face_indices: Option<Vec<u32>>,
+        /// Target edge length for the adaptive refinement of displaced and subdivision
+        /// surfaces (`edgelength`). Only `loopsubdiv` surfaces are refined when tessellating
+        /// at load time, see [crate::LoadOptions::tessellate].
+        edge_length: Option<f32>,
     },
     /// pbrt can also directly read triangle meshes specified in the PLY mesh file format, via the "plymesh" shape.
-    /// TODO: Support loading ply files.
-    PlyMesh { filename: String },
+    /// Files are not read during scene loading, see [crate::TriangleMesh::from_ply_file].
+    PlyMesh {
+        /// Path to the PLY file.
+        filename: PathBuf,
+    },
 }
 
 impl Shape {
-    pub fn new(ty: &str, params: ParamList) -> Result<Self> {
+    /// Read a constant `alpha` parameter.
+    ///
+    /// Returns 1 (opaque) when alpha is missing or given by a texture, see [Alpha].
+    pub(crate) fn constant_alpha(params: &ParamList) -> Result<f32> {
+        match params.get("alpha") {
+            Some(param) if param.ty == ParamType::Texture => Ok(1.0),
+            _ => Ok(params.float("alpha", 1.0)?),
+        }
+    }
+
+    /// Read the optional `edgelength` parameter, which must be positive.
+    pub(crate) fn edge_length(params: &ParamList) -> Result<Option<f32>> {
+        match params.get("edgelength") {
+            Some(_) => {
+                let edge_length = params.float("edgelength", 1.0)?;
+                if edge_length <= 0.0 {
+                    return Err(Error::ValueOutOfRange("edgelength".to_string()));
+                }
+
+                Ok(Some(edge_length))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Create a shape of type `ty`.
+    ///
+    /// External file names are converted to paths with `resolve_path`.
+    pub fn new(
+        ty: &str,
+        params: ParamList,
+        resolve_path: &dyn Fn(&str) -> PathBuf,
+    ) -> Result<Self> {
         // All shapes take an optional "alpha" parameter that can be
         // used to define a mask that cuts away regions of a surface.
-        let alpha = params.float("alpha", 1.0)?;
+        let alpha = Self::constant_alpha(&params)?;
 
         let shape = match ty {
             "curve" => Shape::Curve {
@@ -832,10 +2451,48 @@ impl Shape {
                 let positions = params.floats("P")?.unwrap_or_default();
 
                 let normals = params.floats("N")?.unwrap_or_default();
-                let tangents = params.floats("S")?.unwrap_or_default();
+
+                let tangents = match params.floats("S")? {
+                    Some(tangents) => {
+                        if tangents.len() != positions.len() {
+                            return Err(Error::AttributeCountMismatch);
+                        }
+
+                        Some(tangents.chunks_exact(3).map(Vec3::from_slice).collect())
+                    }
+                    None => None,
+                };
 
                 let uvs = params.floats("uv")?.unwrap_or_default();
 
+                let colors = match params.floats("Cs")? {
+                    Some(colors) => {
+                        if colors.len() != positions.len() {
+                            return Err(Error::AttributeCountMismatch);
+                        }
+
+                        Some(colors.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect())
+                    }
+                    None => None,
+                };
+
+                let face_indices = match params.integers("faceIndices")? {
+                    Some(face_indices) => {
+                        if face_indices.len() != indices.len() / 3 {
+                            return Err(Error::AttributeCountMismatch);
+                        }
+
+                        let face_indices = face_indices
+                            .into_iter()
+                            .map(u32::try_from)
+                            .collect::<std::result::Result<Vec<_>, _>>()
+                            .map_err(|_| Error::ValueOutOfRange("faceIndices".to_string()))?;
+
+                        Some(face_indices)
+                    }
+                    None => None,
+                };
+
                 Shape::TriangleMesh {
                     alpha,
                     indices,
@@ -843,13 +2500,17 @@ impl Shape {
                     normals,
                     uvs,
                     tangents,
+                    colors,
+                    face_indices,
+                    edge_length: Self::edge_length(&params)?,
                 }
             }
             "plymesh" => {
-                let filename = params
-                    .string("filename")
-                    .ok_or(Error::MissingRequiredParameter)?
-                    .to_string();
+                let filename = resolve_path(
+                    params
+                        .string("filename")
+                        .ok_or(Error::MissingRequiredParameter)?,
+                );
 
                 Shape::PlyMesh { filename }
             }
@@ -858,9 +2519,140 @@ impl Shape {
 
         Ok(shape)
     }
+
+    /// Compute area weighted per-vertex normals of a triangle mesh.
+    ///
+    /// Normals follow the counter-clockwise winding order of the triangles, flipped
+    /// if `reverse_orientation` is set. Existing normals are kept unless `force` is `true`.
+    /// Does nothing for other shape types.
+    pub fn compute_normals(&mut self, reverse_orientation: bool, force: bool) {
+        let Shape::TriangleMesh {
+            indices,
+            positions,
+            normals,
+            ..
+        } = self
+        else {
+            return;
+        };
+
+        if !normals.is_empty() && !force {
+            return;
+        }
+
+        let vertex_count = positions.len() / 3;
+        let position = |index: usize| Vec3::from_slice(&positions[index * 3..]);
+
+        let mut accumulated = vec![Vec3::ZERO; vertex_count];
+
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| index as usize);
+            if a >= vertex_count || b >= vertex_count || c >= vertex_count {
+                continue;
+            }
+
+            // Cross product length is twice the triangle area, so larger faces contribute more.
+            let face_normal = (position(b) - position(a)).cross(position(c) - position(a));
+
+            accumulated[a] += face_normal;
+            accumulated[b] += face_normal;
+            accumulated[c] += face_normal;
+        }
+
+        let sign = if reverse_orientation { -1.0 } else { 1.0 };
+
+        *normals = accumulated
+            .into_iter()
+            .flat_map(|normal| (normal.normalize_or_zero() * sign).to_array())
+            .collect();
+    }
+
+    /// Split a triangle mesh with per-face material indices into one mesh per face index.
+    ///
+    /// Submeshes are ordered by face index and only contain the vertices they reference,
+    /// per-vertex attributes are carried over. Returns `None` for other shape types and
+    /// meshes without face indices.
+    pub fn split_by_face_indices(&self) -> Option<Vec<(u32, Shape)>> {
+        let Shape::TriangleMesh {
+            alpha,
+            indices,
+            positions,
+            normals,
+            tangents,
+            uvs,
+            colors,
+            face_indices: Some(face_indices),
+            edge_length,
+        } = self
+        else {
+            return None;
+        };
+
+        let mut faces = BTreeMap::<u32, Vec<&[i32]>>::new();
+        for (triangle, face_index) in indices.chunks_exact(3).zip(face_indices) {
+            faces.entry(*face_index).or_default().push(triangle);
+        }
+
+        // Copy the `stride` components of a vertex attribute, if the vertex has them.
+        let copy = |dst: &mut Vec<f32>, src: &[f32], stride: usize, index: usize| {
+            if let Some(values) = src.get(index * stride..(index + 1) * stride) {
+                dst.extend_from_slice(values);
+            }
+        };
+
+        let submeshes = faces
+            .into_iter()
+            .map(|(face_index, triangles)| {
+                let mut remap = HashMap::new();
+                let mut sub_indices = Vec::with_capacity(triangles.len() * 3);
+                let mut sub_positions = Vec::new();
+                let mut sub_normals = Vec::new();
+                let mut sub_uvs = Vec::new();
+                let mut sub_tangents = tangents.as_ref().map(|_| Vec::new());
+                let mut sub_colors = colors.as_ref().map(|_| Vec::new());
+
+                for &index in triangles.iter().flat_map(|triangle| triangle.iter()) {
+                    let next = remap.len() as i32;
+                    let new_index = *remap.entry(index).or_insert_with(|| {
+                        let index = index as usize;
+
+                        copy(&mut sub_positions, positions, 3, index);
+                        copy(&mut sub_normals, normals, 3, index);
+                        copy(&mut sub_uvs, uvs, 2, index);
+                        if let (Some(dst), Some(src)) = (&mut sub_tangents, tangents) {
+                            dst.extend(src.get(index).copied());
+                        }
+                        if let (Some(dst), Some(src)) = (&mut sub_colors, colors) {
+                            dst.extend(src.get(index).copied());
+                        }
+
+                        next
+                    });
+
+                    sub_indices.push(new_index);
+                }
+
+                let submesh = Shape::TriangleMesh {
+                    alpha: *alpha,
+                    indices: sub_indices,
+                    positions: sub_positions,
+                    normals: sub_normals,
+                    tangents: sub_tangents,
+                    uvs: sub_uvs,
+                    colors: sub_colors,
+                    face_indices: None,
+                    edge_length: *edge_length,
+                };
+
+                (face_index, submesh)
+            })
+            .collect();
+
+        Some(submeshes)
+    }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Medium {}
 
 impl Medium {
@@ -872,7 +2664,15 @@ impl Medium {
 
 #[cfg(test)]
 mod tests {
+    use std::{fs, path::Path};
+
     use super::*;
+    use crate::FsAssetSource;
+
+    /// Leave file names as they are.
+    fn path(filename: &str) -> PathBuf {
+        PathBuf::from(filename)
+    }
 
     #[test]
     fn parse_coord_sys() {
@@ -892,4 +2692,969 @@ mod tests {
         assert!(CoordinateSystem::from_str("").is_err());
         assert!(CoordinateSystem::from_str("foo").is_err());
     }
+
+    #[test]
+    fn parse_mesh_tangents() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("integer indices", "0 1 2")?)?;
+        params.add(Param::new("point3 P", "0 0 0 1 0 0 0 1 0")?)?;
+        params.add(Param::new("vector3 S", "1 0 0 1 0 0 1 0 0")?)?;
+
+        let Shape::TriangleMesh { tangents, .. } =
+            Shape::new("trianglemesh", params, &|filename: &str| {
+                PathBuf::from(filename)
+            })?
+        else {
+            panic!("Unexpected shape type");
+        };
+
+        let tangents = tangents.expect("Tangents must be parsed");
+        assert_eq!(tangents.len(), 3);
+        assert_eq!(tangents[0], Vec3::X);
+
+        Ok(())
+    }
+
+    #[test]
+    fn split_mesh_by_face_indices() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("integer indices", "0 1 2 2 1 3")?)?;
+        params.add(Param::new("point3 P", "0 0 0 1 0 0 0 1 0 1 1 0")?)?;
+        params.add(Param::new("point2 uv", "0 0 1 0 0 1 1 1")?)?;
+        params.add(Param::new("integer faceIndices", "1 0")?)?;
+
+        let shape = Shape::new("trianglemesh", params, &|filename: &str| {
+            PathBuf::from(filename)
+        })?;
+
+        let submeshes = shape
+            .split_by_face_indices()
+            .expect("Mesh has face indices");
+        assert_eq!(submeshes.len(), 2);
+
+        let (
+            face_index,
+            Shape::TriangleMesh {
+                indices,
+                positions,
+                uvs,
+                ..
+            },
+        ) = &submeshes[0]
+        else {
+            panic!("Unexpected shape type");
+        };
+        assert_eq!(*face_index, 0);
+        assert_eq!(indices, &[0, 1, 2]);
+        assert_eq!(positions, &[0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(uvs, &[0.0, 1.0, 1.0, 0.0, 1.0, 1.0]);
+
+        assert_eq!(submeshes[1].0, 1);
+
+        // One face index per triangle is required.
+        let mut params = ParamList::default();
+        params.add(Param::new("integer indices", "0 1 2")?)?;
+        params.add(Param::new("point3 P", "0 0 0 1 0 0 0 1 0")?)?;
+        params.add(Param::new("integer faceIndices", "0 1")?)?;
+
+        assert!(matches!(
+            Shape::new("trianglemesh", params, &|filename: &str| PathBuf::from(
+                filename
+            )),
+            Err(Error::AttributeCountMismatch)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn mesh_vertex_colors() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("integer indices", "0 1 2")?)?;
+        params.add(Param::new("point3 P", "0 0 0 1 0 0 0 1 0")?)?;
+        params.add(Param::new("rgb Cs", "1 0 0 0 1 0 0 0 1")?)?;
+
+        let shape = Shape::new("trianglemesh", params, &|filename: &str| {
+            PathBuf::from(filename)
+        })?;
+        let Shape::TriangleMesh { colors, .. } = &shape else {
+            panic!("Unexpected shape type");
+        };
+        assert_eq!(
+            colors.as_deref(),
+            Some(&[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]][..])
+        );
+
+        // One color per vertex is required.
+        let mut params = ParamList::default();
+        params.add(Param::new("integer indices", "0 1 2")?)?;
+        params.add(Param::new("point3 P", "0 0 0 1 0 0 0 1 0")?)?;
+        params.add(Param::new("rgb Cs", "1 0 0 0 1 0")?)?;
+
+        assert!(matches!(
+            Shape::new("trianglemesh", params, &|filename: &str| PathBuf::from(
+                filename
+            )),
+            Err(Error::AttributeCountMismatch)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn compute_mesh_normals() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("integer indices", "0 1 2 0 2 3")?)?;
+        params.add(Param::new("point3 P", "0 0 0 1 0 0 1 1 0 0 1 0")?)?;
+
+        let mut shape = Shape::new("trianglemesh", params, &|filename: &str| {
+            PathBuf::from(filename)
+        })?;
+
+        shape.compute_normals(false, false);
+
+        let Shape::TriangleMesh { normals, .. } = &shape else {
+            panic!("Unexpected shape type");
+        };
+
+        assert_eq!(normals.len(), 12);
+        for normal in normals.chunks_exact(3) {
+            assert_eq!(normal, [0.0, 0.0, 1.0]);
+        }
+
+        shape.compute_normals(true, true);
+
+        let Shape::TriangleMesh { normals, .. } = &shape else {
+            panic!("Unexpected shape type");
+        };
+
+        assert_eq!(&normals[..3], [0.0, 0.0, -1.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mesh_tangents_count_mismatch() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("integer indices", "0 1 2")?)?;
+        params.add(Param::new("point3 P", "0 0 0 1 0 0 0 1 0")?)?;
+        params.add(Param::new("vector3 S", "1 0 0")?)?;
+
+        assert!(matches!(
+            Shape::new("trianglemesh", params, &|filename: &str| PathBuf::from(
+                filename
+            )),
+            Err(Error::AttributeCountMismatch)
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("integer indices", "0 1 2")?)?;
+        params.add(Param::new("point3 P", "0 0 0 1 0 0 0 1 0")?)?;
+
+        let Shape::TriangleMesh { tangents, .. } =
+            Shape::new("trianglemesh", params, &|filename: &str| {
+                PathBuf::from(filename)
+            })?
+        else {
+            panic!("Unexpected shape type");
+        };
+        assert!(tangents.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_diffuse_transmission() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "diffusetransmission")?)?;
+        params.add(Param::new("rgb reflectance", "0.1 0.2 0.3")?)?;
+        params.add(Param::new("texture transmittance", "checks")?)?;
+
+        let material = Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))?;
+
+        let MaterialType::DiffuseTransmission {
+            reflectance,
+            transmittance,
+        } = material.ty
+        else {
+            panic!("Unexpected material type");
+        };
+
+        assert!(
+            matches!(reflectance, Spectrum::Rgb([r, g, b], _) if r == 0.1 && g == 0.2 && b == 0.3)
+        );
+        assert!(matches!(transmittance, Spectrum::Texture(name) if name == "checks"));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "diffusetransmission")?)?;
+
+        let material = Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))?;
+
+        let MaterialType::DiffuseTransmission {
+            reflectance: Spectrum::Rgb(reflectance, _),
+            transmittance: Spectrum::Rgb(transmittance, _),
+        } = material.ty
+        else {
+            panic!("Unexpected material type");
+        };
+
+        assert_eq!(reflectance, [0.25; 3]);
+        assert_eq!(transmittance, [0.25; 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_ptex_texture() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string filename", "textures/wood.ptx")?)?;
+
+        let texture = Texture::new(
+            "wood",
+            "spectrum",
+            "ptex",
+            params,
+            Mat4::IDENTITY,
+            &HashMap::new(),
+            &|filename| Path::new("/scenes/room").join(filename),
+        )?;
+
+        let TextureClass::Ptex { filename, scale } = texture.class else {
+            panic!("Unexpected texture class");
+        };
+
+        assert_eq!(filename, Path::new("/scenes/room/textures/wood.ptx"));
+        assert_eq!(scale, 1.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_mlt_integrator() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("integer chains", "64")?)?;
+
+        let Integrator::Mlt {
+            max_depth,
+            chains,
+            large_step_probability,
+            ..
+        } = Integrator::new("mlt", params)?
+        else {
+            panic!("Unexpected integrator type");
+        };
+
+        assert_eq!(max_depth, 5);
+        assert_eq!(chains, 64);
+        assert_eq!(large_step_probability, 0.3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_simple_integrators() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("integer maxdepth", "3")?)?;
+        params.add(Param::new("bool samplelights", "false")?)?;
+        params.add(Param::new("bool samplebsdf", "true")?)?;
+
+        assert!(matches!(
+            Integrator::new("simplepath", params)?,
+            Integrator::SimplePath {
+                max_depth: 3,
+                sample_lights: false,
+                sample_bsdf: true,
+            }
+        ));
+
+        assert!(matches!(
+            Integrator::new("simplepath", ParamList::default())?,
+            Integrator::SimplePath {
+                max_depth: 5,
+                sample_lights: true,
+                sample_bsdf: true,
+            }
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("integer maxdepth", "8")?)?;
+
+        assert!(matches!(
+            Integrator::new("simplevolpath", params)?,
+            Integrator::SimpleVolPath { max_depth: 8 }
+        ));
+
+        assert!(matches!(
+            Integrator::new("simplevolpath", ParamList::default())?,
+            Integrator::SimpleVolPath { max_depth: 5 }
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("integer maxdepth", "-1")?)?;
+
+        assert!(matches!(
+            Integrator::new("simplevolpath", params),
+            Err(Error::ValueOutOfRange(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sppm_radius_decay() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("float radius", "0.5")?)?;
+
+        let integrator = Integrator::new("sppm", params)?;
+
+        assert!(matches!(
+            integrator,
+            Integrator::Sppm {
+                max_depth: 5,
+                photons_per_iteration: -1,
+                ..
+            }
+        ));
+
+        assert_eq!(integrator.radius_at_iteration(0), 0.5);
+
+        let mut previous = 0.5;
+        for i in 1..10 {
+            let radius = integrator.radius_at_iteration(i);
+            assert!(radius < previous);
+            previous = radius;
+        }
+
+        assert_eq!(
+            Integrator::new("path", ParamList::default())?.radius_at_iteration(1),
+            0.0
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_regularize() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("bool regularize", "true")?)?;
+        params.add(Param::new("integer maxdepth", "8")?)?;
+
+        assert!(matches!(
+            Integrator::new("path", params)?,
+            Integrator::Path {
+                max_depth: 8,
+                regularize: true
+            }
+        ));
+
+        assert!(matches!(
+            Integrator::new("volpath", ParamList::default())?,
+            Integrator::VolPath {
+                max_depth: 5,
+                regularize: false
+            }
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("integer maxdepth", "-1")?)?;
+        assert!(matches!(
+            Integrator::new("path", params),
+            Err(Error::ValueOutOfRange(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn mlt_large_step_probability_out_of_range() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("float largestepprobability", "1.5")?)?;
+
+        assert!(matches!(
+            Integrator::new("mlt", params),
+            Err(Error::ValueOutOfRange(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn goniometric_light_power() -> Result<()> {
+        let power = format!("{}", 8.0 * PI);
+
+        let mut params = ParamList::default();
+        params.add(Param::new("rgb I", "1 1 1")?)?;
+        params.add(Param::new("float power", &power)?)?;
+
+        let Light::GonioPhotometric { scale, power, .. } =
+            Light::new("goniometric", params, &path)?
+        else {
+            panic!("Unexpected light type");
+        };
+
+        assert!((scale - 2.0).abs() < 1e-5);
+        assert!(power.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn goniometric_light_ies() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string filename", "simple.ies")?)?;
+
+        let light = Light::new("goniometric", params.clone(), &|filename: &str| {
+            Path::new("assets/ies").join(filename)
+        })?;
+        let distribution = light.load_goniometric_distribution(&FsAssetSource)?;
+
+        assert_eq!(distribution.vertical_angles, vec![0.0, 45.0, 90.0]);
+        assert_eq!(distribution.horizontal_angles, vec![0.0, 180.0]);
+        assert_eq!(
+            distribution.candela,
+            vec![500.0, 400.0, 100.0, 500.0, 300.0, 50.0]
+        );
+        assert_eq!(distribution.candela(1, 1), Some(300.0));
+
+        let source = HashMap::from([(
+            PathBuf::from("lights/simple.ies"),
+            fs::read("assets/ies/simple.ies")?,
+        )]);
+        let light = Light::new("goniometric", params, &|filename: &str| {
+            Path::new("lights").join(filename)
+        })?;
+        assert_eq!(light.load_goniometric_distribution(&source)?, distribution);
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string filename", "diagram.exr")?)?;
+        assert!(matches!(
+            Light::new("goniometric", params, &path)?.load_goniometric_distribution(&FsAssetSource),
+            Err(Error::InvalidIes(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn goniometric_light_intensity() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("rgb I", "3 3 3")?)?;
+        params.add(Param::new("float scale", "0.5")?)?;
+
+        let Light::GonioPhotometric {
+            spectrum,
+            scale,
+            power,
+            filename,
+        } = Light::new("goniometric", params, &path)?
+        else {
+            panic!("Unexpected light type");
+        };
+
+        assert!(matches!(spectrum, Some(Spectrum::Rgb(rgb, _)) if rgb == [3.0; 3]));
+        assert_eq!(scale, 0.5);
+        assert_eq!(filename, None);
+        assert_eq!(power, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_film_sensor() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string sensor", "canon_eos_5d_mkiv")?)?;
+        params.add(Param::new("float iso", "150")?)?;
+
+        let film = Film::new("rgb", params)?;
+
+        assert_eq!(film.sensor, Sensor::Camera("canon_eos_5d_mkiv".to_string()));
+        assert_eq!(film.iso, 150.0);
+        assert_eq!(film.white_balance, 0.0);
+
+        let film = Film::new("rgb", ParamList::default())?;
+        assert_eq!(film.sensor, Sensor::Cie1931);
+        assert_eq!(film.iso, 100.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_film_sensor() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string sensor", "pinhole_9000")?)?;
+
+        assert!(matches!(
+            Film::new("rgb", params),
+            Err(Error::UnknownSensor(name)) if name == "pinhole_9000"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_float_constant_texture() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("float value", "0.25")?)?;
+
+        let texture = Texture::new(
+            "c",
+            "float",
+            "constant",
+            params,
+            Mat4::IDENTITY,
+            &HashMap::new(),
+            &|f: &str| PathBuf::from(f),
+        )?;
+
+        let TextureClass::Constant {
+            value: FloatOrSpectrum::Float(value),
+        } = texture.class
+        else {
+            panic!("Unexpected texture class");
+        };
+
+        assert_eq!(value, 0.25);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_bilerp_texture() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("float v00", "0.1")?)?;
+        params.add(Param::new("float v01", "0.2")?)?;
+        params.add(Param::new("float v10", "0.3")?)?;
+        params.add(Param::new("string mapping", "planar")?)?;
+
+        let texture = Texture::new(
+            "b",
+            "float",
+            "bilerp",
+            params,
+            Mat4::IDENTITY,
+            &HashMap::new(),
+            &|f: &str| PathBuf::from(f),
+        )?;
+
+        let TextureClass::Bilerp {
+            v00: FloatOrSpectrum::Float(v00),
+            v01: FloatOrSpectrum::Float(v01),
+            v10: FloatOrSpectrum::Float(v10),
+            v11: FloatOrSpectrum::Float(v11),
+            mapping,
+        } = texture.class
+        else {
+            panic!("Unexpected texture class");
+        };
+
+        assert_eq!([v00, v01, v10, v11], [0.1, 0.2, 0.3, 1.0]);
+        assert_eq!(
+            mapping,
+            TextureMapping::Planar {
+                v1: [1.0, 0.0, 0.0],
+                v2: [0.0, 1.0, 0.0],
+                udelta: 0.0,
+                vdelta: 0.0,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_spectrum_constant_texture() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("rgb value", "0.1 0.2 0.3")?)?;
+
+        let texture = Texture::new(
+            "c",
+            "spectrum",
+            "constant",
+            params,
+            Mat4::IDENTITY,
+            &HashMap::new(),
+            &|f: &str| PathBuf::from(f),
+        )?;
+
+        let TextureClass::Constant {
+            value: FloatOrSpectrum::Spectrum(Spectrum::Rgb(rgb, _)),
+        } = texture.class
+        else {
+            panic!("Unexpected texture class");
+        };
+
+        assert_eq!(rgb, [0.1, 0.2, 0.3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn light_scale() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("rgb I", "3 3 3")?)?;
+
+        let light = Light::new("point", params, &path)?;
+        assert_eq!(light.scale(), 1.0);
+        assert_eq!(light.effective_intensity(), Some(3.0));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("rgb I", "3 3 3")?)?;
+        params.add(Param::new("float scale", "2")?)?;
+
+        let light = Light::new("point", params, &path)?;
+        assert_eq!(light.scale(), 2.0);
+        assert_eq!(light.effective_intensity(), Some(6.0));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("float scale", "0.5")?)?;
+
+        let light = Light::new("infinite", params, &path)?;
+        assert!(matches!(light, Light::Infinite { .. }));
+        assert_eq!(light.scale(), 0.5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_cone_angle_radians() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("float coneangle", "90")?)?;
+
+        let light = Light::new("spot", params, &path)?;
+        assert!((light.cone_angle_radians().unwrap() - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!((light.cone_delta_radians().unwrap() - 5_f32.to_radians()).abs() < 1e-6);
+
+        let light = Light::new("point", ParamList::default(), &path)?;
+        assert_eq!(light.cone_angle_radians(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn area_light_nsamples() -> Result<()> {
+        let AreaLight::Diffuse { n_samples, .. } =
+            AreaLight::new("diffuse", ParamList::default(), &path)?;
+        assert_eq!(n_samples, 1);
+
+        let mut params = ParamList::default();
+        params.add(Param::new("integer nsamples", "8")?)?;
+        let AreaLight::Diffuse { n_samples, .. } = AreaLight::new("diffuse", params, &path)?;
+        assert_eq!(n_samples, 8);
+
+        let mut params = ParamList::default();
+        params.add(Param::new("integer nsamples", "0")?)?;
+        assert!(matches!(
+            AreaLight::new("diffuse", params, &path),
+            Err(Error::ValueOutOfRange(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn infinite_light_forms() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("rgb L", "0.5 0.5 0.5")?)?;
+        let light = Light::new("infinite", params, &path)?;
+        assert!(matches!(
+            light,
+            Light::Infinite {
+                filename: None,
+                spectrum: Some(Spectrum::Rgb(_, _)),
+                ..
+            }
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string filename", "sky.exr")?)?;
+        let light = Light::new("infinite", params, &path)?;
+        assert!(matches!(
+            light,
+            Light::Infinite {
+                filename: Some(_),
+                portal: None,
+                ..
+            }
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string filename", "sky.exr")?)?;
+        params.add(Param::new("point3 portal", "0 0 0 1 0 0 1 1 0 0 1 0")?)?;
+        let light = Light::new("infinite", params, &path)?;
+        let Light::Infinite {
+            portal: Some(portal),
+            ..
+        } = light
+        else {
+            panic!("Unexpected light");
+        };
+        assert_eq!(portal[2], Vec3::new(1.0, 1.0, 0.0));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string filename", "sky.exr")?)?;
+        params.add(Param::new("point3 portal", "0 0 0 2 0 0 2 2 0 0 2 0")?)?;
+        let light = Light::new("infinite", params, &path)?;
+        assert_eq!(
+            light.portal_normal_and_area(),
+            Some((Vec3::new(0.0, 0.0, 1.0), 4.0))
+        );
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string filename", "sky.exr")?)?;
+        params.add(Param::new("point3 portal", "0 0 0 1 0 0 1 1 0.5 0 1 0")?)?;
+        assert!(matches!(
+            Light::new("infinite", params, &path),
+            Err(Error::InvalidPortal)
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string filename", "sky.exr")?)?;
+        params.add(Param::new("rgb L", "1 1 1")?)?;
+        assert!(matches!(
+            Light::new("infinite", params, &path),
+            Err(Error::ConflictingLightParameters)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_conductor_rgb_eta() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "conductor")?)?;
+        params.add(Param::new("rgb eta", "0.2 0.9 1.1")?)?;
+        params.add(Param::new("rgb k", "3.9 2.4 2.1")?)?;
+
+        let material = Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))?;
+
+        let MaterialType::Conductor { eta, k, .. } = material.ty else {
+            panic!("Unexpected material type");
+        };
+
+        assert_eq!(eta, Spectrum::Rgb([0.2, 0.9, 1.1], None));
+        assert_eq!(k, Spectrum::Rgb([3.9, 2.4, 2.1], None));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "conductor")?)?;
+        params.add(Param::new("spectrum eta", "metal-Au-eta")?)?;
+        params.add(Param::new("spectrum k", "400 2 800 4")?)?;
+
+        let material = Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))?;
+
+        let MaterialType::Conductor { eta, k, .. } = material.ty else {
+            panic!("Unexpected material type");
+        };
+
+        assert_eq!(eta, Spectrum::Named("metal-Au-eta".to_string()));
+        assert_eq!(k, Spectrum::Sampled(vec![[400.0, 2.0], [800.0, 4.0]]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn conductor_effective_alpha() -> Result<()> {
+        let conductor = |remap: &str| -> Result<Material> {
+            let mut params = ParamList::default();
+            params.add(Param::new("string type", "conductor")?)?;
+            params.add(Param::new("float uroughness", "0.5")?)?;
+            params.add(Param::new("float vroughness", "0.5")?)?;
+            params.add(Param::new("bool remaproughness", remap)?)?;
+
+            Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))
+        };
+
+        let (u, v) = conductor("true")?.effective_alpha().unwrap();
+        assert_eq!(u, v);
+        assert!((u - 0.70710677).abs() < 1e-6);
+
+        assert_eq!(conductor("false")?.effective_alpha(), Some((0.5, 0.5)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_dielectric_eta() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "dielectric")?)?;
+
+        let material = Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))?;
+        assert!(matches!(
+            material.ty,
+            MaterialType::Dielectric {
+                eta: FloatOrSpectrum::Float(eta),
+                ..
+            } if eta == 1.5
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "dielectric")?)?;
+        params.add(Param::new("rgb eta", "1.4 1.5 1.6")?)?;
+
+        let material = Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))?;
+        assert!(matches!(
+            material.ty,
+            MaterialType::Dielectric {
+                eta: FloatOrSpectrum::Spectrum(Spectrum::Rgb(_, _)),
+                ..
+            }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_dielectric_named_eta() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "dielectric")?)?;
+        params.add(Param::new("spectrum eta", "glass-BK7")?)?;
+
+        let material = Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))?;
+        let MaterialType::Dielectric { eta, .. } = material.ty else {
+            panic!("Unexpected material type");
+        };
+
+        assert_eq!(
+            eta,
+            FloatOrSpectrum::Spectrum(Spectrum::Named("glass-BK7".to_string()))
+        );
+        assert!(eta.is_dispersive());
+
+        // BK7 is about 1.5168 at the sodium d-line and more refractive for blue light.
+        let d_line = eta.eval(587.6).unwrap();
+        assert!((d_line - 1.5168).abs() < 1e-3, "{d_line}");
+        assert!(eta.eval(450.0).unwrap() > eta.eval(650.0).unwrap());
+
+        assert!(!FloatOrSpectrum::Float(1.5).is_dispersive());
+        assert!(!FloatOrSpectrum::Spectrum(Spectrum::Rgb([1.5; 3], None)).is_dispersive());
+        assert_eq!(
+            FloatOrSpectrum::Spectrum(Spectrum::Named("metal-Au-eta".to_string())).eval(550.0),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn coated_diffuse_layer_counts() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "coateddiffuse")?)?;
+        params.add(Param::new("integer nsamples", "0")?)?;
+
+        assert!(matches!(
+            Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f)),
+            Err(Error::InvalidMaterialParameter(name)) if name == "nsamples"
+        ));
+
+        let mut params = ParamList::default();
+        params.add(Param::new("string type", "coateddiffuse")?)?;
+        params.add(Param::new("integer maxdepth", "4")?)?;
+        params.add(Param::new("integer nsamples", "2")?)?;
+
+        let material = Material::new("", params, &HashMap::new(), &|f: &str| PathBuf::from(f))?;
+        assert!(matches!(
+            material.ty,
+            MaterialType::CoatedDiffuse {
+                maxdepth: LayerDepth(4),
+                nsamples: 2,
+                ..
+            }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate_gaussian_filter() {
+        let filter = PixelFilter::default();
+        let PixelFilter::Gaussian { xradius, .. } = filter else {
+            panic!("Unexpected filter type");
+        };
+
+        let peak = filter.evaluate(0.0, 0.0);
+        assert!(peak > 0.0);
+        assert!(filter.evaluate(0.1, 0.0) < peak);
+        assert!(filter.evaluate(0.0, -0.1) < peak);
+        assert!(filter.evaluate(xradius, 0.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn evaluate_pixel_filters() -> Result<()> {
+        let filter = PixelFilter::new("box", ParamList::default())?;
+        assert_eq!(filter.evaluate(0.25, 0.25), 1.0);
+        assert_eq!(filter.evaluate(0.75, 0.0), 0.0);
+
+        let filter = PixelFilter::new("triangle", ParamList::default())?;
+        assert_eq!(filter.evaluate(0.0, 0.0), 0.25);
+        assert_eq!(filter.evaluate(0.5, 0.0), 0.0);
+
+        let filter = PixelFilter::new("mitchell", ParamList::default())?;
+        assert!(filter.evaluate(0.0, 0.0) > 0.0);
+        assert_eq!(filter.evaluate(2.5, 0.0), 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn film_clamp_default() -> Result<()> {
+        assert_eq!(Film::default().max_component_value, f32::INFINITY);
+        assert_eq!(
+            Film::new("rgb", ParamList::default())?.max_component_value,
+            f32::INFINITY
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_sobol_randomization() -> Result<()> {
+        let cases = [
+            ("none", SobolRandomization::None),
+            ("permutedigits", SobolRandomization::PermuteDigits),
+            ("fastowen", SobolRandomization::FastOwen),
+            ("owen", SobolRandomization::Owen),
+        ];
+
+        for (value, expected) in cases {
+            let mut params = ParamList::default();
+            params.add(Param::new("string randomization", value)?)?;
+
+            let sampler = Sampler::new("zsobol", params)?;
+            assert!(
+                matches!(sampler, Sampler::ZSobol { randomization, .. } if randomization == expected)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sobol_randomization_default() -> Result<()> {
+        for ty in ["paddedsobol", "sobol", "zsobol"] {
+            let sampler = Sampler::new(ty, ParamList::default())?;
+
+            let randomization = match sampler {
+                Sampler::PaddedSobol { randomization, .. }
+                | Sampler::Sobol { randomization, .. }
+                | Sampler::ZSobol { randomization, .. } => randomization,
+                _ => panic!("Unexpected sampler type"),
+            };
+
+            assert_eq!(randomization, SobolRandomization::FastOwen);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sobol_randomization_unknown() -> Result<()> {
+        let mut params = ParamList::default();
+        params.add(Param::new("string randomization", "foo")?)?;
+
+        assert!(matches!(
+            Sampler::new("sobol", params),
+            Err(Error::InvalidString)
+        ));
+
+        Ok(())
+    }
 }
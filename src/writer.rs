@@ -0,0 +1,1146 @@
+//! Writing scenes back to the pbrt scene format.
+//!
+//! The output is canonical: directives are emitted in a fixed order, one per line,
+//! parameters are sorted by name and floats use Rust's shortest round-trip representation.
+
+use std::{collections::BTreeMap, io::Write};
+
+use glam::Mat4;
+
+use crate::{
+    param::Spectrum,
+    types::{
+        Accelerator, Alpha, AreaLight, BvhSplitMethod, Camera, ColorSpace, CoordinateSystem, Film,
+        FilmType, FloatOrSpectrum, Integrator, Light, Material, MaterialType, Options, PixelFilter,
+        Sampler, Sensor, Shape, SobolRandomization, Texture, TextureClass, TextureInput,
+        TextureMapping, TextureType,
+    },
+    Result, Scene, ShapeEntity,
+};
+
+/// Format a float so that it parses back to the same value, negative zero is written as zero.
+fn float(value: f32) -> String {
+    format!("{:?}", value + 0.0)
+}
+
+/// Format a list of numbers as a bracketed array.
+fn array<T>(values: impl IntoIterator<Item = T>, format: impl Fn(T) -> String) -> String {
+    let mut out = String::from("[");
+    for value in values {
+        out.push(' ');
+        out.push_str(&format(value));
+    }
+    out.push_str(" ]");
+    out
+}
+
+fn quoted(value: &str) -> String {
+    format!("\"{value}\"")
+}
+
+/// Parameter list of a directive, sorted by name.
+#[derive(Default)]
+struct ParamSet {
+    /// Parameter type and formatted value by name.
+    params: BTreeMap<&'static str, (&'static str, String)>,
+    /// Color space of the RGB values in the list.
+    color_space: Option<ColorSpace>,
+}
+
+impl ParamSet {
+    fn add(&mut self, ty: &'static str, name: &'static str, value: String) {
+        self.params.insert(name, (ty, value));
+    }
+
+    /// Add a float, non-finite values are left out so the parameter takes its default.
+    fn float(&mut self, name: &'static str, value: f32) {
+        if value.is_finite() {
+            self.add("float", name, float(value));
+        }
+    }
+
+    fn floats(&mut self, name: &'static str, values: &[f32]) {
+        self.add("float", name, array(values.iter().copied(), float));
+    }
+
+    fn integer(&mut self, name: &'static str, value: i32) {
+        self.add("integer", name, value.to_string());
+    }
+
+    fn integers(&mut self, name: &'static str, values: impl IntoIterator<Item = i32>) {
+        self.add("integer", name, array(values, |value| value.to_string()));
+    }
+
+    fn boolean(&mut self, name: &'static str, value: bool) {
+        self.add("bool", name, quoted(&value.to_string()));
+    }
+
+    fn string(&mut self, name: &'static str, value: &str) {
+        self.add("string", name, quoted(value));
+    }
+
+    fn point3(&mut self, name: &'static str, value: [f32; 3]) {
+        self.add("point3", name, array(value, float));
+    }
+
+    fn vector3(&mut self, name: &'static str, value: [f32; 3]) {
+        self.add("vector3", name, array(value, float));
+    }
+
+    fn spectrum(&mut self, name: &'static str, spectrum: &Spectrum) {
+        match spectrum {
+            Spectrum::Rgb(rgb, color_space) => {
+                self.color_space = Some(color_space.unwrap_or_default());
+                self.add("rgb", name, array(*rgb, float));
+            }
+            Spectrum::Blackbody(temperature) => {
+                self.add("blackbody", name, temperature.to_string())
+            }
+            Spectrum::Sampled(samples) => self.add(
+                "spectrum",
+                name,
+                array(samples.iter().flatten().copied(), float),
+            ),
+            Spectrum::Named(spectrum) => self.add("spectrum", name, quoted(spectrum)),
+            Spectrum::Texture(texture) => self.add("texture", name, quoted(texture)),
+        }
+    }
+
+    fn float_or_spectrum(&mut self, name: &'static str, value: &FloatOrSpectrum) {
+        match value {
+            FloatOrSpectrum::Float(value) => self.float(name, *value),
+            FloatOrSpectrum::Spectrum(spectrum) => self.spectrum(name, spectrum),
+        }
+    }
+
+    fn texture_input(&mut self, name: &'static str, input: &TextureInput, textures: &[Texture]) {
+        match input {
+            TextureInput::Texture(index) => {
+                self.add("texture", name, quoted(&textures[*index].name))
+            }
+            TextureInput::Constant(value) => self.float_or_spectrum(name, value),
+        }
+    }
+
+    fn mapping(&mut self, mapping: &TextureMapping) {
+        match mapping {
+            TextureMapping::Uv {
+                uscale,
+                vscale,
+                udelta,
+                vdelta,
+            } => {
+                self.string("mapping", "uv");
+                self.float("uscale", *uscale);
+                self.float("vscale", *vscale);
+                self.float("udelta", *udelta);
+                self.float("vdelta", *vdelta);
+            }
+            TextureMapping::Spherical => self.string("mapping", "spherical"),
+            TextureMapping::Cylindrical => self.string("mapping", "cylindrical"),
+            TextureMapping::Planar {
+                v1,
+                v2,
+                udelta,
+                vdelta,
+            } => {
+                self.string("mapping", "planar");
+                self.vector3("v1", *v1);
+                self.vector3("v2", *v2);
+                self.float("udelta", *udelta);
+                self.float("vdelta", *vdelta);
+            }
+        }
+    }
+}
+
+fn color_space_name(color_space: ColorSpace) -> &'static str {
+    match color_space {
+        ColorSpace::Srgb => "srgb",
+        ColorSpace::Rec2020 => "rec2020",
+        ColorSpace::Aces2065_1 => "aces2065-1",
+        ColorSpace::DciP3 => "dci-p3",
+    }
+}
+
+fn randomization_name(randomization: &SobolRandomization) -> &'static str {
+    match randomization {
+        SobolRandomization::None => "none",
+        SobolRandomization::PermuteDigits => "permutedigits",
+        SobolRandomization::FastOwen => "fastowen",
+        SobolRandomization::Owen => "owen",
+    }
+}
+
+fn options_params(options: &Options) -> ParamSet {
+    let defaults = Options::default();
+    let mut params = ParamSet::default();
+
+    let flags = [
+        ("disablepixeljitter", options.disable_pixel_jitter),
+        ("disabletexturecache", options.disable_texture_cache),
+        ("disableimagetextures", options.disable_image_textures),
+        ("disabletexturefiltering", options.disable_texture_filtering),
+        ("disablewavelengthjitter", options.disable_wavelength_jitter),
+        ("wavefront", options.wavefront),
+    ];
+    for (name, value) in flags {
+        if value {
+            params.boolean(name, value);
+        }
+    }
+
+    if options.displacement_edge_scale != defaults.displacement_edge_scale {
+        params.float("displacementedgescale", options.displacement_edge_scale);
+    }
+    if let Some(image) = &options.mse_reference_image {
+        params.string("msereferenceimage", image);
+    }
+    if let Some(out) = &options.mse_reference_out {
+        params.string("msereferenceout", out);
+    }
+    match options.render_coord_sys {
+        CoordinateSystem::CameraWorld => {}
+        CoordinateSystem::Camera => params.string("rendercoordsys", "camera"),
+        CoordinateSystem::World => params.string("rendercoordsys", "world"),
+    }
+    if let Some(bounds) = options.sample_bounds {
+        params.integers("samplebounds", bounds);
+    }
+
+    params
+}
+
+fn film_params(film: &Film) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    params.integer("xresolution", film.xresolution);
+    params.integer("yresolution", film.yresolution);
+    params.floats("cropwindow", &film.crop_window);
+    params.float("diagonal", film.diagonal);
+    params.string("filename", &film.filename);
+    params.boolean("savefp16", film.save_fp16);
+    params.float("iso", film.iso);
+    params.float("whitebalance", film.white_balance);
+    params.string(
+        "sensor",
+        match &film.sensor {
+            Sensor::Cie1931 => "cie1931",
+            Sensor::Camera(name) => name,
+        },
+    );
+    params.float("maxcomponentvalue", film.max_component_value);
+
+    let ty = match &film.ty {
+        FilmType::Rgb => "rgb",
+        FilmType::GBuffer { coordinate_system } => {
+            params.string("coordinatesystem", coordinate_system);
+            "gbuffer"
+        }
+        FilmType::Spectral {
+            nbuckets,
+            lambda_min,
+            lambda_max,
+        } => {
+            params.integer("nbuckets", *nbuckets);
+            params.float("lambdamin", *lambda_min);
+            params.float("lambdamax", *lambda_max);
+            "spectral"
+        }
+    };
+
+    (ty, params)
+}
+
+fn camera_params(camera: &Camera) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    let ty = match camera {
+        Camera::Orthographic {
+            shutter_open,
+            shutter_close,
+            lens_radius,
+            focal_distance,
+            screen_window,
+            near_clip,
+            far_clip,
+        } => {
+            params.float("shutteropen", *shutter_open);
+            params.float("shutterclose", *shutter_close);
+            params.float("lensradius", *lens_radius);
+            params.float("focaldistance", *focal_distance);
+            if let Some(screen_window) = screen_window {
+                params.floats("screenwindow", screen_window);
+            }
+            params.float("hither", *near_clip);
+            params.float("yon", *far_clip);
+            "orthographic"
+        }
+        Camera::Perspective {
+            shutter_open,
+            shutter_close,
+            fov,
+            lens_radius,
+            focal_distance,
+        } => {
+            params.float("shutteropen", *shutter_open);
+            params.float("shutterclose", *shutter_close);
+            params.float("fov", *fov);
+            params.float("lensradius", *lens_radius);
+            params.float("focaldistance", *focal_distance);
+            "perspective"
+        }
+        Camera::Realistic {
+            shutter_open,
+            shutter_close,
+            lensfile,
+            aperture_diameter,
+            focus_distance,
+            aperture,
+        } => {
+            params.float("shutteropen", *shutter_open);
+            params.float("shutterclose", *shutter_close);
+            if let Some(lensfile) = lensfile {
+                params.string("lensfile", lensfile);
+            }
+            params.float("aperturediameter", *aperture_diameter);
+            params.float("focusdistance", *focus_distance);
+            if let Some(aperture) = aperture {
+                params.string("aperture", aperture);
+            }
+            "realistic"
+        }
+        Camera::Spherical {
+            shutter_open,
+            shutter_close,
+            mapping,
+        } => {
+            params.float("shutteropen", *shutter_open);
+            params.float("shutterclose", *shutter_close);
+            params.string("mapping", mapping);
+            "spherical"
+        }
+    };
+
+    (ty, params)
+}
+
+fn sampler_params(sampler: &Sampler) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    let ty = match sampler {
+        Sampler::Halton { pixel_samples } => {
+            params.integer("pixelsamples", *pixel_samples);
+            "halton"
+        }
+        Sampler::Independent { pixel_samples } => {
+            params.integer("pixelsamples", *pixel_samples);
+            "independent"
+        }
+        Sampler::PaddedSobol {
+            pixel_samples,
+            randomization,
+        } => {
+            params.integer("pixelsamples", *pixel_samples);
+            params.string("randomization", randomization_name(randomization));
+            "paddedsobol"
+        }
+        Sampler::Sobol {
+            pixel_samples,
+            randomization,
+        } => {
+            params.integer("pixelsamples", *pixel_samples);
+            params.string("randomization", randomization_name(randomization));
+            "sobol"
+        }
+        Sampler::Stratified {
+            x_samples,
+            y_samples,
+            jitter,
+        } => {
+            params.integer("xsamples", *x_samples);
+            params.integer("ysamples", *y_samples);
+            params.boolean("jitter", *jitter);
+            "stratified"
+        }
+        Sampler::ZSobol {
+            pixel_samples,
+            randomization,
+        } => {
+            params.integer("pixelsamples", *pixel_samples);
+            params.string("randomization", randomization_name(randomization));
+            "zsobol"
+        }
+    };
+
+    (ty, params)
+}
+
+fn integrator_params(integrator: &Integrator) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    let ty = match integrator {
+        Integrator::AmbientOcclusion => "ambientocclusion",
+        Integrator::Bdpt => "bdpt",
+        Integrator::LightPath { max_depth } => {
+            params.integer("maxdepth", *max_depth);
+            "lightpath"
+        }
+        Integrator::Mlt {
+            max_depth,
+            bootstrap_samples,
+            chains,
+            mutations_per_pixel,
+            large_step_probability,
+            sigma,
+        } => {
+            params.integer("maxdepth", *max_depth);
+            params.integer("bootstrapsamples", *bootstrap_samples);
+            params.integer("chains", *chains);
+            params.integer("mutationsperpixel", *mutations_per_pixel);
+            params.float("largestepprobability", *large_step_probability);
+            params.float("sigma", *sigma);
+            "mlt"
+        }
+        Integrator::Path {
+            max_depth,
+            regularize,
+        } => {
+            params.integer("maxdepth", *max_depth);
+            params.boolean("regularize", *regularize);
+            "path"
+        }
+        Integrator::RandomWalk => "randomwalk",
+        Integrator::SimplePath {
+            max_depth,
+            sample_lights,
+            sample_bsdf,
+        } => {
+            params.integer("maxdepth", *max_depth);
+            params.boolean("samplelights", *sample_lights);
+            params.boolean("samplebsdf", *sample_bsdf);
+            "simplepath"
+        }
+        Integrator::SimpleVolPath { max_depth } => {
+            params.integer("maxdepth", *max_depth);
+            "simplevolpath"
+        }
+        Integrator::Sppm {
+            max_depth,
+            photons_per_iteration,
+            radius,
+            seed,
+        } => {
+            params.integer("maxdepth", *max_depth);
+            params.integer("photonsperiteration", *photons_per_iteration);
+            params.float("radius", *radius);
+            params.integer("seed", *seed);
+            "sppm"
+        }
+        Integrator::VolPath {
+            max_depth,
+            regularize,
+        } => {
+            params.integer("maxdepth", *max_depth);
+            params.boolean("regularize", *regularize);
+            "volpath"
+        }
+    };
+
+    (ty, params)
+}
+
+fn pixel_filter_params(filter: &PixelFilter) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    let (ty, xradius, yradius) = match filter {
+        PixelFilter::Box { xradius, yradius } => ("box", xradius, yradius),
+        PixelFilter::Gaussian {
+            xradius,
+            yradius,
+            sigma,
+        } => {
+            params.float("sigma", *sigma);
+            ("gaussian", xradius, yradius)
+        }
+        PixelFilter::Mitchell {
+            xradius,
+            yradius,
+            b,
+            c,
+        } => {
+            params.float("B", *b);
+            params.float("C", *c);
+            ("mitchell", xradius, yradius)
+        }
+        PixelFilter::Sinc {
+            xradius,
+            yradius,
+            tau,
+        } => {
+            params.float("tau", *tau);
+            ("sinc", xradius, yradius)
+        }
+        PixelFilter::Triangle { xradius, yradius } => ("triangle", xradius, yradius),
+    };
+
+    params.float("xradius", *xradius);
+    params.float("yradius", *yradius);
+
+    (ty, params)
+}
+
+fn accelerator_params(accelerator: &Accelerator) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    let ty = match accelerator {
+        Accelerator::Bvh {
+            max_node_prims,
+            split_method,
+        } => {
+            params.integer("maxnodeprims", *max_node_prims);
+            params.string(
+                "splitmethod",
+                match split_method {
+                    BvhSplitMethod::Sah => "sah",
+                    BvhSplitMethod::Middle => "middle",
+                    BvhSplitMethod::Equal => "equal",
+                    BvhSplitMethod::Hlbvh => "hlbvh",
+                },
+            );
+            "bvh"
+        }
+        Accelerator::KdTree {
+            intersect_cost,
+            traversal_cost,
+            empty_bonus,
+            max_prims,
+            max_depth,
+        } => {
+            params.integer("intersectcost", *intersect_cost);
+            params.integer("traversalcost", *traversal_cost);
+            params.float("emptybonus", *empty_bonus);
+            params.integer("maxprims", *max_prims);
+            params.integer("maxdepth", *max_depth);
+            "kdtree"
+        }
+    };
+
+    (ty, params)
+}
+
+fn light_params(light: &Light) -> ParamSet {
+    let mut params = ParamSet::default();
+    params.float("scale", light.scale());
+
+    // The scale of lights given by their power is already normalized,
+    // so `power` is left out to not apply it twice.
+    match light {
+        Light::Distant {
+            spectrum, from, to, ..
+        } => {
+            if let Some(spectrum) = spectrum {
+                params.spectrum("L", spectrum);
+            }
+            params.point3("from", *from);
+            params.point3("to", *to);
+        }
+        Light::GonioPhotometric {
+            spectrum, filename, ..
+        } => {
+            if let Some(spectrum) = spectrum {
+                params.spectrum("I", spectrum);
+            }
+            if let Some(filename) = filename {
+                params.string("filename", &filename.to_string_lossy());
+            }
+        }
+        Light::Infinite {
+            filename,
+            spectrum,
+            portal,
+            ..
+        } => {
+            // Environment maps and constant radiance are exclusive.
+            match (filename, spectrum) {
+                (Some(filename), _) => params.string("filename", &filename.to_string_lossy()),
+                (None, Some(spectrum)) => params.spectrum("L", spectrum),
+                (None, None) => {}
+            }
+            if let Some(portal) = portal {
+                let points = portal.iter().flat_map(|point| point.to_array());
+                params.add("point3", "portal", array(points, float));
+            }
+        }
+        Light::Point { spectrum, from, .. } => {
+            if let Some(spectrum) = spectrum {
+                params.spectrum("I", spectrum);
+            }
+            params.point3("from", *from);
+        }
+        Light::Projection { spectrum, fov, .. } => {
+            if let Some(spectrum) = spectrum {
+                params.spectrum("I", spectrum);
+            }
+            params.float("fov", *fov);
+        }
+        Light::Spot {
+            spectrum,
+            from,
+            to,
+            cone_angle,
+            cone_delta,
+            ..
+        } => {
+            if let Some(spectrum) = spectrum {
+                params.spectrum("I", spectrum);
+            }
+            params.point3("from", *from);
+            params.point3("to", *to);
+            params.float("coneangle", *cone_angle);
+            params.float("conedelta", *cone_delta);
+        }
+    }
+
+    params
+}
+
+fn area_light_params(area_light: &AreaLight) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    let AreaLight::Diffuse {
+        filename,
+        two_sided,
+        spectrum,
+        scale,
+        n_samples,
+        power,
+    } = area_light;
+
+    if let Some(filename) = filename {
+        params.string("filename", &filename.to_string_lossy());
+    }
+    params.boolean("twosided", *two_sided);
+    if let Some(spectrum) = spectrum {
+        params.spectrum("L", spectrum);
+    }
+    params.float("scale", *scale);
+    params.integer("nsamples", *n_samples as i32);
+    if let Some(power) = power {
+        params.float("power", *power);
+    }
+
+    ("diffuse", params)
+}
+
+fn texture_params(texture: &Texture, textures: &[Texture]) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    let class = match &texture.class {
+        TextureClass::Bilerp {
+            v00,
+            v01,
+            v10,
+            v11,
+            mapping,
+        } => {
+            params.float_or_spectrum("v00", v00);
+            params.float_or_spectrum("v01", v01);
+            params.float_or_spectrum("v10", v10);
+            params.float_or_spectrum("v11", v11);
+            params.mapping(mapping);
+            "bilerp"
+        }
+        TextureClass::Checkerboard {
+            tex1,
+            tex2,
+            dimension,
+            mapping,
+        } => {
+            params.texture_input("tex1", tex1, textures);
+            params.texture_input("tex2", tex2, textures);
+            params.integer("dimension", *dimension);
+            params.mapping(mapping);
+            "checkerboard"
+        }
+        TextureClass::Constant { value } => {
+            params.float_or_spectrum("value", value);
+            "constant"
+        }
+        TextureClass::DirectionMix => "directionmix",
+        TextureClass::Dots {
+            inside,
+            outside,
+            mapping,
+        } => {
+            params.texture_input("inside", inside, textures);
+            params.texture_input("outside", outside, textures);
+            params.mapping(mapping);
+            "dots"
+        }
+        TextureClass::Fbm => "fbm",
+        TextureClass::ImageMap { filename } => {
+            params.string("filename", &filename.to_string_lossy());
+            "imagemap"
+        }
+        TextureClass::Marble => "marble",
+        TextureClass::Mix { tex1, tex2, amount } => {
+            params.texture_input("tex1", tex1, textures);
+            params.texture_input("tex2", tex2, textures);
+            params.texture_input("amount", amount, textures);
+            "mix"
+        }
+        TextureClass::Ptex { filename, scale } => {
+            params.string("filename", &filename.to_string_lossy());
+            params.float("scale", *scale);
+            "ptex"
+        }
+        TextureClass::Scale { tex, scale } => {
+            params.texture_input("tex", tex, textures);
+            params.texture_input("scale", scale, textures);
+            "scale"
+        }
+        TextureClass::Windy => "windy",
+        TextureClass::Wrinkled => "wrinkled",
+    };
+
+    if !matches!(texture.class, TextureClass::Scale { .. }) {
+        params.float("scale", texture.scale);
+    }
+
+    (class, params)
+}
+
+fn material_params(material: &Material, textures: &[Texture]) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    let ty = match &material.ty {
+        MaterialType::CoatedDiffuse {
+            albedo,
+            g,
+            maxdepth,
+            nsamples,
+            thickness,
+            reflectance,
+            uroughness,
+            vroughness,
+            remaproughness,
+        } => {
+            params.spectrum("albedo", albedo);
+            params.float("g", *g);
+            params.integer("maxdepth", maxdepth.0);
+            params.integer("nsamples", *nsamples);
+            params.float("thickness", *thickness);
+            params.spectrum("reflectance", reflectance);
+            params.float("uroughness", *uroughness);
+            params.float("vroughness", *vroughness);
+            params.boolean("remaproughness", *remaproughness);
+            "coateddiffuse"
+        }
+        MaterialType::CoatedConductor => "coatedconductor",
+        MaterialType::Conductor {
+            eta,
+            k,
+            uroughness,
+            vroughness,
+            remaproughness,
+        } => {
+            params.spectrum("eta", eta);
+            params.spectrum("k", k);
+            params.float("uroughness", *uroughness);
+            params.float("vroughness", *vroughness);
+            params.boolean("remaproughness", *remaproughness);
+            "conductor"
+        }
+        MaterialType::Dielectric {
+            eta,
+            uroughness,
+            vroughness,
+            remaproughness,
+        } => {
+            params.float_or_spectrum("eta", eta);
+            params.float("uroughness", *uroughness);
+            params.float("vroughness", *vroughness);
+            params.boolean("remaproughness", *remaproughness);
+            "dielectric"
+        }
+        MaterialType::Diffuse { reflectance } => {
+            params.spectrum("reflectance", reflectance);
+            "diffuse"
+        }
+        MaterialType::DiffuseTransmission {
+            reflectance,
+            transmittance,
+        } => {
+            params.spectrum("reflectance", reflectance);
+            params.spectrum("transmittance", transmittance);
+            "diffusetransmission"
+        }
+        MaterialType::Hair => "hair",
+        MaterialType::Interface => "interface",
+        MaterialType::Measured => "measured",
+        MaterialType::Mix => "mix",
+        MaterialType::Subsurface => "subsurface",
+        MaterialType::ThinDielectric => "thindielectric",
+    };
+
+    if let Some(displacement) = material.displacement {
+        params.add(
+            "texture",
+            "displacement",
+            quoted(&textures[displacement].name),
+        );
+    }
+    if let Some(normal_map) = &material.normal_map {
+        params.string("normalmap", &normal_map.to_string_lossy());
+    }
+
+    (ty, params)
+}
+
+fn shape_params(shape: &ShapeEntity, textures: &[Texture]) -> (&'static str, ParamSet) {
+    let mut params = ParamSet::default();
+
+    match shape.alpha {
+        Alpha::Constant(alpha) => params.float("alpha", alpha),
+        Alpha::Texture(index) => params.add("texture", "alpha", quoted(&textures[index].name)),
+    }
+
+    let ty = match &shape.params {
+        Shape::Curve {
+            positions,
+            basis,
+            degree,
+            ty,
+            normals,
+            width,
+            width0,
+            width1,
+            splitdepth,
+            ..
+        } => {
+            params.add("point3", "P", array(positions.iter().copied(), float));
+            params.string("basis", basis);
+            params.integer("degree", *degree);
+            params.string("type", ty);
+            if let Some(normals) = normals {
+                params.add("normal", "N", array(normals.iter().copied(), float));
+            }
+            params.float("width", *width);
+            params.float("width0", *width0);
+            params.float("width1", *width1);
+            params.integer("splitdepth", *splitdepth);
+            "curve"
+        }
+        Shape::Cylinder {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => {
+            params.float("radius", *radius);
+            params.float("zmin", *zmin);
+            params.float("zmax", *zmax);
+            params.float("phimax", *phimax);
+            "cylinder"
+        }
+        Shape::Disk {
+            height,
+            radius,
+            innerradius,
+            phimax,
+            ..
+        } => {
+            params.float("height", *height);
+            params.float("radius", *radius);
+            params.float("innerradius", *innerradius);
+            params.float("phimax", *phimax);
+            "disk"
+        }
+        Shape::Sphere {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => {
+            params.float("radius", *radius);
+            params.float("zmin", *zmin);
+            params.float("zmax", *zmax);
+            params.float("phimax", *phimax);
+            "sphere"
+        }
+        Shape::TriangleMesh {
+            indices,
+            positions,
+            normals,
+            tangents,
+            uvs,
+            colors,
+            face_indices,
+            edge_length,
+            ..
+        } => {
+            params.integers("indices", indices.iter().copied());
+            params.add("point3", "P", array(positions.iter().copied(), float));
+            if !normals.is_empty() {
+                params.add("normal", "N", array(normals.iter().copied(), float));
+            }
+            if let Some(tangents) = tangents {
+                let values = tangents.iter().flat_map(|tangent| tangent.to_array());
+                params.add("vector3", "S", array(values, float));
+            }
+            if !uvs.is_empty() {
+                params.add("point2", "uv", array(uvs.iter().copied(), float));
+            }
+            if let Some(colors) = colors {
+                params.add("rgb", "Cs", array(colors.iter().flatten().copied(), float));
+            }
+            if let Some(face_indices) = face_indices {
+                params.integers("faceIndices", face_indices.iter().map(|&i| i as i32));
+            }
+            if let Some(edge_length) = edge_length {
+                params.float("edgelength", *edge_length);
+            }
+            "trianglemesh"
+        }
+        Shape::PlyMesh { filename } => {
+            params.string("filename", &filename.to_string_lossy());
+            "plymesh"
+        }
+    };
+
+    (ty, params)
+}
+
+/// Writes directives, keeping track of the attribute nesting and the current color space.
+pub(crate) struct SceneWriter<W> {
+    out: W,
+    depth: usize,
+    /// Current color space, with the color spaces of the enclosing attribute blocks.
+    color_space: ColorSpace,
+    color_space_stack: Vec<ColorSpace>,
+}
+
+impl<W: Write> SceneWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            depth: 0,
+            color_space: ColorSpace::default(),
+            color_space_stack: Vec::new(),
+        }
+    }
+
+    fn line(&mut self, line: &str) -> Result<()> {
+        writeln!(self.out, "{:indent$}{line}", "", indent = self.depth * 4)?;
+        Ok(())
+    }
+
+    /// Write a directive with quoted `args`, followed by one parameter per line.
+    fn directive(&mut self, name: &str, args: &[&str], params: &ParamSet) -> Result<()> {
+        if let Some(color_space) = params.color_space {
+            if color_space != self.color_space {
+                self.line(&format!(
+                    "ColorSpace {}",
+                    quoted(color_space_name(color_space))
+                ))?;
+                self.color_space = color_space;
+            }
+        }
+
+        let mut line = name.to_string();
+        for arg in args {
+            line.push(' ');
+            line.push_str(&quoted(arg));
+        }
+        self.line(&line)?;
+
+        self.depth += 1;
+        for (name, (ty, value)) in &params.params {
+            self.line(&format!("\"{ty} {name}\" {value}"))?;
+        }
+        self.depth -= 1;
+
+        Ok(())
+    }
+
+    fn attribute_begin(&mut self) -> Result<()> {
+        self.line("AttributeBegin")?;
+        self.depth += 1;
+        self.color_space_stack.push(self.color_space);
+        Ok(())
+    }
+
+    fn attribute_end(&mut self) -> Result<()> {
+        self.depth -= 1;
+        self.color_space = self.color_space_stack.pop().unwrap_or_default();
+        self.line("AttributeEnd")
+    }
+
+    fn transform(&mut self, transform: &Mat4) -> Result<()> {
+        self.line(&format!(
+            "Transform {}",
+            array(transform.to_cols_array(), float)
+        ))
+    }
+
+    pub fn write_scene(&mut self, scene: &Scene) -> Result<()> {
+        let options = options_params(&scene.options);
+        for (name, (ty, value)) in &options.params {
+            self.line(&format!("Option \"{ty} {name}\" {value}"))?;
+        }
+
+        if let Some(film) = &scene.film {
+            let (ty, params) = film_params(film);
+            self.directive("Film", &[ty], &params)?;
+        }
+        if let Some(sampler) = &scene.sampler {
+            let (ty, params) = sampler_params(sampler);
+            self.directive("Sampler", &[ty], &params)?;
+        }
+        if let Some(integrator) = &scene.integrator {
+            let (ty, params) = integrator_params(integrator);
+            self.directive("Integrator", &[ty], &params)?;
+        }
+        if let Some(filter) = &scene.pixel_filter {
+            let (ty, params) = pixel_filter_params(filter);
+            self.directive("PixelFilter", &[ty], &params)?;
+        }
+        if let Some(accelerator) = &scene.accelerator {
+            let (ty, params) = accelerator_params(accelerator);
+            self.directive("Accelerator", &[ty], &params)?;
+        }
+        for camera in &scene.cameras {
+            self.transform(&camera.camera_from_world())?;
+            let (ty, params) = camera_params(&camera.params);
+            self.directive("Camera", &[ty], &params)?;
+        }
+
+        self.line("WorldBegin")?;
+
+        // Textures can only reference textures defined before them, so the order is kept.
+        for texture in &scene.textures {
+            let (class, params) = texture_params(texture, &scene.textures);
+            let ty = match texture.ty {
+                TextureType::Float => "float",
+                TextureType::Spectrum => "spectrum",
+            };
+
+            match &texture.transform {
+                Some(transform) => {
+                    self.attribute_begin()?;
+                    self.transform(transform)?;
+                    self.directive("Texture", &[&texture.name, ty, class], &params)?;
+                    self.attribute_end()?;
+                }
+                None => self.directive("Texture", &[&texture.name, ty, class], &params)?,
+            }
+        }
+
+        for material in scene.materials.iter().filter(|m| !m.name.is_empty()) {
+            let (ty, mut params) = material_params(material, &scene.textures);
+            params.string("type", ty);
+            self.directive("MakeNamedMaterial", &[&material.name], &params)?;
+        }
+
+        for light in &scene.lights {
+            self.attribute_begin()?;
+            self.transform(&light.transform)?;
+            let params = light_params(&light.params);
+            self.directive("LightSource", &[light.params.type_name()], &params)?;
+            self.attribute_end()?;
+        }
+
+        let mut object_shapes = vec![false; scene.shapes.len()];
+        for object in &scene.objects {
+            if let Some(start) = object.shape_start {
+                object_shapes[start..start + object.shape_count].fill(true);
+            }
+        }
+
+        for (shape, _) in scene
+            .shapes
+            .iter()
+            .zip(&object_shapes)
+            .filter(|(_, in_object)| !**in_object)
+        {
+            self.shape(scene, shape)?;
+        }
+
+        for object in &scene.objects {
+            self.attribute_begin()?;
+            self.transform(&object.object_to_instance)?;
+            self.directive("ObjectBegin", &[&object.name], &ParamSet::default())?;
+            self.depth += 1;
+            if let Some(start) = object.shape_start {
+                for shape in &scene.shapes[start..start + object.shape_count] {
+                    self.shape(scene, shape)?;
+                }
+            }
+            self.depth -= 1;
+            self.line("ObjectEnd")?;
+            self.attribute_end()?;
+        }
+
+        for instance in &scene.instances {
+            self.attribute_begin()?;
+            self.transform(&instance.instance_to_world)?;
+            self.attributes(
+                scene,
+                instance.material_override,
+                instance.area_light_index,
+                instance.reverse_orientation,
+            )?;
+            let name = &scene.objects[instance.object_index].name;
+            self.directive("ObjectInstance", &[name], &ParamSet::default())?;
+            self.attribute_end()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the material, area light and orientation that apply to a shape or instance.
+    fn attributes(
+        &mut self,
+        scene: &Scene,
+        material_index: Option<usize>,
+        area_light_index: Option<usize>,
+        reverse_orientation: bool,
+    ) -> Result<()> {
+        if let Some(material) = material_index.map(|index| &scene.materials[index]) {
+            if material.name.is_empty() {
+                let (ty, params) = material_params(material, &scene.textures);
+                self.directive("Material", &[ty], &params)?;
+            } else {
+                self.directive("NamedMaterial", &[&material.name], &ParamSet::default())?;
+            }
+        }
+
+        if let Some(area_light) = area_light_index.map(|index| &scene.area_lights[index]) {
+            let (ty, params) = area_light_params(area_light);
+            self.directive("AreaLightSource", &[ty], &params)?;
+        }
+
+        if reverse_orientation {
+            self.line("ReverseOrientation")?;
+        }
+
+        Ok(())
+    }
+
+    fn shape(&mut self, scene: &Scene, shape: &ShapeEntity) -> Result<()> {
+        self.attribute_begin()?;
+        self.transform(&shape.transform)?;
+        self.attributes(
+            scene,
+            shape.material_index,
+            shape.area_light_index,
+            shape.reverse_orientation,
+        )?;
+        let (ty, params) = shape_params(shape, &scene.textures);
+        self.directive("Shape", &[ty], &params)?;
+        self.attribute_end()
+    }
+}
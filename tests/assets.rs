@@ -21,7 +21,7 @@ fn assert_eq_f32_arr<const N: usize>(a: impl Borrow<[f32; N]>, b: impl Borrow<[f
 fn disney_cloud() {
     let scene = Scene::from_file("assets/disney-cloud/disney-cloud.pbrt").unwrap();
 
-    let camera = scene.camera.unwrap();
+    let camera = scene.cameras.first().unwrap();
     let Camera::Perspective { fov, .. } = camera.params else {
         panic!("Unexpected camera type");
     };
@@ -32,13 +32,13 @@ fn disney_cloud() {
 
     // Infinite light
     {
-        let infinite = &scene.lights[0];
+        let infinite = &scene.lights[0].params;
 
         let Light::Infinite { spectrum , ..} = infinite else {
             panic!("Unexpected light type at 0, want Infinite");
         };
 
-        let Spectrum::Rgb(rgb) = spectrum.as_ref().unwrap() else {
+        let Spectrum::Rgb(rgb, _) = spectrum.as_ref().unwrap() else {
             panic!("Unexpected spectrum value type");
         };
 
@@ -47,7 +47,7 @@ fn disney_cloud() {
 
     // Distant light
     {
-        let distant = &scene.lights[1];
+        let distant = &scene.lights[1].params;
         assert!(matches!(distant, Light::Distant { .. }));
     }
 
@@ -88,3 +88,27 @@ fn kroken() {
 
     assert_eq!(scene.textures.len(), 74);
 }
+
+#[test]
+fn write_canonical_round_trip() {
+    for path in [
+        "assets/kroken/camera-1.pbrt",
+        "assets/disney-cloud/disney-cloud.pbrt",
+    ] {
+        let write = |scene: &Scene| {
+            let mut out = Vec::new();
+            scene.write_canonical(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let written = write(&Scene::from_file(path).unwrap());
+        // Written paths are already resolved.
+        let reloaded = Scene::load(&written, None).unwrap();
+        let rewritten = write(&reloaded);
+
+        for (line, (a, b)) in written.lines().zip(rewritten.lines()).enumerate() {
+            assert_eq!(a, b, "{path}:{line}");
+        }
+        assert_eq!(rewritten, written, "{path}");
+    }
+}